@@ -0,0 +1,177 @@
+// The prelude of mini-rustc.
+//
+// This file is compiled into every crate as the module `prelude`,
+// and its items are imported into the crate root by an implicit `use prelude::*;`.
+// Items declared in the crate shadow the ones of the prelude.
+// Compile with `--no-prelude` to disable it.
+//
+// TODO: add `Option` and `Result` once enums are supported
+
+// not every crate uses every item
+#![allow(unused)]
+
+/// Bindings to the C library
+pub mod libc {
+    extern "C" {
+        pub fn printf(fmt: &str, ...) -> i32;
+        pub fn dprintf(fd: i32, fmt: &str, ...) -> i32;
+        pub fn puts(s: &str) -> i32;
+        pub fn abort() -> !;
+        pub fn exit(status: i32) -> !;
+        pub fn malloc(size: i32) -> *const i32;
+        pub fn free(ptr: *const i32) -> ();
+        pub fn memset(dest: *const i32, c: i32, n: i32) -> *const i32;
+        pub fn memcpy(dest: *const i32, src: *const i32, n: i32) -> *const i32;
+    }
+}
+
+pub use self::libc::{free, malloc, memcpy, memset, printf, puts};
+
+/// Print a string to the standard output
+pub fn print(s: &str) -> () {
+    unsafe {
+        libc::printf("%s", s);
+    }
+}
+
+/// Print a string followed by a newline to the standard output
+pub fn println(s: &str) -> () {
+    unsafe {
+        libc::printf("%s\n", s);
+    }
+}
+
+pub fn print_i32(n: i32) -> () {
+    unsafe {
+        libc::printf("%d", n);
+    }
+}
+
+pub fn println_i32(n: i32) -> () {
+    unsafe {
+        libc::printf("%d\n", n);
+    }
+}
+
+/// Terminate the process abnormally
+pub fn abort() -> ! {
+    unsafe { libc::abort() }
+}
+
+/// Terminate the process with `status`
+pub fn exit(status: i32) -> ! {
+    unsafe { libc::exit(status) }
+}
+
+/// Operations implemented by the compiler
+pub mod intrinsics {
+    extern "rust-intrinsic" {
+        pub fn wrapping_add(a: i32, b: i32) -> i32;
+        pub fn wrapping_sub(a: i32, b: i32) -> i32;
+        pub fn wrapping_mul(a: i32, b: i32) -> i32;
+        pub fn saturating_add(a: i32, b: i32) -> i32;
+        pub fn saturating_sub(a: i32, b: i32) -> i32;
+        pub fn saturating_mul(a: i32, b: i32) -> i32;
+    }
+}
+
+/// Associated constants and functions of `i32`, used like `i32::MAX` and `i32::wrapping_add(a, b)`.
+/// The functions are also the methods of `i32`, like `a.wrapping_add(b)`.
+pub mod num {
+    pub const MIN: i32 = -2147483648;
+    pub const MAX: i32 = 2147483647;
+
+    /// `a + b`, wrapping around at the bounds of `i32`
+    pub fn wrapping_add(a: i32, b: i32) -> i32 {
+        unsafe { super::intrinsics::wrapping_add(a, b) }
+    }
+
+    pub fn wrapping_sub(a: i32, b: i32) -> i32 {
+        unsafe { super::intrinsics::wrapping_sub(a, b) }
+    }
+
+    pub fn wrapping_mul(a: i32, b: i32) -> i32 {
+        unsafe { super::intrinsics::wrapping_mul(a, b) }
+    }
+
+    /// `a + b`, saturating at the bounds of `i32` instead of overflowing
+    pub fn saturating_add(a: i32, b: i32) -> i32 {
+        unsafe { super::intrinsics::saturating_add(a, b) }
+    }
+
+    pub fn saturating_sub(a: i32, b: i32) -> i32 {
+        unsafe { super::intrinsics::saturating_sub(a, b) }
+    }
+
+    pub fn saturating_mul(a: i32, b: i32) -> i32 {
+        unsafe { super::intrinsics::saturating_mul(a, b) }
+    }
+
+    /// Result of the `checked_*` operations: `value` is the result if `is_some`,
+    /// and 0 if the operation overflowed
+    ///
+    /// TODO: return `Option<i32>` instead once enums are supported
+    pub struct Checked {
+        pub is_some: bool,
+        pub value: i32,
+    }
+
+    fn checked(is_some: bool, value: i32) -> Checked {
+        if (is_some) {
+            Checked { is_some: true, value: value }
+        } else {
+            Checked { is_some: false, value: 0 }
+        }
+    }
+
+    /// `a + b`, or nothing if it overflows
+    pub fn checked_add(a: i32, b: i32) -> Checked {
+        // the wrapped and the saturated results differ exactly when the operation overflows
+        let value = wrapping_add(a, b);
+        checked(value == saturating_add(a, b), value)
+    }
+
+    pub fn checked_sub(a: i32, b: i32) -> Checked {
+        let value = wrapping_sub(a, b);
+        checked(value == saturating_sub(a, b), value)
+    }
+
+    pub fn checked_mul(a: i32, b: i32) -> Checked {
+        let value = wrapping_mul(a, b);
+        if (a == 0) {
+            return checked(true, value);
+        }
+        // `MIN / -1` would overflow in the check below
+        if (a == -1) {
+            return checked(b != MIN, value);
+        }
+        checked(value / a == b, value)
+    }
+}
+
+/// Runtime of the built-in macros
+pub mod rt {
+    /// Called by `panic!` and failed `assert!`s
+    pub fn begin_panic(msg: &str, location: &str) -> ! {
+        unsafe {
+            super::libc::dprintf(2, "thread 'main' panicked at %s:\n%s\n", location, msg);
+            super::libc::exit(101)
+        }
+    }
+}
+
+pub fn min(a: i32, b: i32) -> i32 {
+    if (a < b) {
+        a
+    } else {
+        b
+    }
+}
+
+pub fn max(a: i32, b: i32) -> i32 {
+    if (a < b) {
+        b
+    } else {
+        a
+    }
+}
@@ -1,8 +1,10 @@
+pub mod tokenstream;
 pub mod visitor;
 
+use self::tokenstream::{Delimiter, TokenTree};
 use crate::span::{Ident, Span};
 
-#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct NodeId {
     private: u32,
 }
@@ -19,17 +21,37 @@ impl std::fmt::Debug for NodeId {
     }
 }
 
+impl std::fmt::Display for NodeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.private)
+    }
+}
+
 #[derive(Debug)]
 pub struct Crate {
+    /// inner attributes `#![...]` at the start of the crate root
+    pub attrs: Vec<Attribute>,
     pub items: Vec<Item>,
     pub id: NodeId,
 }
 
 #[derive(Debug)]
 pub struct Item {
+    /// outer attributes `#[...]` before the item
+    pub attrs: Vec<Attribute>,
     pub kind: ItemKind,
 }
 
+/// attr ::= "#" "!"? "[" path delimTokenTree? "]"
+/// Only lint attributes like `#[allow(unused_variables)]` are supported.
+#[derive(Debug)]
+pub struct Attribute {
+    pub path: Path,
+    /// tokens between the delimiters after the path, like `unused_variables` above
+    pub args: Option<Vec<TokenTree>>,
+    pub span: Span,
+}
+
 #[derive(Debug)]
 pub enum ItemKind {
     Func(Func),
@@ -38,10 +60,111 @@ pub enum ItemKind {
     Mod(Module),
     Impl(Impl), 
     TypeAlias(Type), 
+    Const(ConstItem),
+    Static(StaticItem),
+    Use(UseItem),
+    ExternCrate(ExternCrateItem),
+    MacroRules(MacroRulesDef),
+    /// replaced by its expansion before name resolution
+    MacCall(MacCall),
+    /// an item which failed to parse, recorded so that later items are still checked
+    Err,
+}
+
+/// macroRules ::= "macro_rules" "!" ident delimTokenTree ";"?
+/// The rules are checked when the macro is defined in the expansion stage.
+#[derive(Debug)]
+pub struct MacroRulesDef {
+    pub name: Ident,
+    pub body: Vec<TokenTree>,
+    pub span: Span,
+    pub id: NodeId,
+}
+
+/// macCall ::= path "!" delimTokenTree
+#[derive(Debug)]
+pub struct MacCall {
+    pub path: Path,
+    pub delim: Delimiter,
+    pub args: Vec<TokenTree>,
+    /// span of the whole call
+    pub span: Span,
+}
+
+/// useItem ::= "use" useTree ";"
+#[derive(Debug)]
+pub struct UseItem {
+    pub vis: Visibility,
+    pub tree: UseTree,
+    pub id: NodeId,
+}
+
+/// externCrate ::= "extern" "crate" ident ("as" ident)? ";"
+#[derive(Debug)]
+pub struct ExternCrateItem {
+    pub vis: Visibility,
+    pub name: Ident,
+    pub rename: Option<Ident>,
+    pub id: NodeId,
+}
+
+/// useTree ::= (path "::")? ("*" | "{" (useTree ("," useTree)* ","?)? "}")
+///           | path ("as" ident)?
+#[derive(Debug)]
+pub struct UseTree {
+    /// Leading path. Empty for `{...}` and `*` without a path.
+    pub prefix: Vec<Ident>,
+    pub kind: UseTreeKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum UseTreeKind {
+    /// `a::b` or `a::b as c`
+    Simple(Option<Ident>),
+    /// `a::*`
+    Glob,
+    /// `a::{b, c}`
+    Nested(Vec<UseTree>),
+}
+
+/// vis ::= ("pub" ("(" ("crate" | "super" | "self") ")")?)?
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// `pub`
+    Public,
+    /// `pub(crate)`
+    Crate,
+    /// `pub(super)`
+    Super,
+    /// No modifier or `pub(self)`: visible in the current module and its descendants
+    Private,
+}
+
+/// constItem ::= "const" ident ":" type "=" expr ";"
+#[derive(Debug)]
+pub struct ConstItem {
+    pub vis: Visibility,
+    pub name: Ident,
+    pub ty: Ty,
+    pub init: Expr,
+    pub id: NodeId,
+}
+
+/// staticItem ::= "static" "mut"? ident ":" type "=" expr ";"
+#[derive(Debug)]
+pub struct StaticItem {
+    pub vis: Visibility,
+    pub name: Ident,
+    pub ty: Ty,
+    pub init: Expr,
+    pub mutable: bool,
+    pub id: NodeId,
 }
 
 #[derive(Debug)]
 pub struct Type {
+    pub vis: Visibility,
     pub name: Ident, 
     pub aliasof: Ty
 }
@@ -55,7 +178,10 @@ pub struct Impl {
 
 #[derive(Debug)]
 pub struct Module {
+    pub vis: Visibility,
     pub name: Ident,
+    /// inner attributes `#![...]` at the start of the module
+    pub attrs: Vec<Attribute>,
     pub items: Vec<Item>,
     pub id: NodeId,
 }
@@ -67,13 +193,23 @@ pub struct ExternBlock {
 
 #[derive(Debug)]
 pub struct StructItem {
+    pub vis: Visibility,
     pub ident: Ident,
-    pub fields: Vec<(Ident, Ty)>,
+    pub fields: Vec<FieldDef>,
     pub id: NodeId,
 }
 
+/// structField ::= vis ident ":" type
+#[derive(Debug)]
+pub struct FieldDef {
+    pub vis: Visibility,
+    pub name: Ident,
+    pub ty: Ty,
+}
+
 #[derive(Debug)]
 pub struct Func {
+    pub vis: Visibility,
     pub name: Ident,
     pub params: Vec<(Ident, Ty)>,
     pub ret_ty: Ty,
@@ -82,6 +218,10 @@ pub struct Func {
     pub body: Option<Block>,
     pub id: NodeId,
     pub variadic: bool, 
+    /// `const fn`
+    pub is_const: bool,
+    /// `unsafe fn`
+    pub is_unsafe: bool,
 }
 
 #[derive(Debug)]
@@ -92,6 +232,7 @@ pub struct Stmt {
 }
 
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum StmtKind {
     /// Expression without trailing semicolon
     Expr(Box<Expr>),
@@ -127,6 +268,8 @@ pub enum ExprKind {
     Assign(Box<Expr>, Box<Expr>),
     Return(Box<Expr>),
     Call(Box<Expr>, Vec<Expr>),
+    /// `receiver.method(args)`, whose method is looked up by the type of the receiver in typeck
+    MethodCall(Box<Expr>, Ident, Vec<Expr>),
     Block(Block),
     /// cond, then (only block expr), else
     If(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
@@ -134,9 +277,55 @@ pub enum ExprKind {
     Field(Box<Expr>, Ident),
     Struct(Path, Vec<(Ident, Box<Expr>)>),
     Array(Vec<Expr>),
+    /// `[elem; count]`, where count is a constant expression
+    Repeat(Box<Expr>, Box<Expr>),
     Cast(Box<Expr>, Ty),
     Ref(Box<Expr>), 
     Deref(Path), 
+    /// replaced by its expansion before name resolution
+    MacCall(MacCall),
+    /// `format_args!(...)`, which evaluates to the formatted `&str`
+    FormatArgs(FormatArgs),
+    Closure(Box<Closure>),
+    /// an expression which failed to parse; it has type `{error}` so that no further errors are reported for it
+    Err,
+}
+
+/// `move |x: T, y| -> R body`
+#[derive(Debug)]
+pub struct Closure {
+    /// `move` closures copy the captured variables instead of referring to them
+    pub is_move: bool,
+    /// types of parameters can be omitted if the closure is coerced to a `fn` pointer
+    pub params: Vec<(Ident, Option<Ty>)>,
+    pub ret_ty: Option<Ty>,
+    pub body: Expr,
+}
+
+/// Format string and arguments of `format_args!`, checked against each other at compile time
+#[derive(Debug)]
+pub struct FormatArgs {
+    pub pieces: Vec<FormatPiece>,
+    /// arguments followed by variables captured by `{name}`
+    pub args: Vec<Expr>,
+}
+
+#[derive(Debug)]
+pub enum FormatPiece {
+    /// text between placeholders, with `{{` and `}}` unescaped
+    Lit(String),
+    Placeholder(FormatPlaceholder),
+}
+
+/// `{}`, `{:?}`, `{0}` or `{name:?}`
+#[derive(Debug)]
+pub struct FormatPlaceholder {
+    /// index in `FormatArgs::args`
+    pub arg: usize,
+    /// `{:?}`
+    pub debug: bool,
+    /// span inside the string literal
+    pub span: Span,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -165,6 +354,8 @@ pub struct Block {
     pub stmts: Vec<Stmt>,
     pub span: Span,
     pub id: NodeId,
+    /// `unsafe { ... }`
+    pub is_unsafe: bool,
 }
 
 #[derive(Debug)]
@@ -172,6 +363,8 @@ pub enum BinOp {
     Add,
     Sub,
     Mul,
+    Div,
+    Rem,
     Eq,
     Ne,
     Gt,
@@ -196,11 +389,14 @@ pub enum TyKind {
     Bool,
     I32,
     Str,
-    Array(Box<Ty>, usize),
+    /// `[T; N]`: the length is a constant expression
+    Array(Box<Ty>, Box<Expr>),
     Adt(Path),
     Ref(Option<Region>, Box<Ty>),
     ConstPtr(Box<Ty>),
     Never,
+    /// `fn(T1, T2) -> R`, a pointer to a function
+    Fn(Vec<Ty>, Box<Ty>),
 }
 
 pub type Region = String;
@@ -0,0 +1,157 @@
+//! Token trees, which are the input and output of macros
+//!
+//! A token tree is either a single token or a sequence of token trees
+//! enclosed in matching delimiters `()`, `[]` or `{}`.
+
+use crate::lexer::{Token, TokenKind};
+use crate::span::Span;
+
+#[derive(Debug, Clone)]
+pub enum TokenTree {
+    /// A token other than delimiters
+    Token(Token),
+    Delimited(Delimited),
+}
+
+#[derive(Debug, Clone)]
+pub struct Delimited {
+    pub delim: Delimiter,
+    /// spans of the opening and closing delimiters
+    pub open: Span,
+    pub close: Span,
+    pub tts: Vec<TokenTree>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    /// `( ... )`
+    Paren,
+    /// `[ ... ]`
+    Bracket,
+    /// `{ ... }`
+    Brace,
+}
+
+impl Delimiter {
+    /// Delimiter opened by `kind`
+    pub fn from_open(kind: &TokenKind) -> Option<Delimiter> {
+        match kind {
+            TokenKind::OpenParen => Some(Delimiter::Paren),
+            TokenKind::OpenBracket => Some(Delimiter::Bracket),
+            TokenKind::OpenBrace => Some(Delimiter::Brace),
+            _ => None,
+        }
+    }
+
+    pub fn is_close(kind: &TokenKind) -> bool {
+        matches!(
+            kind,
+            TokenKind::CloseParen | TokenKind::CloseBracket | TokenKind::CloseBrace
+        )
+    }
+
+    pub fn open_token(&self) -> TokenKind {
+        match self {
+            Delimiter::Paren => TokenKind::OpenParen,
+            Delimiter::Bracket => TokenKind::OpenBracket,
+            Delimiter::Brace => TokenKind::OpenBrace,
+        }
+    }
+
+    pub fn close_token(&self) -> TokenKind {
+        match self {
+            Delimiter::Paren => TokenKind::CloseParen,
+            Delimiter::Bracket => TokenKind::CloseBracket,
+            Delimiter::Brace => TokenKind::CloseBrace,
+        }
+    }
+}
+
+impl TokenTree {
+    /// Span of the token, or of the whole delimited sequence
+    pub fn span(&self) -> Span {
+        match self {
+            TokenTree::Token(t) => t.span.clone(),
+            TokenTree::Delimited(d) => d.open.concat(&d.close),
+        }
+    }
+}
+
+/// Flatten token trees into tokens, restoring the delimiters
+pub fn flatten(tts: &[TokenTree]) -> Vec<Token> {
+    let mut tokens = vec![];
+    for tt in tts {
+        flatten_into(tt, &mut tokens);
+    }
+    tokens
+}
+
+fn flatten_into(tt: &TokenTree, tokens: &mut Vec<Token>) {
+    match tt {
+        TokenTree::Token(t) => tokens.push(t.clone()),
+        TokenTree::Delimited(d) => {
+            tokens.push(Token {
+                kind: d.delim.open_token(),
+                span: d.open.clone(),
+            });
+            for tt in &d.tts {
+                flatten_into(tt, tokens);
+            }
+            tokens.push(Token {
+                kind: d.delim.close_token(),
+                span: d.close.clone(),
+            });
+        }
+    }
+}
+
+/// Number of tokens of `tt` when flattened
+pub fn token_count(tt: &TokenTree) -> usize {
+    match tt {
+        TokenTree::Token(_) => 1,
+        TokenTree::Delimited(d) => 2 + d.tts.iter().map(token_count).sum::<usize>(),
+    }
+}
+
+/// Kind of syntax matched by a macro variable, such as `expr` in `$e:expr`
+/// https://doc.rust-lang.org/reference/macros-by-example.html#metavariables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FragmentKind {
+    Expr,
+    Ident,
+    Ty,
+    Tt,
+    Literal,
+    Block,
+    Path,
+    Item,
+}
+
+impl FragmentKind {
+    pub fn from_name(name: &str) -> Option<FragmentKind> {
+        match name {
+            "expr" => Some(FragmentKind::Expr),
+            "ident" => Some(FragmentKind::Ident),
+            "ty" => Some(FragmentKind::Ty),
+            "tt" => Some(FragmentKind::Tt),
+            "literal" => Some(FragmentKind::Literal),
+            "block" => Some(FragmentKind::Block),
+            "path" => Some(FragmentKind::Path),
+            "item" => Some(FragmentKind::Item),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FragmentKind::Expr => "expr",
+            FragmentKind::Ident => "ident",
+            FragmentKind::Ty => "ty",
+            FragmentKind::Tt => "tt",
+            FragmentKind::Literal => "literal",
+            FragmentKind::Block => "block",
+            FragmentKind::Path => "path",
+            FragmentKind::Item => "item",
+        }
+    }
+}
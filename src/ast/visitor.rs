@@ -17,6 +17,12 @@ pub trait Visitor<'ctx>: Sized {
     fn visit_struct_item_post(&mut self, _struct: &'ctx StructItem) {}
     fn visit_extern_block(&mut self, _block: &'ctx ExternBlock) {}
     fn visit_extern_block_post(&mut self, _block: &'ctx ExternBlock) {}
+    fn visit_const_item(&mut self, _const: &'ctx ConstItem) {}
+    fn visit_const_item_post(&mut self, _const: &'ctx ConstItem) {}
+    fn visit_static_item(&mut self, _static: &'ctx StaticItem) {}
+    fn visit_static_item_post(&mut self, _static: &'ctx StaticItem) {}
+    fn visit_use_item(&mut self, _use: &'ctx UseItem) {}
+    fn visit_extern_crate_item(&mut self, _extern_crate: &'ctx ExternCrateItem) {}
     fn visit_stmt(&mut self, _stmt: &'ctx Stmt) {}
     fn visit_stmt_post(&mut self, _stmt: &'ctx Stmt) {}
     fn visit_expr(&mut self, _expr: &'ctx Expr) {}
@@ -36,6 +42,10 @@ pub fn go_func<'ctx, V: Visitor<'ctx>>(v: &mut V, func: &'ctx Func) {
     walk_func(v, func);
 }
 
+pub fn go_expr<'ctx, V: Visitor<'ctx>>(v: &mut V, expr: &'ctx Expr) {
+    walk_expr(v, expr);
+}
+
 fn walk_crate<'ctx, V: Visitor<'ctx>>(v: &mut V, krate: &'ctx Crate) {
     v.visit_crate(krate);
     for item in &krate.items {
@@ -68,6 +78,22 @@ fn walk_item<'ctx, V: Visitor<'ctx>>(v: &mut V, item: &'ctx Item) {
         ItemKind::TypeAlias(alias) => {
             walk_type(v, &alias.aliasof); 
         }
+        ItemKind::Const(const_item) => {
+            walk_const_item(v, const_item);
+        }
+        ItemKind::Static(static_item) => {
+            walk_static_item(v, static_item);
+        }
+        // paths in `use` are resolved by the resolver itself
+        ItemKind::Use(use_item) => {
+            v.visit_use_item(use_item);
+        }
+        ItemKind::ExternCrate(extern_crate) => {
+            v.visit_extern_crate_item(extern_crate);
+        }
+        // macro definitions are only used by the expander
+        ItemKind::MacroRules(_) | ItemKind::Err => (),
+        ItemKind::MacCall(_) => panic!("ICE: macro calls are expanded before visiting"),
     }
     v.visit_item_post(item);
 }
@@ -86,10 +112,8 @@ fn walk_func<'ctx, V: Visitor<'ctx>>(v: &mut V, func: &'ctx Func) {
 
 fn walk_struct_item<'ctx, V: Visitor<'ctx>>(v: &mut V, struct_item: &'ctx StructItem) {
     v.visit_struct_item(struct_item);
-    for (_ident, ty) in &struct_item.fields {
-        {
-            walk_type(v, ty);
-        }
+    for field in &struct_item.fields {
+        walk_type(v, &field.ty);
     }
     v.visit_struct_item_post(struct_item);
 }
@@ -102,6 +126,20 @@ fn walk_extern_block<'ctx, V: Visitor<'ctx>>(v: &mut V, block: &'ctx ExternBlock
     v.visit_extern_block_post(block);
 }
 
+fn walk_const_item<'ctx, V: Visitor<'ctx>>(v: &mut V, const_item: &'ctx ConstItem) {
+    v.visit_const_item(const_item);
+    walk_type(v, &const_item.ty);
+    walk_expr(v, &const_item.init);
+    v.visit_const_item_post(const_item);
+}
+
+fn walk_static_item<'ctx, V: Visitor<'ctx>>(v: &mut V, static_item: &'ctx StaticItem) {
+    v.visit_static_item(static_item);
+    walk_type(v, &static_item.ty);
+    walk_expr(v, &static_item.init);
+    v.visit_static_item_post(static_item);
+}
+
 fn walk_module_item<'ctx, V: Visitor<'ctx>>(v: &mut V, module: &'ctx Module) {
     v.visit_module_item(module);
     for item in &module.items {
@@ -132,8 +170,9 @@ fn walk_type<'ctx, V: Visitor<'ctx>>(v: &mut V, ty: &'ctx Ty) {
     v.visit_type(ty);
     match &ty.kind {
         TyKind::Bool | TyKind::I32 | TyKind::Never | TyKind::Str | TyKind::Unit => (),
-        TyKind::Array(elem_ty, _n) => {
+        TyKind::Array(elem_ty, len) => {
             walk_type(v, elem_ty);
+            walk_expr(v, len);
         }
         TyKind::Ref(_region, inner_ty) => {
             walk_type(v, inner_ty);
@@ -141,8 +180,14 @@ fn walk_type<'ctx, V: Visitor<'ctx>>(v: &mut V, ty: &'ctx Ty) {
         TyKind::Adt(path) => {
             walk_path(v, path);
         }
+        TyKind::Fn(params, ret_ty) => {
+            for param in params {
+                walk_type(v, param);
+            }
+            walk_type(v, ret_ty);
+        }
         TyKind::ConstPtr(referent) => {
-            walk_type(v, &referent);
+            walk_type(v, referent);
         }
     }
     v.visit_type_post(ty);
@@ -151,7 +196,7 @@ fn walk_type<'ctx, V: Visitor<'ctx>>(v: &mut V, ty: &'ctx Ty) {
 fn walk_expr<'ctx, V: Visitor<'ctx>>(v: &mut V, expr: &'ctx Expr) {
     v.visit_expr(expr);
     match &expr.kind {
-        ExprKind::NumLit(_) | ExprKind::BoolLit(_) | ExprKind::StrLit(_) | ExprKind::Unit => (),
+        ExprKind::NumLit(_) | ExprKind::BoolLit(_) | ExprKind::StrLit(_) | ExprKind::Unit | ExprKind::Err => (),
         ExprKind::Binary(_, l, r) | ExprKind::Assign(l, r) => {
             walk_expr(v, l);
             walk_expr(v, r);
@@ -171,6 +216,12 @@ fn walk_expr<'ctx, V: Visitor<'ctx>>(v: &mut V, expr: &'ctx Expr) {
                 walk_expr(v, arg);
             }
         }
+        ExprKind::MethodCall(receiver, _, args) => {
+            walk_expr(v, receiver);
+            for arg in args {
+                walk_expr(v, arg);
+            }
+        }
         ExprKind::Block(block) => {
             walk_block(v, block);
         }
@@ -199,6 +250,10 @@ fn walk_expr<'ctx, V: Visitor<'ctx>>(v: &mut V, expr: &'ctx Expr) {
                 walk_expr(v, e);
             }
         }
+        ExprKind::Repeat(elem, count) => {
+            walk_expr(v, elem);
+            walk_expr(v, count);
+        }
         ExprKind::Cast(inner, ty) => {
             walk_expr(v, inner);
             walk_type(v, ty);
@@ -209,6 +264,23 @@ fn walk_expr<'ctx, V: Visitor<'ctx>>(v: &mut V, expr: &'ctx Expr) {
         ExprKind::Ref(path) => {
             walk_expr(v, path);  
         }
+        ExprKind::FormatArgs(fmt) => {
+            for arg in &fmt.args {
+                walk_expr(v, arg);
+            }
+        }
+        ExprKind::Closure(closure) => {
+            for (_, ty) in &closure.params {
+                if let Some(ty) = ty {
+                    walk_type(v, ty);
+                }
+            }
+            if let Some(ret_ty) = &closure.ret_ty {
+                walk_type(v, ret_ty);
+            }
+            walk_expr(v, &closure.body);
+        }
+        ExprKind::MacCall(_) => panic!("ICE: macro calls are expanded before visiting"),
     }
     v.visit_expr_post(expr);
 }
@@ -1,23 +1,30 @@
 use std::rc::Rc;
-use super::{Codegen, LLValue};
+use super::{Codegen, CodegenError, LLValue};
 use crate::{
-    ast::{Block, Crate, ExternBlock, Func, Item, ItemKind, LetStmt, Stmt, StmtKind},
+    ast::{
+        Block, Closure, Crate, Expr, ExternBlock, Func, Item, ItemKind, LetStmt, StaticItem, Stmt,
+        StmtKind,
+    },
     backend_llvm::{
-        frame::{compute_frame, LocalKind},
+        const_to_llimm,
+        frame::{compute_closure_frame, compute_frame, LocalKind},
+        ir::{Function, Global, Instruction},
         llvm::{LLReg, LLTy},
         LLImm,
     },
+    metadata::ExportedItemKind,
+    middle::{intrinsics::INTRINSIC_ABI, ty::TyKind},
 };
 
 impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
-    pub fn gen_crate(&mut self, krate: &'gen Crate) -> Result<(), ()> {
+    pub fn gen_crate(&mut self, krate: &'gen Crate) -> Result<(), CodegenError> {
         for item in &krate.items {
             self.gen_item(item)?;
         }
         Ok(())
     }
 
-    pub fn gen_item(&mut self, item: &'gen Item) -> Result<(), ()> {
+    pub fn gen_item(&mut self, item: &'gen Item) -> Result<(), CodegenError> {
         match &item.kind {
             ItemKind::Impl(implements) => {
                 for func in &implements.methods {
@@ -34,34 +41,127 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                     self.gen_item(inner_item)?;
                 }
             }
-            ItemKind::TypeAlias(alias) => {
-                let binding = self.ctx.get_binding(&alias.name).unwrap(); 
-                println!("Binding: {:?}", binding); 
+            // constants are inlined at their use sites
+            ItemKind::Const(_) | ItemKind::Use(_) | ItemKind::ExternCrate(_) => (),
+            ItemKind::MacroRules(_) => (),
+            ItemKind::MacCall(_) => panic!("ICE: macro calls are expanded before codegen"),
+            ItemKind::Err => panic!("ICE: crates with syntax errors are not compiled"),
+            ItemKind::Static(static_item) => self.gen_static_item(static_item)?,
+            // type aliases have no code
+            ItemKind::TypeAlias(_) => (),
+        }
+        Ok(())
+    }
+
+    /// Declare functions and statics of extern crates
+    pub fn gen_extern_crate_decls(&mut self) {
+        let mut decls = vec![];
+        for krate in self.ctx.extern_crates() {
+            for item in &krate.items {
+                match &item.kind {
+                    ExportedItemKind::Fn { ty, .. } => {
+                        decls.push((Rc::clone(&item.cpath), Rc::clone(ty), None));
+                    }
+                    ExportedItemKind::Static(ty, mutable) => {
+                        decls.push((Rc::clone(&item.cpath), Rc::clone(ty), Some(*mutable)));
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        for (cpath, ty, is_static) in decls {
+            let symbol = self.symbol_name(&cpath);
+            if let Some(mutable) = is_static {
+                let llty = self.ty_to_llty(&ty);
+                // ZST statics do not need memory
+                if !llty.is_void() {
+                    self.module.globals.push(Global {
+                        name: symbol,
+                        linkage: Some("external"),
+                        constant: !mutable,
+                        ty: llty.to_string(),
+                        init: None,
+                    });
+                }
+                continue;
+            }
+            // foreign functions may be declared by this crate too
+            if !self.fn_symbols.insert(symbol.clone()) {
+                continue;
+            }
+            let (param_tys, ret_ty, variadic) = match &ty.kind {
+                TyKind::Fn(params, ret, variadic) => (params, ret, *variadic),
+                _ => unreachable!(),
+            };
+            let ret_llty = self.ty_to_llty(ret_ty);
+            let mut params = vec![];
+            // We use `sret` to return ADTs or arrays
+            let actual_ret_llty = if ret_llty.eval_to_ptr() {
+                params.push(format!("ptr sret({})", ret_llty));
+                LLTy::Void
+            } else {
+                ret_llty
+            };
+            for param_ty in param_tys.iter() {
+                let llty = self.ty_to_llty(param_ty);
+                if llty.eval_to_ptr() {
+                    params.push(LLTy::Ptr(Rc::new(llty)).to_string());
+                } else if !llty.is_void() {
+                    params.push(llty.to_string());
+                }
             }
+            if variadic {
+                params.push("...".to_string());
+            }
+            self.module
+                .functions
+                .push(Function::new(symbol, actual_ret_llty.to_string(), params));
         }
+    }
+
+    fn gen_static_item(&mut self, static_item: &'gen StaticItem) -> Result<(), CodegenError> {
+        let binding = self.ctx.get_binding(&static_item.name).unwrap();
+        let ty = self.ctx.lookup_name_type(&binding).unwrap();
+        let llty = self.ty_to_llty(&ty);
+        // ZST statics do not need memory
+        if llty.is_void() {
+            return Ok(());
+        }
+        let init = self.ctx.lookup_const_value(&binding).unwrap();
+        self.module.globals.push(Global {
+            name: self.symbol_name(&binding.cpath),
+            linkage: None,
+            constant: !static_item.mutable,
+            ty: llty.to_string(),
+            init: Some(const_to_llimm(init).to_string()),
+        });
         Ok(())
     }
 
-    pub fn gen_external_block(&mut self, ext_block: &'gen ExternBlock) -> Result<(), ()> {
+    pub fn gen_external_block(&mut self, ext_block: &'gen ExternBlock) -> Result<(), CodegenError> {
         for func in &ext_block.funcs {
             self.gen_func(func)?;
         }
         Ok(())
     }
 
-    fn gen_func(&mut self, func: &'gen Func) -> Result<(), ()> {
-        // do not generate code for the func if it does not have its body
-        if func.body.is_none() {
-            print!("declare ")
-        } else {
-            print!("define ")
+    fn gen_func(&mut self, func: &'gen Func) -> Result<(), CodegenError> {
+        let fn_name_binding = self.ctx.get_binding(&func.name).unwrap();
+        let symbol = self.symbol_name(&fn_name_binding.cpath);
+        // a foreign function can be declared in several extern blocks, e.g. in the prelude too
+        if func.body.is_none() && self.fn_symbols.contains(&symbol) {
+            return Ok(());
+        }
+        // intrinsics are lowered at their call sites
+        if func.ext.as_deref() == Some(INTRINSIC_ABI) {
+            return Ok(());
         }
 
         // collect information about all variables including parameters
         let frame = compute_frame(self, func);
         self.push_frame(frame);
 
-        let fn_name_binding = self.ctx.get_binding(&func.name).unwrap();
         let (_param_tys, ret_ty) = self
             .ctx
             .lookup_name_type(&fn_name_binding)
@@ -76,96 +176,174 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
             &ret_llty
         };
 
-        print!(
-            "{} @{}(",
-            actual_ret_llty.to_string(),
-            fn_name_binding.cpath.demangle()
-        );
+        self.current_symbol = symbol.clone();
+        self.fn_symbols.insert(symbol.clone());
 
+        let mut params = vec![];
         // sret
         if ret_llty.eval_to_ptr() {
             let sret_reg_name = self.peek_frame_mut().get_fresh_reg();
-            print!("ptr sret({}) {}", ret_llty.to_string(), sret_reg_name);
+            params.push(format!("ptr sret({}) {}", ret_llty, sret_reg_name));
             self.peek_frame_mut().set_sret_reg(LLReg::new(
                 sret_reg_name,
                 Rc::new(LLTy::Ptr(Rc::clone(&ret_llty))),
             ));
-            if !func.params.is_empty() {
-                print!(",");
-            }
         }
 
-        // parameters
-        let mut it = self
-            .peek_frame()
-            .get_locals()
-            .iter()
-            .filter(|(bind, l)| bind.kind.is_param() && !l.reg.llty.is_void())
-            .peekable();
-        while let Some((_, local)) = it.next() {
-            print!("{}", local.reg.to_string_with_type());
-            if it.peek().is_some() {
-                print!(", ");
+        // parameters, in the order of declaration
+        for (param, _) in &func.params {
+            let binding = self.ctx.get_binding(param).unwrap();
+            let local = self.peek_frame().get_local(&binding);
+            if !local.reg.llty.is_void() {
+                params.push(local.reg.to_string_with_type());
             }
         }
-        
         if func.variadic {
-            print!(", ..."); 
+            params.push("...".to_string());
         }
-        print!(")");
+        let mut function = Function::new(symbol, actual_ret_llty.to_string(), params);
 
-        let Some(body) = &func.body else{
-            println!();
+        // do not generate code for the func if it does not have its body
+        let Some(body) = &func.body else {
+            self.module.functions.push(function);
             return Ok(());
         };
 
-        println!(" {{");
-        println!("start:");
+        function.start_block("start".to_string(), None);
+        self.function = Some(function);
+        self.gen_allocas();
+
+        let body_val = self.gen_block(body)?;
+        self.gen_return(body_val, self.ctx.get_type(body.id).is_never(), &ret_llty);
+        self.finish_function();
+
+        self.pop_frame();
+        self.gen_pending_closures()
+    }
 
+    /// Allocate the local and temporary variables of the current frame
+    fn gen_allocas(&mut self) {
         // allocate local variables
-        for (bind, local) in self.peek_frame().get_locals() {
-            if bind.kind.is_let() && !local.reg.llty.is_void() {
+        let frame = self.peek_frame();
+        let mut allocas = vec![];
+        for (bind, local) in frame.get_locals() {
+            if bind.kind.is_let() && !frame.is_capture(bind) && !local.reg.llty.is_void() {
                 assert!(local.kind == LocalKind::Ptr);
-                println!(
-                    "\t{} = alloca {}",
-                    local.reg.name,
-                    local.reg.llty.peel_ptr().unwrap().to_string()
-                );
+                allocas.push(Rc::clone(&local.reg));
             }
         }
 
         // allocate temporary variables
-        for reg in self.peek_frame().get_ptrs_to_temporary().values() {
-            println!(
-                "\t{} = alloca {}",
-                reg.name,
-                reg.llty.peel_ptr().unwrap().to_string()
-            );
+        allocas.extend(self.peek_frame().get_ptrs_to_temporary().values().cloned());
+        for reg in allocas {
+            self.emit(Instruction::with_result(
+                &reg.name,
+                "alloca",
+                reg.llty.peel_ptr().unwrap().to_string(),
+            ));
         }
+    }
 
-        let body_val = self.gen_block(body)?;
-
-        if !self.ctx.get_type(body.id).is_never() {
+    /// Return the value of the body of the current function
+    fn gen_return(&mut self, body_val: LLValue, diverges: bool, ret_llty: &LLTy) {
+        if !diverges {
             if ret_llty.eval_to_ptr() {
                 let LLValue::Reg(body_val_reg) = body_val else {
                     panic!("ICE");
                 };
                 self.memcpy(&self.peek_frame().get_sret_reg().unwrap(), &body_val_reg);
-                println!("\tret void");
+                self.emit(Instruction::new("ret", "void"));
             } else {
-                println!("\tret {}", body_val.to_string_with_type());
+                self.emit(Instruction::new("ret", body_val.to_string_with_type()));
             }
+        } else {
+            // terminate the dead block after `return` or a call to a function returning `!`
+            self.emit(Instruction::unreachable());
         }
+    }
 
-        println!("}}");
-        println!();
+    /// Generate the code of the closures used by the function generated last
+    fn gen_pending_closures(&mut self) -> Result<(), CodegenError> {
+        let closures = std::mem::take(&mut self.pending_closures);
+        for (expr, closure, symbol) in closures {
+            self.gen_closure_fn(expr, closure, symbol)?;
+        }
+        Ok(())
+    }
 
-        self.pop_frame();
+    /// The code of a closure is an internal function taking a pointer to the environment
+    /// before its parameters, unless it captures nothing
+    fn gen_closure_fn(&mut self, expr: &'gen Expr, closure: &'gen Closure, symbol: String) -> Result<(), CodegenError> {
+        let ty = self.ctx.get_type(expr.id);
+        let (_param_tys, ret_ty, _) = ty.get_callable_sig().unwrap();
+        let captures = match &ty.kind {
+            TyKind::Closure(_) => self.closure_env_captures(expr.id, closure.is_move),
+            _ => vec![],
+        };
+        let frame = compute_closure_frame(self, expr, closure, &captures);
+        self.push_frame(frame);
+        self.current_symbol = symbol.clone();
 
-        Ok(())
+        let ret_llty = Rc::new(self.ty_to_llty(ret_ty));
+        let actual_ret_llty = if ret_llty.is_void() || ret_llty.eval_to_ptr() {
+            &LLTy::Void
+        } else {
+            &ret_llty
+        };
+
+        let mut params = vec![];
+        if ret_llty.eval_to_ptr() {
+            let sret_reg_name = self.peek_frame_mut().get_fresh_reg();
+            params.push(format!("ptr sret({}) {}", ret_llty, sret_reg_name));
+            self.peek_frame_mut().set_sret_reg(LLReg::new(
+                sret_reg_name,
+                Rc::new(LLTy::Ptr(Rc::clone(&ret_llty))),
+            ));
+        }
+        if !captures.is_empty() {
+            params.push("ptr %env".to_string());
+        }
+        for (param, _) in &closure.params {
+            let binding = self.ctx.get_binding(param).unwrap();
+            let local = self.peek_frame().get_local(&binding);
+            if !local.reg.llty.is_void() {
+                params.push(local.reg.to_string_with_type());
+            }
+        }
+        let mut function = Function::new(symbol, actual_ret_llty.to_string(), params);
+        function.linkage = Some("internal");
+        function.start_block("start".to_string(), None);
+        self.function = Some(function);
+        self.gen_allocas();
+
+        // captured variables are reached through the environment
+        for (i, (binding, by_ref)) in captures.iter().enumerate() {
+            let local = self.peek_frame().get_local(binding);
+            if *by_ref {
+                let field = self.gen_closure_field_lval(expr.id, "%env", i + 1);
+                self.emit(Instruction::with_result(
+                    &local.reg.name,
+                    "load",
+                    format!("ptr, ptr {}", field.name),
+                ));
+            } else {
+                self.emit(Instruction::with_result(
+                    &local.reg.name,
+                    "getelementptr",
+                    format!("%Closure.{}, ptr %env, i32 0, i32 {}", expr.id, i + 1),
+                ));
+            }
+        }
+
+        let body_val = self.eval_expr(&closure.body)?;
+        self.gen_return(body_val, self.ctx.get_type(closure.body.id).is_never(), &ret_llty);
+        self.finish_function();
+
+        self.pop_frame();
+        self.gen_pending_closures()
     }
 
-    pub fn gen_block(&mut self, block: &'gen Block) -> Result<LLValue, ()> {
+    pub fn gen_block(&mut self, block: &'gen Block) -> Result<LLValue, CodegenError> {
         let mut last_stmt_val = None;
         for stmt in &block.stmts {
             last_stmt_val = Some(self.gen_stmt(stmt)?);
@@ -174,7 +352,7 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
         Ok(ret)
     }
 
-    fn gen_stmt(&mut self, stmt: &'gen Stmt) -> Result<LLValue, ()> {
+    fn gen_stmt(&mut self, stmt: &'gen Stmt) -> Result<LLValue, CodegenError> {
         // println!("; Starts stmt `{}`", stmt.span.to_snippet());
         let val = match &stmt.kind {
             StmtKind::Semi(expr) => {
@@ -186,10 +364,12 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                 let binding = self.ctx.get_binding(ident).unwrap();
                 let local = self.peek_frame().get_local(&binding);
 
-                if let Some(init) = init && local.kind == LocalKind::Ptr {
-                    let ptr = self.gen_binding_lval(&binding).unwrap();
-                    // assign initializer
-                    self.initialize_memory_with_value(&ptr, init)?;
+                if let Some(init) = init {
+                    if local.kind == LocalKind::Ptr {
+                        let ptr = self.gen_binding_lval(&binding)?;
+                        // assign initializer
+                        self.initialize_memory_with_value(&ptr, init)?;
+                    }
                 }
                 LLValue::Imm(LLImm::Void)
             }
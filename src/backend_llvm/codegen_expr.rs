@@ -1,18 +1,25 @@
-use super::{Codegen, LLValue};
+use super::{Codegen, CodegenError, LLValue};
 use crate::{
     ast::{self, Expr, ExprKind, NodeId},
-    backend_llvm::{llvm::LLConst, LLImm, LLReg, LLTy, TyKind},
+    backend_llvm::{const_to_llimm, ir::Instruction, llvm::LLConst, LLImm, LLReg, LLTy, TyKind},
+    middle::ty::Ty,
 };
 use std::rc::Rc;
-use crate::resolve::BindingKind; 
+use crate::resolve::{Binding, BindingKind}; 
 
 
 impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
     // evaluate expression
     // expr struct/array -> sturct*/array*
     // otherwise: expr: LLTY -> LLTY/void
-    pub fn eval_expr(&mut self, expr: &'gen Expr) -> Result<LLValue, ()> {
+    pub fn eval_expr(&mut self, expr: &'gen Expr) -> Result<LLValue, CodegenError> {
         //println!("; Starts expr `{}`", expr.span.to_snippet());
+        // a variable holding a closure which captures nothing is coerced to its code pointer
+        if let Some(closure) = self.ctx.coerced_closure(expr.id) {
+            let env = self.gen_lval(expr)?;
+            let code_ptr = self.gen_closure_field_lval(closure, &env.name, 0);
+            return Ok(LLValue::Reg(self.load_ptr(&code_ptr)?));
+        }
         let llty = self.ty_to_llty(&self.ctx.get_type(expr.id));
         if llty.eval_to_ptr() {
             return Ok(LLValue::Reg(self.gen_lval(expr)?));
@@ -32,28 +39,35 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                 }
             }
             ExprKind::Unit => LLValue::Imm(LLImm::Void),
-            ExprKind::StrLit(s) => {
-                let llcons = Rc::new(LLConst {
-                    name: self.get_fresh_str_name(),
-                    string_lit: s.clone(),
-                    // FIXME: +1 for \00
-                    llty: Rc::new(LLTy::Array(Rc::new(LLTy::I8), s.len() + 1)),
-                });
-                self.constants.push(Rc::clone(&llcons));
-                LLValue::PtrConst(llcons)
-            }
+            ExprKind::StrLit(s) => LLValue::PtrConst(self.gen_str_const(s)),
             ExprKind::Unary(unop, inner) => match unop {
-                ast::UnOp::Minus => {
-                    let inner_val = self.eval_expr(inner)?;
-                    assert!(inner_val.llty().is_integer());
-                    let reg = self.peek_frame_mut().get_fresh_reg();
-                    println!(
-                        "\t{reg} = sub {} 0, {}",
-                        inner_val.llty().to_string(),
-                        inner_val.to_string()
-                    );
-                    LLValue::Reg(LLReg::new(reg, inner_val.llty()))
-                }
+                ast::UnOp::Minus => match &inner.kind {
+                    // negative literals are constants, so `-2147483648` does not overflow
+                    ExprKind::NumLit(n) if i32::try_from(-i64::from(*n)).is_ok() => {
+                        LLValue::Imm(LLImm::I32(-i64::from(*n) as i32))
+                    }
+                    _ => {
+                        let inner_val = self.eval_expr(inner)?;
+                        assert!(inner_val.llty().is_integer());
+                        if self.opts.overflow_checks {
+                            let zero = LLValue::Imm(LLImm::I32(0));
+                            return Ok(self.gen_overflow_checked_op(
+                                "ssub",
+                                &zero,
+                                &inner_val,
+                                &expr.span,
+                                "attempt to negate with overflow",
+                            ));
+                        }
+                        let reg = self.peek_frame_mut().get_fresh_reg();
+                        self.emit(Instruction::with_result(
+                            &reg,
+                            "sub",
+                            format!("{} 0, {}", inner_val.llty(), inner_val),
+                        ));
+                        LLValue::Reg(LLReg::new(reg, inner_val.llty()))
+                    }
+                },
                 ast::UnOp::Plus => self.eval_expr(inner)?,
             },
             ExprKind::Binary(binop, lhs, rhs) => {
@@ -62,73 +76,67 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                 // checks if rhs and lhs have the same type
                 assert_eq!(self.ctx.get_type(lhs.id), self.ctx.get_type(rhs.id));
                 let rhs_lhs_llty = self.ty_to_llty(&self.ctx.get_type(lhs.id));
+                if matches!(binop, ast::BinOp::Div | ast::BinOp::Rem) {
+                    self.gen_div_check(binop, &l, &r, &expr.span);
+                }
+                // in debug builds, arithmetic overflow panics instead of wrapping
+                if self.opts.overflow_checks {
+                    let checked = match binop {
+                        ast::BinOp::Add => Some(("sadd", "attempt to add with overflow")),
+                        ast::BinOp::Sub => Some(("ssub", "attempt to subtract with overflow")),
+                        ast::BinOp::Mul => Some(("smul", "attempt to multiply with overflow")),
+                        _ => None,
+                    };
+                    if let Some((op, msg)) = checked {
+                        return Ok(self.gen_overflow_checked_op(op, &l, &r, &expr.span, msg));
+                    }
+                }
 
                 let reg_name = self.peek_frame_mut().get_fresh_reg();
-                let llty = match binop {
+                // `icmp` takes the condition before its operands
+                let (opcode, cond, llty) = match binop {
                     ast::BinOp::Add => {
                         assert!(rhs_lhs_llty.is_integer());
-                        println!(
-                            "\t{reg_name} = add {}, {}",
-                            l.to_string_with_type(),
-                            r.to_string()
-                        );
-                        rhs_lhs_llty
+                        ("add", "", rhs_lhs_llty)
                     }
                     ast::BinOp::Sub => {
                         assert!(rhs_lhs_llty.is_integer());
-                        println!(
-                            "\t{reg_name} = sub {}, {}",
-                            l.to_string_with_type(),
-                            r.to_string()
-                        );
-                        rhs_lhs_llty
+                        ("sub", "", rhs_lhs_llty)
                     }
                     ast::BinOp::Mul => {
                         assert!(rhs_lhs_llty.is_integer());
-                        println!(
-                            "\t{reg_name} = mul {}, {}",
-                            l.to_string_with_type(),
-                            r.to_string()
-                        );
-                        rhs_lhs_llty
+                        ("mul", "", rhs_lhs_llty)
+                    }
+                    ast::BinOp::Div => {
+                        assert!(rhs_lhs_llty.is_signed_integer());
+                        ("sdiv", "", rhs_lhs_llty)
+                    }
+                    ast::BinOp::Rem => {
+                        assert!(rhs_lhs_llty.is_signed_integer());
+                        ("srem", "", rhs_lhs_llty)
                     }
                     ast::BinOp::Eq => {
                         assert!(rhs_lhs_llty.is_integer());
-                        println!(
-                            "\t{reg_name} = icmp eq {}, {}",
-                            l.to_string_with_type(),
-                            r.to_string()
-                        );
-                        LLTy::I1
+                        ("icmp", "eq ", LLTy::I1)
                     }
                     ast::BinOp::Ne => {
                         assert!(rhs_lhs_llty.is_integer());
-                        println!(
-                            "\t{reg_name} = icmp ne {}, {}",
-                            l.to_string_with_type(),
-                            r.to_string()
-                        );
-                        LLTy::I1
+                        ("icmp", "ne ", LLTy::I1)
                     }
                     ast::BinOp::Gt => {
                         assert!(rhs_lhs_llty.is_signed_integer());
-                        println!(
-                            "\t{reg_name} = icmp sgt {}, {}",
-                            l.to_string_with_type(),
-                            r.to_string()
-                        );
-                        LLTy::I1
+                        ("icmp", "sgt ", LLTy::I1)
                     }
                     ast::BinOp::Lt => {
                         assert!(rhs_lhs_llty.is_signed_integer());
-                        println!(
-                            "\t{reg_name} = icmp slt {}, {}",
-                            l.to_string_with_type(),
-                            r.to_string()
-                        );
-                        LLTy::I1
+                        ("icmp", "slt ", LLTy::I1)
                     }
                 };
+                self.emit(Instruction::with_result(
+                    &reg_name,
+                    opcode,
+                    format!("{}{}, {}", cond, l.to_string_with_type(), r),
+                ));
                 LLValue::Reg(LLReg::new(reg_name, Rc::new(llty)))
             }
             ExprKind::Return(inner) => {
@@ -140,16 +148,30 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                         panic!("ICE");
                     };
                     self.memcpy(&sret_reg, &reg);
-                    println!("\tret void");
+                    self.emit(Instruction::new("ret", "void"));
                 } else {
                     // value
-                    println!("\tret {}", inner_val_or_ptr.to_string_with_type());
+                    self.emit(Instruction::new("ret", inner_val_or_ptr.to_string_with_type()));
                 }
+                // code after `return` is dead
+                let label = self.get_fresh_label_name();
+                self.start_block(label, None);
                 LLValue::Imm(LLImm::Void)
             }
             ExprKind::Block(block) => self.gen_block(block)?,
             // identifiers may not be allocated on memory
-            ExprKind::Path(path) => LLValue::Reg(self.load_path(path)?),
+            ExprKind::Path(path) => {
+                let binding = self.ctx.resolve_path(path).unwrap();
+                if let BindingKind::Const = binding.kind {
+                    LLValue::Imm(const_to_llimm(self.ctx.lookup_const_value(&binding).unwrap()))
+                } else if binding.kind == BindingKind::Item {
+                    // functions evaluate to their addresses
+                    let symbol = format!("@{}", self.symbol_name(&binding.cpath));
+                    LLValue::Reg(LLReg::new(symbol, Rc::new(llty)))
+                } else {
+                    LLValue::Reg(self.load_path(path)?)
+                }
+            }
             ExprKind::Ref(path) => {
                 let j  = self.gen_lval(path)?;
                 LLValue::Reg(j)
@@ -159,6 +181,8 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                 let some = self.load_ptr(&some)?;
                 LLValue::Reg(some)
             }
+            ExprKind::FormatArgs(fmt) => self.gen_format_args(fmt)?,
+            ExprKind::MacCall(_) => panic!("ICE: macro calls are expanded before codegen"),
             // arrays and structs are always allocated on memory
             ExprKind::Index(_, _) | ExprKind::Field(_, _) => {
                 let lval = self.gen_lval(expr)?;
@@ -168,63 +192,25 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
             ExprKind::Assign(lhs, rhs) => {
                 let rhs_llty = self.ty_to_llty(&self.ctx.get_type(rhs.id));
 
-                match &lhs.kind {
-                    ExprKind::Path(path) | ExprKind::Deref(path)=> {
-                        let binding = self.ctx.resolve_path(&path).unwrap();  
-                        match &binding.kind {
-                            BindingKind::Let(_, mutable) => {
-                                if !mutable {
-                                    println!("cannot re-assign to a non mutable variable"); 
-                                    std::process::exit(1); 
-                                }
-
-                                if let Some(ty) = self.ctx.lookup_name_type(&binding) {
-                                    match &ty.kind {                                       
-                                        TyKind::ConstPtr(inner) => {
-                                            println!(
-                                                "assignment target hides behind a `*const` of type {:?}, signifying that the underlying memory cannot be changed", 
-                                                inner
-                                            );
-                                            std::process::exit(1); 
-                                        } 
-                                        _ => (), 
-                                    }
-                                } 
-                            }
-                            _ => {
-                                println!("Internal error when asigning a value to {:?}", path); 
-                                std::process::exit(1); 
-                            }
-                        }
-                    }
-                    _ => {
-                        println!("Cannot assign to expr of kind {:?}", lhs.kind);
-                        std::process::exit(1); 
-                    }
-                }
                 if rhs_llty.eval_to_ptr() {
                     let lhs_ptr = self.gen_lval(lhs)?;
                     let rhs_ptr = self.gen_lval(rhs)?;
                     self.memcpy(&lhs_ptr, &rhs_ptr);
                 } else {
                     let rhs_val = self.eval_expr(rhs)?;
-                    let lhs_ptr = self.gen_lval(lhs).unwrap();
+                    let lhs_ptr = self.gen_lval(lhs)?;
 
-                    println!(
-                        "\tstore {}, {} {}",
-                        rhs_val.to_string_with_type(),
-                        lhs_ptr.llty.to_string(),
-                        lhs_ptr.name,
-                    );
+                    self.emit(Instruction::new(
+                        "store",
+                        format!("{}, {} {}", rhs_val.to_string_with_type(), lhs_ptr.llty, lhs_ptr.name),
+                    ));
                 }
 
                 LLValue::Imm(LLImm::Void)
             }
             ExprKind::Call(func, args) => self.gen_call_expr(expr.id, func, args)?,
-            ExprKind::If(cond, then, els) => {
-                let res = self.gen_if_expr(cond, then, els)?;
-                LLValue::Reg(res.0)
-            }
+            ExprKind::MethodCall(receiver, _, args) => self.gen_method_call_expr(expr.id, receiver, args)?,
+            ExprKind::If(cond, then, els) => self.gen_if_expr(cond, then, els)?,
             ExprKind::Cast(inner, _) => {
                 // ref: https://doc.rust-lang.org/reference/expressions/operator-expr.html#type-cast-expressions
                 let to = self.ty_to_llty(&self.ctx.get_type(expr.id));
@@ -234,101 +220,174 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                     _ => panic!("ICE"),
                 }
             }
-            ExprKind::Struct(..) | ExprKind::Array(..) => panic!("ICE"),
+            // closures capturing nothing can be coerced to function pointers
+            ExprKind::Closure(closure) => {
+                let symbol = self.queue_closure(expr, closure);
+                LLValue::Reg(LLReg::new(format!("@{}", symbol), Rc::new(llty)))
+            }
+            // aggregates evaluate to pointers
+            ExprKind::Struct(..) | ExprKind::Array(..) | ExprKind::Repeat(..) => {
+                LLValue::Reg(self.gen_lval(expr)?)
+            }
+            ExprKind::Err => panic!("ICE: crates with syntax errors are not compiled"),
         };
 
         // println!("; Finishes expr `{}`", expr.span.to_snippet());
         Ok(ret)
     }
 
-    /// Generate code for if expression. Returns the label of the last bb.
+    /// Generate code for if expression
     pub fn gen_if_expr(
         &mut self,
         cond: &'gen Expr,
         then: &'gen Expr,
         els: &'gen Option<Box<Expr>>,
-    ) -> Result<(Rc<LLReg>, String), ()> {
+    ) -> Result<LLValue, CodegenError> {
         let cond = self.eval_expr(cond)?;
+        let cond = self.gen_i1(&cond);
         let then_label = self.get_fresh_label_name();
         let endif_label = self.get_fresh_label_name();
+        // value of the else branch and the label of its last bb
         let mut else_result = None;
-        // if `else if` is found, this contains its endif label
-        let mut else_label = None;
 
         if let Some(els) = els {
-            else_label = Some(self.get_fresh_label_name());
-            println!(
-                "\tbr {}, label %{}, label %{}",
-                cond.to_string_with_type(),
-                then_label,
-                else_label.as_ref().unwrap()
-            );
-            // else_label:
-            println!("{}:\t; Else", else_label.as_ref().unwrap());
+            let else_label = self.get_fresh_label_name();
+            self.emit(Instruction::cond_br(&cond, &then_label, &else_label));
+            self.start_block(else_label, Some("Else"));
             // else block
-            else_result = match &els.kind {
-                ExprKind::If(cond2, then2, else2) => {
-                    let res = self.gen_if_expr(cond2, then2, else2)?;
-                    else_label = Some(res.1);
-                    Some(LLValue::Reg(res.0))
-                }
-                ExprKind::Block(_) => Some(self.eval_expr(els)?),
-                _ => panic!("ICE: else must be if expr or block expr"),
-            };
-            println!("\tbr label %{}", endif_label);
+            if !matches!(&els.kind, ExprKind::If(..) | ExprKind::Block(_)) {
+                panic!("ICE: else must be if expr or block expr");
+            }
+            let val = self.eval_expr(els)?;
+            else_result = Some((val, self.gen_branch_end(&endif_label)));
         } else {
-            println!(
-                "\tbr {}, label %{}, label %{}",
-                cond.to_string_with_type(),
-                then_label,
-                endif_label
-            );
+            self.emit(Instruction::cond_br(&cond, &then_label, &endif_label));
         }
-        // then_label:
-        println!("{}:\t;Then", then_label);
+        self.start_block(then_label, Some("Then"));
         // then block
         let then_result = self.eval_expr(then)?;
-        println!("\tbr label %{}", endif_label);
+        let then_end_label = self.gen_branch_end(&endif_label);
 
-        println!("{}:\t; Endif", endif_label);
-        if let Some(else_result) = else_result {
-            let reg_name = self.peek_frame_mut().get_fresh_reg();
-            println!(
-                "\t{} = phi {} [{}, %{}], [{}, %{}]",
-                reg_name,
-                then_result.llty().to_string(),
-                then_result.to_string(),
-                then_label,
-                else_result.to_string(),
-                else_label.as_ref().unwrap(),
-            );
-            Ok((LLReg::new(reg_name, then_result.llty()), endif_label))
+        self.start_block(endif_label, Some("Endif"));
+        let Some((else_result, else_end_label)) = else_result else {
+            return Ok(LLValue::Imm(LLImm::Void));
+        };
+        // a diverging branch evaluates to void, and its value is never used
+        let llty = if then_result.llty().is_void() {
+            else_result.llty()
         } else {
-            let reg_name = self.peek_frame_mut().get_fresh_reg();
-            println!(
-                "\t{} = phi {} [{}, %{}]",
-                reg_name,
-                then_result.llty().to_string(),
-                then_result.to_string(),
-                then_label,
-            );
-            Ok((LLReg::new(reg_name, then_result.llty()), endif_label))
+            then_result.llty()
+        };
+        if llty.is_void() {
+            return Ok(LLValue::Imm(LLImm::Void));
+        }
+        let incoming = |val: &LLValue| {
+            if val.llty().is_void() {
+                "undef".to_string()
+            } else {
+                val.to_string()
+            }
+        };
+        let reg_name = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(
+            &reg_name,
+            "phi",
+            format!(
+                "{} [{}, %{}], [{}, %{}]",
+                llty,
+                incoming(&then_result),
+                then_end_label,
+                incoming(&else_result),
+                else_end_label,
+            ),
+        ));
+        Ok(LLValue::Reg(LLReg::new(reg_name, llty)))
+    }
+
+    /// End a branch of an if expression with a jump to `endif_label`. The branch may contain
+    /// other bbs, so this starts a new bb whose label is returned for `phi`.
+    /// Booleans are `i1` as values but `i8` in memory
+    fn gen_i1(&mut self, val: &LLValue) -> String {
+        if *val.llty() == LLTy::I1 {
+            val.to_string()
+        } else {
+            let reg = self.peek_frame_mut().get_fresh_reg();
+            self.emit(Instruction::with_result(&reg, "icmp", format!("ne {}, 0", val.to_string_with_type())));
+            reg
         }
     }
 
+    fn gen_branch_end(&mut self, endif_label: &str) -> String {
+        let label = self.get_fresh_label_name();
+        self.emit(Instruction::br(&label));
+        self.start_block(label.clone(), None);
+        self.emit(Instruction::br(endif_label));
+        label
+    }
+
     pub fn gen_call_expr(
         &mut self,
         // node id of this call expression
         node_id: NodeId,
         func: &'gen Expr,
         args: &'gen [Expr],
-    ) -> Result<LLValue, ()> {
-        let ExprKind::Path(path) = &func.kind else {
-            // TODO:
-            todo!();
+    ) -> Result<LLValue, CodegenError> {
+        // functions are called directly, and function pointers and closures through their
+        // code pointers
+        let direct = match &func.kind {
+            ExprKind::Path(path) => Some(self.ctx.resolve_path(path).unwrap())
+                .filter(|binding| binding.kind == BindingKind::Item),
+            _ => None,
+        };
+        let mut env = None;
+        let callee = if let Some(binding) = &direct {
+            format!("@{}", self.symbol_name(&binding.cpath))
+        } else {
+            let func_ty = self.ctx.get_type(func.id);
+            let func_val = self.eval_expr(func)?;
+            match &func_ty.kind {
+                TyKind::Fn(..) => func_val.to_string(),
+                TyKind::Closure(closure) => {
+                    let LLValue::Reg(env_ptr) = func_val else {
+                        panic!("ICE: closure must evaluate to a pointer to its environment");
+                    };
+                    let code_ptr = self.gen_closure_field_lval(closure.id, &env_ptr.name, 0);
+                    let code = self.load_ptr(&code_ptr)?;
+                    if self.get_closure_lladt(closure.id).fields.len() > 1 {
+                        env = Some(env_ptr);
+                    }
+                    code.name.clone()
+                }
+                _ => panic!("ICE: cannot call `{}`", func_ty),
+            }
         };
+        self.gen_call(node_id, direct, &callee, env, &args.iter().collect::<Vec<_>>())
+    }
 
+    /// `receiver.method(args)` calls the function found by typeck with the receiver first
+    pub fn gen_method_call_expr(
+        &mut self,
+        // node id of this method call expression
+        node_id: NodeId,
+        receiver: &'gen Expr,
+        args: &'gen [Expr],
+    ) -> Result<LLValue, CodegenError> {
+        let binding = self.ctx.method_callee(node_id);
+        let callee = format!("@{}", self.symbol_name(&binding.cpath));
+        let args: Vec<_> = std::iter::once(receiver).chain(args).collect();
+        self.gen_call(node_id, Some(binding), &callee, None, &args)
+    }
 
+    /// Call `callee`, which is the function `direct` if it is called directly, and pass the
+    /// environment `env` of a closure before the arguments
+    fn gen_call(
+        &mut self,
+        node_id: NodeId,
+        direct: Option<Rc<Binding>>,
+        callee: &str,
+        env: Option<Rc<LLReg>>,
+        args: &[&'gen Expr],
+    ) -> Result<LLValue, CodegenError> {
         let mut arg_vals = vec![];
         for arg in args {
             let arg_ty = &self.ctx.get_type(arg.id);
@@ -338,6 +397,11 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                 arg_vals.push(arg_val);
             }
         }
+        if let Some(binding) = &direct {
+            if let Some(intrinsic) = self.ctx.lookup_intrinsic(&binding.cpath) {
+                return Ok(self.gen_intrinsic(intrinsic, &arg_vals));
+            }
+        }
 
         let ret_llty = self.ty_to_llty(&self.ctx.get_type(node_id));
         // We use `sret` to return ADTs or arrays. In this case, actual return type become `void`
@@ -347,53 +411,183 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
             &ret_llty
         };
 
+        let mut operands = vec![];
+        // sret
+        if ret_llty.eval_to_ptr() {
+            let temp = self.peek_frame().get_ptr_to_temporary(node_id).unwrap();
+            operands.push(format!("ptr sret({}) {}", temp.llty.peel_ptr().unwrap(), temp.name));
+        }
+        if let Some(env) = &env {
+            operands.push(format!("ptr {}", env.name));
+        }
+        // arguments
+        for arg_val in &arg_vals {
+            if !arg_val.llty().is_void() {
+                operands.push(arg_val.to_string_with_type());
+            }
+        }
+        let operands = format!("{} {}({})", actual_ret_llty, callee, operands.join(", "));
+
         // instructions returning void cannot have a reg name
-        print!("\t");
         let return_reg = if !actual_ret_llty.is_void() {
             let r = self.peek_frame_mut().get_fresh_reg();
-            print!("{} = ", r);
+            self.emit(Instruction::with_result(&r, "call", operands));
             Some(r)
         } else {
+            self.emit(Instruction::new("call", operands));
             None
         };
 
-        let binding = self.ctx.resolve_path(path).unwrap();
+        // a call to a function returning `!` does not return, and code after it is dead
+        if self.ctx.get_type(node_id).is_never() {
+            self.emit(Instruction::unreachable());
+            let label = self.get_fresh_label_name();
+            self.start_block(label, None);
+        }
 
-        // println!("Call: {:?}", binding);
-        print!(
-            "call {} @{}(",
-            actual_ret_llty.to_string(),
-            binding.cpath.demangle()
-        );
+        if let Some(reg_name) = return_reg {
+            Ok(LLValue::Reg(LLReg::new(reg_name, Rc::new(ret_llty))))
+        } else {
+            Ok(LLValue::Imm(LLImm::Void))
+        }
+    }
+}
 
-        // sret
-        if ret_llty.eval_to_ptr() {
-            let temp = self.peek_frame().get_ptr_to_temporary(node_id).unwrap();
-            print!(
-                "ptr sret({}) {}",
-                temp.llty.peel_ptr().unwrap().to_string(),
-                temp.name
-            );
-            if !args.is_empty() {
-                print!(",")
+impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
+    /// Global constant holding `s` followed by `\00`
+    pub fn gen_str_const(&mut self, s: &str) -> Rc<LLConst> {
+        let llcons = Rc::new(LLConst {
+            name: self.get_fresh_str_name(),
+            string_lit: s.to_string(),
+            llty: Rc::new(LLTy::Array(Rc::new(LLTy::I8), s.len() + 1)),
+        });
+        self.constants.push(Rc::clone(&llcons));
+        llcons
+    }
+
+    /// `format_args!` is lowered to a C format string, which is printed with `snprintf` into a
+    /// buffer allocated by `malloc`. The buffer is never freed.
+    fn gen_format_args(&mut self, fmt: &'gen ast::FormatArgs) -> Result<LLValue, CodegenError> {
+        // arguments are evaluated once and in order, even if they are used several times
+        let mut arg_vals = vec![];
+        for arg in &fmt.args {
+            arg_vals.push(self.eval_expr(arg)?);
+        }
+
+        let mut c_fmt = String::new();
+        let mut c_args = vec![];
+        for piece in &fmt.pieces {
+            match piece {
+                ast::FormatPiece::Lit(s) => c_fmt.push_str(&s.replace('%', "%%")),
+                ast::FormatPiece::Placeholder(placeholder) => {
+                    let ty = self.ctx.get_type(fmt.args[placeholder.arg].id);
+                    let val = &arg_vals[placeholder.arg];
+                    self.gen_format_value(&ty, val, placeholder.debug, &mut c_fmt, &mut c_args);
+                }
             }
         }
+        let c_fmt = self.gen_str_const(&c_fmt);
+        let mut args = format!("ptr {}", c_fmt.name);
+        for arg in &c_args {
+            args.push_str(", ");
+            args.push_str(arg);
+        }
 
-        // arguments
-        for (i, arg_val) in arg_vals.iter().enumerate() {
-            if !arg_val.llty().is_void() {
-                print!("{}", arg_val.to_string_with_type());
-                if i != arg_vals.len() - 1 {
-                    print!(", ");
+        // the first call computes the length of the output
+        let len = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(
+            &len,
+            "call",
+            format!("i32 (ptr, i64, ptr, ...) @snprintf(ptr null, i64 0, {args})"),
+        ));
+        let size = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(&size, "add", format!("i32 {len}, 1")));
+        let size64 = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(&size64, "sext", format!("i32 {size} to i64")));
+        let buf = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(&buf, "call", format!("ptr @malloc(i64 {size64})")));
+        let written = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(
+            &written,
+            "call",
+            format!("i32 (ptr, i64, ptr, ...) @snprintf(ptr {buf}, i64 {size64}, {args})"),
+        ));
+        self.uses_format_runtime = true;
+
+        Ok(LLValue::Reg(LLReg::new(buf, Rc::new(LLTy::Ptr(Rc::new(LLTy::I8))))))
+    }
+
+    /// Append the conversion of `val` of type `ty` to `c_fmt`, and its operands to `c_args`.
+    /// Values of ADTs and arrays are pointers to their memory.
+    fn gen_format_value(&mut self, ty: &Ty, val: &LLValue, debug: bool, c_fmt: &mut String, c_args: &mut Vec<String>) {
+        match &ty.kind {
+            TyKind::I32 => {
+                c_fmt.push_str("%d");
+                c_args.push(val.to_string_with_type());
+            }
+            TyKind::Bool => {
+                let cond = self.gen_i1(val);
+                let t = self.gen_str_const("true");
+                let f = self.gen_str_const("false");
+                let reg = self.peek_frame_mut().get_fresh_reg();
+                self.emit(Instruction::with_result(
+                    &reg,
+                    "select",
+                    format!("i1 {cond}, ptr {}, ptr {}", t.name, f.name),
+                ));
+                c_fmt.push_str("%s");
+                c_args.push(format!("ptr {reg}"));
+            }
+            TyKind::Ref(_) => {
+                c_fmt.push_str(if debug { "\"%s\"" } else { "%s" });
+                c_args.push(format!("ptr {}", val));
+            }
+            TyKind::Adt(cpath) => {
+                let adt = self.ctx.lookup_adt_def(cpath).unwrap();
+                let lladt = self.get_lladt(cpath).unwrap();
+                c_fmt.push_str(cpath.segments().last().unwrap());
+                for (i, (fd, fd_ty)) in adt.fields.iter().enumerate() {
+                    c_fmt.push_str(if i == 0 { " { " } else { ", " });
+                    c_fmt.push_str(fd);
+                    c_fmt.push_str(": ");
+                    let index = lladt.get_field_index(fd).unwrap();
+                    let fd_val = self.gen_format_elem(&LLTy::Adt(Rc::clone(cpath)), val, index, fd_ty);
+                    self.gen_format_value(fd_ty, &fd_val, debug, c_fmt, c_args);
+                }
+                if !adt.fields.is_empty() {
+                    c_fmt.push_str(" }");
+                }
+            }
+            TyKind::Array(elem_ty, n) => {
+                let arr_llty = self.ty_to_llty(ty);
+                c_fmt.push('[');
+                for i in 0..*n {
+                    if i != 0 {
+                        c_fmt.push_str(", ");
+                    }
+                    let elem_val = self.gen_format_elem(&arr_llty, val, i, elem_ty);
+                    self.gen_format_value(elem_ty, &elem_val, debug, c_fmt, c_args);
                 }
+                c_fmt.push(']');
             }
+            _ => panic!("ICE: `{}` cannot be formatted", ty),
         }
-        println!(")");
+    }
 
-        if let Some(reg_name) = return_reg {
-            Ok(LLValue::Reg(LLReg::new(reg_name, Rc::new(ret_llty))))
+    /// Field or element `index` of the aggregate `base` points to
+    fn gen_format_elem(&mut self, base_llty: &LLTy, base: &LLValue, index: usize, ty: &Ty) -> LLValue {
+        let llty = Rc::new(self.ty_to_llty(ty));
+        let ptr = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(
+            &ptr,
+            "getelementptr",
+            format!("{base_llty}, ptr {base}, i32 0, i32 {index}"),
+        ));
+        let ptr = LLReg::new(ptr, Rc::new(LLTy::Ptr(Rc::clone(&llty))));
+        if llty.eval_to_ptr() {
+            LLValue::Reg(ptr)
         } else {
-            Ok(LLValue::Imm(LLImm::Void))
+            LLValue::Reg(self.load_ptr(&ptr).unwrap())
         }
     }
 }
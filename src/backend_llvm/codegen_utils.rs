@@ -1,19 +1,22 @@
 use super::{
     frame::LocalKind,
-    llvm::{LLReg, LLValue},
-    Codegen,
+    ir::Instruction,
+    llvm::{LLImm, LLReg, LLValue},
+    Codegen, CodegenError, PANIC_BOUNDS_CHECK_FN, PANIC_FN,
 };
 use crate::{
-    ast::{Expr, ExprKind, Path},
+    ast::{self, BinOp, Expr, ExprKind, NodeId, Path},
     backend_llvm::llvm::LLTy,
-    resolve::Binding,
-    span::Ident,
+    diagnostics::Diagnostic,
+    middle::intrinsics::Intrinsic,
+    resolve::{Binding, BindingKind},
+    span::{Ident, Span},
 };
 use std::rc::Rc;
 
 impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
     // expr: LLTY -> LLTY*
-    pub fn gen_lval(&mut self, expr: &'gen Expr) -> Result<Rc<LLReg>, ()> {
+    pub fn gen_lval(&mut self, expr: &'gen Expr) -> Result<Rc<LLReg>, CodegenError> {
         match &expr.kind {
             ExprKind::Path(path) => {
                 let binding = self.ctx.resolve_path(path).unwrap();
@@ -23,39 +26,31 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                 // TODO: move to another func
                 let arr_ptr_reg = self.gen_lval(arr)?;
                 let index_val = self.eval_expr(index)?;
-                let new_reg = self.peek_frame_mut().get_fresh_reg();
-
-                println!(
-                    "\t{} = getelementptr {}, {}, i32 0, {}",
-                    new_reg,
-                    arr_ptr_reg.llty.peel_ptr().unwrap().to_string(),
-                    arr_ptr_reg.to_string_with_type(),
-                    index_val.to_string_with_type()
-                );
-                // `[N x elem_ty]*` => `elem_ty*`
-                let ret_llty = LLTy::Ptr(
-                    arr_ptr_reg
-                        .llty
-                        .peel_ptr()
-                        .unwrap()
-                        .get_element_type()
-                        .unwrap(),
-                );
-                Ok(LLReg::new(new_reg, Rc::new(ret_llty)))
+                if self.opts.bounds_checks {
+                    let len = match &*arr_ptr_reg.llty.peel_ptr().unwrap() {
+                        LLTy::Array(_, len) => *len,
+                        _ => panic!("ICE: only arrays can be indexed"),
+                    };
+                    self.gen_bounds_check(&index_val, len, &expr.span);
+                }
+                Ok(self.gen_elem_lval(&arr_ptr_reg, &index_val))
             }
             ExprKind::Field(strct, field) => {
                 let struct_ptr = self.gen_lval(strct)?;
                 self.gen_field_lval(&struct_ptr, field)
             }
-            ExprKind::Struct(_, _) | ExprKind::Array(_) => {
+            ExprKind::Struct(_, _)
+            | ExprKind::Array(_)
+            | ExprKind::Repeat(_, _)
+            | ExprKind::Closure(_) => {
                 let ptr = self.peek_frame().get_ptr_to_temporary(expr.id).unwrap();
                 self.initialize_memory_with_value(&ptr, expr)?;
                 Ok(ptr)
             }
-            ExprKind::If(cond, then, els) => {
-                let res = self.gen_if_expr(cond, then, els)?;
-                Ok(res.0)
-            }
+            ExprKind::If(cond, then, els) => match self.gen_if_expr(cond, then, els)? {
+                LLValue::Reg(reg) => Ok(reg),
+                _ => panic!("ICE: if expression of an aggregate type must evaluate to a pointer"),
+            },
             ExprKind::Block(block) => {
                 let llval = self.gen_block(block)?;
                 if let LLValue::Reg(reg) = llval {
@@ -70,15 +65,20 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                 let temp = self.peek_frame().get_ptr_to_temporary(expr.id).unwrap();
                 Ok(temp)
             }
+            ExprKind::MethodCall(receiver, _, args) => {
+                let must_be_void = self.gen_method_call_expr(expr.id, receiver, args)?;
+                assert_eq!(*must_be_void.llty(), LLTy::Void);
+                let temp = self.peek_frame().get_ptr_to_temporary(expr.id).unwrap();
+                Ok(temp)
+            }
             ExprKind::Binary(_,_,_) => {
                 let e = self.eval_expr(expr)?; 
                 let new_reg = self.peek_frame_mut().get_fresh_reg();
                 let ty = self.ctx.get_type(expr.id); 
                 let tmp = self.ty_to_llty(&ty); 
                 let ty = tmp.to_string(); 
-                println!("\t{} = alloca {}", new_reg, ty); 
-                println!("\tstore {}, {}* {}", e.to_string_with_type(), ty, new_reg); 
-                //println!("\n\n{:?} = {:?}", new_reg, e.to_string_with_type()); 
+                self.emit(Instruction::with_result(&new_reg, "alloca", &ty));
+                self.emit(Instruction::new("store", format!("{}, {}* {}", e.to_string_with_type(), ty, new_reg)));
                 
                 Ok(LLReg::new(new_reg, Rc::new(LLTy::Ptr(Rc::new(tmp)))))
             }
@@ -88,8 +88,8 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                 let new_reg = self.peek_frame_mut().get_fresh_reg(); 
                 let ty = self.ctx.get_type(expr.id); 
                 let ty = self.ty_to_llty(&ty).to_string(); 
-                println!("\t{} = alloca {}", new_reg, ty); 
-                println!("\tstore {}, {}* {}", e.to_string_with_type(), ty, new_reg); 
+                self.emit(Instruction::with_result(&new_reg, "alloca", &ty));
+                self.emit(Instruction::new("store", format!("{}, {}* {}", e.to_string_with_type(), ty, new_reg)));
                 
                 Ok(LLReg::new(new_reg, Rc::new(LLTy::Ptr(Rc::new(LLTy::I32)))))
             }
@@ -107,7 +107,7 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
         &mut self,
         struct_ptr_reg: &Rc<LLReg>,
         field: &'gen Ident,
-    ) -> Result<Rc<LLReg>, ()> {
+    ) -> Result<Rc<LLReg>, CodegenError> {
         let adt_name = struct_ptr_reg
             .llty
             .peel_ptr()
@@ -120,23 +120,32 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
         let ret_llty = LLTy::Ptr(Rc::clone(&lladt.fields[field_index].1));
 
         let new_reg = self.peek_frame_mut().get_fresh_reg();
-        println!(
-            "\t{} = getelementptr {}, {}, i32 0, i32 {}",
-            new_reg,
-            struct_ptr_reg.llty.peel_ptr().unwrap().to_string(),
-            struct_ptr_reg.to_string_with_type(),
-            field_index
-        );
+        self.emit(Instruction::with_result(
+            &new_reg,
+            "getelementptr",
+            format!(
+                "{}, {}, i32 0, i32 {}",
+                struct_ptr_reg.llty.peel_ptr().unwrap(),
+                struct_ptr_reg.to_string_with_type(),
+                field_index
+            ),
+        ));
 
         Ok(LLReg::new(new_reg, Rc::new(ret_llty)))
     }
 
     // ident: LLTY* (i.e. LocalKind::Ptr) -> LLTY*
-    // ident: LLTY  (i.e. LocalKind::Val)  -> Err
-    pub fn gen_binding_lval(&mut self, binding: &Binding) -> Result<Rc<LLReg>, ()> {
+    // ident: LLTY  (i.e. LocalKind::Value)  -> Err
+    pub fn gen_binding_lval(&mut self, binding: &Binding) -> Result<Rc<LLReg>, CodegenError> {
+        if let BindingKind::Static(_) = binding.kind {
+            return Ok(self.get_static_reg(binding));
+        }
         let local = self.peek_frame().get_local(binding);
         match &local.kind {
-            LocalKind::Value => Err(()),
+            LocalKind::Value => Err(CodegenError::new(Diagnostic::error(format!(
+                "ICE: `{}` is not in memory",
+                binding.cpath.demangle()
+            )))),
             LocalKind::Ptr => Ok(Rc::clone(&local.reg)),
         }
     }
@@ -144,9 +153,17 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
     /// ident is allocated on stack => load fromm its reg and returns the value
     /// ident is not allocated => returns its reg
     /// ident: LLTY -> returns LLTY*
-    pub fn load_path(&mut self, path: &'gen Path) -> Result<Rc<LLReg>, ()> {
+    pub fn load_path(&mut self, path: &'gen Path) -> Result<Rc<LLReg>, CodegenError> {
         let name = self.ctx.resolve_path(path).unwrap();
-        let local = &self.peek_frame().get_local(&name);
+        self.load_binding(&name)
+    }
+
+    pub fn load_binding(&mut self, name: &Binding) -> Result<Rc<LLReg>, CodegenError> {
+        if let BindingKind::Static(_) = name.kind {
+            let ptr = self.get_static_reg(name);
+            return self.load_ptr(&ptr);
+        }
+        let local = &self.peek_frame().get_local(name);
         match &local.kind {
             LocalKind::Value => Ok(Rc::clone(&local.reg)),
             LocalKind::Ptr => self.load_ptr(&local.reg),
@@ -154,17 +171,15 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
     }
 
     // llty* -> llty
-    pub fn load_ptr(&mut self, ptr: &Rc<LLReg>) -> Result<Rc<LLReg>, ()> {
+    pub fn load_ptr(&mut self, ptr: &Rc<LLReg>) -> Result<Rc<LLReg>, CodegenError> {
         assert!(matches!(*ptr.llty, LLTy::Ptr(_)));
         let new_reg = self.peek_frame_mut().get_fresh_reg();
         let derefed_ty = ptr.llty.peel_ptr().unwrap();
-        println!(
-            "\t{} = load {}, {} {}",
-            new_reg,
-            derefed_ty.to_string(),
-            ptr.llty.to_string(),
-            ptr.name
-        );
+        self.emit(Instruction::with_result(
+            &new_reg,
+            "load",
+            format!("{}, {} {}", derefed_ty, ptr.llty, ptr.name),
+        ));
         Ok(LLReg::new(new_reg, derefed_ty))
     }
 
@@ -173,7 +188,7 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
         &mut self,
         ptr: &Rc<LLReg>,
         init: &'gen Expr,
-    ) -> Result<(), ()> {
+    ) -> Result<(), CodegenError> {
         let init_llty = self.ty_to_llty(&self.ctx.get_type(init.id));
         assert_eq!(*ptr.llty.peel_ptr().unwrap(), init_llty);
 
@@ -189,34 +204,371 @@ impl<'gen, 'ctx> Codegen<'gen, 'ctx> {
                     self.initialize_memory_with_value(&fd_ptr, fd_expr)?
                 }
             }
-            ExprKind::Array(_) => {
-                todo!()
+            ExprKind::Array(elems) => {
+                for (i, elem) in elems.iter().enumerate() {
+                    if self.ctx.get_type(elem.id).is_never() {
+                        self.eval_expr(elem)?;
+                        continue;
+                    }
+                    let elem_ptr = self.gen_elem_lval(ptr, &LLValue::Imm(LLImm::I32(i as i32)));
+                    self.initialize_memory_with_value(&elem_ptr, elem)?;
+                }
+            }
+            ExprKind::Closure(closure) if init_llty.eval_to_ptr() => {
+                self.initialize_closure(ptr, init, closure)?
+            }
+            ExprKind::Repeat(elem, _) => {
+                let LLTy::Array(_, len) = init_llty else {
+                    panic!("ICE: repeat expression must have an array type");
+                };
+                self.initialize_array_with_repeat(ptr, elem, len)?;
             }
             _ => {
                 if init_llty.eval_to_ptr() {
-                    let e = self.eval_expr(init)?; 
-                    println!("\tstore {}, {}", e.to_string_with_type(), ptr.to_string_with_type());  
+                    // aggregates are copied
+                    let src = self.gen_lval(init)?;
+                    self.memcpy(ptr, &src);
                 } else {
                     let init_val = self.eval_expr(init)?;
-                    println!(
-                        "\tstore {}, {}",
-                        init_val.to_string_with_type(),
-                        ptr.to_string_with_type()
-                    );
+                    self.emit(Instruction::new(
+                        "store",
+                        format!("{}, {}", init_val.to_string_with_type(), ptr.to_string_with_type()),
+                    ));
                 }
             }
         }
         Ok(())
     }
 
+    /// Pointer to the field at `index` of the environment `env` of the closure `closure`
+    pub fn gen_closure_field_lval(&mut self, closure: NodeId, env: &str, index: usize) -> Rc<LLReg> {
+        let new_reg = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(
+            &new_reg,
+            "getelementptr",
+            format!("%Closure.{}, ptr {}, i32 0, i32 {}", closure, env, index),
+        ));
+        let fd_llty = &self.get_closure_lladt(closure).fields[index].1;
+        LLReg::new(new_reg, Rc::new(LLTy::Ptr(Rc::clone(fd_llty))))
+    }
+
+    /// Queue the code of a closure to be generated after the current function, and return
+    /// its symbol
+    pub fn queue_closure(&mut self, expr: &'gen Expr, closure: &'gen ast::Closure) -> String {
+        let symbol = format!("{}.closure{}", self.current_symbol, expr.id);
+        self.pending_closures.push((expr, closure, symbol.clone()));
+        symbol
+    }
+
+    /// Build the environment of a closure at `ptr`
+    fn initialize_closure(
+        &mut self,
+        ptr: &Rc<LLReg>,
+        expr: &'gen Expr,
+        closure: &'gen ast::Closure,
+    ) -> Result<(), CodegenError> {
+        let symbol = self.queue_closure(expr, closure);
+        let code_ptr = self.gen_closure_field_lval(expr.id, &ptr.name, 0);
+        self.emit(Instruction::new("store", format!("ptr @{}, ptr {}", symbol, code_ptr.name)));
+        let captures = self.closure_env_captures(expr.id, closure.is_move);
+        for (i, (binding, by_ref)) in captures.iter().enumerate() {
+            let field_ptr = self.gen_closure_field_lval(expr.id, &ptr.name, i + 1);
+            if *by_ref {
+                let var_ptr = self.gen_binding_lval(binding)?;
+                self.emit(Instruction::new("store", format!("ptr {}, ptr {}", var_ptr.name, field_ptr.name)));
+            } else if field_ptr.llty.peel_ptr().unwrap().eval_to_ptr() {
+                let var_ptr = self.gen_binding_lval(binding)?;
+                self.memcpy(&field_ptr, &var_ptr);
+            } else {
+                let val = self.load_binding(binding)?;
+                self.emit(Instruction::new(
+                    "store",
+                    format!("{}, {}", val.to_string_with_type(), field_ptr.to_string_with_type()),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Pointer to the element of the array `arr_ptr` at `index`
+    fn gen_elem_lval(&mut self, arr_ptr: &Rc<LLReg>, index: &LLValue) -> Rc<LLReg> {
+        let new_reg = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(
+            &new_reg,
+            "getelementptr",
+            format!(
+                "{}, {}, i32 0, {}",
+                arr_ptr.llty.peel_ptr().unwrap(),
+                arr_ptr.to_string_with_type(),
+                index.to_string_with_type()
+            ),
+        ));
+        // `[N x elem_ty]*` => `elem_ty*`
+        let elem_llty = LLTy::Ptr(arr_ptr.llty.peel_ptr().unwrap().get_element_type().unwrap());
+        LLReg::new(new_reg, Rc::new(elem_llty))
+    }
+
+    /// Fill the array at `ptr` with `len` copies of `elem`, which is evaluated once.
+    /// Zeros are written by memset and other values in a loop
+    fn initialize_array_with_repeat(
+        &mut self,
+        ptr: &Rc<LLReg>,
+        elem: &'gen Expr,
+        len: usize,
+    ) -> Result<(), CodegenError> {
+        let elem_llty = self.ty_to_llty(&self.ctx.get_type(elem.id));
+        // value of the element, or pointer to it if it is an aggregate
+        let elem_val = if elem_llty.eval_to_ptr() {
+            LLValue::Reg(self.gen_lval(elem)?)
+        } else {
+            self.eval_expr(elem)?
+        };
+        if len == 0 {
+            return Ok(());
+        }
+        if matches!(elem_val, LLValue::Imm(LLImm::I32(0) | LLImm::I1(false))) {
+            self.memset_zero(ptr);
+            return Ok(());
+        }
+
+        // for (i = 0; i < len; i++) ptr[i] = elem
+        let entry_label = self.get_fresh_label_name();
+        let cond_label = self.get_fresh_label_name();
+        let body_label = self.get_fresh_label_name();
+        let end_label = self.get_fresh_label_name();
+        let index = format!("%{}.i", cond_label);
+        let next_index = format!("%{}.next", cond_label);
+        self.emit(Instruction::br(&entry_label));
+        self.start_block(entry_label.clone(), None);
+        self.emit(Instruction::br(&cond_label));
+        self.start_block(cond_label.clone(), Some("Repeat"));
+        self.emit(Instruction::with_result(
+            &index,
+            "phi",
+            format!("i32 [0, %{}], [{}, %{}]", entry_label, next_index, body_label),
+        ));
+        let cond = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(&cond, "icmp", format!("slt i32 {}, {}", index, len)));
+        self.emit(Instruction::cond_br(&cond, &body_label, &end_label));
+        self.start_block(body_label, None);
+        let index = LLValue::Reg(LLReg::new(index, Rc::new(LLTy::I32)));
+        let elem_ptr = self.gen_elem_lval(ptr, &index);
+        match &elem_val {
+            LLValue::Reg(src) if elem_llty.eval_to_ptr() => self.memcpy(&elem_ptr, src),
+            _ => self.emit(Instruction::new(
+                "store",
+                format!("{}, {}", elem_val.to_string_with_type(), elem_ptr.to_string_with_type()),
+            )),
+        }
+        self.emit(Instruction::with_result(&next_index, "add", format!("i32 {}, 1", index)));
+        self.emit(Instruction::br(&cond_label));
+        self.start_block(end_label, None);
+        Ok(())
+    }
+
     // TODO: alignment?
     pub fn memcpy(&mut self, dist: &Rc<LLReg>, src: &Rc<LLReg>) {
         assert_eq!(dist.llty, src.llty);
         let target_llty = src.llty.peel_ptr().unwrap();
         let size = self.get_size(&target_llty);
-        println!(
-            "\tcall void @llvm.memcpy.p0i8.p0i8.i64(ptr {}, ptr {}, i64 {}, i1 false)",
-            dist.name, src.name, size
-        );
+        self.emit(Instruction::new(
+            "call",
+            format!(
+                "void @llvm.memcpy.p0i8.p0i8.i64(ptr {}, ptr {}, i64 {}, i1 false)",
+                dist.name, src.name, size
+            ),
+        ));
+    }
+
+    pub fn memset_zero(&mut self, dist: &Rc<LLReg>) {
+        let size = self.get_size(&dist.llty.peel_ptr().unwrap());
+        self.emit(Instruction::new(
+            "call",
+            format!("void @llvm.memset.p0i8.i64(ptr {}, i8 0, i64 {}, i1 false)", dist.name, size),
+        ));
+    }
+
+    /// Panic with `msg` at the location of `span` unless the `i1` value `ok` is true
+    pub fn gen_assert(&mut self, ok: &str, span: &Span, msg: &str) {
+        let ok_label = self.get_fresh_label_name();
+        let panic_label = self.get_fresh_label_name();
+        self.emit(Instruction::cond_br(ok, &ok_label, &panic_label));
+        self.start_block(panic_label, Some("Panic"));
+        self.gen_panic(span, msg);
+        self.start_block(ok_label, None);
+    }
+
+    /// Call the panic runtime, which prints `msg` and the location of `span` to the standard
+    /// error and exits with 101. Ends the current bb.
+    pub fn gen_panic(&mut self, span: &Span, msg: &str) {
+        let msg = self.gen_str_const(msg);
+        let location = self.gen_str_const(&span.location());
+        self.emit(Instruction::new(
+            "call",
+            format!("void @{}(ptr {}, ptr {})", PANIC_FN, msg.name, location.name),
+        ));
+        self.emit(Instruction::unreachable());
+        self.uses_panic_runtime = true;
+    }
+
+    /// Division and remainder by zero panic, and so do `i32::MIN / -1` and `i32::MIN % -1`
+    /// even in release builds
+    pub fn gen_div_check(&mut self, op: &BinOp, dividend: &LLValue, divisor: &LLValue, span: &Span) {
+        let (zero_msg, overflow_msg) = if let BinOp::Div = op {
+            ("attempt to divide by zero", "attempt to divide with overflow")
+        } else {
+            (
+                "attempt to calculate the remainder with a divisor of zero",
+                "attempt to calculate the remainder with overflow",
+            )
+        };
+        let divisor_const = match divisor {
+            LLValue::Imm(LLImm::I32(n)) => Some(*n),
+            _ => None,
+        };
+        if divisor_const.is_none() || divisor_const == Some(0) {
+            let ok = self.peek_frame_mut().get_fresh_reg();
+            self.emit(Instruction::with_result(&ok, "icmp", format!("ne {}, 0", divisor.to_string_with_type())));
+            self.gen_assert(&ok, span, zero_msg);
+        }
+        if divisor_const.is_none() || divisor_const == Some(-1) {
+            let is_min = self.peek_frame_mut().get_fresh_reg();
+            self.emit(Instruction::with_result(
+                &is_min,
+                "icmp",
+                format!("eq {}, {}", dividend.to_string_with_type(), i32::MIN),
+            ));
+            let is_minus_one = self.peek_frame_mut().get_fresh_reg();
+            self.emit(Instruction::with_result(
+                &is_minus_one,
+                "icmp",
+                format!("eq {}, -1", divisor.to_string_with_type()),
+            ));
+            let overflow = self.peek_frame_mut().get_fresh_reg();
+            self.emit(Instruction::with_result(&overflow, "and", format!("i1 {}, {}", is_min, is_minus_one)));
+            let ok = self.peek_frame_mut().get_fresh_reg();
+            self.emit(Instruction::with_result(&ok, "xor", format!("i1 {}, true", overflow)));
+            self.gen_assert(&ok, span, overflow_msg);
+        }
+    }
+
+    /// `l op r` where `op` is `sadd`, `ssub` or `smul`, panicking with `msg` on overflow
+    pub fn gen_overflow_checked_op(
+        &mut self,
+        op: &str,
+        l: &LLValue,
+        r: &LLValue,
+        span: &Span,
+        msg: &str,
+    ) -> LLValue {
+        let (res, overflow) = self.gen_op_with_overflow(op, l, r);
+        let ok = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(&ok, "xor", format!("i1 {}, true", overflow)));
+        self.gen_assert(&ok, span, msg);
+        LLValue::Reg(LLReg::new(res, Rc::new(LLTy::I32)))
+    }
+
+    /// Call `llvm.{op}.with.overflow.i32`. Returns the wrapped result and the overflow flag.
+    fn gen_op_with_overflow(&mut self, op: &str, l: &LLValue, r: &LLValue) -> (String, String) {
+        let pair = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(
+            &pair,
+            "call",
+            format!(
+                "{{ i32, i1 }} @llvm.{}.with.overflow.i32({}, {})",
+                op,
+                l.to_string_with_type(),
+                r.to_string_with_type()
+            ),
+        ));
+        let res = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(&res, "extractvalue", format!("{{ i32, i1 }} {}, 0", pair)));
+        let overflow = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(&overflow, "extractvalue", format!("{{ i32, i1 }} {}, 1", pair)));
+        (res, overflow)
+    }
+
+    /// Lower a call to an intrinsic
+    pub fn gen_intrinsic(&mut self, intrinsic: Intrinsic, args: &[LLValue]) -> LLValue {
+        let [l, r] = args else {
+            panic!("ICE: intrinsics take two arguments");
+        };
+        let res = match intrinsic {
+            Intrinsic::WrappingAdd | Intrinsic::WrappingSub | Intrinsic::WrappingMul => {
+                let inst = match intrinsic {
+                    Intrinsic::WrappingAdd => "add",
+                    Intrinsic::WrappingSub => "sub",
+                    _ => "mul",
+                };
+                let res = self.peek_frame_mut().get_fresh_reg();
+                self.emit(Instruction::with_result(&res, inst, format!("{}, {}", l.to_string_with_type(), r)));
+                res
+            }
+            Intrinsic::SaturatingAdd | Intrinsic::SaturatingSub => {
+                let op = if intrinsic == Intrinsic::SaturatingAdd { "sadd" } else { "ssub" };
+                let res = self.peek_frame_mut().get_fresh_reg();
+                self.emit(Instruction::with_result(
+                    &res,
+                    "call",
+                    format!(
+                        "i32 @llvm.{}.sat.i32({}, {})",
+                        op,
+                        l.to_string_with_type(),
+                        r.to_string_with_type()
+                    ),
+                ));
+                res
+            }
+            Intrinsic::SaturatingMul => {
+                // the result saturates to MIN if the signs of the operands differ, or to MAX
+                let (wrapped, overflow) = self.gen_op_with_overflow("smul", l, r);
+                let signs = self.peek_frame_mut().get_fresh_reg();
+                self.emit(Instruction::with_result(&signs, "xor", format!("{}, {}", l.to_string_with_type(), r)));
+                let negative = self.peek_frame_mut().get_fresh_reg();
+                self.emit(Instruction::with_result(&negative, "icmp", format!("slt i32 {}, 0", signs)));
+                let saturated = self.peek_frame_mut().get_fresh_reg();
+                self.emit(Instruction::with_result(
+                    &saturated,
+                    "select",
+                    format!("i1 {}, i32 {}, i32 {}", negative, i32::MIN, i32::MAX),
+                ));
+                let res = self.peek_frame_mut().get_fresh_reg();
+                self.emit(Instruction::with_result(
+                    &res,
+                    "select",
+                    format!("i1 {}, i32 {}, i32 {}", overflow, saturated, wrapped),
+                ));
+                res
+            }
+        };
+        LLValue::Reg(LLReg::new(res, Rc::new(LLTy::I32)))
+    }
+
+    /// Indexing out of bounds panics. Negative indices are out of bounds too.
+    pub fn gen_bounds_check(&mut self, index: &LLValue, len: usize, span: &Span) {
+        // constant indices are checked by typeck
+        if let LLValue::Imm(LLImm::I32(_)) = index {
+            return;
+        }
+        let ok = self.peek_frame_mut().get_fresh_reg();
+        self.emit(Instruction::with_result(&ok, "icmp", format!("ult {}, {}", index.to_string_with_type(), len)));
+        let ok_label = self.get_fresh_label_name();
+        let panic_label = self.get_fresh_label_name();
+        self.emit(Instruction::cond_br(&ok, &ok_label, &panic_label));
+        self.start_block(panic_label, Some("Panic"));
+        let location = self.gen_str_const(&span.location());
+        self.emit(Instruction::new(
+            "call",
+            format!(
+                "void @{}({}, i32 {}, ptr {})",
+                PANIC_BOUNDS_CHECK_FN,
+                index.to_string_with_type(),
+                len,
+                location.name
+            ),
+        ));
+        self.emit(Instruction::unreachable());
+        self.start_block(ok_label, None);
+        self.uses_bounds_check_runtime = true;
     }
 }
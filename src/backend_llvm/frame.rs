@@ -5,17 +5,71 @@ use crate::{
     resolve::{Binding, BindingKind},
     span::Ident,
 };
-use std::{collections::HashMap, rc::Rc};
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 pub fn compute_frame<'gen, 'ctx>(codegen: &mut Codegen<'gen, 'ctx>, func: &ast::Func) -> Frame {
     let mut analyzer = VisitFrame {
         codegen,
         frame: Frame::new(),
+        closure_depth: 0,
     };
     ast::visitor::go_func(&mut analyzer, func);
     analyzer.frame
 }
 
+/// Frame of the code of a closure. Captured variables are pointers into its environment, or
+/// pointers loaded from it if they are captured by reference.
+pub fn compute_closure_frame<'gen, 'ctx>(
+    codegen: &mut Codegen<'gen, 'ctx>,
+    expr: &ast::Expr,
+    closure: &ast::Closure,
+    captures: &[(Rc<Binding>, bool)],
+) -> Frame {
+    let mut analyzer = VisitFrame {
+        codegen,
+        frame: Frame::new(),
+        closure_depth: 0,
+    };
+    for (param, _) in &closure.params {
+        let binding = analyzer.codegen.ctx.get_binding(param).unwrap();
+        let param_ty = analyzer.codegen.ctx.lookup_name_type(&binding).unwrap();
+        if analyzer.codegen.ty_to_llty(&param_ty).eval_to_ptr() {
+            analyzer.add_local(param, &param_ty, binding.kind, LocalKind::Ptr);
+        } else {
+            analyzer.add_local(param, &param_ty, binding.kind, LocalKind::Value);
+        }
+    }
+    for (i, (binding, _)) in captures.iter().enumerate() {
+        let ty = analyzer.codegen.ctx.lookup_name_type(binding).unwrap();
+        let llty = LLTy::Ptr(Rc::new(analyzer.codegen.ty_to_llty(&ty)));
+        let reg_name = format!("%{}.capture{}", binding.cpath.segments().last().unwrap(), i);
+        let reg = LLReg::new(reg_name, Rc::new(llty));
+        analyzer
+            .frame
+            .locals
+            .insert(Rc::clone(binding), Rc::new(Local::new(LocalKind::Ptr, reg)));
+        analyzer.frame.captures.insert(Rc::clone(binding));
+    }
+    // captured variables of `()` have no memory
+    for binding in analyzer.codegen.ctx.closure_captures(expr.id) {
+        let ty = analyzer.codegen.ctx.lookup_name_type(&binding).unwrap();
+        let llty = analyzer.codegen.ty_to_llty(&ty);
+        if llty.is_void() {
+            let reg_name = format!("%{}", binding.cpath.segments().last().unwrap());
+            let reg = LLReg::new(reg_name, Rc::new(llty));
+            analyzer
+                .frame
+                .locals
+                .insert(Rc::clone(&binding), Rc::new(Local::new(LocalKind::Value, reg)));
+        }
+    }
+    ast::visitor::go_expr(&mut analyzer, &closure.body);
+    analyzer.frame
+}
+
 #[derive(Debug)]
 pub struct Frame {
     locals: HashMap<Rc<Binding>, Rc<Local>>,
@@ -23,6 +77,8 @@ pub struct Frame {
     /// Can be used only for non-lvalue array and structs
     temporary_regs: HashMap<ast::NodeId, Rc<LLReg>>,
     sret_reg: Option<Rc<LLReg>>,
+    /// variables of enclosing functions captured by the closure of this frame
+    captures: HashSet<Rc<Binding>>,
     next_reg: usize,
     next_tmp_reg: usize,
 }
@@ -53,6 +109,7 @@ impl Frame {
             locals: HashMap::new(),
             temporary_regs: HashMap::new(),
             sret_reg: None,
+            captures: HashSet::new(),
             next_reg: 0,
             next_tmp_reg: 0,
         }
@@ -74,6 +131,10 @@ impl Frame {
         &self.locals
     }
 
+    pub fn is_capture(&self, binding: &Binding) -> bool {
+        self.captures.contains(binding)
+    }
+
     pub fn get_ptr_to_temporary(&self, node_id: ast::NodeId) -> Option<Rc<LLReg>> {
         self.temporary_regs.get(&node_id).map(Rc::clone)
     }
@@ -98,6 +159,8 @@ impl Frame {
 pub struct VisitFrame<'ctx, 'gen, 'frm> {
     pub codegen: &'frm mut Codegen<'gen, 'ctx>,
     pub frame: Frame,
+    /// number of closures around the visited node, whose variables belong to their own frames
+    closure_depth: usize,
 }
 
 impl VisitFrame<'_, '_, '_> {
@@ -135,7 +198,7 @@ impl VisitFrame<'_, '_, '_> {
     }
 }
 
-impl<'ctx: 'a, 'a> ast::visitor::Visitor<'ctx> for VisitFrame<'_, '_, '_> {
+impl<'ctx> ast::visitor::Visitor<'ctx> for VisitFrame<'_, '_, '_> {
     fn visit_func(&mut self, func: &'ctx ast::Func) {
         let binding = self.codegen.ctx.get_binding(&func.name).unwrap();
         let (param_tys, _ret_ty) = self
@@ -158,34 +221,48 @@ impl<'ctx: 'a, 'a> ast::visitor::Visitor<'ctx> for VisitFrame<'_, '_, '_> {
     }
 
     fn visit_stmt(&mut self, stmt: &'ctx ast::Stmt) {
-        match &stmt.kind {
-            StmtKind::Let(let_stmt) => {
-                let binding = self.codegen.ctx.get_binding(&let_stmt.ident).unwrap();
-                let var_ty = self.codegen.ctx.lookup_name_type(&binding).unwrap();
-
-                if self.codegen.ty_to_llty(&var_ty).is_void() {
-                    // cannot `alloca void` so register void-like (i.e. `()`) local variables as `LocalKind::Value`
-                    self.add_local(&let_stmt.ident, &var_ty, binding.kind, LocalKind::Value);
-                } else {
-                    self.add_local(&let_stmt.ident, &var_ty, binding.kind, LocalKind::Ptr);
-                }
+        if self.closure_depth > 0 {
+            return;
+        }
+        if let StmtKind::Let(let_stmt) = &stmt.kind {
+            let binding = self.codegen.ctx.get_binding(&let_stmt.ident).unwrap();
+            let var_ty = self.codegen.ctx.lookup_name_type(&binding).unwrap();
+
+            if self.codegen.ty_to_llty(&var_ty).is_void() {
+                // cannot `alloca void` so register void-like (i.e. `()`) local variables as `LocalKind::Value`
+                self.add_local(&let_stmt.ident, &var_ty, binding.kind, LocalKind::Value);
+            } else {
+                self.add_local(&let_stmt.ident, &var_ty, binding.kind, LocalKind::Ptr);
             }
-            _ => (),
         }
     }
 
     fn visit_expr(&mut self, expr: &'ctx ast::Expr) {
-        if matches!(
-            &expr.kind,
-            ast::ExprKind::Array(_) | ast::ExprKind::Struct(_, _)
-        ) || (matches!(&expr.kind, ast::ExprKind::Call(_, _))
-            && self
-                .codegen
-                .ty_to_llty(&self.codegen.ctx.get_type(expr.id))
-                .eval_to_ptr())
+        if self.closure_depth == 0
+            && (matches!(
+                &expr.kind,
+                ast::ExprKind::Array(_) | ast::ExprKind::Repeat(_, _) | ast::ExprKind::Struct(_, _)
+            ) || (matches!(
+                &expr.kind,
+                ast::ExprKind::Call(_, _) | ast::ExprKind::MethodCall(_, _, _) | ast::ExprKind::Closure(_)
+            )
+                && self
+                    .codegen
+                    .ty_to_llty(&self.codegen.ctx.get_type(expr.id))
+                    .eval_to_ptr()))
         {
             let ty = self.codegen.ctx.get_type(expr.id);
             self.add_temporary(expr.id, &ty);
         }
+        if matches!(&expr.kind, ast::ExprKind::Closure(_)) {
+            self.closure_depth += 1;
+        }
+    }
+
+    fn visit_expr_post(&mut self, expr: &'ctx ast::Expr) -> Rc<Ty> {
+        if matches!(&expr.kind, ast::ExprKind::Closure(_)) {
+            self.closure_depth -= 1;
+        }
+        Rc::new(Ty::never())
     }
 }
@@ -0,0 +1,354 @@
+//! LLVM IR built in memory by codegen, and written out as the textual `.ll` format
+//!
+//! Types and operands are kept as they are written in LLVM IR, so the structure only models
+//! what is needed to check and rearrange code: functions, their basic blocks, and the
+//! instructions which end the blocks.
+
+use super::llvm::LLTy;
+use std::fmt;
+use std::rc::Rc;
+
+pub struct Module {
+    pub target_triple: String,
+    pub type_defs: Vec<TypeDef>,
+    pub globals: Vec<Global>,
+    /// definitions and declarations, in the order they are written
+    pub functions: Vec<Function>,
+}
+
+/// `%Struct.A = type { i32, i8 }`
+pub struct TypeDef {
+    pub name: LLTy,
+    pub fields: Vec<Rc<LLTy>>,
+}
+
+/// `@x = private constant [3 x i8] c"ab\00"`
+pub struct Global {
+    /// symbol without `@`
+    pub name: String,
+    /// like `private` or `external`
+    pub linkage: Option<&'static str>,
+    /// `constant` instead of `global`, for data which is never written
+    pub constant: bool,
+    pub ty: String,
+    /// `None` for globals defined by other modules
+    pub init: Option<String>,
+}
+
+/// A function, which is only declared if it has no basic blocks
+pub struct Function {
+    /// like `internal` or `private`
+    pub linkage: Option<&'static str>,
+    pub ret_ty: String,
+    /// symbol without `@`
+    pub name: String,
+    /// types of parameters with their names in definitions, and `...` for variadic functions
+    pub params: Vec<String>,
+    /// like `cold` and `noreturn`
+    pub attrs: Vec<&'static str>,
+    pub blocks: Vec<BasicBlock>,
+}
+
+pub struct BasicBlock {
+    pub label: String,
+    /// written after the label to tell what the block is for
+    pub comment: Option<&'static str>,
+    pub insts: Vec<Instruction>,
+}
+
+/// `%3 = add i32 %1, %2` is `add` with the operands `i32 %1, %2` and the result `%3`
+pub struct Instruction {
+    pub result: Option<String>,
+    pub opcode: &'static str,
+    pub operands: String,
+}
+
+impl Module {
+    pub fn new(target_triple: String) -> Self {
+        Module {
+            target_triple,
+            type_defs: vec![],
+            globals: vec![],
+            functions: vec![],
+        }
+    }
+
+    /// Check that every basic block ends with its only terminator and branches only to
+    /// blocks of its function, which LLVM requires
+    pub fn verify(&self) -> Result<(), String> {
+        for func in &self.functions {
+            func.verify().map_err(|e| format!("in function `@{}`: {}", func.name, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl Function {
+    pub fn new(name: String, ret_ty: String, params: Vec<String>) -> Self {
+        Function {
+            linkage: None,
+            ret_ty,
+            name,
+            params,
+            attrs: vec![],
+            blocks: vec![],
+        }
+    }
+
+    pub fn is_declaration(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Start a new basic block, where instructions are appended from now on
+    pub fn start_block(&mut self, label: String, comment: Option<&'static str>) {
+        self.blocks.push(BasicBlock {
+            label,
+            comment,
+            insts: vec![],
+        });
+    }
+
+    /// Append `inst` to the last basic block
+    pub fn push(&mut self, inst: Instruction) {
+        self.blocks
+            .last_mut()
+            .expect("ICE: instruction outside a basic block")
+            .insts
+            .push(inst);
+    }
+
+    fn verify(&self) -> Result<(), String> {
+        let mut labels = std::collections::HashSet::new();
+        for block in &self.blocks {
+            if !labels.insert(block.label.as_str()) {
+                return Err(format!("label `{}` is defined twice", block.label));
+            }
+        }
+        for block in &self.blocks {
+            match block.insts.iter().position(Instruction::is_terminator) {
+                None => return Err(format!("block `{}` does not end with a terminator", block.label)),
+                Some(i) if i != block.insts.len() - 1 => {
+                    return Err(format!("block `{}` has instructions after its terminator", block.label))
+                }
+                Some(_) => (),
+            }
+            for inst in &block.insts {
+                if let Some(target) = inst.targets().find(|target| !labels.contains(target)) {
+                    return Err(format!("block `{}` branches to undefined label `{}`", block.label, target));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Instruction {
+    pub fn new(opcode: &'static str, operands: impl Into<String>) -> Self {
+        Instruction {
+            result: None,
+            opcode,
+            operands: operands.into(),
+        }
+    }
+
+    /// Instruction whose value is the register `result`
+    pub fn with_result(result: impl Into<String>, opcode: &'static str, operands: impl Into<String>) -> Self {
+        Instruction {
+            result: Some(result.into()),
+            ..Instruction::new(opcode, operands)
+        }
+    }
+
+    /// `br label %target`
+    pub fn br(target: &str) -> Self {
+        Instruction::new("br", format!("label %{}", target))
+    }
+
+    /// `br i1 cond, label %then, label %els`
+    pub fn cond_br(cond: &str, then: &str, els: &str) -> Self {
+        Instruction::new("br", format!("i1 {}, label %{}, label %{}", cond, then, els))
+    }
+
+    pub fn unreachable() -> Self {
+        Instruction::new("unreachable", "")
+    }
+
+    pub fn is_terminator(&self) -> bool {
+        matches!(self.opcode, "br" | "ret" | "unreachable")
+    }
+
+    /// Labels of the blocks a branch jumps to
+    fn targets(&self) -> impl Iterator<Item = &str> {
+        let operands = if self.opcode == "br" { self.operands.as_str() } else { "" };
+        operands
+            .split(", ")
+            .filter_map(|operand| operand.strip_prefix("label %"))
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "target triple = \"{}\"", self.target_triple)?;
+        writeln!(f)?;
+        for type_def in &self.type_defs {
+            writeln!(f, "{}", type_def)?;
+        }
+        if !self.type_defs.is_empty() {
+            writeln!(f)?;
+        }
+        for global in &self.globals {
+            writeln!(f, "{}", global)?;
+        }
+        if !self.globals.is_empty() {
+            writeln!(f)?;
+        }
+        // declarations are grouped, and definitions separated by blank lines
+        for (i, func) in self.functions.iter().enumerate() {
+            write!(f, "{}", func)?;
+            let next_is_declaration = self.functions.get(i + 1).is_some_and(Function::is_declaration);
+            if !(func.is_declaration() && next_is_declaration) {
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TypeDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = type {{", self.name)?;
+        for (i, field) in self.fields.iter().enumerate() {
+            write!(f, "{} {}", if i == 0 { "" } else { "," }, field)?;
+        }
+        write!(f, " }}")
+    }
+}
+
+impl fmt::Display for Global {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{} = ", self.name)?;
+        if let Some(linkage) = self.linkage {
+            write!(f, "{} ", linkage)?;
+        }
+        write!(f, "{} {}", if self.constant { "constant" } else { "global" }, self.ty)?;
+        if let Some(init) = &self.init {
+            write!(f, " {}", init)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", if self.is_declaration() { "declare" } else { "define" })?;
+        if let Some(linkage) = self.linkage {
+            write!(f, "{} ", linkage)?;
+        }
+        write!(f, "{} @{}({})", self.ret_ty, self.name, self.params.join(", "))?;
+        for attr in &self.attrs {
+            write!(f, " {}", attr)?;
+        }
+        if self.is_declaration() {
+            return writeln!(f);
+        }
+        writeln!(f, " {{")?;
+        for block in &self.blocks {
+            write!(f, "{}", block)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for BasicBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.comment {
+            Some(comment) => writeln!(f, "{}:\t; {}", self.label, comment)?,
+            None => writeln!(f, "{}:", self.label)?,
+        }
+        for inst in &self.insts {
+            writeln!(f, "\t{}", inst)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(result) = &self.result {
+            write!(f, "{} = ", result)?;
+        }
+        write!(f, "{}", self.opcode)?;
+        if !self.operands.is_empty() {
+            write!(f, " {}", self.operands)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function_with_blocks(blocks: Vec<(&str, Vec<Instruction>)>) -> Function {
+        let mut func = Function::new("f".to_string(), "i32".to_string(), vec!["i32 %x".to_string()]);
+        for (label, insts) in blocks {
+            func.start_block(label.to_string(), None);
+            for inst in insts {
+                func.push(inst);
+            }
+        }
+        func
+    }
+
+    #[test]
+    fn test_serialise() {
+        let mut module = Module::new("x86_64-unknown-linux-gnu".to_string());
+        module.globals.push(Global {
+            name: ".str.1".to_string(),
+            linkage: Some("private"),
+            constant: true,
+            ty: "[1 x i8]".to_string(),
+            init: Some("c\"\\00\"".to_string()),
+        });
+        module.functions.push(Function::new("g".to_string(), "void".to_string(), vec![]));
+        module.functions.push(function_with_blocks(vec![(
+            "start",
+            vec![
+                Instruction::with_result("%0", "add", "i32 %x, 1"),
+                Instruction::new("ret", "i32 %0"),
+            ],
+        )]));
+        assert_eq!(
+            module.to_string(),
+            "target triple = \"x86_64-unknown-linux-gnu\"\n\n\
+             @.str.1 = private constant [1 x i8] c\"\\00\"\n\n\
+             declare void @g()\n\n\
+             define i32 @f(i32 %x) {\nstart:\n\t%0 = add i32 %x, 1\n\tret i32 %0\n}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let ok = function_with_blocks(vec![
+            ("start", vec![Instruction::cond_br("%c", "bb1", "bb2")]),
+            ("bb1", vec![Instruction::br("bb2")]),
+            ("bb2", vec![Instruction::unreachable()]),
+        ]);
+        assert!(ok.verify().is_ok());
+
+        let unterminated = function_with_blocks(vec![("start", vec![Instruction::with_result("%0", "add", "i32 1, 2")])]);
+        assert!(unterminated.verify().is_err());
+
+        let undefined_label = function_with_blocks(vec![("start", vec![Instruction::br("bb1")])]);
+        assert_eq!(
+            undefined_label.verify().unwrap_err(),
+            "block `start` branches to undefined label `bb1`"
+        );
+
+        let dead_code = function_with_blocks(vec![(
+            "start",
+            vec![Instruction::unreachable(), Instruction::new("ret", "void")],
+        )]);
+        assert!(dead_code.verify().is_err());
+    }
+}
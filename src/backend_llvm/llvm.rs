@@ -1,4 +1,5 @@
 use std::rc::Rc;
+use crate::ast::NodeId;
 use crate::resolve::CanonicalPath;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -10,21 +11,26 @@ pub enum LLTy {
     Ptr(Rc<LLTy>),
     Array(Rc<LLTy>, usize),
     Adt(Rc<CanonicalPath>),
+    /// environment of a closure expression: its code pointer followed by captured variables
+    Closure(NodeId),
 }
 
-impl LLTy {
-    pub fn to_string(&self) -> String {
+impl std::fmt::Display for LLTy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            LLTy::Void => "void".to_string(),
-            LLTy::I1 => "i1".to_string(),
-            LLTy::I8 => "i8".to_string(),
-            LLTy::I32 => "i32".to_string(),
-            LLTy::Ptr(inner) => format!("{}*", inner.to_string()),
-            LLTy::Array(elem_ty, n) => format!("[{} x {}]", n, elem_ty.to_string()),
-            LLTy::Adt(name) => format!("%Struct.{}", name.demangle()),
+            LLTy::Void => write!(f, "void"),
+            LLTy::I1 => write!(f, "i1"),
+            LLTy::I8 => write!(f, "i8"),
+            LLTy::I32 => write!(f, "i32"),
+            LLTy::Ptr(inner) => write!(f, "{}*", inner),
+            LLTy::Array(elem_ty, n) => write!(f, "[{} x {}]", n, elem_ty),
+            LLTy::Adt(name) => write!(f, "%Struct.{}", name.demangle()),
+            LLTy::Closure(id) => write!(f, "%Closure.{}", id),
         }
     }
+}
 
+impl LLTy {
     pub fn is_integer(&self) -> bool {
         matches!(self, LLTy::I32)
     }
@@ -58,10 +64,10 @@ impl LLTy {
         matches!(self, LLTy::Void)
     }
 
-    // ADTs and arrays
+    // ADTs, arrays and closures
     // parameters of these types are passed by pointer
     pub fn eval_to_ptr(&self) -> bool {
-        matches!(*self, LLTy::Adt(_) | LLTy::Array(_, _))
+        matches!(*self, LLTy::Adt(_) | LLTy::Array(_, _) | LLTy::Closure(_))
     }
 }
 
@@ -71,15 +77,17 @@ pub enum LLValue {
     PtrConst(Rc<LLConst>),
 }
 
-impl LLValue {
-    pub fn to_string(&self) -> String {
+impl std::fmt::Display for LLValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            LLValue::Reg(reg) => reg.name.clone(),
-            LLValue::Imm(imm) => imm.to_string(),
-            LLValue::PtrConst(cons) => cons.name.clone(),
+            LLValue::Reg(reg) => write!(f, "{}", reg.name),
+            LLValue::Imm(imm) => write!(f, "{}", imm),
+            LLValue::PtrConst(cons) => write!(f, "{}", cons.name),
         }
     }
+}
 
+impl LLValue {
     pub fn llty(&self) -> Rc<LLTy> {
         match self {
             LLValue::Reg(reg) => Rc::clone(&reg.llty),
@@ -110,7 +118,7 @@ impl LLReg {
     }
 
     pub fn to_string_with_type(&self) -> String {
-        format!("{} {}", self.llty.to_string(), self.name)
+        format!("{} {}", self.llty, self.name)
     }
 }
 
@@ -120,15 +128,17 @@ pub enum LLImm {
     Void,
 }
 
-impl LLImm {
-    pub fn to_string(&self) -> String {
+impl std::fmt::Display for LLImm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            LLImm::I32(n) => format!("{n}"),
-            LLImm::I1(b) => format!("{}", if *b { 1 } else { 0 }),
-            LLImm::Void => "void".to_string(),
+            LLImm::I32(n) => write!(f, "{n}"),
+            LLImm::I1(b) => write!(f, "{}", if *b { 1 } else { 0 }),
+            LLImm::Void => write!(f, "void"),
         }
     }
+}
 
+impl LLImm {
     pub fn to_string_with_type(&self) -> String {
         match self {
             LLImm::I32(n) => format!("i32 {n}"),
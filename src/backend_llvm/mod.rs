@@ -2,44 +2,162 @@ mod codegen_crate;
 mod codegen_expr;
 mod codegen_utils;
 mod frame;
+pub mod ir;
 mod llvm;
 
 use self::frame::Frame;
+use self::ir::{Function, Global, Instruction, TypeDef};
 use self::llvm::*;
-use crate::ast::Crate;
+use crate::ast::{self, Crate, NodeId};
+use crate::diagnostics::Diagnostic;
 use crate::middle::ty::{AdtDef, Ty, TyKind};
-use crate::middle::Ctxt;
-use crate::resolve::CanonicalPath;
-use std::collections::HashMap;
+use crate::middle::ctfe::ConstValue;
+use crate::middle::{CrateType, Ctxt};
+use crate::resolve::{Binding, BindingKind, CanonicalPath};
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
-pub fn compile<'ctx, 'gen: 'ctx>(ctx: &'gen mut Ctxt<'ctx>, krate: &'gen Crate) -> Result<(), ()> {
-    let mut codegen = Codegen::new(ctx);
+/// Runtime function called by the checks inserted by the compiler. The name cannot conflict with
+/// symbols of Rust items.
+const PANIC_FN: &str = "mini-rustc.panic";
+/// Runtime function called by failed bounds checks
+const PANIC_BOUNDS_CHECK_FN: &str = "mini-rustc.panic_bounds_check";
+
+/// Targets whose C ABI and layout of data the generated code follows
+pub const TARGETS: &[&str] = &[
+    "x86_64-unknown-linux-gnu",
+    "aarch64-unknown-linux-gnu",
+    "x86_64-apple-darwin",
+    "aarch64-apple-darwin",
+];
+
+/// Options given by `-C`, `-O`, `--release` and `--target`
+#[derive(Clone)]
+pub struct CodegenOptions {
+    /// Check that indices are in bounds of arrays
+    pub bounds_checks: bool,
+    /// Panic on integer overflow instead of wrapping, which is disabled in release builds
+    pub overflow_checks: bool,
+    /// `-O0` to `-O3`, the optimization level of LLVM
+    pub opt_level: u8,
+    /// target triple, one of `TARGETS`
+    pub target: String,
+}
+
+impl Default for CodegenOptions {
+    fn default() -> Self {
+        CodegenOptions {
+            bounds_checks: true,
+            overflow_checks: true,
+            opt_level: 0,
+            target: TARGETS[0].to_string(),
+        }
+    }
+}
+
+/// Failure to generate code, for code which the checks before codegen do not reject yet
+#[derive(Debug)]
+pub struct CodegenError(pub Box<Diagnostic>);
+
+impl CodegenError {
+    fn new(diag: Diagnostic) -> Self {
+        CodegenError(Box::new(diag))
+    }
+}
+
+/// Generate the LLVM IR of the crate
+pub fn compile<'ctx, 'gen: 'ctx>(
+    ctx: &'gen mut Ctxt<'ctx>,
+    krate: &'gen Crate,
+    opts: CodegenOptions,
+) -> Result<ir::Module, CodegenError> {
+    let module = ir::Module::new(opts.target.clone());
+    let mut codegen = Codegen::new(ctx, opts, module);
     codegen.go(krate)?;
-    Ok(())
+    if let Err(e) = codegen.module.verify() {
+        return Err(CodegenError::new(Diagnostic::error(format!("ICE: invalid LLVM IR generated {}", e))));
+    }
+    Ok(codegen.module)
 }
 
-pub struct Codegen<'gen, 'ctx> {
+pub(crate) struct Codegen<'gen, 'ctx> {
     ctx: &'gen mut Ctxt<'ctx>,
+    opts: CodegenOptions,
     current_frame: Option<Frame>,
+    module: ir::Module,
+    /// function being generated, which is added to `module` when it is finished
+    function: Option<Function>,
     ll_adt_defs: HashMap<Rc<CanonicalPath>, Rc<LLAdtDef>>,
+    /// environments of closures, registered before generating functions
+    closure_lladts: HashMap<NodeId, Rc<LLAdtDef>>,
+    /// closures whose code is generated after the function being generated
+    pending_closures: Vec<(&'gen ast::Expr, &'gen ast::Closure, String)>,
+    /// symbol of the function or closure being generated
+    current_symbol: String,
     next_label_id: usize,
     constants: Vec<Rc<LLConst>>,
     next_str_id: usize,
+    /// symbols of functions defined or declared in this crate
+    fn_symbols: HashSet<String>,
+    /// whether `format_args!` needs `snprintf` and `malloc` to be declared
+    uses_format_runtime: bool,
+    /// whether a check calls `PANIC_FN`
+    uses_panic_runtime: bool,
+    /// whether a bounds check calls `PANIC_BOUNDS_CHECK_FN`, which calls `PANIC_FN`
+    uses_bounds_check_runtime: bool,
 }
 
 impl<'ctx, 'gen> Codegen<'ctx, 'gen> {
-    fn new(ctx: &'gen mut Ctxt<'ctx>) -> Self {
+    fn new(ctx: &'gen mut Ctxt<'ctx>, opts: CodegenOptions, module: ir::Module) -> Self {
         Codegen {
             ctx,
+            opts,
             current_frame: None,
+            module,
+            function: None,
             ll_adt_defs: HashMap::new(),
+            closure_lladts: HashMap::new(),
+            pending_closures: vec![],
+            current_symbol: String::new(),
             next_label_id: 1,
             constants: vec![],
             next_str_id: 1,
+            fn_symbols: HashSet::new(),
+            uses_format_runtime: false,
+            uses_panic_runtime: false,
+            uses_bounds_check_runtime: false,
         }
     }
 
+    /// Name of a function or a static in LLVM IR.
+    /// Items of libraries are prefixed with the crate name so that they do not conflict
+    /// with items of other crates, while foreign functions keep their names.
+    fn symbol_name(&self, cpath: &CanonicalPath) -> String {
+        if self.ctx.is_foreign_fn(cpath) {
+            cpath.segments().last().unwrap().to_string()
+        } else if cpath.is_local() && self.ctx.crate_type == CrateType::Lib {
+            format!("{}..{}", self.ctx.crate_name(), cpath.demangle())
+        } else {
+            cpath.demangle()
+        }
+    }
+
+    /// Append `inst` to the current bb of the function being generated
+    fn emit(&mut self, inst: Instruction) {
+        self.function.as_mut().unwrap().push(inst);
+    }
+
+    /// Start a new bb in the function being generated
+    fn start_block(&mut self, label: String, comment: Option<&'static str>) {
+        self.function.as_mut().unwrap().start_block(label, comment);
+    }
+
+    /// Add the function being generated to the module
+    fn finish_function(&mut self) {
+        let function = self.function.take().unwrap();
+        self.module.functions.push(function);
+    }
+
     fn get_fresh_label_name(&mut self) -> String {
         let i = self.next_label_id;
         self.next_label_id += 1;
@@ -68,10 +186,56 @@ impl<'ctx, 'gen> Codegen<'ctx, 'gen> {
             },
             TyKind::Error => panic!("ICE: typecheck failed but codegen has started"),
             TyKind::Str => todo!(),
-            TyKind::Fn(_, _, _) => todo!(),
+            // function pointers are code addresses
+            TyKind::Fn(_, _, _) => LLTy::Ptr(Rc::new(LLTy::I8)),
+            TyKind::Closure(closure) => LLTy::Closure(closure.id),
         }
     }
 
+    /// Variables captured by a closure in the order of the fields of its environment, and
+    /// whether each is captured by reference. Variables of `()` need no field.
+    fn closure_env_captures(&self, closure: NodeId, is_move: bool) -> Vec<(Rc<Binding>, bool)> {
+        self.ctx
+            .closure_captures(closure)
+            .into_iter()
+            .filter_map(|binding| {
+                let llty = self.ty_to_llty(&self.ctx.lookup_name_type(&binding).unwrap());
+                if llty.is_void() {
+                    return None;
+                }
+                // parameters passed in registers are immutable, so their copies cannot be told apart
+                let by_ref = !is_move && (binding.kind != BindingKind::Param || llty.eval_to_ptr());
+                Some((binding, by_ref))
+            })
+            .collect()
+    }
+
+    /// Register the environment of a closure which is not coerced to a function pointer:
+    /// a pointer to its code followed by the captured variables or pointers to them
+    fn register_closure(&mut self, expr: &ast::Expr, closure: &ast::Closure) {
+        if !matches!(self.ctx.get_type(expr.id).kind, TyKind::Closure(_)) {
+            return;
+        }
+        let mut fields = vec![(Rc::new("{code}".to_string()), Rc::new(LLTy::Ptr(Rc::new(LLTy::I8))))];
+        for (binding, by_ref) in self.closure_env_captures(expr.id, closure.is_move) {
+            let llty = self.ty_to_llty(&self.ctx.lookup_name_type(&binding).unwrap());
+            let llty = if by_ref { LLTy::Ptr(Rc::new(llty)) } else { llty };
+            fields.push((Rc::clone(binding.cpath.segments().last().unwrap()), Rc::new(llty)));
+        }
+        self.closure_lladts.insert(expr.id, Rc::new(LLAdtDef { fields }));
+    }
+
+    fn get_closure_lladt(&self, closure: NodeId) -> Rc<LLAdtDef> {
+        Rc::clone(&self.closure_lladts[&closure])
+    }
+
+    /// Pointer to the global variable of a `static` item
+    fn get_static_reg(&self, binding: &Binding) -> Rc<LLReg> {
+        let ty = self.ctx.lookup_name_type(binding).unwrap();
+        let llty = LLTy::Ptr(Rc::new(self.ty_to_llty(&ty)));
+        LLReg::new(format!("@{}", self.symbol_name(&binding.cpath)), Rc::new(llty))
+    }
+
     fn construct_lladt(&self, adt: &AdtDef) -> LLAdtDef {
         let mut fields = vec![];
         for (fd, fd_ty) in &adt.fields {
@@ -108,11 +272,20 @@ impl<'ctx, 'gen> Codegen<'ctx, 'gen> {
     }
 
     /// Generate code for top-level
-    fn go(&mut self, krate: &'gen Crate) -> Result<(), ()> {
-        println!(r#"target triple = "x86_64-unknown-linux-gnu""#);
-        println!();
-        println!("declare void @llvm.memcpy.p0i8.p0i8.i64(i8* noalias nocapture writeonly, i8* noalias nocapture readonly, i64, i1 immarg) #1");
-        println!();
+    fn go(&mut self, krate: &'gen Crate) -> Result<(), CodegenError> {
+        self.declare_fn(
+            "llvm.memcpy.p0i8.p0i8.i64",
+            "void",
+            &["i8* noalias nocapture writeonly", "i8* noalias nocapture readonly", "i64", "i1 immarg"],
+        );
+        self.module.functions.last_mut().unwrap().attrs.push("#1");
+        self.declare_fn("llvm.memset.p0i8.i64", "void", &["i8* nocapture writeonly", "i8", "i64", "i1 immarg"]);
+        for op in ["sadd", "ssub", "smul"] {
+            self.declare_fn(&format!("llvm.{}.with.overflow.i32", op), "{ i32, i1 }", &["i32", "i32"]);
+        }
+        for op in ["sadd", "ssub"] {
+            self.declare_fn(&format!("llvm.{}.sat.i32", op), "i32", &["i32", "i32"]);
+        }
 
         // register all ADTs
         let mut lladts = vec![];
@@ -121,45 +294,133 @@ impl<'ctx, 'gen> Codegen<'ctx, 'gen> {
             lladts.push((Rc::clone(name), lladt));
         }
         for (cpath, lladt) in lladts {
-            print!("%Struct.{} = type {{", cpath.demangle());
-            for (i, (_, fd_llty)) in lladt.fields.iter().enumerate() {
-                print!(" {}", fd_llty.to_string());
-                if i != lladt.fields.len() - 1 {
-                    print!(",");
-                }
-            }
-            println!(" }}");
+            self.module.type_defs.push(TypeDef {
+                name: LLTy::Adt(Rc::clone(&cpath)),
+                fields: lladt.fields.iter().map(|(_, llty)| Rc::clone(llty)).collect(),
+            });
             self.add_lladt(&cpath, lladt);
         }
 
-        println!();
+        // register environments of all closures
+        ast::visitor::go(&mut CollectClosures { codegen: self }, krate);
+        let mut closures: Vec<_> = self.closure_lladts.iter().collect();
+        closures.sort_by_key(|(id, _)| **id);
+        for (id, lladt) in closures {
+            self.module.type_defs.push(TypeDef {
+                name: LLTy::Closure(*id),
+                fields: lladt.fields.iter().map(|(_, llty)| Rc::clone(llty)).collect(),
+            });
+        }
+
         self.gen_crate(krate)?;
+        self.gen_extern_crate_decls();
+        if self.uses_format_runtime {
+            self.gen_format_runtime_decls();
+        }
+        if self.uses_panic_runtime || self.uses_bounds_check_runtime {
+            self.gen_panic_runtime();
+        }
 
         // string literals
         for cons in &self.constants {
-            println!(
-                "{} = constant {} c\"{}\\00\"",
-                cons.name,
-                cons.llty.to_string(),
-                cons.string_lit
-            );
+            self.module.globals.push(Global {
+                name: cons.name.trim_start_matches('@').to_string(),
+                linkage: Some("private"),
+                constant: true,
+                ty: cons.llty.to_string(),
+                init: Some(format!("c\"{}\\00\"", escape_string_lit(&cons.string_lit))),
+            });
         }
 
         Ok(())
     }
 
+    /// Declare the function `symbol` defined outside the crate
+    fn declare_fn(&mut self, symbol: &str, ret_ty: &str, params: &[&str]) {
+        let params = params.iter().map(|param| param.to_string()).collect();
+        self.module
+            .functions
+            .push(Function::new(symbol.to_string(), ret_ty.to_string(), params));
+    }
+
+    /// Functions of libc called by `format_args!`, unless the program declares them
+    fn gen_format_runtime_decls(&mut self) {
+        if self.fn_symbols.insert("snprintf".to_string()) {
+            self.declare_fn("snprintf", "i32", &["ptr", "i64", "ptr", "..."]);
+        }
+        if self.fn_symbols.insert("malloc".to_string()) {
+            self.declare_fn("malloc", "ptr", &["i64"]);
+        }
+    }
+
+    /// Functions called by failed checks, which print like `panic!` and exit with 101.
+    /// They are private, so every crate has its own copy.
+    fn gen_panic_runtime(&mut self) {
+        // void PANIC_BOUNDS_CHECK_FN(i32 index, i32 len, ptr location), which formats the message
+        // and calls PANIC_FN
+        if self.uses_bounds_check_runtime {
+            // enough for the message with any two i32s
+            let size = 80;
+            let fmt = self.gen_str_const("index out of bounds: the len is %d but the index is %d");
+            self.start_panic_fn(PANIC_BOUNDS_CHECK_FN, &["i32 %index", "i32 %len", "ptr %location"]);
+            self.emit(Instruction::with_result("%msg", "alloca", format!("[{} x i8]", size)));
+            self.emit(Instruction::with_result(
+                "%0",
+                "call",
+                format!(
+                    "i32 (ptr, i64, ptr, ...) @snprintf(ptr %msg, i64 {}, ptr {}, i32 %len, i32 %index)",
+                    size, fmt.name
+                ),
+            ));
+            self.emit(Instruction::new("call", format!("void @{}(ptr %msg, ptr %location)", PANIC_FN)));
+            self.emit(Instruction::unreachable());
+            self.finish_function();
+            if self.fn_symbols.insert("snprintf".to_string()) {
+                self.declare_fn("snprintf", "i32", &["ptr", "i64", "ptr", "..."]);
+            }
+        }
+        // void PANIC_FN(ptr msg, ptr location)
+        let fmt = self.gen_str_const("thread 'main' panicked at %s:\n%s\n");
+        self.start_panic_fn(PANIC_FN, &["ptr %msg", "ptr %location"]);
+        self.emit(Instruction::with_result(
+            "%0",
+            "call",
+            format!("i32 (i32, ptr, ...) @dprintf(i32 2, ptr {}, ptr %location, ptr %msg)", fmt.name),
+        ));
+        self.emit(Instruction::new("call", "void @exit(i32 101)"));
+        self.emit(Instruction::unreachable());
+        self.finish_function();
+        if self.fn_symbols.insert("dprintf".to_string()) {
+            self.declare_fn("dprintf", "i32", &["i32", "ptr", "..."]);
+        }
+        if self.fn_symbols.insert("exit".to_string()) {
+            self.declare_fn("exit", "void", &["i32"]);
+        }
+    }
+
+    /// Start generating a function of the panic runtime
+    fn start_panic_fn(&mut self, symbol: &str, params: &[&str]) {
+        let params = params.iter().map(|param| param.to_string()).collect();
+        let mut function = Function::new(symbol.to_string(), "void".to_string(), params);
+        function.linkage = Some("private");
+        function.attrs = vec!["cold", "noreturn"];
+        function.start_block("start".to_string(), None);
+        self.function = Some(function);
+    }
+
     pub fn get_size(&self, llty: &LLTy) -> usize {
         match llty {
             LLTy::I32 => 4,
             LLTy::I8 => 1,
             LLTy::I1 => 1,
-            LLTy::Ptr(_) => 1,
+            LLTy::Ptr(_) => 8,
             LLTy::Array(elem_llty, n) => self.get_align(elem_llty) * n,
             LLTy::Void => panic!(),
             LLTy::Adt(name) => {
                 let lladt = self.get_lladt(name).unwrap();
                 self.get_lladt_size(&lladt)
             }
+            LLTy::Closure(id) => self.get_lladt_size(&self.get_closure_lladt(*id)),
         }
     }
 
@@ -179,13 +440,14 @@ impl<'ctx, 'gen> Codegen<'ctx, 'gen> {
             LLTy::I32 => 4,
             LLTy::I8 => 1,
             LLTy::I1 => 1,
-            LLTy::Ptr(_) => 1,
+            LLTy::Ptr(_) => 8,
             LLTy::Array(elem_llty, _) => self.get_align(elem_llty),
             LLTy::Void => panic!(),
             LLTy::Adt(name) => {
                 let lladt = self.get_lladt(name).unwrap();
                 self.get_lladt_align(&lladt)
             }
+            LLTy::Closure(id) => self.get_lladt_align(&self.get_closure_lladt(*id)),
         }
     }
 
@@ -201,9 +463,42 @@ impl<'ctx, 'gen> Codegen<'ctx, 'gen> {
     }
 }
 
+struct CollectClosures<'a, 'gen, 'ctx> {
+    codegen: &'a mut Codegen<'gen, 'ctx>,
+}
+
+impl<'gen> ast::visitor::Visitor<'gen> for CollectClosures<'_, '_, '_> {
+    fn visit_expr(&mut self, expr: &'gen ast::Expr) {
+        if let ast::ExprKind::Closure(closure) = &expr.kind {
+            self.codegen.register_closure(expr, closure);
+        }
+    }
+}
+
+fn const_to_llimm(val: ConstValue) -> LLImm {
+    match val {
+        ConstValue::I32(n) => LLImm::I32(n),
+        ConstValue::Bool(b) => LLImm::I1(b),
+        ConstValue::Unit => LLImm::Void,
+    }
+}
+
+/// Bytes other than printable ASCII, `"` and `\\` are written as `\\XX` in LLVM IR
+fn escape_string_lit(s: &str) -> String {
+    let mut escaped = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_graphic() && b != b'"' && b != b'\\' || b == b' ' {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("\\{b:02X}"));
+        }
+    }
+    escaped
+}
+
 // e.g. ofs: 1, align: 4 => 3
 fn padding_size(ofs: usize, align: usize) -> usize {
-    if ofs % align == 0 {
+    if ofs.is_multiple_of(align) {
         0
     } else {
         align - (ofs % align)
@@ -0,0 +1,70 @@
+//! Similarity of names, for suggestions of what a misspelled name may have meant
+
+/// Number of inserted, deleted or substituted characters, or swapped adjacent characters,
+/// to turn `a` into `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances from the prefixes of `a` of the previous two lengths to each prefix of `b`
+    let mut prev_prev: Vec<usize> = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let substitution = prev[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            row[j] = substitution.min(prev[j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(prev_prev[j - 2] + 1);
+            }
+        }
+        prev_prev = std::mem::replace(&mut prev, row);
+    }
+    prev[b.len()]
+}
+
+/// The candidate closest to `lookup`, if it is close enough to be a likely misspelling.
+/// Like rustc, up to a third of the characters may differ, and a candidate differing only in
+/// case is preferred. Ties are broken by the order of `candidates`.
+pub fn find_best_match<'a>(candidates: impl IntoIterator<Item = &'a str>, lookup: &str) -> Option<&'a str> {
+    let max_dist = std::cmp::max(lookup.chars().count(), 3) / 3;
+    let mut best: Option<(usize, &str)> = None;
+    for candidate in candidates {
+        if candidate == lookup {
+            continue;
+        }
+        if candidate.eq_ignore_ascii_case(lookup) {
+            return Some(candidate);
+        }
+        let dist = edit_distance(candidate, lookup);
+        if dist <= max_dist && best.is_none_or(|(best_dist, _)| dist < best_dist) {
+            best = Some((dist, candidate));
+        }
+    }
+    best.map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("count", "conut"), 1);
+        assert_eq!(edit_distance("abc", "ca"), 3);
+        assert_eq!(edit_distance("lén", "len"), 1);
+    }
+
+    #[test]
+    fn test_find_best_match() {
+        assert_eq!(find_best_match(["count", "total"], "coutn"), Some("count"));
+        assert_eq!(find_best_match(["count", "total"], "cuotn"), None);
+        assert_eq!(find_best_match(["count", "total"], "cont"), Some("count"));
+        assert_eq!(find_best_match(["foo", "Foo"], "FOO"), Some("foo"));
+        assert_eq!(find_best_match(["value"], "value"), None);
+        assert_eq!(find_best_match(["ab", "x"], "y"), Some("x"));
+        assert_eq!(find_best_match(["length"], "x"), None);
+    }
+}
@@ -0,0 +1,218 @@
+//! Human-readable rendering of diagnostics, in the style of rustc:
+//!
+//! ```text
+//! error: mismatched types
+//!  --> main.rs:2:18
+//!   |
+//! 2 |     let x: i32 = true;
+//!   |            ---   ^^^^ expected `i32`, found `bool`
+//!   |            |
+//!   |            expected due to this
+//!   |
+//!   = note: ...
+//! ```
+//!
+//! Labels on the same line are stacked under it, and multi-line spans are underlined up to the
+//! end of their first line.
+
+use super::{Diagnostic, Label};
+use crate::span::SourceFile;
+use std::rc::Rc;
+
+/// Columns of a tab in rendered source lines
+const TAB_WIDTH: usize = 4;
+
+pub fn render(diag: &Diagnostic) -> String {
+    let mut out = match diag.code {
+        Some(code) => format!("{}[{}]: {}\n", diag.level, code, diag.message),
+        None => format!("{}: {}\n", diag.level, diag.message),
+    };
+
+    // labels grouped by file, the one of the primary span first
+    let mut files: Vec<(Rc<SourceFile>, Vec<&Label>)> = vec![];
+    let primary = diag.primary_span();
+    let mut labels: Vec<&Label> = diag.labels.iter().collect();
+    labels.sort_by_key(|label| Some(&label.span) != primary);
+    for label in labels {
+        match files.iter_mut().find(|(file, _)| file == label.span.file()) {
+            Some((_, labels)) => labels.push(label),
+            None => files.push((Rc::clone(label.span.file()), vec![label])),
+        }
+    }
+
+    let max_line = diag
+        .labels
+        .iter()
+        .map(|label| label.span.line_col().0)
+        .max()
+        .unwrap_or(0);
+    let gutter = " ".repeat(max_line.to_string().len());
+
+    for (i, (file, labels)) in files.iter().enumerate() {
+        let (line, col) = labels[0].span.line_col();
+        let arrow = if i == 0 { "-->" } else { ":::" };
+        out += &format!("{gutter}{arrow} {}:{}:{}\n", file.name, line, col);
+        out += &format!("{gutter} |\n");
+        render_lines(&mut out, file, labels, &gutter);
+    }
+
+    if !diag.labels.is_empty() && (!diag.notes.is_empty() || !diag.helps.is_empty()) {
+        out += &format!("{gutter} |\n");
+    }
+    for note in &diag.notes {
+        out += &format!("{gutter} = note: {}\n", indent_continuation(note, &gutter));
+    }
+    for help in &diag.helps {
+        let message = indent_continuation(&help.message, &gutter);
+        match &help.suggestion {
+            Some(sugg) => out += &format!("{gutter} = help: {}: `{}`\n", message, sugg.replacement),
+            None => out += &format!("{gutter} = help: {}\n", message),
+        }
+    }
+    // no trailing newline, so that diagnostics can be printed with `eprintln!`
+    out.pop();
+    out
+}
+
+/// Source lines of `labels` in a file with their underlines, in the order of lines
+fn render_lines(out: &mut String, file: &SourceFile, labels: &[&Label], gutter: &str) {
+    let mut lines: Vec<usize> = labels.iter().map(|label| label.span.line_col().0).collect();
+    lines.sort_unstable();
+    lines.dedup();
+
+    let mut prev_line = None;
+    for line in lines {
+        if prev_line.is_some_and(|prev| line > prev + 1) {
+            out.push_str("...\n");
+        }
+        prev_line = Some(line);
+
+        let text = file.line_text(line);
+        out.push_str(&format!("{:>width$} | {}\n", line, expand_tabs(text), width = gutter.len()));
+
+        // labels starting at this line, rightmost first so that the messages do not overlap
+        let mut line_labels: Vec<(usize, usize, &Label)> = labels
+            .iter()
+            .filter(|label| label.span.line_col().0 == line)
+            .map(|label| {
+                let (start, end) = underline_columns(text, label);
+                (start, end, *label)
+            })
+            .collect();
+        line_labels.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+
+        // the rightmost label is written next to its underline, and the others below it
+        let mut underline = String::new();
+        for (start, end, label) in line_labels.iter().rev() {
+            let marker = if label.primary { '^' } else { '-' };
+            pad_to(&mut underline, *start);
+            underline.extend(std::iter::repeat_n(marker, end - start));
+        }
+        let (_, _, rightmost) = line_labels[0];
+        if rightmost.message.is_empty() {
+            out.push_str(&format!("{gutter} | {}\n", underline));
+        } else {
+            out.push_str(&format!("{gutter} | {} {}\n", underline, rightmost.message));
+        }
+        let others: Vec<(usize, &Label)> = line_labels[1..]
+            .iter()
+            .filter(|(_, _, label)| !label.message.is_empty())
+            .map(|(start, _, label)| (*start, *label))
+            .collect();
+        if others.is_empty() {
+            continue;
+        }
+        // vertical bars lead from the underlines to their messages
+        let mut bars = String::new();
+        for (start, _) in others.iter().rev() {
+            pad_to(&mut bars, *start);
+            bars.push('|');
+        }
+        out.push_str(&format!("{gutter} | {}\n", bars));
+        for (i, (start, label)) in others.iter().enumerate() {
+            let mut row = String::new();
+            for (left, _) in others[i + 1..].iter().rev() {
+                pad_to(&mut row, *left);
+                row.push('|');
+            }
+            pad_to(&mut row, *start);
+            row.push_str(&label.message);
+            out.push_str(&format!("{gutter} | {}\n", row));
+        }
+    }
+}
+
+/// Columns in a rendered line where the underline of `label` starts and ends. Spans over
+/// several lines are underlined to the end of the first line, and empty spans get one marker.
+fn underline_columns(text: &str, label: &Label) -> (usize, usize) {
+    let (start_line, start_col) = label.span.line_col();
+    let (end_line, end_col) = label.span.end_line_col();
+    let start = display_width(text, start_col - 1);
+    let end = if end_line == start_line {
+        display_width(text, end_col - 1)
+    } else {
+        display_width(text, text.chars().count())
+    };
+    (start, end.max(start + 1))
+}
+
+/// Rendered width of the first `chars` characters of `text`
+fn display_width(text: &str, chars: usize) -> usize {
+    text.chars()
+        .take(chars)
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+fn pad_to(s: &mut String, col: usize) {
+    while s.len() < col {
+        s.push(' ');
+    }
+}
+
+/// Align the lines after the first of a multi-line note with its first line
+fn indent_continuation(msg: &str, gutter: &str) -> String {
+    msg.replace('\n', &format!("\n{gutter}         "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::{FileId, Span};
+
+    fn file(src: &str) -> Rc<SourceFile> {
+        Rc::new(SourceFile::new(FileId(0), "main.rs".to_string(), src.to_string()))
+    }
+
+    #[test]
+    fn test_render_labels() {
+        let f = file("fn main() {\n    let x: i32 = true;\n}\n");
+        let diag = Diagnostic::error("mismatched types")
+            .code("E0308")
+            .span_label(&Span::new(29, 33, Rc::clone(&f)), "expected `i32`, found `bool`")
+            .secondary_label(&Span::new(23, 26, Rc::clone(&f)), "expected due to this")
+            .note("a note");
+        assert_eq!(
+            render(&diag),
+            "error[E0308]: mismatched types
+ --> main.rs:2:18
+  |
+2 |     let x: i32 = true;
+  |            ---   ^^^^ expected `i32`, found `bool`
+  |            |
+  |            expected due to this
+  |
+  = note: a note"
+        );
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let diag = Diagnostic::warning("unused").help("remove it");
+        assert_eq!(render(&diag), "warning: unused\n  = help: remove it");
+    }
+}
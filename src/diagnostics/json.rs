@@ -0,0 +1,158 @@
+//! Diagnostics as JSON for tools, printed by `--error-format=json`.
+//! Each diagnostic is an object on its own line, in the shape of the JSON output of rustc.
+
+use super::{emitter, explanation, Diagnostic, Help, Label, Suggestion};
+use crate::span::Span;
+
+pub fn render(diag: &Diagnostic) -> String {
+    let code = match diag.code {
+        Some(code) => format!(
+            r#"{{"code":{},"explanation":{}}}"#,
+            string(code),
+            explanation(code).map_or("null".to_string(), string)
+        ),
+        None => "null".to_string(),
+    };
+    let spans: Vec<String> = diag.labels.iter().map(label_span).collect();
+    let children: Vec<String> = diag
+        .notes
+        .iter()
+        .map(|note| child("note", note, &[]))
+        .chain(diag.helps.iter().map(help))
+        .collect();
+    format!(
+        r#"{{"$message_type":"diagnostic","message":{},"code":{},"level":{},"spans":[{}],"children":[{}],"rendered":{}}}"#,
+        string(&diag.message),
+        code,
+        string(&diag.level.to_string()),
+        spans.join(","),
+        children.join(","),
+        string(&format!("{}\n", emitter::render(diag)))
+    )
+}
+
+/// Notes, helps and suggestions, which are rendered as part of their parent
+fn child(level: &str, message: &str, spans: &[String]) -> String {
+    format!(
+        r#"{{"message":{},"code":null,"level":{},"spans":[{}],"children":[],"rendered":null}}"#,
+        string(message),
+        string(level),
+        spans.join(",")
+    )
+}
+
+/// Suggestions are spans of their help, like in rustc
+fn help(help: &Help) -> String {
+    let spans: Vec<String> = help.suggestion.iter().map(suggestion_span).collect();
+    child("help", &help.message, &spans)
+}
+
+fn label_span(label: &Label) -> String {
+    let message = (!label.message.is_empty()).then_some(label.message.as_str());
+    span(&label.span, label.primary, message, None)
+}
+
+fn suggestion_span(sugg: &Suggestion) -> String {
+    span(&sugg.span, true, None, Some(&sugg.replacement))
+}
+
+/// Location of `span` with the lines it covers. Lines and columns are 1-based, and the
+/// end column is exclusive.
+fn span(span: &Span, primary: bool, label: Option<&str>, replacement: Option<&str>) -> String {
+    let file = span.file();
+    let (line_start, column_start) = span.line_col();
+    let (line_end, column_end) = span.end_line_col();
+    let text: Vec<String> = (line_start..=line_end)
+        .map(|line| {
+            let text = file.line_text(line);
+            let highlight_start = if line == line_start { column_start } else { 1 };
+            let highlight_end = if line == line_end {
+                column_end
+            } else {
+                text.chars().count() + 1
+            };
+            format!(
+                r#"{{"text":{},"highlight_start":{},"highlight_end":{}}}"#,
+                string(text),
+                highlight_start,
+                highlight_end
+            )
+        })
+        .collect();
+    format!(
+        r#"{{"file_name":{},"byte_start":{},"byte_end":{},"line_start":{},"line_end":{},"column_start":{},"column_end":{},"is_primary":{},"text":[{}],"label":{},"suggested_replacement":{},"suggestion_applicability":{},"expansion":null}}"#,
+        string(&file.name),
+        span.lo(),
+        span.hi(),
+        line_start,
+        line_end,
+        column_start,
+        column_end,
+        primary,
+        text.join(","),
+        label.map_or("null".to_string(), string),
+        replacement.map_or("null".to_string(), string),
+        // suggestions are not checked to compile, like rustc's `MaybeIncorrect` ones
+        replacement.map_or("null", |_| r#""MaybeIncorrect""#),
+    )
+}
+
+/// JSON string literal of `s`
+fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::{FileId, SourceFile};
+    use std::rc::Rc;
+
+    #[test]
+    fn test_string() {
+        assert_eq!(string("a \"b\"\n\\"), r#""a \"b\"\n\\""#);
+        assert_eq!(string("\u{1}"), r#""\u0001""#);
+    }
+
+    #[test]
+    fn test_render_json() {
+        let f = Rc::new(SourceFile::new(
+            FileId(0),
+            "main.rs".to_string(),
+            "fn main() {\n    let x: i32 = true;\n}\n".to_string(),
+        ));
+        let diag = Diagnostic::error("mismatched types")
+            .span_label(&Span::new(29, 33, Rc::clone(&f)), "expected `i32`, found `bool`")
+            .secondary_label(&Span::new(23, 26, Rc::clone(&f)), "")
+            .note("a note")
+            .span_suggestion(&Span::new(23, 26, Rc::clone(&f)), "change the type", "bool");
+        let json = render(&diag);
+        assert!(!json.contains('\n'));
+        assert!(json.starts_with(
+            r#"{"$message_type":"diagnostic","message":"mismatched types","code":null,"level":"error","spans":[{"file_name":"main.rs","byte_start":29,"byte_end":33,"line_start":2,"line_end":2,"column_start":18,"column_end":22,"is_primary":true,"text":[{"text":"    let x: i32 = true;","highlight_start":18,"highlight_end":22}],"label":"expected `i32`, found `bool`","suggested_replacement":null,"suggestion_applicability":null,"expansion":null},"#
+        ));
+        assert!(json.contains(r#""is_primary":false,"#));
+        assert!(json.contains(r#""label":null,"#));
+        assert!(json.contains(
+            r#"{"message":"a note","code":null,"level":"note","spans":[],"children":[],"rendered":null}"#
+        ));
+        assert!(json.contains(
+            r#""label":null,"suggested_replacement":"bool","suggestion_applicability":"MaybeIncorrect""#
+        ));
+        assert!(json.ends_with(r#""rendered":"error: mismatched types\n --> main.rs:2:18\n  |\n2 |     let x: i32 = true;\n  |            ---   ^^^^ expected `i32`, found `bool`\n  |\n  = note: a note\n  = help: change the type: `bool`\n"}"#));
+    }
+}
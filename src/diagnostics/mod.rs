@@ -0,0 +1,176 @@
+//! Errors and warnings reported to users, with the source locations they are about
+
+mod edit_distance;
+mod emitter;
+mod json;
+mod registry;
+
+pub use self::edit_distance::find_best_match;
+pub use self::registry::explanation;
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+impl std::fmt::Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+            Level::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A span with a message shown under it
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    /// Primary labels point at the problem and are underlined with `^`, secondary labels
+    /// give context and are underlined with `-`
+    pub primary: bool,
+}
+
+/// Advice on fixing the problem, optionally with code that may fix it
+#[derive(Debug, Clone)]
+pub struct Help {
+    pub message: String,
+    pub suggestion: Option<Suggestion>,
+}
+
+/// A replacement of the code at `span`
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+}
+
+/// How diagnostics are printed, chosen by `--error-format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// Rendered with source snippets for people
+    Human,
+    /// One JSON object per line for tools, in the shape of rustc's
+    Json,
+}
+
+/// An error or a warning, built like
+/// `Diagnostic::error("mismatched types").span_label(&span, "expected `i32`")`
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub level: Level,
+    /// Stable identifier like `E0308`, explained by `mini-rustc --explain E0308`
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub helps: Vec<Help>,
+}
+
+impl Diagnostic {
+    pub fn new(level: Level, message: impl Into<String>) -> Self {
+        Diagnostic {
+            level,
+            code: None,
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+            helps: vec![],
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic::new(Level::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Diagnostic::new(Level::Warning, message)
+    }
+
+    /// Identify the kind of the problem by `code`, which must have an explanation
+    pub fn code(mut self, code: &'static str) -> Self {
+        debug_assert!(explanation(code).is_some(), "ICE: error code {} is not registered", code);
+        self.code = Some(code);
+        self
+    }
+
+    /// Point at `span` as the location of the problem
+    pub fn span_label(mut self, span: &Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span: span.clone(),
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    /// Point at `span` as context of the problem
+    pub fn secondary_label(mut self, span: &Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span: span.clone(),
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn help(mut self, help: impl Into<String>) -> Self {
+        self.helps.push(Help {
+            message: help.into(),
+            suggestion: None,
+        });
+        self
+    }
+
+    /// Suggest replacing the code at `span` with `replacement`
+    pub fn span_suggestion(
+        mut self,
+        span: &Span,
+        message: impl Into<String>,
+        replacement: impl Into<String>,
+    ) -> Self {
+        self.helps.push(Help {
+            message: message.into(),
+            suggestion: Some(Suggestion {
+                span: span.clone(),
+                replacement: replacement.into(),
+            }),
+        });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.level == Level::Error
+    }
+
+    /// Span where the diagnostic is reported
+    pub fn primary_span(&self) -> Option<&Span> {
+        self.labels
+            .iter()
+            .find(|label| label.primary)
+            .or_else(|| self.labels.first())
+            .map(|label| &label.span)
+    }
+
+    pub fn render(&self, format: ErrorFormat) -> String {
+        match format {
+            ErrorFormat::Human => emitter::render(self),
+            ErrorFormat::Json => json::render(self),
+        }
+    }
+}
+
+/// Rendered like rustc, with the lines of the labelled spans
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", emitter::render(self))
+    }
+}
@@ -0,0 +1,725 @@
+//! Long-form explanations of error codes, printed by `mini-rustc --explain CODE`.
+//! Codes shared with rustc have the same meaning, and `M` codes are specific to mini-rustc:
+//! `M00xx` for syntax errors, `M01xx` for name resolution, `M02xx` for type checking and
+//! `M03xx` for macro expansion.
+
+/// Error codes and their explanations, sorted by code
+const EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "E0015",
+        "A constant function called a function which is not `const`, or a function pointer.
+
+Erroneous code example:
+
+    fn f() -> i32 { 1 }
+    const fn g() -> i32 { f() }
+
+Constant functions are evaluated at compile time, so they can only call other
+constant functions. Make the callee a `const fn` as well.",
+    ),
+    (
+        "E0061",
+        "A function was called with the wrong number of arguments.
+
+Erroneous code example:
+
+    fn f(a: i32, b: i32) -> i32 { a + b }
+    fn main() -> i32 { f(1) }
+
+Pass exactly as many arguments as the function has parameters. Only variadic
+extern functions like `printf` take more.",
+    ),
+    (
+        "E0062",
+        "A field of a struct was given more than once in a struct expression.
+
+Erroneous code example:
+
+    struct S { a: i32 }
+    fn main() -> i32 { let s = S { a: 1, a: 2 }; s.a }
+
+Give each field exactly once.",
+    ),
+    (
+        "E0063",
+        "A struct expression does not give some fields of the struct.
+
+Erroneous code example:
+
+    struct S { a: i32, b: i32 }
+    fn main() -> i32 { let s = S { a: 1 }; s.a }
+
+Give a value to every field of the struct.",
+    ),
+    (
+        "E0070",
+        "The left-hand side of an assignment is not a place that can be assigned to.
+
+Erroneous code example:
+
+    fn f() -> i32 { 1 }
+    fn main() -> i32 { f = f; 1 = 2; 0 }
+
+Only variables, static items, dereferenced pointers and their fields and
+elements can be assigned to.",
+    ),
+    (
+        "E0080",
+        "A constant expression failed to evaluate at compile time.
+
+Erroneous code example:
+
+    const X: i32 = 1 / 0;
+
+Division by zero, overflow and out-of-bounds indexing in constants and statics
+are reported when the crate is compiled.",
+    ),
+    (
+        "E0093",
+        "An unknown intrinsic function was declared.
+
+Erroneous code example:
+
+    extern \"rust-intrinsic\" {
+        fn foo(a: i32, b: i32) -> i32;
+    }
+
+Only the intrinsics known to the compiler, such as `wrapping_add`, can be
+declared in an `extern \"rust-intrinsic\"` block.",
+    ),
+    (
+        "E0133",
+        "An unsafe operation was used outside of an unsafe function or block.
+
+Erroneous code example:
+
+    extern \"C\" { fn abs(x: i32) -> i32; }
+    fn main() -> i32 { abs(-1) }
+
+Calls to `unsafe fn`s and extern functions, dereferences of raw pointers and
+accesses to `static mut`s must be wrapped in an `unsafe` block:
+
+    fn main() -> i32 { unsafe { abs(-1) } }",
+    ),
+    (
+        "E0252",
+        "Two items with the same name were imported into the same module.
+
+Erroneous code example:
+
+    mod a { pub fn f() -> () {} }
+    mod b { pub fn f() -> () {} }
+    use a::f;
+    use b::f;
+
+Rename one of the imports with `as`, or refer to one of them by its full path.",
+    ),
+    (
+        "E0277",
+        "A value of a type which does not support an operation was used with it.
+
+Erroneous code example:
+
+    struct S {}
+    fn main() -> i32 { println!(\"{}\", S {}); 0 }
+
+Only `i32`, `bool` and `&str` can be formatted with `{}`, and arrays can only
+be indexed by an `i32`.",
+    ),
+    (
+        "E0282",
+        "The type of a value could not be inferred.
+
+Erroneous code example:
+
+    fn main() -> i32 { let a = []; 0 }
+
+Annotate the type, like `let a: [i32; 0] = [];`. Parameters of closures which
+are not passed where a `fn` type is expected also need annotations.",
+    ),
+    (
+        "E0308",
+        "An expression has a different type than the one expected where it is used.
+
+Erroneous code example:
+
+    fn main() -> i32 { let x: i32 = true; x }
+
+The expected type comes from a type annotation, the return type of a function,
+a parameter, or another branch of an `if`. Change either the expression or the
+expected type so that they match. There are no implicit conversions.",
+    ),
+    (
+        "E0369",
+        "A binary operator was used with operands of types it does not support.
+
+Erroneous code example:
+
+    fn main() -> i32 { 1 + true }
+
+Arithmetic and comparisons need two `i32`s, and `==` and `!=` need two `i32`s
+or two `bool`s.",
+    ),
+    (
+        "E0384",
+        "An immutable variable was assigned to after its initialization.
+
+Erroneous code example:
+
+    fn main() -> i32 { let x: i32 = 1; x = 2; x }
+
+Declare the variable with `let mut` to allow assigning to it.",
+    ),
+    (
+        "E0412",
+        "A type name was not found.
+
+Erroneous code example:
+
+    fn f(x: Foo) -> () {}
+
+Check the spelling of the type, and import it with `use` if it is defined in
+another module.",
+    ),
+    (
+        "E0422",
+        "A struct expression names a struct which does not exist.
+
+Erroneous code example:
+
+    fn main() -> i32 { let s = Foo { a: 1 }; 0 }
+
+Check the spelling of the struct, and import it with `use` if it is defined in
+another module.",
+    ),
+    (
+        "E0425",
+        "A value was used which is not defined at that point.
+
+Erroneous code example:
+
+    fn main() -> i32 { x }
+
+Check the spelling of the name. Local variables can only be used after the
+`let` statement which declares them.",
+    ),
+    (
+        "E0432",
+        "A `use` declaration names an item which does not exist.
+
+Erroneous code example:
+
+    mod a {}
+    use a::f;
+
+Check that the path is spelled correctly, and that the item is defined in the
+module the path refers to.",
+    ),
+    (
+        "E0433",
+        "A path could not be resolved.
+
+Erroneous code example:
+
+    fn main() -> i32 { super::f() }
+
+`super` refers to the parent module, so it cannot be used in the crate root or
+more times than the module is nested.",
+    ),
+    (
+        "E0449",
+        "A visibility qualifier was used where it has no effect.
+
+Erroneous code example:
+
+    struct S {}
+    pub impl S {}
+
+Items in `impl` blocks, extern blocks and macro definitions have their own
+visibility, so remove the `pub`.",
+    ),
+    (
+        "E0452",
+        "An invalid lint attribute was given.
+
+Erroneous code example:
+
+    #![allow(dead_code = 1)]
+
+Lint attributes take a comma-separated list of lint names, like
+`#[allow(dead_code, unused_variables)]`.",
+    ),
+    (
+        "E0463",
+        "An `extern crate` names a crate which was not passed to the compiler.
+
+Erroneous code example:
+
+    extern crate foo;
+
+Build the library with `--crate-type lib` and pass its metadata with
+`--extern foo=libfoo.rmeta`.",
+    ),
+    (
+        "E0560",
+        "A struct expression gives a field which the struct does not have.
+
+Erroneous code example:
+
+    struct S { a: i32 }
+    fn main() -> i32 { let s = S { a: 1, b: 2 }; s.a }
+
+Check the name of the field in the declaration of the struct.",
+    ),
+    (
+        "E0574",
+        "A struct expression names something which is not a struct.
+
+Erroneous code example:
+
+    fn f() -> i32 { 0 }
+    fn main() -> i32 { let s = f { a: 1 }; 0 }
+
+Only structs can be built with `Name { field: value }`.",
+    ),
+    (
+        "E0583",
+        "The file of an out-of-line module was not found.
+
+Erroneous code example:
+
+    mod foo;
+
+The items of `mod foo;` are read from `foo.rs` or `foo/mod.rs` next to the
+file declaring it. Create one of them, or declare the module inline with
+`mod foo { ... }`.",
+    ),
+    (
+        "E0594",
+        "A value was assigned to an immutable static item, or through a `*const` pointer.
+
+Erroneous code example:
+
+    static S: i32 = 1;
+    fn main() -> i32 { S = 2; 0 }
+
+Declare the item as `static mut` and assign to it in an `unsafe` block. Values
+behind a `*const` pointer cannot be assigned to.",
+    ),
+    (
+        "E0599",
+        "A method or associated function was used which the type does not have.
+
+Erroneous code example:
+
+    fn main() -> i32 { 1.wrapping_neg() }
+
+Only `i32` has methods and associated functions, which are the functions of the
+prelude module `num` like `wrapping_add`, `saturating_sub` and `checked_mul`.
+`x.method(y)` is the same as `i32::method(x, y)`.",
+    ),
+    (
+        "E0600",
+        "A unary operator was applied to a value of a type which does not support it.
+
+Erroneous code example:
+
+    fn main() -> i32 { -true }
+
+Negation with `-` is only defined for `i32`.",
+    ),
+    (
+        "E0603",
+        "A private item was used outside of the module it is defined in.
+
+Erroneous code example:
+
+    mod a { fn f() -> () {} }
+    fn main() -> i32 { a::f(); 0 }
+
+Make the item visible with `pub`, `pub(crate)` or `pub(super)`.",
+    ),
+    (
+        "E0605",
+        "A cast between types which cannot be converted to each other was used.
+
+Erroneous code example:
+
+    fn main() -> i32 { let b = true as *const i32; 0 }
+
+Only references and raw pointers can be cast to raw pointers.",
+    ),
+    (
+        "E0608",
+        "A value which is not an array was indexed.
+
+Erroneous code example:
+
+    fn main() -> i32 { let x: i32 = 1; x[0] }
+
+Only arrays can be indexed with `[]`.",
+    ),
+    (
+        "E0609",
+        "A field which does not exist was accessed.
+
+Erroneous code example:
+
+    struct S { a: i32 }
+    fn main() -> i32 { let s = S { a: 1 }; s.b }
+
+Check the spelling of the field against the definition of the struct.",
+    ),
+    (
+        "E0610",
+        "A field of a value of a primitive type was accessed.
+
+Erroneous code example:
+
+    fn main() -> i32 { let x: i32 = 1; x.a }
+
+Only structs have fields.",
+    ),
+    (
+        "E0614",
+        "A value which is neither a reference nor a raw pointer was dereferenced.
+
+Erroneous code example:
+
+    fn main() -> i32 { let x: i32 = 1; *x }
+
+Only `&T` and `*const T` can be dereferenced with `*`.",
+    ),
+    (
+        "E0616",
+        "A private field of a struct was accessed outside of the module the struct is defined in.
+
+Erroneous code example:
+
+    mod a { pub struct S { x: i32 } }
+    fn f(s: a::S) -> i32 { s.x }
+
+Make the field visible with `pub`, or add a public function to the module
+which reads it.",
+    ),
+    (
+        "E0618",
+        "Something which is not a function was called.
+
+Erroneous code example:
+
+    fn main() -> i32 { let x: i32 = 1; x() }
+
+Only functions, function pointers and closures can be called.",
+    ),
+    (
+        "E0659",
+        "A name is ambiguous because it is imported by several glob imports.
+
+Erroneous code example:
+
+    mod a { pub fn f() -> () {} }
+    mod b { pub fn f() -> () {} }
+    use a::*;
+    use b::*;
+    fn main() -> i32 { f(); 0 }
+
+Import the one to use explicitly, like `use a::f;`, or use its full path.",
+    ),
+    (
+        "E0703",
+        "An unknown ABI was given to an `extern` block or function.
+
+Erroneous code example:
+
+    extern \"foo\" { fn f() -> (); }
+
+The supported ABIs are `\"C\"` and `\"rust-intrinsic\"`.",
+    ),
+    (
+        "E0704",
+        "The scope of a visibility restriction is not `crate`, `super` or `self`.
+
+Erroneous code example:
+
+    pub(foo) fn f() -> () {}
+
+Use `pub(crate)`, `pub(super)` or `pub(self)`.",
+    ),
+    (
+        "E0761",
+        "The file of an out-of-line module was found at two locations.
+
+Erroneous code example:
+
+    mod foo;
+
+where both `foo.rs` and `foo/mod.rs` exist. Delete or rename one of them.",
+    ),
+    (
+        "E0765",
+        "A string literal is not closed before the end of its line.
+
+Erroneous code example:
+
+    fn main() -> i32 { let s = \"abc; 0 }
+
+Add the closing `\"`. Write `\\n` for a newline in a string.",
+    ),
+    (
+        "M0001",
+        "The parser found a token which cannot appear at that point.
+
+Erroneous code example:
+
+    fn main() -> i32 { let x = 1 + ; x }
+
+The message says what was expected instead. The parser skips to the next
+statement or item after a syntax error, so all of them are reported at once.",
+    ),
+    (
+        "M0002",
+        "A delimiter such as `(`, `[` or `{` is not closed, or is closed by the wrong one.
+
+Erroneous code example:
+
+    fn main() -> i32 { 0 } }
+
+Check that each closing delimiter matches the last unclosed opening one.",
+    ),
+    (
+        "M0003",
+        "The source code contains a character which does not start any token.
+
+Erroneous code example:
+
+    fn main() -> i32 { 1 @ 2 }
+
+Remove the character, or check that the code is not missing a quote.",
+    ),
+    (
+        "M0004",
+        "A string literal contains an unknown escape sequence.
+
+Erroneous code example:
+
+    fn main() -> i32 { let s = \"\\q\"; 0 }
+
+The supported escapes are `\\n`, `\\t` and `\\r`.",
+    ),
+    (
+        "M0005",
+        "The file of an out-of-line module exists but could not be read.
+
+Check the permissions of the file, and that it is valid UTF-8.",
+    ),
+    (
+        "M0006",
+        "A `macro_rules!` definition is malformed.
+
+Erroneous code example:
+
+    macro_rules! m {
+        ($x) => { $x };
+    }
+
+Each rule is a matcher and a transcriber separated by `=>`, and rules are
+separated by `;`. Meta-variables in the matcher need a fragment specifier like
+`$x:expr`, and repetitions like `$($x:expr),*` end with `*`, `+` or `?`.",
+    ),
+    (
+        "M0101",
+        "A glob import names an item which is not a module.
+
+Erroneous code example:
+
+    fn f() -> () {}
+    use f::*;
+
+Only the items of a module can be imported with `*`.",
+    ),
+    (
+        "M0102",
+        "Imports refer to each other in a cycle, so none of them can be resolved.
+
+Erroneous code example:
+
+    mod a { pub use super::b::f; }
+    mod b { pub use super::a::f; }
+
+At least one of the imports must refer to the item where it is defined.",
+    ),
+    (
+        "M0103",
+        "A macro was invoked which is not defined.
+
+Erroneous code example:
+
+    fn main() -> i32 { foo!(1) }
+
+A `macro_rules!` macro can only be used after its definition, in the same
+module or in a module nested in it.",
+    ),
+    (
+        "M0104",
+        "An item was annotated with an attribute which is not known.
+
+Erroneous code example:
+
+    #[inline]
+    fn f() -> i32 { 1 }
+
+Only the lint attributes `allow`, `warn` and `deny` are supported.",
+    ),
+    (
+        "M0201",
+        "An operation is known at compile time to panic whenever it is executed.
+
+Erroneous code example:
+
+    fn main() -> i32 { let a: [i32; 3] = [1, 2, 3]; a[3] }
+
+A constant index is checked against the length of the array.",
+    ),
+    (
+        "M0202",
+        "The length of an array type or repeat expression is negative.
+
+Erroneous code example:
+
+    fn main() -> i32 { let a: [i32; -1]; 0 }
+
+Array lengths are constant expressions which must evaluate to a non-negative
+integer.",
+    ),
+    (
+        "M0203",
+        "A static has a type which is not supported.
+
+Erroneous code example:
+
+    static S: [i32; 2] = [1, 2];
+
+Only `i32`, `bool` and `()` statics are supported.",
+    ),
+    (
+        "M0204",
+        "An intrinsic function was used as a value.
+
+Erroneous code example:
+
+    extern \"rust-intrinsic\" {
+        fn wrapping_add(a: i32, b: i32) -> i32;
+    }
+    fn main() -> i32 { let f: fn(i32, i32) -> i32 = wrapping_add; 0 }
+
+Intrinsics are expanded by the compiler where they are called, so they have no
+address. Call them directly, or wrap them in a function.",
+    ),
+    (
+        "M0301",
+        "None of the rules of a `macro_rules!` macro matched the arguments of a call.
+
+Erroneous code example:
+
+    macro_rules! double {
+        ($x:expr) => { $x * 2 };
+    }
+    fn main() -> i32 { double!(1, 2) }
+
+Rules are tried in order, and the first one whose matcher matches all of the
+arguments is used. Change the arguments, or add a rule matching them.",
+    ),
+    (
+        "M0302",
+        "A macro expanded to a call of a macro too many times.
+
+Erroneous code example:
+
+    macro_rules! forever {
+        () => { forever!() };
+    }
+    fn main() -> i32 { forever!(); 0 }
+
+Expansion stops at a fixed depth. Make sure recursive rules eventually expand
+to something without the recursive call.",
+    ),
+    (
+        "M0303",
+        "The transcriber of a matched `macro_rules!` rule could not be expanded.
+
+Erroneous code example:
+
+    macro_rules! m {
+        ($($x:expr),*) => { $x };
+    }
+    fn main() -> i32 { m!(1, 2) }
+
+Meta-variables must be bound by the matcher of the rule, and a meta-variable
+bound in a repetition must be used in a repetition as deep as the one in the
+matcher, like `$($x),*`.",
+    ),
+    (
+        "M0304",
+        "The format string of a formatting macro like `println!` is invalid, or does
+not match the arguments.
+
+Erroneous code example:
+
+    fn main() -> i32 { println!(\"{} {}\", 1); 0 }
+
+The format string must be a string literal. Each `{}` or `{:?}` takes the next
+argument, `{0}` or `{x}` names one, and every argument must be used. Write
+`{{` and `}}` for literal braces.",
+    ),
+    (
+        "M0305",
+        "A built-in macro was used in a way it does not support.
+
+Erroneous code example:
+
+    println!(\"hello\");
+    fn main() -> i32 { 0 }
+
+Built-in macros like `println!` and `assert!` expand to expressions, so they
+cannot be used as items, and need their arguments, like the format string of
+`println!`. Macros which call prelude functions need the prelude, which
+`--no-prelude` disables.",
+    ),
+];
+
+/// Explanation of `code`, like `E0308`. The `E` of rustc's codes can be omitted like
+/// `--explain 0308`
+pub fn explanation(code: &str) -> Option<&'static str> {
+    let code = if code.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("E{}", code)
+    } else {
+        code.to_string()
+    };
+    EXPLANATIONS
+        .binary_search_by_key(&code.as_str(), |(code, _)| code)
+        .ok()
+        .map(|i| EXPLANATIONS[i].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_sorted() {
+        assert!(EXPLANATIONS.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(EXPLANATIONS
+            .iter()
+            .all(|(code, _)| code.len() == 5 && code[1..].chars().all(|c| c.is_ascii_digit())));
+    }
+
+    #[test]
+    fn test_explanation() {
+        assert!(explanation("E0308").unwrap().starts_with("An expression has a different type"));
+        assert_eq!(explanation("0308"), explanation("E0308"));
+        assert!(explanation("M0001").is_some());
+        assert!(explanation("M0301").unwrap().starts_with("None of the rules"));
+        assert!(explanation("E9999").is_none());
+    }
+}
@@ -0,0 +1,326 @@
+//! Command-line arguments of `mini-rustc`, parsed into what to compile and where to write it
+
+use super::link::LinkOptions;
+use super::{Edition, EmitKind, Input, Options};
+use crate::backend_llvm::TARGETS;
+use crate::diagnostics::ErrorFormat;
+use crate::lint::LintLevel;
+use crate::middle::CrateType;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: mini-rustc [OPTIONS] FILE
+       mini-rustc [OPTIONS] -e SOURCE
+       mini-rustc --explain CODE
+
+Options:
+  -o PATH                 Write the output to PATH
+  -e SOURCE               Compile SOURCE instead of a file
+  --emit KIND[=PATH],...  Outputs to write, to PATH or else to stdout for tokens, ast, resolved,
+                          typed-ast and llvm-ir, and to files named after the crate for asm, obj
+                          and link (the default: an executable, or lib<name>.a for libraries)
+  -O0, -O1, -O2, -O3      Optimization level. Integer overflow wraps instead of panicking above -O0
+  -O, --release           Same as -O2
+  --target TRIPLE         Target to generate code for, like x86_64-unknown-linux-gnu
+  --edition YEAR          Edition of Rust: 2015, 2018, 2021 (the default) or 2024
+  --crate-type bin|lib    Kind of crate to build. `lib` also writes its metadata next to the
+                          archive, like lib<name>.rmeta
+  --crate-name NAME       Name of the crate
+  --extern NAME=PATH      Use the library whose metadata is at PATH as NAME, and link the
+                          archive next to it
+  -l NAME                 Link the library NAME
+  -L DIR                  Search libraries to link in DIR
+  --no-prelude            Do not import the prelude into the crate
+  -C no-bounds-checks     Do not check that array indices are in bounds
+  -C llc=PATH             Path to llc, which compiles LLVM IR to machine code
+  -C linker=PATH          Path to the linker, a C compiler like cc (the default) or clang
+  -C ar=PATH              Path to ar, which archives libraries
+  -C save-temps           Keep the intermediate .ll and .o files in the current directory
+  -A, -W, -D LINT         Allow, warn about or deny a lint or a group of lints like `unused`
+  --error-format human|json
+                          Print errors for people, or as JSON lines for tools
+  --dump                  Dump compiler states for debugging
+  --explain CODE          Explain the error code CODE
+  -h, --help              Print this help
+  -V, --version           Print the version";
+
+pub enum Command {
+    Compile(Box<Config>),
+    /// `--explain CODE`
+    Explain(String),
+    Help,
+    Version,
+}
+
+/// What to compile and where to write the outputs
+pub struct Config {
+    pub input: Input,
+    pub options: Options,
+    /// outputs in the order given by `--emit`, written to the path if any or else to stdout
+    pub outputs: Vec<(EmitKind, Option<PathBuf>)>,
+    pub link: LinkOptions,
+    pub error_format: ErrorFormat,
+}
+
+/// Invalid command line, reported with a hint to run `mini-rustc --help`
+#[derive(Debug, PartialEq, Eq)]
+pub struct ArgsError(pub String);
+
+/// Parse the arguments following the program name. Options with a value can be given either
+/// as `--name value` or `--name=value`.
+pub fn parse_args(args: &[String]) -> Result<Command, ArgsError> {
+    let mut options = Options::default();
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    let mut output = None;
+    let mut link = LinkOptions::default();
+    let mut error_format = ErrorFormat::Human;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if arg.starts_with("--") => (name, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = |what: &str| match inline_value {
+            Some(value) => Ok(value.to_string()),
+            None => args
+                .next()
+                .cloned()
+                .ok_or_else(|| ArgsError(format!("`{}` must be followed by {}", name, what))),
+        };
+        match name {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--explain" => return Ok(Command::Explain(value("an error code")?)),
+            "-o" => output = Some(PathBuf::from(value("a path")?)),
+            "-e" => inputs.push(Input::Str(value("source code")?)),
+            "--emit" => {
+                for spec in value("a list of outputs")?.split(',') {
+                    let (kind, path) = match spec.split_once('=') {
+                        Some((kind, path)) => (kind, Some(PathBuf::from(path))),
+                        None => (spec, None),
+                    };
+                    outputs.push((parse_emit_kind(kind)?, path));
+                }
+            }
+            "-O" | "--release" => options.codegen.opt_level = 2,
+            "-O0" | "-O1" | "-O2" | "-O3" => options.codegen.opt_level = name.as_bytes()[2] - b'0',
+            "--target" => {
+                let target = value("a target triple")?;
+                if !TARGETS.contains(&target.as_str()) {
+                    return Err(ArgsError(format!(
+                        "unknown target `{}`; expected one of {}",
+                        target,
+                        TARGETS.join(", ")
+                    )));
+                }
+                options.codegen.target = target;
+            }
+            "--edition" => {
+                let year = value("a year")?;
+                options.edition = Edition::from_year(&year)
+                    .ok_or_else(|| ArgsError(format!("unknown edition `{}`; expected 2015, 2018, 2021 or 2024", year)))?;
+            }
+            "--crate-type" => {
+                options.crate_type = match value("a crate type")?.as_str() {
+                    "bin" => CrateType::Bin,
+                    "lib" => CrateType::Lib,
+                    _ => return Err(ArgsError("`--crate-type` must be `bin` or `lib`".to_string())),
+                }
+            }
+            "--crate-name" => options.crate_name = Some(value("a name")?),
+            "--extern" => {
+                let spec = value("`NAME=PATH`")?;
+                let Some((name, path)) = spec.split_once('=') else {
+                    return Err(ArgsError("`--extern` must be in the form of `NAME=PATH`".to_string()));
+                };
+                link.externs.push((name.to_string(), PathBuf::from(path).with_extension("a")));
+                options.externs.push((name.to_string(), path.to_string()));
+            }
+            "-l" => link.libs.push(value("a library name")?),
+            "-L" => link.lib_paths.push(PathBuf::from(value("a directory")?)),
+            "-C" => {
+                let opt = value("a codegen option")?;
+                match opt.split_once('=') {
+                    None if opt == "no-bounds-checks" => options.codegen.bounds_checks = false,
+                    None if opt == "save-temps" => link.save_temps = true,
+                    Some(("llc", path)) => link.llc = PathBuf::from(path),
+                    Some(("linker", path)) => link.linker = PathBuf::from(path),
+                    Some(("ar", path)) => link.ar = PathBuf::from(path),
+                    _ => return Err(ArgsError(format!("unknown codegen option `{}`", opt))),
+                }
+            }
+            "-A" | "-W" | "-D" => {
+                let lint = value("a lint name")?;
+                options.lint_levels.push((LintLevel::from_flag(name).unwrap(), lint));
+            }
+            "--error-format" => {
+                error_format = match value("`human` or `json`")?.as_str() {
+                    "human" => ErrorFormat::Human,
+                    "json" => ErrorFormat::Json,
+                    _ => return Err(ArgsError("`--error-format` must be `human` or `json`".to_string())),
+                }
+            }
+            "--dump" => options.dump_enabled = true,
+            "--no-prelude" => options.prelude_enabled = false,
+            _ if arg.starts_with('-') => {
+                // `-Dlint` and `-lm` are the same as `-D lint` and `-l m`
+                let unknown = || ArgsError(format!("unknown option `{}`", arg));
+                let (flag, value) = match (arg.get(..2), arg.get(2..)) {
+                    (Some(flag), Some(value)) if !value.is_empty() => (flag, value),
+                    _ => return Err(unknown()),
+                };
+                match flag {
+                    "-l" => link.libs.push(value.to_string()),
+                    "-L" => link.lib_paths.push(PathBuf::from(value)),
+                    _ => {
+                        let level = LintLevel::from_flag(flag).ok_or_else(unknown)?;
+                        options.lint_levels.push((level, value.to_string()));
+                    }
+                }
+            }
+            _ => inputs.push(Input::File(PathBuf::from(arg))),
+        }
+    }
+
+    let input = match inputs.len() {
+        0 => return Err(ArgsError("no input file; give a file or source code with `-e`".to_string())),
+        1 => inputs.pop().unwrap(),
+        _ => return Err(ArgsError("more than one input; give either a file or `-e`".to_string())),
+    };
+    if outputs.is_empty() {
+        outputs.push((EmitKind::Link, None));
+    }
+    if let Some(output) = output {
+        match outputs.as_mut_slice() {
+            [(_, path @ None)] => *path = Some(output),
+            _ => {
+                return Err(ArgsError(
+                    "`-o` can only be used with a single output; give a path to each output like `--emit=ast=PATH`"
+                        .to_string(),
+                ))
+            }
+        }
+    }
+    options.emit = outputs.iter().map(|(kind, _)| *kind).collect();
+    options.codegen.overflow_checks = options.codegen.opt_level == 0;
+
+    Ok(Command::Compile(Box::new(Config {
+        input,
+        options,
+        outputs,
+        link,
+        error_format,
+    })))
+}
+
+fn parse_emit_kind(name: &str) -> Result<EmitKind, ArgsError> {
+    EmitKind::from_name(name).ok_or_else(|| {
+        let names: Vec<&str> = EmitKind::ALL.iter().map(EmitKind::name).collect();
+        ArgsError(format!("unknown output `{}`; expected one of {}", name, names.join(", ")))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, ArgsError> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&args)
+    }
+
+    fn config(args: &[&str]) -> Config {
+        match parse(args) {
+            Ok(Command::Compile(config)) => *config,
+            Ok(_) => panic!("not a compilation: {:?}", args),
+            Err(e) => panic!("{:?}: {}", args, e.0),
+        }
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args) {
+            Err(e) => e.0,
+            Ok(_) => panic!("no error: {:?}", args),
+        }
+    }
+
+    #[test]
+    fn test_inputs() {
+        assert!(matches!(config(&["a.rs"]).input, Input::File(path) if path.as_os_str() == "a.rs"));
+        // a file is not required to end with `.rs`
+        assert!(matches!(config(&["main"]).input, Input::File(_)));
+        assert!(matches!(config(&["-e", "fn main() {}"]).input, Input::Str(src) if src == "fn main() {}"));
+        assert_eq!(error(&[]), "no input file; give a file or source code with `-e`");
+        assert_eq!(error(&["a.rs", "-e", "fn main() {}"]), "more than one input; give either a file or `-e`");
+        assert!(matches!(parse(&["a.rs", "--help"]), Ok(Command::Help)));
+        assert!(matches!(parse(&["-V"]), Ok(Command::Version)));
+        assert!(matches!(parse(&["--explain=E0425"]), Ok(Command::Explain(code)) if code == "E0425"));
+    }
+
+    #[test]
+    fn test_outputs() {
+        let default = config(&["a.rs"]);
+        assert_eq!(default.outputs, vec![(EmitKind::Link, None)]);
+        assert_eq!(default.options.emit, vec![EmitKind::Link]);
+
+        let with_o = config(&["a.rs", "--emit", "ast", "-o", "a.txt"]);
+        assert_eq!(with_o.outputs, vec![(EmitKind::Ast, Some(PathBuf::from("a.txt")))]);
+        assert_eq!(config(&["a.rs", "-o", "a"]).outputs, vec![(EmitKind::Link, Some(PathBuf::from("a")))]);
+
+        let several = config(&["a.rs", "--emit=tokens,llvm-ir=a.ll", "--emit=typed-ast"]);
+        assert_eq!(
+            several.outputs,
+            vec![
+                (EmitKind::Tokens, None),
+                (EmitKind::LlvmIr, Some(PathBuf::from("a.ll"))),
+                (EmitKind::TypedAst, None)
+            ]
+        );
+        assert!(error(&["a.rs", "--emit=ast,llvm-ir", "-o", "a"]).starts_with("`-o` can only be used"));
+        assert!(error(&["a.rs", "--emit=hir"]).starts_with("unknown output `hir`"));
+    }
+
+    #[test]
+    fn test_link_options() {
+        let link = config(&["a.rs", "-l", "m", "-lpthread", "-L", "/opt/lib", "-L.", "--extern", "b=dir/libb.rmeta"]).link;
+        assert_eq!(link.libs, vec!["m", "pthread"]);
+        assert_eq!(link.lib_paths, vec![PathBuf::from("/opt/lib"), PathBuf::from(".")]);
+        assert_eq!(link.externs, vec![("b".to_string(), PathBuf::from("dir/libb.a"))]);
+
+        let tools = config(&["a.rs", "-C", "llc=/usr/bin/llc-14", "-C", "linker=clang", "-C", "save-temps"]).link;
+        assert_eq!(tools.llc, PathBuf::from("/usr/bin/llc-14"));
+        assert_eq!(tools.linker, PathBuf::from("clang"));
+        assert_eq!(tools.ar, PathBuf::from("ar"));
+        assert!(tools.save_temps);
+        assert_eq!(error(&["a.rs", "-C", "cc=gcc"]), "unknown codegen option `cc=gcc`");
+        assert_eq!(error(&["a.rs", "-l"]), "`-l` must be followed by a library name");
+    }
+
+    #[test]
+    fn test_options() {
+        let default = config(&["a.rs"]);
+        assert_eq!(default.options.codegen.opt_level, 0);
+        assert!(default.options.codegen.overflow_checks);
+
+        let optimized = config(&["a.rs", "-O3", "--target", "aarch64-apple-darwin", "--edition=2018"]);
+        assert_eq!(optimized.options.codegen.opt_level, 3);
+        assert!(!optimized.options.codegen.overflow_checks);
+        assert_eq!(optimized.options.codegen.target, "aarch64-apple-darwin");
+        assert_eq!(optimized.options.edition, Edition::E2018);
+        assert_eq!(config(&["a.rs", "--release"]).options.codegen.opt_level, 2);
+
+        let lints = config(&["a.rs", "-D", "unused", "-Adead_code"]).options.lint_levels;
+        assert_eq!(
+            lints,
+            vec![(LintLevel::Deny, "unused".to_string()), (LintLevel::Allow, "dead_code".to_string())]
+        );
+
+        assert_eq!(error(&["a.rs", "-O4"]), "unknown option `-O4`");
+        assert_eq!(error(&["a.rs", "--edition", "2020"]), "unknown edition `2020`; expected 2015, 2018, 2021 or 2024");
+        assert!(error(&["a.rs", "--target=wasm32"]).starts_with("unknown target `wasm32`"));
+        assert_eq!(error(&["a.rs", "-o"]), "`-o` must be followed by a path");
+        assert_eq!(error(&["a.rs", "--frobnicate"]), "unknown option `--frobnicate`");
+    }
+}
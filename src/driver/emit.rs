@@ -0,0 +1,150 @@
+//! Outputs selected by `--emit`, and the text of those showing the states of the compiler
+
+use crate::ast::visitor::{self, Visitor};
+use crate::ast::{Crate, Expr, ExprKind, Path};
+use crate::lexer::{Lexer, TokenKind};
+use crate::middle::Ctxt;
+use crate::span::{SourceFile, Span};
+use std::fmt::Write;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmitKind {
+    /// tokens of the root file
+    Tokens,
+    /// items of the crate after macro expansion
+    Ast,
+    /// what the paths in expressions refer to
+    Resolved,
+    /// types of expressions
+    TypedAst,
+    LlvmIr,
+    Asm,
+    Obj,
+    /// executable, or archive of a library
+    Link,
+}
+
+impl EmitKind {
+    pub const ALL: [EmitKind; 8] = [
+        EmitKind::Tokens,
+        EmitKind::Ast,
+        EmitKind::Resolved,
+        EmitKind::TypedAst,
+        EmitKind::LlvmIr,
+        EmitKind::Asm,
+        EmitKind::Obj,
+        EmitKind::Link,
+    ];
+
+    pub fn from_name(name: &str) -> Option<EmitKind> {
+        EmitKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// Whether the output is made from the LLVM IR by `llc` and the linker, in `link::build`
+    pub fn needs_llc(&self) -> bool {
+        matches!(self, EmitKind::Asm | EmitKind::Obj | EmitKind::Link)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmitKind::Tokens => "tokens",
+            EmitKind::Ast => "ast",
+            EmitKind::Resolved => "resolved",
+            EmitKind::TypedAst => "typed-ast",
+            EmitKind::LlvmIr => "llvm-ir",
+            EmitKind::Asm => "asm",
+            EmitKind::Obj => "obj",
+            EmitKind::Link => "link",
+        }
+    }
+}
+
+/// One token per line, like `1:4: Ident("main")`
+pub(super) fn tokens(file: &Rc<SourceFile>) -> String {
+    let mut out = String::new();
+    let mut lexer = Lexer::from_file(Rc::clone(file));
+    loop {
+        let t = lexer.skip_token();
+        if t.kind == TokenKind::Eof {
+            return out;
+        }
+        writeln!(out, "{}: {:?}", location(&t.span), t.kind).unwrap();
+    }
+}
+
+/// One path per line with its canonical path, like `3:5: x => crate::main::x (local variable)`
+pub(super) fn resolved(ctx: &mut Ctxt, krate: &Crate) -> String {
+    let mut lister = ExprLister {
+        ctx,
+        kind: EmitKind::Resolved,
+        out: String::new(),
+    };
+    visitor::go(&mut lister, krate);
+    lister.out
+}
+
+/// One expression per line with its type, like `3:5: x + 1: i32`
+pub(super) fn typed_ast(ctx: &mut Ctxt, krate: &Crate) -> String {
+    let mut lister = ExprLister {
+        ctx,
+        kind: EmitKind::TypedAst,
+        out: String::new(),
+    };
+    visitor::go(&mut lister, krate);
+    lister.out
+}
+
+/// Lists the expressions of the crate, except those of the prelude
+struct ExprLister<'a, 'ctx> {
+    ctx: &'a mut Ctxt<'ctx>,
+    kind: EmitKind,
+    out: String,
+}
+
+impl ExprLister<'_, '_> {
+    fn list_path(&mut self, path: &Path) {
+        let Some(binding) = self.ctx.resolve_path(path) else {
+            return;
+        };
+        let cpath: Vec<&str> = binding.cpath.segments().iter().map(|seg| seg.as_str()).collect();
+        writeln!(
+            self.out,
+            "{}: {} => {} ({})",
+            location(&path.span),
+            path.span.to_snippet(),
+            cpath.join("::"),
+            binding.kind.descr()
+        )
+        .unwrap();
+    }
+}
+
+impl<'ctx> Visitor<'ctx> for ExprLister<'_, '_> {
+    fn visit_expr(&mut self, expr: &'ctx Expr) {
+        if expr.span.file().name == "<prelude>" {
+            return;
+        }
+        match self.kind {
+            EmitKind::Resolved => match &expr.kind {
+                ExprKind::Path(path) | ExprKind::Struct(path, _) | ExprKind::Deref(path) => self.list_path(path),
+                _ => (),
+            },
+            _ => {
+                if let Some(ty) = self.ctx.lookup_type(expr.id) {
+                    writeln!(self.out, "{}: {}: {}", location(&expr.span), one_line(&expr.span), ty).unwrap();
+                }
+            }
+        }
+    }
+}
+
+fn location(span: &Span) -> String {
+    let (line, col) = span.line_col();
+    format!("{}:{}", line, col)
+}
+
+/// Source of `span` with runs of whitespace, like newlines in blocks, replaced by a space
+fn one_line(span: &Span) -> String {
+    span.to_snippet().split_whitespace().collect::<Vec<_>>().join(" ")
+}
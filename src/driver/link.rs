@@ -0,0 +1,234 @@
+//! Outputs made from the LLVM IR by external tools: assembly and objects by `llc`, executables
+//! by the linker, and archives of libraries by `ar`
+
+use super::{CompileError, Compiled, EmitKind};
+use crate::backend_llvm::CodegenOptions;
+use crate::middle::CrateType;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{env, fs, io};
+
+/// Tools and libraries given by `-C`, `-l`, `-L` and `--extern`
+pub struct LinkOptions {
+    pub llc: PathBuf,
+    /// C compiler driver like `cc` or `clang`, which links executables with the C library
+    pub linker: PathBuf,
+    pub ar: PathBuf,
+    /// libraries given by `-l`, passed through to the linker
+    pub libs: Vec<String>,
+    /// directories given by `-L`, where the linker searches libraries
+    pub lib_paths: Vec<PathBuf>,
+    /// archives of the libraries given by `--extern`, which are next to their metadata
+    pub externs: Vec<(String, PathBuf)>,
+    /// keep the intermediate files in the current directory
+    pub save_temps: bool,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        LinkOptions {
+            llc: PathBuf::from("llc"),
+            linker: PathBuf::from("cc"),
+            ar: PathBuf::from("ar"),
+            libs: vec![],
+            lib_paths: vec![],
+            externs: vec![],
+            save_temps: false,
+        }
+    }
+}
+
+/// Write the outputs `asm`, `obj` and `link` among `outputs`, to their paths or else to files
+/// named after the crate. The LLVM IR and objects they are made from are written to a
+/// temporary directory, which is removed afterwards.
+pub fn build(
+    compiled: &Compiled,
+    codegen: &CodegenOptions,
+    opts: &LinkOptions,
+    outputs: &[(EmitKind, Option<PathBuf>)],
+) -> Result<(), CompileError> {
+    let path_of = |kind| {
+        let (_, path) = outputs.iter().find(|(k, _)| *k == kind)?;
+        Some(path.clone().unwrap_or_else(|| default_path(compiled, kind)))
+    };
+    let (asm, obj, link) = (path_of(EmitKind::Asm), path_of(EmitKind::Obj), path_of(EmitKind::Link));
+    if asm.is_none() && obj.is_none() && link.is_none() {
+        return Ok(());
+    }
+
+    let temps = TempDir::new(opts.save_temps)?;
+    let ir = temps.path.join(format!("{}.ll", compiled.crate_name));
+    if fs::write(&ir, compiled.llvm_ir.to_string()).is_err() {
+        return Err(CompileError(format!("Could not write temporary file {}", ir.display())));
+    }
+    if let Some(asm) = &asm {
+        llc(opts, codegen, &ir, "asm", asm)?;
+    }
+    if obj.is_none() && link.is_none() {
+        return Ok(());
+    }
+    let obj = obj.unwrap_or_else(|| temps.path.join(format!("{}.o", compiled.crate_name)));
+    llc(opts, codegen, &ir, "obj", &obj)?;
+
+    match (link, compiled.crate_type) {
+        (None, _) => Ok(()),
+        (Some(exe), CrateType::Bin) => {
+            let mut cmd = Command::new(&opts.linker);
+            cmd.arg("-o").arg(exe).arg(&obj);
+            for (name, archive) in &opts.externs {
+                if !archive.exists() {
+                    return Err(CompileError(format!(
+                        "Could not find {}, the archive of the library `{}`; compile it with `--crate-type lib`",
+                        archive.display(),
+                        name
+                    )));
+                }
+                cmd.arg(archive);
+            }
+            for dir in &opts.lib_paths {
+                cmd.arg("-L").arg(dir);
+            }
+            for lib in &opts.libs {
+                cmd.arg(format!("-l{}", lib));
+            }
+            run(cmd, "linker")
+        }
+        (Some(archive), CrateType::Lib) => {
+            // `ar` adds to an existing archive instead of replacing it
+            let _ = fs::remove_file(&archive);
+            let mut cmd = Command::new(&opts.ar);
+            cmd.arg("rcs").arg(archive).arg(&obj);
+            run(cmd, "ar")
+        }
+    }
+}
+
+/// Path of the metadata of a library, which is next to its archive with the extension `rmeta`,
+/// where `--extern` finds the archive again
+pub fn metadata_path(compiled: &Compiled, outputs: &[(EmitKind, Option<PathBuf>)]) -> PathBuf {
+    let archive = match outputs.iter().find(|(kind, _)| *kind == EmitKind::Link) {
+        Some((_, Some(path))) => path.clone(),
+        _ => default_path(compiled, EmitKind::Link),
+    };
+    archive.with_extension("rmeta")
+}
+
+/// `<crate>.s`, `<crate>.o`, and `<crate>` for executables or `lib<crate>.a` for libraries
+fn default_path(compiled: &Compiled, kind: EmitKind) -> PathBuf {
+    let name = &compiled.crate_name;
+    PathBuf::from(match (kind, compiled.crate_type) {
+        (EmitKind::Asm, _) => format!("{}.s", name),
+        (EmitKind::Obj, _) => format!("{}.o", name),
+        (_, CrateType::Bin) => name.clone(),
+        (_, CrateType::Lib) => format!("lib{}.a", name),
+    })
+}
+
+fn llc(opts: &LinkOptions, codegen: &CodegenOptions, ir: &Path, filetype: &str, output: &Path) -> Result<(), CompileError> {
+    let mut cmd = Command::new(&opts.llc);
+    cmd.arg(format!("-O{}", codegen.opt_level))
+        .arg(format!("-filetype={}", filetype))
+        // code is linked into position independent executables by default
+        .args(["-opaque-pointers", "-relocation-model=pic"])
+        .arg("-o")
+        .arg(output)
+        .arg(ir);
+    run(cmd, "llc")
+}
+
+/// Run the tool given by `-C <option>=PATH`, with its errors in the message if it fails
+fn run(mut cmd: Command, option: &str) -> Result<(), CompileError> {
+    let tool = Path::new(cmd.get_program()).display().to_string();
+    match cmd.output() {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(CompileError(format!(
+            "`{}` failed with {}\n{}",
+            tool,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(CompileError(format!(
+            "Could not find `{}`; install it or give its path with `-C {}=PATH`",
+            tool, option
+        ))),
+        Err(e) => Err(CompileError(format!("Could not run `{}`: {}", tool, e))),
+    }
+}
+
+/// Number of random names tried for a temporary directory before giving up
+const TEMP_DIR_ATTEMPTS: usize = 16;
+
+/// Directory for intermediate files, which is removed when dropped unless they are saved
+struct TempDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl TempDir {
+    fn new(save_temps: bool) -> Result<Self, CompileError> {
+        if save_temps {
+            return Ok(TempDir {
+                path: PathBuf::from("."),
+                keep: true,
+            });
+        }
+        // a new directory with a random name, so that one made by someone else is never used
+        for _ in 0..TEMP_DIR_ATTEMPTS {
+            let suffix = RandomState::new().build_hasher().finish();
+            let path = env::temp_dir().join(format!("mini-rustc-{}-{:016x}", std::process::id(), suffix));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(TempDir { path, keep: false }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    return Err(CompileError(format!(
+                        "Could not create temporary directory {}: {}",
+                        path.display(),
+                        e
+                    )))
+                }
+            }
+        }
+        Err(CompileError(format!(
+            "Could not create a temporary directory in {}",
+            env::temp_dir().display()
+        )))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        assert!(run(Command::new("true"), "linker").is_ok());
+        assert_eq!(
+            run(Command::new("no-such-llc"), "llc").unwrap_err().0,
+            "Could not find `no-such-llc`; install it or give its path with `-C llc=PATH`"
+        );
+        let mut failing = Command::new("sh");
+        failing.args(["-c", "echo oops >&2; exit 3"]);
+        assert_eq!(run(failing, "linker").unwrap_err().0, "`sh` failed with exit status: 3\noops");
+    }
+
+    #[test]
+    fn test_temp_dir() {
+        let a = TempDir::new(false).unwrap();
+        let b = TempDir::new(false).unwrap();
+        assert_ne!(a.path, b.path);
+        assert!(a.path.is_dir() && b.path.is_dir());
+        let path = a.path.clone();
+        drop(a);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,321 @@
+//! The stages of the compiler, run one after another on a crate
+//!
+//! Stages report errors and warnings as diagnostics, which are collected in the `Session`
+//! instead of being printed, so that the caller decides how to show them.
+
+pub mod args;
+mod emit;
+pub mod link;
+
+pub use self::emit::EmitKind;
+
+use crate::backend_llvm::{self, ir, CodegenOptions};
+use crate::diagnostics::Diagnostic;
+use crate::lint::{self, LintLevel};
+use crate::middle::{self, CrateType};
+use crate::span::{FileId, SourceFile};
+use crate::{expand, lexer, metadata, parse, typeck, unsafeck};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Source of the prelude, which is compiled into every crate unless `--no-prelude` is given
+const PRELUDE: &str = include_str!("../../library/prelude.rs");
+
+pub enum Input {
+    /// Path to the root file of the crate
+    File(PathBuf),
+    /// Source code of the crate
+    Str(String),
+}
+
+/// Edition of Rust given by `--edition`, which does not change how crates compile yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edition {
+    E2015,
+    E2018,
+    E2021,
+    E2024,
+}
+
+impl Edition {
+    pub fn from_year(year: &str) -> Option<Edition> {
+        match year {
+            "2015" => Some(Edition::E2015),
+            "2018" => Some(Edition::E2018),
+            "2021" => Some(Edition::E2021),
+            "2024" => Some(Edition::E2024),
+            _ => None,
+        }
+    }
+}
+
+pub struct Options {
+    pub crate_type: CrateType,
+    pub edition: Edition,
+    /// outputs to produce, the textual ones of which are returned in `Compiled::outputs`
+    pub emit: Vec<EmitKind>,
+    /// defaults to the name of the root file like rustc
+    pub crate_name: Option<String>,
+    /// libraries given by `--extern NAME=PATH`
+    pub externs: Vec<(String, String)>,
+    pub prelude_enabled: bool,
+    pub codegen: CodegenOptions,
+    /// levels of lints given by `-A`, `-W` and `-D`, in order
+    pub lint_levels: Vec<(LintLevel, String)>,
+    /// record compiler states for debugging
+    pub dump_enabled: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            crate_type: CrateType::Bin,
+            edition: Edition::E2021,
+            emit: vec![EmitKind::Link],
+            crate_name: None,
+            externs: vec![],
+            prelude_enabled: true,
+            codegen: CodegenOptions::default(),
+            lint_levels: vec![],
+            dump_enabled: false,
+        }
+    }
+}
+
+/// Outputs of a crate which compiled successfully
+pub struct Compiled {
+    pub crate_name: String,
+    pub crate_type: CrateType,
+    /// metadata of a library, to be written next to its archive by `link::metadata_path`
+    pub metadata: Option<String>,
+    /// code of the crate, written out as a `.ll` file by its `Display`
+    pub llvm_ir: ir::Module,
+    /// text of the outputs of `Options::emit` showing the states of the compiler, like `ast`
+    pub outputs: Vec<(EmitKind, String)>,
+}
+
+impl std::fmt::Debug for Compiled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Compiled").field("crate_name", &self.crate_name).finish_non_exhaustive()
+    }
+}
+
+/// Why compilation stopped, like "Failed to typecheck crate".
+/// The errors themselves are in the diagnostics of the session.
+#[derive(Debug)]
+pub struct CompileError(pub String);
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Diagnostics and debugging output of a compilation
+#[derive(Default)]
+pub struct Session {
+    /// Errors and warnings of all stages, in the order they were found
+    pub diagnostics: Vec<Diagnostic>,
+    /// Compiler states recorded with `Options::dump_enabled`
+    pub dumps: Vec<String>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session::default()
+    }
+
+    fn fail(&mut self, mut errors: Vec<Diagnostic>, message: &str) -> CompileError {
+        self.diagnostics.append(&mut errors);
+        CompileError(message.to_string())
+    }
+
+    /// Compile the crate `input` to LLVM IR
+    pub fn compile(&mut self, input: &Input, options: Options) -> Result<Compiled, CompileError> {
+        let mut outputs = vec![];
+        let emits = |kind| options.emit.contains(&kind);
+        let (lexer, root_dir) = match input {
+            Input::File(path) => {
+                let Ok(src) = std::fs::read_to_string(path) else {
+                    return Err(self.fail(vec![], &format!("Could not read file {}", path.display())));
+                };
+                let root_dir = path.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+                let file = SourceFile::new(FileId(0), path.display().to_string(), src);
+                (lexer::Lexer::from_file(Rc::new(file)), root_dir)
+            }
+            Input::Str(src) => (lexer::Lexer::new(src.clone()), PathBuf::new()),
+        };
+        if emits(EmitKind::Tokens) {
+            outputs.push((EmitKind::Tokens, emit::tokens(lexer.file())));
+        }
+
+        // Parse stage
+        let mut parser = parse::Parser::new(lexer, root_dir);
+        // syntax errors are recovered from, so names and types are still checked to report
+        // their errors too
+        let (mut krate, parse_failed) = match parser.parse_crate() {
+            Ok(krate) => (krate, false),
+            Err(parse::ParseError {
+                mut errors,
+                recovered,
+            }) => {
+                self.diagnostics.append(&mut errors);
+                (recovered.unwrap(), true)
+            }
+        };
+
+        // The prelude comes first so that its functions are declared before they are used
+        let mut prelude_len = 0;
+        if options.prelude_enabled {
+            let prelude = match parser.parse_prelude(PRELUDE) {
+                Ok(prelude) => prelude,
+                Err(e) => return Err(self.fail(e.errors, "ICE: Failed to parse the prelude")),
+            };
+            prelude_len = prelude.len();
+            krate.items.splice(0..0, prelude);
+        }
+
+        // Macro expansion stage
+        if let Err(errors) = expand::expand_crate(&mut parser, &mut krate, options.prelude_enabled) {
+            return Err(self.fail(errors, "Failed to expand macros"));
+        }
+        if emits(EmitKind::Ast) {
+            outputs.push((EmitKind::Ast, format!("{:#?}\n", &krate.items[prelude_len..])));
+        }
+
+        // crate name defaults to the file name like rustc
+        let crate_name = options.crate_name.unwrap_or_else(|| match input {
+            Input::File(path) => path
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .replace('-', "_"),
+            Input::Str(_) => "rust_out".to_string(),
+        });
+        if !crate_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            || crate_name.starts_with(|c: char| c.is_ascii_digit())
+        {
+            return Err(self.fail(vec![], &format!("Invalid crate name `{}`", crate_name)));
+        }
+
+        let mut ctx = middle::Ctxt::new(options.dump_enabled, Rc::new(crate_name.clone()), options.crate_type);
+
+        // Load extern crates
+        let mut next_file_id = parser.next_file_id();
+        for (name, path) in options.externs {
+            let res = metadata::load(&path, next_file_id)
+                .and_then(|metadata| ctx.load_extern_crate(Rc::new(name), metadata));
+            if let Err(e) = res {
+                return Err(self.fail(vec![Diagnostic::error(e)], "Failed to load extern crates"));
+            }
+            next_file_id.0 += 1;
+        }
+
+        if ctx.dump_enabled {
+            self.dumps.push(format!("{:#?}", krate));
+        }
+
+        // Name resolution stage
+        if let Err(errors) = ctx.run_resolver(&krate) {
+            return Err(self.fail(errors, "Failed to resolve names"));
+        }
+
+        if ctx.dump_enabled {
+            self.dumps.push(ctx.dump_ribs());
+        }
+
+        // Typecheck stage
+        if let Err(errors) = typeck::typeck(&mut ctx, &krate) {
+            return Err(self.fail(errors, "Failed to typecheck crate"));
+        }
+
+        if parse_failed {
+            return Err(self.fail(vec![], "Failed to parse source code"));
+        }
+
+        if ctx.dump_enabled {
+            self.dumps.push(ctx.dump_resolution());
+        }
+        if emits(EmitKind::Resolved) {
+            outputs.push((EmitKind::Resolved, emit::resolved(&mut ctx, &krate)));
+        }
+        if emits(EmitKind::TypedAst) {
+            outputs.push((EmitKind::TypedAst, emit::typed_ast(&mut ctx, &krate)));
+        }
+
+        // Unsafety check stage
+        match unsafeck::unsafeck(&mut ctx, &krate) {
+            Ok(mut warnings) => self.diagnostics.append(&mut warnings),
+            Err(diags) => return Err(self.fail(diags, "Failed to check unsafety of crate")),
+        }
+
+        // Lint stage
+        match lint::check(&mut ctx, &krate, &options.lint_levels) {
+            Ok(mut warnings) => self.diagnostics.append(&mut warnings),
+            Err(diags) => return Err(self.fail(diags, "Failed to lint crate")),
+        }
+
+        // Lvalue analysis stage
+        // lvalue::analyze(&mut ctx, &krate);
+
+        // Metadata of libraries for other crates
+        let metadata = if ctx.crate_type == CrateType::Lib {
+            Some(metadata::encode(&mut ctx, &krate))
+        } else {
+            None
+        };
+
+        // Codegen stage
+        let llvm_ir = match backend_llvm::compile(&mut ctx, &krate, options.codegen) {
+            Ok(module) => module,
+            Err(e) => return Err(self.fail(vec![*e.0], "Failed to generate code")),
+        };
+
+        Ok(Compiled {
+            crate_name,
+            crate_type: options.crate_type,
+            metadata,
+            llvm_ir,
+            outputs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile_str(src: &str) -> (Session, Result<Compiled, CompileError>) {
+        let mut sess = Session::new();
+        let options = Options {
+            prelude_enabled: false,
+            ..Options::default()
+        };
+        let result = sess.compile(&Input::Str(src.to_string()), options);
+        (sess, result)
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let (sess, result) = compile_str("fn main() -> i32 { let x = 1 + ; y }");
+        assert_eq!(result.unwrap_err().0, "Failed to typecheck crate");
+        let messages: Vec<_> = sess.diagnostics.iter().map(|diag| diag.message.as_str()).collect();
+        assert_eq!(messages, ["expected expression, found `;`", "cannot find value `y` in this scope"]);
+    }
+
+    #[test]
+    fn test_llvm_ir() {
+        let (_, result) = compile_str("fn main() -> i32 { 0 }");
+        let llvm_ir = result.unwrap().llvm_ir.to_string();
+        assert!(llvm_ir.contains("define i32 @main() {\nstart:\n\tret i32 0\n}\n"));
+    }
+
+    #[test]
+    fn test_type_errors() {
+        let (sess, result) = compile_str("fn main() -> i32 { true }");
+        assert_eq!(result.unwrap_err().0, "Failed to typecheck crate");
+        assert_eq!(sess.diagnostics[0].code, Some("E0308"));
+    }
+}
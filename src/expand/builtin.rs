@@ -0,0 +1,397 @@
+//! Built-in macros
+//!
+//! `format_args!` parses its format string at compile time into `ExprKind::FormatArgs`,
+//! whose placeholders are checked against the types of the arguments by typeck.
+//! `print!`, `println!`, `panic!` and `assert!` pass the formatted string to the runtime
+//! functions in the prelude.
+//! https://doc.rust-lang.org/std/fmt/index.html
+
+use std::rc::Rc;
+
+use crate::ast::tokenstream::flatten;
+use crate::ast::{
+    Block, Expr, ExprKind, FormatArgs, FormatPiece, FormatPlaceholder, MacCall, Path, Stmt, StmtKind,
+};
+use crate::diagnostics::Diagnostic;
+use crate::parse::Parser;
+use crate::span::{Ident, Span, SyntaxContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinMacro {
+    FormatArgs,
+    Print,
+    Println,
+    Panic,
+    Assert,
+}
+
+impl BuiltinMacro {
+    pub fn from_name(name: &str) -> Option<BuiltinMacro> {
+        match name {
+            "format_args" => Some(BuiltinMacro::FormatArgs),
+            "print" => Some(BuiltinMacro::Print),
+            "println" => Some(BuiltinMacro::Println),
+            "panic" => Some(BuiltinMacro::Panic),
+            "assert" => Some(BuiltinMacro::Assert),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinMacro::FormatArgs => "format_args",
+            BuiltinMacro::Print => "print",
+            BuiltinMacro::Println => "println",
+            BuiltinMacro::Panic => "panic",
+            BuiltinMacro::Assert => "assert",
+        }
+    }
+
+    /// Whether the expansion calls functions of the prelude
+    fn needs_prelude(&self) -> bool {
+        *self != BuiltinMacro::FormatArgs
+    }
+}
+
+/// Builds the expansion of a call to a built-in macro.
+/// Nodes created here get the span of the call in the hygiene context `ctxt`.
+pub struct BuiltinExpander<'a> {
+    pub parser: &'a mut Parser,
+    pub mac: &'a MacCall,
+    pub ctxt: SyntaxContext,
+}
+
+impl BuiltinExpander<'_> {
+    /// Fails with `None` when the error is already recorded by the parser
+    pub fn expand(&mut self, kind: BuiltinMacro, prelude_enabled: bool) -> Result<Expr, Option<Diagnostic>> {
+        if kind.needs_prelude() && !prelude_enabled {
+            return Err(Some(
+                Diagnostic::error(format!("`{}!` needs the prelude", kind.name()))
+                    .code("M0305")
+                    .span_label(&self.mac.span, "the prelude is disabled by `--no-prelude`"),
+            ));
+        }
+        let Some(mut args) = self
+            .parser
+            .parse_macro_args(flatten(&self.mac.args), self.mac.span.clone())
+        else {
+            return Err(None);
+        };
+
+        match kind {
+            BuiltinMacro::FormatArgs => Ok(self.format_args(kind, args)?),
+            BuiltinMacro::Print | BuiltinMacro::Println => {
+                // `println!()` prints an empty line
+                let fmt = if args.is_empty() && kind == BuiltinMacro::Println {
+                    self.str_lit(String::new())
+                } else {
+                    self.format_args(kind, args)?
+                };
+                let callee = if kind == BuiltinMacro::Print { "print" } else { "println" };
+                Ok(self.call(&["crate", "prelude", callee], vec![fmt]))
+            }
+            BuiltinMacro::Panic => {
+                let msg = if args.is_empty() {
+                    self.str_lit("explicit panic".to_string())
+                } else {
+                    self.format_args(kind, args)?
+                };
+                Ok(self.panic(msg))
+            }
+            BuiltinMacro::Assert => {
+                if args.is_empty() {
+                    return Err(Some(
+                        Diagnostic::error("`assert!` needs a boolean argument")
+                            .code("M0305")
+                            .span_label(&self.mac.span, "missing condition"),
+                    ));
+                }
+                let cond = args.remove(0);
+                let msg = if args.is_empty() {
+                    self.str_lit(format!("assertion failed: {}", cond.span.to_snippet()))
+                } else {
+                    self.format_args(kind, args)?
+                };
+                // if (cond) {} else { panic }
+                let then = self.block(vec![]);
+                let panic = self.panic(msg);
+                let panic_stmt = self.stmt(StmtKind::Semi(Box::new(panic)));
+                let els = self.block(vec![panic_stmt]);
+                Ok(self.expr(ExprKind::If(Box::new(cond), Box::new(then), Some(Box::new(els)))))
+            }
+        }
+    }
+
+    /// `format_args!(fmt, args...)`
+    fn format_args(&mut self, kind: BuiltinMacro, mut args: Vec<Expr>) -> Result<Expr, Diagnostic> {
+        if args.is_empty() {
+            return Err(
+                Diagnostic::error(format!("`{}!` needs a format string argument", kind.name()))
+                    .code("M0305")
+                    .span_label(&self.mac.span, "missing format string"),
+            );
+        }
+        let fmt = args.remove(0);
+        let mut parser = FormatParser::new(&fmt)?;
+        let pieces = parser.parse(self.parser, &args)?;
+        let mut used = vec![false; args.len()];
+        for piece in &pieces {
+            if let FormatPiece::Placeholder(p) = piece {
+                if p.arg < args.len() {
+                    used[p.arg] = true;
+                }
+            }
+        }
+        if let Some(i) = used.iter().position(|used| !used) {
+            return Err(Diagnostic::error("argument never used")
+                .code("M0304")
+                .span_label(&args[i].span, "argument never used")
+                .secondary_label(&fmt.span, "formatting specifier missing"));
+        }
+        args.extend(parser.captures);
+        Ok(self.expr(ExprKind::FormatArgs(FormatArgs { pieces, args })))
+    }
+
+    /// `crate::prelude::rt::begin_panic(msg, "file:line:col")`
+    fn panic(&mut self, msg: Expr) -> Expr {
+        let location = self.str_lit(self.mac.span.location());
+        self.call(&["crate", "prelude", "rt", "begin_panic"], vec![msg, location])
+    }
+
+    fn span(&self) -> Span {
+        self.mac.span.with_ctxt(self.ctxt)
+    }
+
+    fn expr(&mut self, kind: ExprKind) -> Expr {
+        Expr {
+            kind,
+            id: self.parser.get_next_id(),
+            span: self.span(),
+        }
+    }
+
+    fn str_lit(&mut self, s: String) -> Expr {
+        self.expr(ExprKind::StrLit(s))
+    }
+
+    fn call(&mut self, path: &[&str], args: Vec<Expr>) -> Expr {
+        let segments = path
+            .iter()
+            .map(|seg| Ident {
+                symbol: Rc::new(seg.to_string()),
+                span: self.span(),
+            })
+            .collect();
+        let callee = self.expr(ExprKind::Path(Path {
+            segments,
+            span: self.span(),
+        }));
+        self.expr(ExprKind::Call(Box::new(callee), args))
+    }
+
+    fn stmt(&mut self, kind: StmtKind) -> Stmt {
+        Stmt {
+            kind,
+            id: self.parser.get_next_id(),
+            span: self.span(),
+        }
+    }
+
+    fn block(&mut self, stmts: Vec<Stmt>) -> Expr {
+        let block = Block {
+            stmts,
+            span: self.span(),
+            id: self.parser.get_next_id(),
+            is_unsafe: false,
+        };
+        self.expr(ExprKind::Block(block))
+    }
+}
+
+/// Parser of format strings like `"{} and {:?}"`.
+/// Works on the source of the literal so that errors can point inside it.
+struct FormatParser {
+    /// characters of the string with escapes resolved, and their ranges in the source
+    chars: Vec<(char, usize, usize)>,
+    /// span of the literal
+    lit: Span,
+    /// `{name}` placeholders capturing variables
+    captures: Vec<Expr>,
+    capture_names: Vec<Rc<String>>,
+}
+
+impl FormatParser {
+    fn new(fmt: &Expr) -> Result<FormatParser, Diagnostic> {
+        if !matches!(fmt.kind, ExprKind::StrLit(_)) {
+            return Err(Diagnostic::error("format argument must be a string literal")
+                .code("M0304")
+                .span_label(&fmt.span, "not a string literal"));
+        }
+        let src = &fmt.span.file().src;
+        // the literal may be wrapped by parentheses of an `expr` fragment
+        let start = fmt.span.lo() + src[fmt.span.lo()..fmt.span.hi()].find('"').unwrap() + 1;
+        let mut chars = vec![];
+        let mut iter = src[start..].char_indices().map(|(i, c)| (i + start, c));
+        while let Some((i, c)) = iter.next() {
+            match c {
+                '"' => break,
+                '\\' => {
+                    let (j, escaped) = iter.next().unwrap();
+                    let c = match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        c => c,
+                    };
+                    chars.push((c, i, j + escaped.len_utf8()));
+                }
+                c => chars.push((c, i, i + c.len_utf8())),
+            }
+        }
+        Ok(FormatParser {
+            chars,
+            lit: fmt.span.clone(),
+            captures: vec![],
+            capture_names: vec![],
+        })
+    }
+
+    /// Span of `chars[lo..hi]`
+    fn span(&self, lo: usize, hi: usize) -> Span {
+        let lo = self.chars[lo].1;
+        let hi = self.chars[hi - 1].2;
+        Span::new(lo, hi, Rc::clone(self.lit.file())).with_ctxt(self.lit.ctxt())
+    }
+
+    /// format ::= (text | "{{" | "}}" | "{" argument? (":" "?")? "}")*
+    /// argument ::= integer | identifier
+    fn parse(&mut self, parser: &mut Parser, args: &[Expr]) -> Result<Vec<FormatPiece>, Diagnostic> {
+        let mut pieces = vec![];
+        let mut text = String::new();
+        let mut next_arg = 0;
+        let mut i = 0;
+        while i < self.chars.len() {
+            let c = self.chars[i].0;
+            let next = self.chars.get(i + 1).map(|c| c.0);
+            if (c == '{' && next == Some('{')) || (c == '}' && next == Some('}')) {
+                text.push(c);
+                i += 2;
+                continue;
+            }
+            if c == '}' {
+                return Err(Diagnostic::error("invalid format string: unmatched `}` found")
+                    .code("M0304")
+                    .span_label(&self.span(i, i + 1), "unmatched `}` in format string")
+                    .note("if you intended to print `}`, you can escape it using `}}`"));
+            }
+            if c != '{' {
+                text.push(c);
+                i += 1;
+                continue;
+            }
+
+            let Some(close) = (i..self.chars.len()).find(|&j| self.chars[j].0 == '}') else {
+                return Err(Diagnostic::error("invalid format string: expected `}` but string was terminated")
+                    .code("M0304")
+                    .span_label(&self.span(i, i + 1), "because of this opening brace")
+                    .note("if you intended to print `{`, you can escape it using `{{`"));
+            };
+            let span = self.span(i, close + 1);
+            let inner: String = self.chars[i + 1..close].iter().map(|c| c.0).collect();
+            let (name, spec) = match inner.split_once(':') {
+                Some((name, spec)) => (name, Some(spec)),
+                None => (inner.as_str(), None),
+            };
+            let debug = match spec {
+                None | Some("") => false,
+                Some("?") => true,
+                Some(spec) => {
+                    return Err(Diagnostic::error(format!("unknown format spec `{}`", spec))
+                        .code("M0304")
+                        .span_label(&span, "unknown format spec")
+                        .note("only `{}` and `{:?}` are supported"))
+                }
+            };
+            let arg = if name.is_empty() {
+                next_arg += 1;
+                if next_arg > args.len() {
+                    return Err(Diagnostic::error(format!(
+                        "{} positional argument{} in format string, but {}",
+                        next_arg,
+                        if next_arg == 1 { "" } else { "s" },
+                        num_arguments(args.len())
+                    ))
+                    .code("M0304")
+                    .span_label(&span, "no argument for this placeholder"));
+                }
+                next_arg - 1
+            } else if let Ok(index) = name.parse::<usize>() {
+                if index >= args.len() {
+                    return Err(Diagnostic::error(format!(
+                        "invalid reference to positional argument {} ({})",
+                        index,
+                        num_arguments(args.len())
+                    ))
+                    .code("M0304")
+                    .span_label(&span, "no such argument"));
+                }
+                index
+            } else if is_ident(name) {
+                let name_span = self.span(i + 1, i + 1 + name.chars().count());
+                args.len() + self.capture(parser, name, name_span)
+            } else {
+                return Err(Diagnostic::error(format!(
+                    "invalid format string: invalid argument name `{}`",
+                    name
+                ))
+                .code("M0304")
+                .span_label(&span, "invalid argument name"));
+            };
+
+            if !text.is_empty() {
+                pieces.push(FormatPiece::Lit(std::mem::take(&mut text)));
+            }
+            pieces.push(FormatPiece::Placeholder(FormatPlaceholder { arg, debug, span }));
+            i = close + 1;
+        }
+        if !text.is_empty() {
+            pieces.push(FormatPiece::Lit(text));
+        }
+        Ok(pieces)
+    }
+
+    /// Index of the variable captured by `{name}` in `captures`
+    fn capture(&mut self, parser: &mut Parser, name: &str, span: Span) -> usize {
+        if let Some(i) = self.capture_names.iter().position(|n| **n == name) {
+            return i;
+        }
+        let ident = Ident {
+            symbol: Rc::new(name.to_string()),
+            span: span.clone(),
+        };
+        self.capture_names.push(Rc::clone(&ident.symbol));
+        self.captures.push(Expr {
+            kind: ExprKind::Path(Path {
+                segments: vec![ident],
+                span: span.clone(),
+            }),
+            id: parser.get_next_id(),
+            span,
+        });
+        self.captures.len() - 1
+    }
+}
+
+fn num_arguments(n: usize) -> String {
+    match n {
+        0 => "no arguments were given".to_string(),
+        1 => "there is 1 argument".to_string(),
+        n => format!("there are {} arguments", n),
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
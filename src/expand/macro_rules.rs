@@ -0,0 +1,573 @@
+//! `macro_rules!` definitions
+//!
+//! A definition is compiled into rules, each of which is a matcher and a transcriber.
+//! A call is expanded by the first rule whose matcher matches its arguments:
+//! the fragments bound to macro variables are substituted into the transcriber.
+//! https://doc.rust-lang.org/reference/macros-by-example.html
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::tokenstream::{Delimited, Delimiter, FragmentKind, TokenTree};
+use crate::ast::{MacCall, MacroRulesDef};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{BinOp, Token, TokenKind};
+use crate::parse::{can_begin_fragment, Parser};
+use crate::span::{Ident, Span, SyntaxContext};
+
+#[derive(Debug)]
+pub struct MacroRules {
+    pub name: Rc<String>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    matcher: Vec<MacroTree>,
+    transcriber: Vec<MacroTree>,
+}
+
+/// Token tree of a matcher or a transcriber
+#[derive(Debug)]
+enum MacroTree {
+    Token(Token),
+    Delimited(Delimiter, Span, Span, Vec<MacroTree>),
+    /// `$name:kind`, only in matchers
+    MetaVarDecl(Ident, FragmentKind),
+    /// `$name`, only in transcribers
+    MetaVar(Ident),
+    /// `$( ... ) sep? op`
+    Repetition(Repetition),
+}
+
+#[derive(Debug)]
+struct Repetition {
+    tts: Vec<MacroTree>,
+    separator: Option<Token>,
+    op: RepOp,
+    span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RepOp {
+    /// `*`
+    ZeroOrMore,
+    /// `+`
+    OneOrMore,
+    /// `?`
+    ZeroOrOne,
+}
+
+/// Fragments bound to a macro variable
+#[derive(Debug)]
+enum NamedMatch {
+    Leaf(Vec<TokenTree>, FragmentKind),
+    /// one match for each repetition
+    Seq(Vec<NamedMatch>),
+}
+
+type Bindings = HashMap<Rc<String>, NamedMatch>;
+
+impl MacroRules {
+    /// macroRulesBody ::= rule (";" rule)* ";"?
+    /// rule ::= delimTokenTree "=>" delimTokenTree
+    pub fn compile(def: &MacroRulesDef) -> Result<MacroRules, Diagnostic> {
+        let mut rules = vec![];
+        let mut tts = def.body.iter();
+        while let Some(tt) = tts.next() {
+            let TokenTree::Delimited(matcher) = tt else {
+                return Err(Diagnostic::error(format!(
+                    "expected a rule like `(...) => {{ ... }}` in macro `{}`, found `{}`",
+                    def.name.symbol,
+                    tt.span().to_snippet()
+                ))
+                .code("M0006")
+                .span_label(&tt.span(), "expected a rule"));
+            };
+            let Some(TokenTree::Token(Token {
+                kind: TokenKind::FatArrow,
+                ..
+            })) = tts.next()
+            else {
+                return Err(Diagnostic::error("expected `=>` after the matcher")
+                    .code("M0006")
+                    .span_label(&matcher.close, "expected `=>` after this"));
+            };
+            let Some(TokenTree::Delimited(transcriber)) = tts.next() else {
+                return Err(Diagnostic::error("expected a transcriber after `=>`")
+                    .code("M0006")
+                    .span_label(&matcher.open, "in this rule"));
+            };
+            let matcher = parse_macro_trees(&matcher.tts, true)?;
+            check_duplicate_bindings(&matcher, &mut HashMap::new())?;
+            rules.push(Rule {
+                matcher,
+                transcriber: parse_macro_trees(&transcriber.tts, false)?,
+            });
+
+            match tts.next() {
+                None => (),
+                Some(TokenTree::Token(Token {
+                    kind: TokenKind::Semi,
+                    ..
+                })) => (),
+                Some(tt) => {
+                    return Err(Diagnostic::error(format!(
+                        "expected `;` between rules of macro `{}`, found `{}`",
+                        def.name.symbol,
+                        tt.span().to_snippet()
+                    ))
+                    .code("M0006")
+                    .span_label(&tt.span(), "expected `;`"))
+                }
+            }
+        }
+        if rules.is_empty() {
+            return Err(Diagnostic::error(format!("macro `{}` has no rules", def.name.symbol))
+                .code("M0006")
+                .span_label(&def.span, "no rules"));
+        }
+        Ok(MacroRules {
+            name: Rc::clone(&def.name.symbol),
+            rules,
+        })
+    }
+
+    /// Expand `mac` by the first matching rule.
+    /// Tokens written in the transcriber get the hygiene context `ctxt`.
+    pub fn expand(&self, parser: &mut Parser, mac: &MacCall, ctxt: SyntaxContext) -> Result<Vec<TokenTree>, Diagnostic> {
+        for rule in &self.rules {
+            let mut bindings = HashMap::new();
+            let mut pos = 0;
+            let matched = Matcher {
+                parser: &mut *parser,
+                eof: mac.span.clone(),
+            }
+            .match_trees(&rule.matcher, &mac.args, &mut pos, &mut bindings)?;
+            if matched && pos == mac.args.len() {
+                let mut out = vec![];
+                transcribe(&rule.transcriber, &bindings, &mut vec![], ctxt, &mut out)?;
+                return Ok(out);
+            }
+        }
+        Err(Diagnostic::error(format!("no rules of macro `{}` matched this call", self.name))
+            .code("M0301")
+            .span_label(&mac.span, "no rules expected this input"))
+    }
+}
+
+/// Parse `$` forms in the matcher or the transcriber of a rule
+fn parse_macro_trees(tts: &[TokenTree], is_matcher: bool) -> Result<Vec<MacroTree>, Diagnostic> {
+    let mut trees = vec![];
+    let mut iter = tts.iter().peekable();
+    while let Some(tt) = iter.next() {
+        let dollar = match tt {
+            TokenTree::Token(t) if t.kind == TokenKind::Dollar => t,
+            TokenTree::Token(t) => {
+                trees.push(MacroTree::Token(t.clone()));
+                continue;
+            }
+            TokenTree::Delimited(d) => {
+                let inner = parse_macro_trees(&d.tts, is_matcher)?;
+                trees.push(MacroTree::Delimited(d.delim, d.open.clone(), d.close.clone(), inner));
+                continue;
+            }
+        };
+        match iter.next() {
+            Some(TokenTree::Token(Token {
+                kind: TokenKind::Ident(name),
+                span,
+            })) => {
+                let ident = Ident {
+                    symbol: Rc::new(name.clone()),
+                    span: span.clone(),
+                };
+                if !is_matcher {
+                    trees.push(MacroTree::MetaVar(ident));
+                    continue;
+                }
+                let kind = match (iter.next(), iter.next()) {
+                    (
+                        Some(TokenTree::Token(Token {
+                            kind: TokenKind::Colon,
+                            ..
+                        })),
+                        Some(TokenTree::Token(Token {
+                            kind: TokenKind::Ident(kind),
+                            span,
+                        })),
+                    ) => FragmentKind::from_name(kind).ok_or_else(|| {
+                        Diagnostic::error(format!("invalid fragment specifier `{}`", kind))
+                            .code("M0006")
+                            .span_label(span, "invalid fragment specifier")
+                            .note("valid fragment specifiers are `expr`, `ident`, `ty`, `tt`, `literal`, `block`, `path` and `item`")
+                    })?,
+                    _ => {
+                        return Err(Diagnostic::error(format!("missing fragment specifier of `${}`", name))
+                            .code("M0006")
+                            .span_label(span, "missing fragment specifier"))
+                    }
+                };
+                trees.push(MacroTree::MetaVarDecl(ident, kind));
+            }
+            Some(TokenTree::Delimited(d)) if d.delim == Delimiter::Paren => {
+                let inner = parse_macro_trees(&d.tts, is_matcher)?;
+                let (separator, op) = parse_repetition_op(&mut iter, d)?;
+                trees.push(MacroTree::Repetition(Repetition {
+                    tts: inner,
+                    separator,
+                    op,
+                    span: dollar.span.concat(&d.close),
+                }));
+            }
+            _ => {
+                return Err(Diagnostic::error("expected an identifier or `(` after `$`")
+                    .code("M0006")
+                    .span_label(&dollar.span, "expected an identifier or `(` after this"))
+            }
+        }
+    }
+    Ok(trees)
+}
+
+/// repetitionOp ::= separator? ("*" | "+" | "?")
+fn parse_repetition_op<'a>(
+    iter: &mut impl Iterator<Item = &'a TokenTree>,
+    d: &Delimited,
+) -> Result<(Option<Token>, RepOp), Diagnostic> {
+    fn to_op(t: &Token) -> Option<RepOp> {
+        match t.kind {
+            TokenKind::BinOp(BinOp::Star) => Some(RepOp::ZeroOrMore),
+            TokenKind::BinOp(BinOp::Plus) => Some(RepOp::OneOrMore),
+            TokenKind::Question => Some(RepOp::ZeroOrOne),
+            _ => None,
+        }
+    }
+    let err = || {
+        Diagnostic::error("expected one of `*`, `+` or `?` after the repetition")
+            .code("M0006")
+            .span_label(&d.close, "expected one of `*`, `+` or `?` after this")
+    };
+    let Some(TokenTree::Token(t)) = iter.next() else {
+        return Err(err());
+    };
+    if let Some(op) = to_op(t) {
+        return Ok((None, op));
+    }
+    let Some(TokenTree::Token(op)) = iter.next() else {
+        return Err(err());
+    };
+    match to_op(op) {
+        Some(RepOp::ZeroOrOne) => Err(Diagnostic::error("the `?` repetition cannot have a separator")
+            .code("M0006")
+            .span_label(&t.span, "separator")),
+        Some(op) => Ok((Some(t.clone()), op)),
+        None => Err(err()),
+    }
+}
+
+fn check_duplicate_bindings(trees: &[MacroTree], seen: &mut HashMap<Rc<String>, Span>) -> Result<(), Diagnostic> {
+    for tree in trees {
+        match tree {
+            MacroTree::MetaVarDecl(ident, _) => {
+                if let Some(prev) = seen.insert(Rc::clone(&ident.symbol), ident.span.clone()) {
+                    return Err(Diagnostic::error(format!("duplicate matcher binding `${}`", ident.symbol))
+                        .code("M0006")
+                        .span_label(&ident.span, "duplicate binding")
+                        .secondary_label(&prev, "previous binding"));
+                }
+            }
+            MacroTree::Delimited(_, _, _, inner) => check_duplicate_bindings(inner, seen)?,
+            MacroTree::Repetition(rep) => check_duplicate_bindings(&rep.tts, seen)?,
+            MacroTree::Token(_) | MacroTree::MetaVar(_) => (),
+        }
+    }
+    Ok(())
+}
+
+/// Names of the macro variables declared or used in `trees`
+fn meta_vars(trees: &[MacroTree], names: &mut Vec<Ident>) {
+    for tree in trees {
+        match tree {
+            MacroTree::MetaVarDecl(ident, _) | MacroTree::MetaVar(ident) => names.push(ident.clone()),
+            MacroTree::Delimited(_, _, _, inner) => meta_vars(inner, names),
+            MacroTree::Repetition(rep) => meta_vars(&rep.tts, names),
+            MacroTree::Token(_) => (),
+        }
+    }
+}
+
+/// Matches the arguments of a call with a matcher.
+/// Repetitions and fragments are matched greedily without backtracking.
+struct Matcher<'a> {
+    parser: &'a mut Parser,
+    /// end of the arguments, where the parser reports an unexpected end
+    eof: Span,
+}
+
+impl Matcher<'_> {
+    /// Returns whether `trees` match the input from `pos`, and advances `pos`.
+    /// Fragments which start well but fail to parse are errors.
+    fn match_trees(
+        &mut self,
+        trees: &[MacroTree],
+        input: &[TokenTree],
+        pos: &mut usize,
+        bindings: &mut Bindings,
+    ) -> Result<bool, Diagnostic> {
+        for tree in trees {
+            if !self.match_tree(tree, input, pos, bindings)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn match_tree(
+        &mut self,
+        tree: &MacroTree,
+        input: &[TokenTree],
+        pos: &mut usize,
+        bindings: &mut Bindings,
+    ) -> Result<bool, Diagnostic> {
+        match tree {
+            MacroTree::Token(expected) => match input.get(*pos) {
+                Some(TokenTree::Token(t)) if t.kind == expected.kind => {
+                    *pos += 1;
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+            MacroTree::Delimited(delim, _, _, inner) => match input.get(*pos) {
+                Some(TokenTree::Delimited(d)) if d.delim == *delim => {
+                    let mut inner_pos = 0;
+                    let matched = self.match_trees(inner, &d.tts, &mut inner_pos, bindings)?;
+                    *pos += 1;
+                    Ok(matched && inner_pos == d.tts.len())
+                }
+                _ => Ok(false),
+            },
+            MacroTree::MetaVarDecl(ident, kind) => {
+                let Some(n) = self.match_fragment(*kind, &input[*pos..])? else {
+                    return Ok(false);
+                };
+                let fragment = input[*pos..*pos + n].to_vec();
+                bindings.insert(Rc::clone(&ident.symbol), NamedMatch::Leaf(fragment, *kind));
+                *pos += n;
+                Ok(true)
+            }
+            MacroTree::Repetition(rep) => self.match_repetition(rep, input, pos, bindings),
+            MacroTree::MetaVar(_) => unreachable!("ICE: `$x` in a matcher"),
+        }
+    }
+
+    /// Number of token trees of the fragment at the start of `input`,
+    /// or `None` if it cannot start there
+    fn match_fragment(&mut self, kind: FragmentKind, input: &[TokenTree]) -> Result<Option<usize>, Diagnostic> {
+        let Some(first) = input.first() else {
+            return Ok(None);
+        };
+        let first_token = match first {
+            TokenTree::Token(t) => t.clone(),
+            TokenTree::Delimited(d) => Token {
+                kind: d.delim.open_token(),
+                span: d.open.clone(),
+            },
+        };
+        if !can_begin_fragment(kind, &first_token) {
+            return Ok(None);
+        }
+        match kind {
+            FragmentKind::Tt | FragmentKind::Ident => Ok(Some(1)),
+            FragmentKind::Literal => {
+                if first_token.kind != TokenKind::BinOp(BinOp::Minus) {
+                    return Ok(Some(1));
+                }
+                // negative number
+                match input.get(1) {
+                    Some(TokenTree::Token(Token {
+                        kind: TokenKind::NumLit(_),
+                        ..
+                    })) => Ok(Some(2)),
+                    _ => Ok(None),
+                }
+            }
+            _ => match self.parser.parse_fragment(kind, input, self.eof.clone()) {
+                Some(n) => Ok(Some(n)),
+                None => Err(Diagnostic::error(format!("could not parse `{}` fragment", kind.name()))
+                    .code("M0301")
+                    .span_label(&first.span(), format!("expected `{}`", kind.name()))),
+            },
+        }
+    }
+
+    fn match_repetition(
+        &mut self,
+        rep: &Repetition,
+        input: &[TokenTree],
+        pos: &mut usize,
+        bindings: &mut Bindings,
+    ) -> Result<bool, Diagnostic> {
+        let mut matches: Vec<Bindings> = vec![];
+        loop {
+            if rep.op == RepOp::ZeroOrOne && matches.len() == 1 {
+                break;
+            }
+            let mut next_pos = *pos;
+            if let Some(sep) = &rep.separator {
+                if !matches.is_empty() {
+                    match input.get(next_pos) {
+                        Some(TokenTree::Token(t)) if t.kind == sep.kind => next_pos += 1,
+                        _ => break,
+                    }
+                }
+            }
+            let mut iteration = HashMap::new();
+            if !self.match_trees(&rep.tts, input, &mut next_pos, &mut iteration)? || next_pos == *pos {
+                break;
+            }
+            *pos = next_pos;
+            matches.push(iteration);
+        }
+        if rep.op == RepOp::OneOrMore && matches.is_empty() {
+            return Ok(false);
+        }
+
+        let mut names = vec![];
+        meta_vars(&rep.tts, &mut names);
+        for name in names {
+            let seq = matches
+                .iter_mut()
+                .filter_map(|m| m.remove(&name.symbol))
+                .collect();
+            bindings.insert(name.symbol, NamedMatch::Seq(seq));
+        }
+        Ok(true)
+    }
+}
+
+/// Substitute fragments into `trees`.
+/// `repeats` is the index of the current iteration of each enclosing repetition.
+fn transcribe(
+    trees: &[MacroTree],
+    bindings: &Bindings,
+    repeats: &mut Vec<usize>,
+    ctxt: SyntaxContext,
+    out: &mut Vec<TokenTree>,
+) -> Result<(), Diagnostic> {
+    for tree in trees {
+        match tree {
+            MacroTree::Token(t) => out.push(TokenTree::Token(Token {
+                kind: t.kind.clone(),
+                span: t.span.with_ctxt(ctxt),
+            })),
+            MacroTree::Delimited(delim, open, close, inner) => {
+                let mut tts = vec![];
+                transcribe(inner, bindings, repeats, ctxt, &mut tts)?;
+                out.push(TokenTree::Delimited(Delimited {
+                    delim: *delim,
+                    open: open.with_ctxt(ctxt),
+                    close: close.with_ctxt(ctxt),
+                    tts,
+                }));
+            }
+            MacroTree::MetaVar(ident) => {
+                let Some(matched) = lookup(bindings, ident, repeats) else {
+                    return Err(Diagnostic::error(format!("unknown macro variable `${}`", ident.symbol))
+                        .code("M0303")
+                        .span_label(&ident.span, "unknown macro variable"));
+                };
+                match matched {
+                    // keep precedence of the expression, like rustc does with invisible delimiters
+                    NamedMatch::Leaf(tts, FragmentKind::Expr) => {
+                        out.push(TokenTree::Delimited(Delimited {
+                            delim: Delimiter::Paren,
+                            open: tts.first().unwrap().span(),
+                            close: tts.last().unwrap().span(),
+                            tts: tts.clone(),
+                        }))
+                    }
+                    // fragments keep the hygiene context of the caller
+                    NamedMatch::Leaf(tts, _) => out.extend(tts.iter().cloned()),
+                    NamedMatch::Seq(_) => {
+                        return Err(Diagnostic::error(format!(
+                            "variable `${}` is still repeating at this depth",
+                            ident.symbol
+                        ))
+                        .code("M0303")
+                        .span_label(&ident.span, "still repeating"))
+                    }
+                }
+            }
+            MacroTree::Repetition(rep) => {
+                let count = repetition_count(rep, bindings, repeats)?;
+                for i in 0..count {
+                    if i > 0 {
+                        if let Some(sep) = &rep.separator {
+                            out.push(TokenTree::Token(Token {
+                                kind: sep.kind.clone(),
+                                span: sep.span.with_ctxt(ctxt),
+                            }));
+                        }
+                    }
+                    repeats.push(i);
+                    transcribe(&rep.tts, bindings, repeats, ctxt, out)?;
+                    repeats.pop();
+                }
+            }
+            MacroTree::MetaVarDecl(ident, _) => {
+                return Err(Diagnostic::error("fragment specifiers are only allowed in matchers")
+                    .code("M0006")
+                    .span_label(&ident.span, "fragment specifier"))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Match of the variable in the current iterations of repetitions
+fn lookup<'a>(bindings: &'a Bindings, ident: &Ident, repeats: &[usize]) -> Option<&'a NamedMatch> {
+    let mut matched = bindings.get(&ident.symbol)?;
+    for &i in repeats {
+        match matched {
+            NamedMatch::Seq(seq) => matched = &seq[i],
+            // variables which do not repeat can be used in any repetition
+            NamedMatch::Leaf(_, _) => break,
+        }
+    }
+    Some(matched)
+}
+
+/// How many times `rep` repeats, which is decided by the repeating variables in it
+fn repetition_count(rep: &Repetition, bindings: &Bindings, repeats: &[usize]) -> Result<usize, Diagnostic> {
+    let mut names = vec![];
+    meta_vars(&rep.tts, &mut names);
+    let mut count: Option<(usize, &Ident)> = None;
+    for name in &names {
+        let Some(NamedMatch::Seq(seq)) = lookup(bindings, name, repeats) else {
+            continue;
+        };
+        match count {
+            Some((n, prev)) if n != seq.len() => {
+                return Err(Diagnostic::error(format!(
+                    "meta-variable `${}` repeats {} times, but `${}` repeats {} times",
+                    prev.symbol,
+                    n,
+                    name.symbol,
+                    seq.len()
+                ))
+                .code("M0303")
+                .span_label(&rep.span, "mismatched repetitions"))
+            }
+            _ => count = Some((seq.len(), name)),
+        }
+    }
+    match count {
+        Some((n, _)) => Ok(n),
+        None => Err(Diagnostic::error(
+            "attempted to repeat an expression containing no syntax variables matched as repeating at this depth",
+        )
+        .code("M0303")
+        .span_label(&rep.span, "no repeating variables")),
+    }
+}
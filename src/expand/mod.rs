@@ -0,0 +1,399 @@
+//! Macro expansion
+//!
+//! Runs between parsing and name resolution, and replaces macro calls in items,
+//! statements and expressions by the code produced by the macros.
+//!
+//! `macro_rules!` definitions are textually scoped like rustc: a macro can be used after its
+//! definition in the same module and in modules declared after it, but not outside the module.
+//!
+//! Hygiene: each expansion gets a fresh `SyntaxContext` which is attached to the tokens written in
+//! the macro, so local variables they introduce do not capture or shadow names of the caller.
+//! Items are not hygienic.
+//!
+//! Built-in macros such as `println!` are used when no `macro_rules!` of the same name is in scope.
+
+mod builtin;
+mod macro_rules;
+
+use std::rc::Rc;
+
+use self::builtin::{BuiltinExpander, BuiltinMacro};
+use self::macro_rules::MacroRules;
+use crate::ast::tokenstream::flatten;
+use crate::ast::{Block, Crate, Expr, ExprKind, Item, ItemKind, MacCall, Stmt, StmtKind, Ty, TyKind};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Token;
+use crate::parse::Parser;
+use crate::span::{Span, SyntaxContext};
+
+/// Macros calling themselves deeper than this are considered infinitely recursive
+const RECURSION_LIMIT: usize = 128;
+
+pub fn expand_crate(parser: &mut Parser, krate: &mut Crate, prelude_enabled: bool) -> Result<(), Vec<Diagnostic>> {
+    let mut expander = Expander {
+        parser,
+        prelude_enabled,
+        macros: vec![],
+        next_ctxt: 1,
+        depth: 0,
+        errors: vec![],
+    };
+    expander.expand_items(&mut krate.items);
+    if expander.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(expander.errors)
+    }
+}
+
+struct Expander<'a> {
+    parser: &'a mut Parser,
+    /// built-in macros other than `format_args!` call functions of the prelude
+    prelude_enabled: bool,
+    /// macros in scope, in order of definition
+    macros: Vec<Rc<MacroRules>>,
+    next_ctxt: u32,
+    /// depth of nested expansions
+    depth: usize,
+    errors: Vec<Diagnostic>,
+}
+
+impl Expander<'_> {
+    fn expand_items(&mut self, items: &mut Vec<Item>) {
+        let mut i = 0;
+        while i < items.len() {
+            if let ItemKind::MacCall(_) = &items[i].kind {
+                let ItemKind::MacCall(mac) = items.remove(i).kind else {
+                    unreachable!()
+                };
+                if let Some(builtin) = self.find_builtin(&mac) {
+                    self.errors.push(
+                        Diagnostic::error(format!("`{}!` cannot be used as an item", builtin.name()))
+                            .code("M0305")
+                            .span_label(&mac.span, "used as an item"),
+                    );
+                    continue;
+                }
+                let mut expanded = self
+                    .expand_mac_call(&mac, |p, tokens, eof| p.parse_items_expansion(tokens, eof))
+                    .unwrap_or_default();
+                self.enter(|e| e.expand_items(&mut expanded));
+                let n = expanded.len();
+                items.splice(i..i, expanded);
+                i += n;
+                continue;
+            }
+            self.expand_item(&mut items[i]);
+            i += 1;
+        }
+    }
+
+    fn expand_item(&mut self, item: &mut Item) {
+        match &mut item.kind {
+            ItemKind::Func(func) => {
+                for (_, ty) in &mut func.params {
+                    self.expand_ty(ty);
+                }
+                self.expand_ty(&mut func.ret_ty);
+                if let Some(body) = &mut func.body {
+                    self.expand_block(body);
+                }
+            }
+            ItemKind::Struct(strct) => {
+                for field in &mut strct.fields {
+                    self.expand_ty(&mut field.ty);
+                }
+            }
+            ItemKind::Mod(module) => {
+                // macros defined in a module are not visible outside of it
+                let num_macros = self.macros.len();
+                self.expand_items(&mut module.items);
+                self.macros.truncate(num_macros);
+            }
+            ItemKind::Impl(implements) => {
+                for func in &mut implements.methods {
+                    if let Some(body) = &mut func.body {
+                        self.expand_block(body);
+                    }
+                }
+            }
+            ItemKind::TypeAlias(alias) => self.expand_ty(&mut alias.aliasof),
+            ItemKind::Const(const_item) => {
+                self.expand_ty(&mut const_item.ty);
+                self.expand_expr(&mut const_item.init);
+            }
+            ItemKind::Static(static_item) => {
+                self.expand_ty(&mut static_item.ty);
+                self.expand_expr(&mut static_item.init);
+            }
+            ItemKind::MacroRules(def) => match MacroRules::compile(def) {
+                Ok(mac) => self.macros.push(Rc::new(mac)),
+                Err(e) => self.errors.push(e),
+            },
+            ItemKind::ExternBlock(_) | ItemKind::Use(_) | ItemKind::ExternCrate(_) | ItemKind::Err => (),
+            ItemKind::MacCall(_) => unreachable!(),
+        }
+    }
+
+    fn expand_block(&mut self, block: &mut Block) {
+        self.expand_stmts(&mut block.stmts);
+    }
+
+    fn expand_stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        let mut i = 0;
+        while i < stmts.len() {
+            // built-in macros expand to an expression in any position
+            let is_mac_call = match &stmts[i].kind {
+                StmtKind::Expr(expr) | StmtKind::Semi(expr) => {
+                    matches!(&expr.kind, ExprKind::MacCall(mac) if self.find_builtin(mac).is_none())
+                }
+                StmtKind::Let(_) => false,
+            };
+            if !is_mac_call {
+                self.expand_stmt(&mut stmts[i]);
+                i += 1;
+                continue;
+            }
+
+            let (expr, has_semi) = match stmts.remove(i).kind {
+                StmtKind::Expr(expr) => (expr, false),
+                StmtKind::Semi(expr) => (expr, true),
+                StmtKind::Let(_) => unreachable!(),
+            };
+            let ExprKind::MacCall(mac) = expr.kind else {
+                unreachable!()
+            };
+            let mut expanded = self
+                .expand_mac_call(&mac, |p, tokens, eof| p.parse_stmts_expansion(tokens, eof))
+                .unwrap_or_default();
+            // `m!(...);` discards the value of the last expression
+            if has_semi && matches!(expanded.last(), Some(Stmt { kind: StmtKind::Expr(_), .. })) {
+                let last = expanded.pop().unwrap();
+                let StmtKind::Expr(e) = last.kind else {
+                    unreachable!()
+                };
+                expanded.push(Stmt {
+                    kind: StmtKind::Semi(e),
+                    ..last
+                });
+            }
+            self.enter(|e| e.expand_stmts(&mut expanded));
+            let n = expanded.len();
+            stmts.splice(i..i, expanded);
+            i += n;
+        }
+    }
+
+    fn expand_stmt(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            StmtKind::Expr(expr) | StmtKind::Semi(expr) => self.expand_expr(expr),
+            StmtKind::Let(let_stmt) => {
+                if let Some(ty) = &mut let_stmt.ty {
+                    self.expand_ty(ty);
+                }
+                if let Some(init) = &mut let_stmt.init {
+                    self.expand_expr(init);
+                }
+            }
+        }
+    }
+
+    fn expand_expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::MacCall(mac) => {
+                let expanded = match self.find_builtin(mac) {
+                    Some(builtin) => self.expand_builtin(builtin, mac),
+                    None => self.expand_mac_call(mac, |p, tokens, eof| p.parse_expr_expansion(tokens, eof)),
+                };
+                let Some(mut expanded) = expanded else {
+                    return;
+                };
+                self.enter(|e| e.expand_expr(&mut expanded));
+                // errors in the expression point to the call
+                expanded.span = mac.span.clone();
+                *expr = expanded;
+            }
+            ExprKind::NumLit(_) | ExprKind::BoolLit(_) | ExprKind::StrLit(_) | ExprKind::Unit | ExprKind::Err => (),
+            ExprKind::Path(_) | ExprKind::Deref(_) => (),
+            ExprKind::Binary(_, l, r)
+            | ExprKind::Assign(l, r)
+            | ExprKind::Index(l, r)
+            | ExprKind::Repeat(l, r) => {
+                self.expand_expr(l);
+                self.expand_expr(r);
+            }
+            ExprKind::Unary(_, inner)
+            | ExprKind::Return(inner)
+            | ExprKind::Field(inner, _)
+            | ExprKind::Ref(inner) => self.expand_expr(inner),
+            ExprKind::Call(func, args) => {
+                self.expand_expr(func);
+                for arg in args {
+                    self.expand_expr(arg);
+                }
+            }
+            ExprKind::MethodCall(receiver, _, args) => {
+                self.expand_expr(receiver);
+                for arg in args {
+                    self.expand_expr(arg);
+                }
+            }
+            ExprKind::Block(block) => self.expand_block(block),
+            ExprKind::If(cond, then, els) => {
+                self.expand_expr(cond);
+                self.expand_expr(then);
+                if let Some(els) = els {
+                    self.expand_expr(els);
+                }
+            }
+            ExprKind::Struct(_, fields) => {
+                for (_, e) in fields {
+                    self.expand_expr(e);
+                }
+            }
+            ExprKind::Array(elems) => {
+                for e in elems {
+                    self.expand_expr(e);
+                }
+            }
+            ExprKind::FormatArgs(fmt) => {
+                for arg in &mut fmt.args {
+                    self.expand_expr(arg);
+                }
+            }
+            ExprKind::Cast(inner, ty) => {
+                self.expand_expr(inner);
+                self.expand_ty(ty);
+            }
+            ExprKind::Closure(closure) => {
+                for (_, ty) in &mut closure.params {
+                    if let Some(ty) = ty {
+                        self.expand_ty(ty);
+                    }
+                }
+                if let Some(ret_ty) = &mut closure.ret_ty {
+                    self.expand_ty(ret_ty);
+                }
+                self.expand_expr(&mut closure.body);
+            }
+        }
+    }
+
+    fn expand_ty(&mut self, ty: &mut Ty) {
+        match &mut ty.kind {
+            TyKind::Array(elem_ty, len) => {
+                self.expand_ty(elem_ty);
+                self.expand_expr(len);
+            }
+            TyKind::Ref(_, inner) | TyKind::ConstPtr(inner) => self.expand_ty(inner),
+            TyKind::Fn(params, ret_ty) => {
+                for param in params {
+                    self.expand_ty(param);
+                }
+                self.expand_ty(ret_ty);
+            }
+            TyKind::Unit | TyKind::Bool | TyKind::I32 | TyKind::Str | TyKind::Adt(_) | TyKind::Never => (),
+        }
+    }
+
+    /// Expand `mac` and parse the output with `parse`.
+    /// Returns `None` after recording an error.
+    fn expand_mac_call<T>(
+        &mut self,
+        mac: &MacCall,
+        parse: impl FnOnce(&mut Parser, Vec<Token>, Span) -> Option<T>,
+    ) -> Option<T> {
+        if self.depth >= RECURSION_LIMIT {
+            self.errors.push(
+                Diagnostic::error(format!(
+                    "recursion limit reached while expanding `{}!`",
+                    mac.path.segments.last().unwrap().symbol
+                ))
+                .code("M0302")
+                .span_label(&mac.span, "expanded too deeply"),
+            );
+            return None;
+        }
+        let Some(macro_rules) = self.find_macro(mac) else {
+            self.errors.push(
+                Diagnostic::error(format!(
+                    "cannot find macro `{}` in this scope",
+                    mac.path.segments.last().unwrap().symbol
+                ))
+                .code("M0103")
+                .span_label(&mac.path.span, "not found in this scope"),
+            );
+            return None;
+        };
+        let ctxt = SyntaxContext(self.next_ctxt);
+        self.next_ctxt += 1;
+        let tts = macro_rules.expand(self.parser, mac, ctxt);
+        self.take_parser_errors(mac);
+        let tts = match tts {
+            Ok(tts) => tts,
+            Err(e) => {
+                self.errors.push(e);
+                return None;
+            }
+        };
+        let res = parse(self.parser, flatten(&tts), mac.span.clone());
+        self.take_parser_errors(mac);
+        res
+    }
+
+    /// Move errors recorded by the parser while expanding `mac` into `self.errors`
+    fn take_parser_errors(&mut self, mac: &MacCall) {
+        for mut diag in self.parser.take_diagnostics() {
+            // errors at the end of the expansion already point to the call
+            if diag.primary_span() != Some(&mac.span) {
+                diag = diag.secondary_label(&mac.span, "in this macro invocation");
+            }
+            self.errors.push(diag);
+        }
+    }
+
+    /// Built-in macro called by `mac`, unless a `macro_rules!` of the same name shadows it
+    fn find_builtin(&self, mac: &MacCall) -> Option<BuiltinMacro> {
+        if mac.path.segments.len() != 1 || self.find_macro(mac).is_some() {
+            return None;
+        }
+        BuiltinMacro::from_name(&mac.path.segments[0].symbol)
+    }
+
+    /// Expand a call to a built-in macro in expression or statement position
+    fn expand_builtin(&mut self, builtin: BuiltinMacro, mac: &MacCall) -> Option<Expr> {
+        let ctxt = SyntaxContext(self.next_ctxt);
+        self.next_ctxt += 1;
+        let mut expander = BuiltinExpander {
+            parser: self.parser,
+            mac,
+            ctxt,
+        };
+        let res = expander.expand(builtin, self.prelude_enabled);
+        self.take_parser_errors(mac);
+        match res {
+            Ok(expr) => Some(expr),
+            Err(e) => {
+                self.errors.extend(e);
+                None
+            }
+        }
+    }
+
+    /// Latest definition of the macro called by `mac`
+    fn find_macro(&self, mac: &MacCall) -> Option<Rc<MacroRules>> {
+        // macros by example are not items, so they cannot be named by paths
+        if mac.path.segments.len() != 1 {
+            return None;
+        }
+        let name = &mac.path.segments[0].symbol;
+        self.macros.iter().rev().find(|m| m.name == *name).cloned()
+    }
+
+    /// Run `f` on the output of a macro, where nested calls are one level deeper
+    fn enter(&mut self, f: impl FnOnce(&mut Self)) {
+        self.depth += 1;
+        f(self);
+        self.depth -= 1;
+    }
+}
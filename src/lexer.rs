@@ -1,13 +1,14 @@
-use crate::span::Span;
+use crate::diagnostics::Diagnostic;
+use crate::span::{FileId, SourceFile, Span};
 use std::{collections::VecDeque, iter::Peekable, rc::Rc, vec::IntoIter};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token {
     pub kind: TokenKind,
     pub span: Span,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TokenKind {
     // keywords
     I32,
@@ -31,13 +32,25 @@ pub enum TokenKind {
     As,
     Mut,
     Const,
+    Static,
+    Pub,
+    Use,
     Impl, 
     Type, 
+    Move,
     /// ->
     Arrow,
+    /// =>
+    FatArrow,
     /// !
     Bang,
     Eq,
+    /// $
+    Dollar,
+    /// #
+    Pound,
+    /// ?
+    Question,
     /// ;
     Semi,
     /// :
@@ -72,16 +85,19 @@ pub enum TokenKind {
     Unknown,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BinOp {
     Plus,
     Minus,
     Star,
+    Slash,
+    Percent,
     Eq,
     Ne,
     Gt,
     Lt,
     And,
+    Or,
 }
 
 fn is_space(c: char) -> bool {
@@ -93,25 +109,69 @@ pub struct Lexer {
     current_pos: usize,
     char_stream: Peekable<IntoIter<char>>,
     buffered_tokens: VecDeque<Token>,
-    src: Rc<String>,
+    file: Rc<SourceFile>,
+    diagnostics: Vec<Diagnostic>,
+    /// Number of `{` skipped so far without a matching `}`
+    brace_depth: usize,
 }
 
 impl Lexer {
+    /// Lexer of source code which does not come from a file
     pub fn new(src: String) -> Self {
-        let char_stream = src.chars().collect::<Vec<char>>().into_iter().peekable();
+        Lexer::from_file(Rc::new(SourceFile::new(FileId(0), "<anon>".to_string(), src)))
+    }
+
+    pub fn from_file(file: Rc<SourceFile>) -> Self {
+        let char_stream = file.src.chars().collect::<Vec<char>>().into_iter().peekable();
         Lexer {
             token_start_pos: 0,
             current_pos: 0,
             char_stream,
             buffered_tokens: VecDeque::new(),
-            src: Rc::new(src),
+            file,
+            diagnostics: vec![],
+            brace_depth: 0,
         }
     }
 
+    /// Lexer returning `tokens`, such as the output of a macro, followed by EOF at `eof`
+    pub fn from_tokens(tokens: Vec<Token>, eof: Span) -> Self {
+        let mut lexer = Lexer::from_file(Rc::clone(eof.file()));
+        lexer.buffered_tokens.extend(tokens);
+        lexer.buffered_tokens.push_back(Token {
+            kind: TokenKind::Eof,
+            span: eof,
+        });
+        lexer
+    }
+
+    pub fn file(&self) -> &Rc<SourceFile> {
+        &self.file
+    }
+
+    /// Number of tokens not consumed yet, excluding EOF, of a lexer made by `from_tokens`
+    pub fn remaining_tokens(&self) -> usize {
+        self.buffered_tokens
+            .iter()
+            .filter(|t| t.kind != TokenKind::Eof)
+            .count()
+    }
+
+    /// Errors found in the tokens lexed so far
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Record an error about the input lexed since the start of the current token
+    fn error(&mut self, code: &'static str, message: String, label: &str) {
+        let span = Span::new(self.token_start_pos, self.current_pos, Rc::clone(&self.file));
+        self.diagnostics.push(Diagnostic::error(message).code(code).span_label(&span, label));
+    }
+
     fn new_token(&mut self, kind: TokenKind) -> Token {
         let t = Token {
             kind,
-            span: Span::new(self.token_start_pos, self.current_pos, Rc::clone(&self.src)),
+            span: Span::new(self.token_start_pos, self.current_pos, Rc::clone(&self.file)),
         };
         self.token_start_pos = self.current_pos;
         t
@@ -123,8 +183,9 @@ impl Lexer {
 
     fn skip_input(&mut self) -> Option<char> {
         let c = self.char_stream.next();
-        if c.is_some() {
-            self.current_pos += 1;
+        // spans are byte offsets
+        if let Some(c) = c {
+            self.current_pos += c.len_utf8();
         }
         c
     }
@@ -138,8 +199,7 @@ impl Lexer {
     */
 
     fn skip_whitespaces(&mut self) {
-        while let Some(c) = self.peek_input()
-            && is_space(*c) {
+        while matches!(self.peek_input(), Some(c) if is_space(*c)) {
             self.skip_input();
         }
         self.token_start_pos = self.current_pos;
@@ -156,12 +216,11 @@ impl Lexer {
                 '\'' => self.parse_lifetime(),
                 '0'..='9' => self.parse_number_lit(),
                 // skip comments
-                // TODO: div `/`
                 '/' => {
                     // skip first '/'
                     self.skip_input().unwrap();
-                    let c = self.skip_input();
-                    if c == Some('/') {
+                    if self.peek_input() == Some(&'/') {
+                        self.skip_input();
                         loop {
                             let c = self.peek_input();
                             if matches!(c, Some('\n') | None) {
@@ -173,14 +232,21 @@ impl Lexer {
                         }
                         return self.tokenize();
                     } else {
-                        self.new_token(TokenKind::Unknown)
+                        self.new_token(TokenKind::BinOp(BinOp::Slash))
                     }
                 }
+                '%' => {
+                    self.skip_input();
+                    self.new_token(TokenKind::BinOp(BinOp::Percent))
+                }
                 '=' => {
                     self.skip_input();
                     if self.peek_input() == Some(&'=') {
                         self.skip_input();
                         self.new_token(TokenKind::BinOp(BinOp::Eq))
+                    } else if self.peek_input() == Some(&'>') {
+                        self.skip_input();
+                        self.new_token(TokenKind::FatArrow)
                     } else {
                         self.new_token(TokenKind::Eq)
                     }
@@ -215,10 +281,26 @@ impl Lexer {
                     self.skip_input();
                     self.new_token(TokenKind::BinOp(BinOp::And))
                 }
+                '|' => {
+                    self.skip_input();
+                    self.new_token(TokenKind::BinOp(BinOp::Or))
+                }
                 ';' => {
                     self.skip_input();
                     self.new_token(TokenKind::Semi)
                 }
+                '$' => {
+                    self.skip_input();
+                    self.new_token(TokenKind::Dollar)
+                }
+                '#' => {
+                    self.skip_input();
+                    self.new_token(TokenKind::Pound)
+                }
+                '?' => {
+                    self.skip_input();
+                    self.new_token(TokenKind::Question)
+                }
                 ':' => {
                     self.skip_input();
                     if self.peek_input() == Some(&':') {
@@ -271,8 +353,9 @@ impl Lexer {
                 '\"' => self.parse_string_lit(),
                 // Unknown token
                 _ => {
-                    eprintln!("Unknwon token starting with: {:?}", c);
+                    let c = *c;
                     self.skip_input();
+                    self.error("M0003", format!("unknown start of token: {}", c.escape_debug()), "");
                     self.new_token(TokenKind::Unknown)
                 }
             }
@@ -321,8 +404,12 @@ impl Lexer {
             "as" => self.new_token(TokenKind::As),
             "mut" => self.new_token(TokenKind::Mut),
             "const" => self.new_token(TokenKind::Const),
+            "static" => self.new_token(TokenKind::Static),
+            "pub" => self.new_token(TokenKind::Pub),
+            "use" => self.new_token(TokenKind::Use),
             "impl" => self.new_token(TokenKind::Impl), 
             "type" => self.new_token(TokenKind::Type),
+            "move" => self.new_token(TokenKind::Move),
             _ => self.new_token(TokenKind::Ident(s)),
         }
     }
@@ -341,10 +428,7 @@ impl Lexer {
             };
         }
         if chars.is_empty() {
-            eprintln!(
-                "Expected lifetime identifier, but found {:?}",
-                self.peek_input()
-            );
+            self.error("M0003", "expected a lifetime name after `'`".to_string(), "");
             self.new_token(TokenKind::Unknown)
         } else {
             let s: String = chars.into_iter().collect();
@@ -378,31 +462,39 @@ impl Lexer {
         self.skip_input();
 
         let mut chars = vec![];
-        while let Some(c) = &self.peek_input() {
-            match c {
-                '"' => {
+        loop {
+            match self.peek_input().copied() {
+                Some('"') => {
                     self.skip_input();
                     break;
                 }
-                '\\' => {
+                Some('\\') => {
                     self.skip_input();
-                    let escp = match self.skip_input().unwrap() {
-                        'n' => '\n',
-                        't' => '\t', 
-                        'r' => '\r',
-                        c => {
-                            eprintln!("Escape \"\\{c}\" is not supported");
-                            return self.new_token(TokenKind::Unknown); 
+                    let escp = match self.skip_input() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some(c) => {
+                            self.error("M0004", format!("unknown character escape: `{}`", c), "unknown character escape");
+                            return self.new_token(TokenKind::Unknown);
+                        }
+                        None => {
+                            self.error("E0765", "unterminated string literal".to_string(), "end of input in string literal");
+                            return self.new_token(TokenKind::Unknown);
                         }
                     };
                     chars.push(escp);
                 }
-                '\n' => {
-                    eprintln!("Unexpected newline in string literal");
+                Some('\n') => {
+                    self.error("E0765", "unterminated string literal".to_string(), "newline in string literal");
                     return self.new_token(TokenKind::Unknown);
                 }
-                _ => {
-                    chars.push(**c);
+                None => {
+                    self.error("E0765", "unterminated string literal".to_string(), "end of input in string literal");
+                    return self.new_token(TokenKind::Unknown);
+                }
+                Some(c) => {
+                    chars.push(c);
                     self.skip_input();
                 }
             };
@@ -420,13 +512,36 @@ impl Lexer {
         &self.buffered_tokens[0]
     }
 
+    /// Token `n` tokens ahead of the current one, or EOF if the input ends before it
+    pub fn peek_nth_token(&mut self, n: usize) -> &Token {
+        while self.buffered_tokens.len() <= n
+            && self.buffered_tokens.back().is_none_or(|t| t.kind != TokenKind::Eof)
+        {
+            self.tokenize();
+        }
+        let last = self.buffered_tokens.len() - 1;
+        &self.buffered_tokens[n.min(last)]
+    }
+
     /// Skip the current token. Keep returning EOF after lexer reached EOF
     pub fn skip_token(&mut self) -> Token {
         // make sure that the current token is buffered
         if self.buffered_tokens.is_empty() {
             self.tokenize();
         }
-        self.buffered_tokens.pop_front().unwrap()
+        let t = self.buffered_tokens.pop_front().unwrap();
+        match t.kind {
+            TokenKind::OpenBrace => self.brace_depth += 1,
+            TokenKind::CloseBrace => self.brace_depth = self.brace_depth.saturating_sub(1),
+            _ => (),
+        }
+        t
+    }
+
+    /// Number of blocks opened by the tokens skipped so far, which the parser uses to
+    /// find the end of erroneous code
+    pub fn brace_depth(&self) -> usize {
+        self.brace_depth
     }
 }
 
@@ -466,6 +581,28 @@ fn test_lexer() {
     assert_eq!(lexer.skip_token().kind, TokenKind::Eof);
 }
 
+#[test]
+fn test_div_and_comment() {
+    let mut lexer = Lexer::new("6 / 3 % 2 // comment".to_string());
+    assert_eq!(lexer.skip_token().kind, TokenKind::NumLit(6));
+    assert_eq!(lexer.skip_token().kind, TokenKind::BinOp(BinOp::Slash));
+    assert_eq!(lexer.skip_token().kind, TokenKind::NumLit(3));
+    assert_eq!(lexer.skip_token().kind, TokenKind::BinOp(BinOp::Percent));
+    assert_eq!(lexer.skip_token().kind, TokenKind::NumLit(2));
+    assert_eq!(lexer.skip_token().kind, TokenKind::Eof);
+}
+
+#[test]
+fn test_closure() {
+    let mut lexer = Lexer::new("move || |x| x".to_string());
+    assert_eq!(lexer.skip_token().kind, TokenKind::Move);
+    assert_eq!(lexer.skip_token().kind, TokenKind::BinOp(BinOp::Or));
+    assert_eq!(lexer.skip_token().kind, TokenKind::BinOp(BinOp::Or));
+    assert_eq!(lexer.skip_token().kind, TokenKind::BinOp(BinOp::Or));
+    assert_eq!(lexer.skip_token().kind, TokenKind::Ident("x".to_string()));
+    assert_eq!(lexer.skip_token().kind, TokenKind::BinOp(BinOp::Or));
+}
+
 #[test]
 fn test_span() {
     let mut lexer = Lexer::new("let a;".to_string());
@@ -484,3 +621,24 @@ fn test_empty() {
     assert_eq!(t.span.to_snippet(), "");
     assert_eq!(t.kind, TokenKind::Eof);
 }
+
+#[test]
+fn test_macro_tokens() {
+    let mut lexer = Lexer::new("($e:expr),* => $($e)?".to_string());
+    assert_eq!(lexer.skip_token().kind, TokenKind::OpenParen);
+    assert_eq!(lexer.skip_token().kind, TokenKind::Dollar);
+    assert_eq!(lexer.skip_token().kind, TokenKind::Ident("e".to_string()));
+    assert_eq!(lexer.skip_token().kind, TokenKind::Colon);
+    assert_eq!(lexer.skip_token().kind, TokenKind::Ident("expr".to_string()));
+    assert_eq!(lexer.skip_token().kind, TokenKind::CloseParen);
+    assert_eq!(lexer.skip_token().kind, TokenKind::Comma);
+    assert_eq!(lexer.skip_token().kind, TokenKind::BinOp(BinOp::Star));
+    assert_eq!(lexer.skip_token().kind, TokenKind::FatArrow);
+    assert_eq!(lexer.skip_token().kind, TokenKind::Dollar);
+    assert_eq!(lexer.skip_token().kind, TokenKind::OpenParen);
+    lexer.skip_token();
+    lexer.skip_token();
+    assert_eq!(lexer.skip_token().kind, TokenKind::CloseParen);
+    assert_eq!(lexer.skip_token().kind, TokenKind::Question);
+    assert_eq!(lexer.skip_token().kind, TokenKind::Eof);
+}
@@ -0,0 +1,20 @@
+//! mini-rustc, a toy Rust compiler which outputs LLVM IR
+//!
+//! Each stage of the compiler is a module, and `driver` runs them one after another.
+//! Stages return their errors as `Diagnostic`s instead of printing them.
+
+pub mod ast;
+pub mod backend_llvm;
+pub mod diagnostics;
+pub mod driver;
+pub mod expand;
+pub mod lexer;
+pub mod lint;
+//mod lvalue;
+pub mod metadata;
+pub mod middle;
+pub mod parse;
+pub mod resolve;
+pub mod span;
+pub mod typeck;
+pub mod unsafeck;
@@ -0,0 +1,325 @@
+//! The lints of the compiler, checked in one walk over the crate
+//!
+//! Unused variables and items are only known at the end of the crate, so they are recorded
+//! with the lint levels in effect at their declarations and reported by `visit_crate_post`.
+
+use super::{LevelSource, LintLevel, LintLevels, DEAD_CODE, UNREACHABLE_CODE, UNUSED_MUT, UNUSED_VARIABLES};
+use crate::ast::{self, Crate, Expr, ExprKind, Item, ItemKind, NodeId, Path, StmtKind, Visibility};
+use crate::diagnostics::Diagnostic;
+use crate::middle::ty::{Ty, TyKind};
+use crate::middle::{CrateType, Ctxt};
+use crate::resolve::Binding;
+use crate::span::{Ident, Span, SyntaxContext};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// A local variable or parameter
+struct Var {
+    ident: Ident,
+    mutable: bool,
+    unused_variables: (LintLevel, LevelSource),
+    unused_mut: (LintLevel, LevelSource),
+    read: bool,
+    /// assigned to as a whole, like `x = 1`
+    assigned: bool,
+    /// assigned to as a whole or in part, like `x.f = 1`
+    mutated: bool,
+    /// the binding which shadowed this one in its scope while it had not been read
+    shadowed_by: Option<Span>,
+}
+
+/// A function or struct which may be never used
+struct DeadCodeItem {
+    binding: Rc<Binding>,
+    ident: Ident,
+    descr: &'static str,
+    level: (LintLevel, LevelSource),
+}
+
+pub(super) struct LintChecker<'ctx, 'chk> {
+    ctx: &'chk mut Ctxt<'ctx>,
+    pub(super) levels: LintLevels,
+    vars: Vec<Var>,
+    /// indices in `vars`
+    var_indices: HashMap<Rc<Binding>, usize>,
+    /// variables declared in each enclosing block, function and closure, innermost last
+    var_scopes: Vec<HashMap<(Rc<String>, SyntaxContext), usize>>,
+    /// path expressions which are the left-hand side of an assignment
+    assignees: HashSet<NodeId>,
+    items: Vec<DeadCodeItem>,
+    /// functions and structs used outside their own bodies
+    used_items: HashSet<Rc<Binding>>,
+    /// functions whose bodies are being checked, outermost first
+    enclosing_fns: Vec<Rc<Binding>>,
+    /// number of modules around the item being checked
+    module_depth: usize,
+}
+
+impl<'ctx, 'chk> LintChecker<'ctx, 'chk> {
+    pub(super) fn new(ctx: &'chk mut Ctxt<'ctx>, levels: LintLevels) -> Self {
+        LintChecker {
+            ctx,
+            levels,
+            vars: vec![],
+            var_indices: HashMap::new(),
+            var_scopes: vec![],
+            assignees: HashSet::new(),
+            items: vec![],
+            used_items: HashSet::new(),
+            enclosing_fns: vec![],
+            module_depth: 0,
+        }
+    }
+
+    fn declare_var(&mut self, ident: &Ident, mutable: bool) {
+        if ident.symbol.starts_with('_') || ident.span.ctxt() != SyntaxContext::root() {
+            return;
+        }
+        let Some(binding) = self.ctx.get_binding(ident) else {
+            return;
+        };
+        let key = (Rc::clone(&ident.symbol), ident.span.ctxt());
+        let shadowed = self.var_scopes.iter().rev().find_map(|scope| scope.get(&key)).copied();
+        if let Some(shadowed) = shadowed {
+            let shadowed = &mut self.vars[shadowed];
+            if !shadowed.read && shadowed.shadowed_by.is_none() {
+                shadowed.shadowed_by = Some(ident.span.clone());
+            }
+        }
+        let index = self.vars.len();
+        self.vars.push(Var {
+            ident: ident.clone(),
+            mutable,
+            unused_variables: self.levels.get(&UNUSED_VARIABLES),
+            unused_mut: self.levels.get(&UNUSED_MUT),
+            read: false,
+            assigned: false,
+            mutated: false,
+            shadowed_by: None,
+        });
+        self.var_indices.insert(binding, index);
+        self.var_scopes.last_mut().unwrap().insert(key, index);
+    }
+
+    fn var_mut(&mut self, path: &Path) -> Option<&mut Var> {
+        let binding = self.ctx.resolve_path(path)?;
+        let index = *self.var_indices.get(&binding)?;
+        Some(&mut self.vars[index])
+    }
+
+    /// `path` is read, called or constructed
+    fn use_path(&mut self, path: &Path) {
+        let Some(binding) = self.ctx.resolve_path(path) else {
+            return;
+        };
+        if let Some(index) = self.var_indices.get(&binding) {
+            self.vars[*index].read = true;
+        } else if !self.enclosing_fns.contains(&binding) {
+            self.used_items.insert(binding);
+        }
+    }
+
+    /// The assignment `lhs = ...`, which writes but does not read a variable assigned as a whole
+    fn assign(&mut self, lhs: &Expr) {
+        let mut place = lhs;
+        while let ExprKind::Field(inner, _) | ExprKind::Index(inner, _) = &place.kind {
+            place = inner;
+        }
+        let ExprKind::Path(path) = &place.kind else {
+            return;
+        };
+        let whole = std::ptr::eq(place, lhs);
+        if whole {
+            self.assignees.insert(place.id);
+        }
+        if let Some(var) = self.var_mut(path) {
+            var.mutated = true;
+            var.assigned |= whole;
+        }
+    }
+
+    fn record_item(&mut self, ident: &Ident, vis: Visibility, descr: &'static str) {
+        let is_main = self.ctx.crate_type == CrateType::Bin && self.module_depth == 0 && *ident.symbol == "main";
+        let is_exported = self.ctx.crate_type == CrateType::Lib && vis == Visibility::Public;
+        if is_main || is_exported || ident.symbol.starts_with('_') || ident.span.ctxt() != SyntaxContext::root() {
+            return;
+        }
+        let Some(binding) = self.ctx.get_binding(ident) else {
+            return;
+        };
+        self.items.push(DeadCodeItem {
+            binding,
+            ident: ident.clone(),
+            descr,
+            level: self.levels.get(&DEAD_CODE),
+        });
+    }
+
+    /// Report the first statement of `block` which follows an expression of type `!`
+    fn check_unreachable(&mut self, block: &ast::Block) {
+        let diverges = |ctx: &Ctxt, expr: &Expr| ctx.lookup_type(expr.id).is_some_and(|ty| ty.is_never());
+        for pair in block.stmts.windows(2) {
+            let [stmt, next] = pair else { unreachable!() };
+            let diverging = match &stmt.kind {
+                StmtKind::Expr(expr) | StmtKind::Semi(expr) => Some(&**expr).filter(|e| diverges(self.ctx, e)),
+                StmtKind::Let(let_stmt) => let_stmt.init.as_ref().filter(|e| diverges(self.ctx, e)),
+            };
+            let Some(diverging) = diverging else {
+                continue;
+            };
+            if next.span.ctxt() == SyntaxContext::root() {
+                let what = if matches!(next.kind, StmtKind::Expr(_)) { "expression" } else { "statement" };
+                let diag = Diagnostic::warning(format!("unreachable {}", what))
+                    .span_label(&next.span, format!("unreachable {}", what))
+                    .secondary_label(&diverging.span, "any code following this expression is unreachable");
+                self.levels.emit(&UNREACHABLE_CODE, diag);
+            }
+            return;
+        }
+    }
+
+    fn report_unused(&mut self) {
+        for var in std::mem::take(&mut self.vars) {
+            let name = &var.ident.symbol;
+            let span = &var.ident.span;
+            if !var.read {
+                let diag = match &var.shadowed_by {
+                    Some(shadowed_by) => Diagnostic::warning(format!("`{}` is shadowed before it is read", name))
+                        .span_label(span, "this binding is never read")
+                        .secondary_label(shadowed_by, "shadowed here"),
+                    None if var.assigned => {
+                        Diagnostic::warning(format!("variable `{}` is assigned to, but never used", name))
+                            .span_label(span, "never read")
+                    }
+                    None => Diagnostic::warning(format!("unused variable: `{}`", name)).span_label(span, "unused"),
+                };
+                let diag = diag.span_suggestion(
+                    span,
+                    "if this is intentional, prefix it with an underscore",
+                    format!("_{}", name),
+                );
+                self.levels.emit_at(&UNUSED_VARIABLES, var.unused_variables, diag);
+            }
+            if var.mutable && !var.mutated {
+                let diag = Diagnostic::warning("variable does not need to be mutable")
+                    .span_label(span, "declared mutable here")
+                    .help("remove this `mut`");
+                self.levels.emit_at(&UNUSED_MUT, var.unused_mut, diag);
+            }
+        }
+        for item in std::mem::take(&mut self.items) {
+            if self.used_items.contains(&item.binding) {
+                continue;
+            }
+            let unused = if item.descr == "struct" { "constructed" } else { "used" };
+            let diag = Diagnostic::warning(format!("{} `{}` is never {}", item.descr, item.ident.symbol, unused))
+                .span_label(&item.ident.span, format!("never {}", unused));
+            self.levels.emit_at(&DEAD_CODE, item.level, diag);
+        }
+    }
+}
+
+impl<'chk> ast::visitor::Visitor<'chk> for LintChecker<'_, 'chk> {
+    fn visit_crate(&mut self, krate: &'chk Crate) {
+        self.levels.push(&krate.attrs);
+    }
+
+    fn visit_crate_post(&mut self, _krate: &'chk Crate) {
+        self.report_unused();
+        self.levels.pop();
+    }
+
+    fn visit_item(&mut self, item: &'chk Item) {
+        self.levels.push(&item.attrs);
+        match &item.kind {
+            ItemKind::Func(func) if func.body.is_some() => {
+                self.record_item(&func.name, func.vis, "function");
+                if let Some(binding) = self.ctx.get_binding(&func.name) {
+                    self.enclosing_fns.push(binding);
+                }
+            }
+            ItemKind::Struct(strct) => self.record_item(&strct.ident, strct.vis, "struct"),
+            _ => (),
+        }
+    }
+
+    fn visit_item_post(&mut self, item: &'chk Item) {
+        if let ItemKind::Func(func) = &item.kind {
+            if func.body.is_some() {
+                self.enclosing_fns.pop();
+            }
+        }
+        self.levels.pop();
+    }
+
+    fn visit_module_item(&mut self, module: &'chk ast::Module) {
+        self.levels.push(&module.attrs);
+        self.module_depth += 1;
+    }
+
+    fn visit_module_item_post(&mut self, _module: &'chk ast::Module) {
+        self.module_depth -= 1;
+        self.levels.pop();
+    }
+
+    fn visit_func(&mut self, func: &'chk ast::Func) {
+        if func.body.is_some() {
+            self.var_scopes.push(HashMap::new());
+            for (param, _) in &func.params {
+                self.declare_var(param, false);
+            }
+        }
+    }
+
+    fn visit_func_post(&mut self, func: &'chk ast::Func) {
+        if func.body.is_some() {
+            self.var_scopes.pop();
+        }
+    }
+
+    fn visit_block(&mut self, block: &'chk ast::Block) {
+        self.var_scopes.push(HashMap::new());
+        self.check_unreachable(block);
+    }
+
+    fn visit_block_post(&mut self, _block: &'chk ast::Block) {
+        self.var_scopes.pop();
+    }
+
+    // the variable is declared after its initializer, which may read a variable it shadows
+    fn visit_stmt_post(&mut self, stmt: &'chk ast::Stmt) {
+        if let StmtKind::Let(let_stmt) = &stmt.kind {
+            self.declare_var(&let_stmt.ident, let_stmt.mutable);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'chk Expr) {
+        match &expr.kind {
+            ExprKind::Path(path) if !self.assignees.contains(&expr.id) => self.use_path(path),
+            ExprKind::Struct(path, _) | ExprKind::Deref(path) => self.use_path(path),
+            ExprKind::Assign(lhs, _) => self.assign(lhs),
+            // a closure which assigns to captured variables needs `mut` to be called, as in rustc
+            ExprKind::Call(callee, _) => {
+                if let ExprKind::Path(path) = &callee.kind {
+                    if let Some(var) = self.var_mut(path) {
+                        var.mutated = true;
+                    }
+                }
+            }
+            ExprKind::Closure(closure) => {
+                self.var_scopes.push(HashMap::new());
+                for (param, _) in &closure.params {
+                    self.declare_var(param, false);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn visit_expr_post(&mut self, expr: &'chk Expr) -> Rc<Ty> {
+        if let ExprKind::Closure(_) = &expr.kind {
+            self.var_scopes.pop();
+        }
+        Rc::new(Ty::new(TyKind::Never))
+    }
+}
@@ -0,0 +1,320 @@
+//! Lints: warnings about code which compiles but is likely a mistake
+//!
+//! Each lint has a level, which is `allow` (silent), `warn` or `deny` (reported as an error).
+//! Levels are set for the whole crate by `-A`, `-W` and `-D` on the command line, and for
+//! an item and its contents by `#[allow(...)]`, `#[warn(...)]` and `#[deny(...)]`.
+//! Names of groups like `unused` set the levels of all lints in them.
+
+mod builtin;
+
+use crate::ast::tokenstream::TokenTree;
+use crate::ast::{self, Attribute, Crate};
+use crate::diagnostics::{Diagnostic, Level};
+use crate::lexer::TokenKind;
+use crate::middle::Ctxt;
+use crate::span::Span;
+use std::collections::{HashMap, HashSet};
+
+pub struct Lint {
+    pub name: &'static str,
+    pub default_level: LintLevel,
+}
+
+pub const UNUSED_VARIABLES: Lint = Lint {
+    name: "unused_variables",
+    default_level: LintLevel::Warn,
+};
+
+pub const UNUSED_MUT: Lint = Lint {
+    name: "unused_mut",
+    default_level: LintLevel::Warn,
+};
+
+pub const DEAD_CODE: Lint = Lint {
+    name: "dead_code",
+    default_level: LintLevel::Warn,
+};
+
+pub const UNREACHABLE_CODE: Lint = Lint {
+    name: "unreachable_code",
+    default_level: LintLevel::Warn,
+};
+
+/// Names in lint flags and attributes which are neither lints nor groups
+pub const UNKNOWN_LINTS: Lint = Lint {
+    name: "unknown_lints",
+    default_level: LintLevel::Warn,
+};
+
+const LINTS: &[&Lint] = &[&UNUSED_VARIABLES, &UNUSED_MUT, &DEAD_CODE, &UNREACHABLE_CODE, &UNKNOWN_LINTS];
+
+/// Groups of lints, usable wherever a lint name is
+const GROUPS: &[(&str, &[&str])] = &[
+    ("unused", &["unused_variables", "unused_mut", "dead_code", "unreachable_code"]),
+    (
+        "warnings",
+        &["unused_variables", "unused_mut", "dead_code", "unreachable_code", "unknown_lints"],
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl LintLevel {
+    /// Level of the flag `-A`, `-W` or `-D`
+    pub fn from_flag(flag: &str) -> Option<LintLevel> {
+        match flag {
+            "-A" => Some(LintLevel::Allow),
+            "-W" => Some(LintLevel::Warn),
+            "-D" => Some(LintLevel::Deny),
+            _ => None,
+        }
+    }
+
+    fn from_attr(name: &str) -> Option<LintLevel> {
+        match name {
+            "allow" => Some(LintLevel::Allow),
+            "warn" => Some(LintLevel::Warn),
+            "deny" => Some(LintLevel::Deny),
+            _ => None,
+        }
+    }
+
+    fn flag(&self) -> &'static str {
+        match self {
+            LintLevel::Allow => "-A",
+            LintLevel::Warn => "-W",
+            LintLevel::Deny => "-D",
+        }
+    }
+
+    fn attr(&self) -> &'static str {
+        match self {
+            LintLevel::Allow => "allow",
+            LintLevel::Warn => "warn",
+            LintLevel::Deny => "deny",
+        }
+    }
+}
+
+/// Where the level of a lint was set, which is told when the lint is first reported
+#[derive(Debug, Clone)]
+enum LevelSource {
+    Default,
+    /// flag and the lint or group name given to it
+    CommandLine(LintLevel, String),
+    Attribute(Span),
+}
+
+/// Check the lints of the crate, whose levels are first set by `cmdline_levels` from the
+/// command line. Returns warnings on success, and warnings followed by errors of `deny`
+/// lints on failure.
+pub fn check<'ctx, 'chk>(
+    ctx: &'chk mut Ctxt<'ctx>,
+    krate: &'chk Crate,
+    cmdline_levels: &[(LintLevel, String)],
+) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    let mut levels = LintLevels::new();
+    let mut unknown = vec![];
+    for (level, name) in cmdline_levels {
+        let name = name.replace('-', "_");
+        if !levels.set(&name, *level, LevelSource::CommandLine(*level, name.clone())) {
+            unknown.push((*level, name));
+        }
+    }
+    let mut checker = builtin::LintChecker::new(ctx, levels);
+    for (level, name) in unknown {
+        let diag = Diagnostic::warning(format!("unknown lint: `{}`", name))
+            .note(format!("requested on the command line with `{} {}`", level.flag(), name));
+        checker.levels.emit(&UNKNOWN_LINTS, diag);
+    }
+    ast::visitor::go(&mut checker, krate);
+    checker.levels.finish()
+}
+
+/// Levels of lints in the scopes of the items being checked, and the lints reported so far
+struct LintLevels {
+    /// levels set by each enclosing scope, outermost first
+    scopes: Vec<HashMap<&'static str, (LintLevel, LevelSource)>>,
+    /// lints whose source of the level has been told
+    noted: HashSet<&'static str>,
+    warnings: Vec<Diagnostic>,
+    errors: Vec<Diagnostic>,
+}
+
+impl LintLevels {
+    fn new() -> Self {
+        LintLevels {
+            scopes: vec![HashMap::new()],
+            noted: HashSet::new(),
+            warnings: vec![],
+            errors: vec![],
+        }
+    }
+
+    /// Set the level of the lint or group `name` in the innermost scope.
+    /// Returns false if there is no such lint or group.
+    fn set(&mut self, name: &str, level: LintLevel, source: LevelSource) -> bool {
+        let lints: Vec<&'static str> = if let Some(lint) = LINTS.iter().find(|lint| lint.name == name) {
+            vec![lint.name]
+        } else if let Some((_, lints)) = GROUPS.iter().find(|(group, _)| *group == name) {
+            lints.to_vec()
+        } else {
+            return false;
+        };
+        let scope = self.scopes.last_mut().unwrap();
+        for lint in lints {
+            scope.insert(lint, (level, source.clone()));
+        }
+        true
+    }
+
+    fn get(&self, lint: &Lint) -> (LintLevel, LevelSource) {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(lint.name).cloned())
+            .unwrap_or((lint.default_level, LevelSource::Default))
+    }
+
+    /// Enter the scope of an item, whose lint levels are set by `attrs`
+    fn push(&mut self, attrs: &[Attribute]) {
+        self.scopes.push(HashMap::new());
+        for attr in attrs {
+            self.apply_attr(attr);
+        }
+    }
+
+    fn pop(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// `#[allow(a, b)]`, `#[warn(a, b)]` or `#[deny(a, b)]`
+    fn apply_attr(&mut self, attr: &Attribute) {
+        let name = match attr.path.segments.as_slice() {
+            [name] => name.symbol.as_str(),
+            _ => "",
+        };
+        let Some(level) = LintLevel::from_attr(name) else {
+            self.errors.push(
+                Diagnostic::error(format!("cannot find attribute `{}` in this scope", attr.path.span.to_snippet()))
+                    .code("M0104")
+                    .span_label(&attr.path.span, "unknown attribute")
+                    .note("only the lint attributes `allow`, `warn` and `deny` are supported"),
+            );
+            return;
+        };
+        let Some(names) = attr.args.as_deref().and_then(lint_names) else {
+            self.errors.push(
+                Diagnostic::error("malformed lint attribute input")
+                    .code("E0452")
+                    .span_label(&attr.span, "bad attribute argument")
+                    .help(format!("lints are given like `#[{}(unused_variables, dead_code)]`", name)),
+            );
+            return;
+        };
+        for (name, span) in names {
+            if !self.set(&name, level, LevelSource::Attribute(attr.span.clone())) {
+                let diag = Diagnostic::warning(format!("unknown lint: `{}`", name)).span_label(&span, "unknown lint");
+                self.emit(&UNKNOWN_LINTS, diag);
+            }
+        }
+    }
+
+    /// Report `diag`, a warning of `lint`, at the current level of the lint
+    fn emit(&mut self, lint: &Lint, diag: Diagnostic) {
+        let level = self.get(lint);
+        self.emit_at(lint, level, diag);
+    }
+
+    /// Report `diag`, a warning of `lint`, at `level` found where the code is
+    fn emit_at(&mut self, lint: &Lint, (level, source): (LintLevel, LevelSource), mut diag: Diagnostic) {
+        if level == LintLevel::Allow {
+            return;
+        }
+        match source {
+            LevelSource::Attribute(span) => {
+                diag = diag.secondary_label(&span, "the lint level is defined here");
+            }
+            _ if !self.noted.insert(lint.name) => (),
+            LevelSource::Default => {
+                diag = diag.note(format!("`#[{}({})]` on by default", level.attr(), lint.name));
+            }
+            LevelSource::CommandLine(flag_level, name) if name == lint.name => {
+                diag = diag.note(format!("requested on the command line with `{} {}`", flag_level.flag(), name));
+            }
+            LevelSource::CommandLine(flag_level, name) => {
+                diag = diag.note(format!(
+                    "`{} {}` implied by `{} {}`",
+                    level.flag(),
+                    lint.name,
+                    flag_level.flag(),
+                    name
+                ));
+            }
+        }
+        if level == LintLevel::Deny {
+            diag.level = Level::Error;
+            self.errors.push(diag);
+        } else {
+            self.warnings.push(diag);
+        }
+    }
+
+    fn finish(mut self) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        // lints of declarations are reported after their uses are seen, so they are put back
+        // in the order of the source
+        let key = |diag: &Diagnostic| diag.primary_span().map(|span| (span.file().id.0, span.lo()));
+        self.warnings.sort_by_key(key);
+        self.errors.sort_by_key(key);
+        if self.errors.is_empty() {
+            Ok(self.warnings)
+        } else {
+            self.warnings.append(&mut self.errors);
+            Err(self.warnings)
+        }
+    }
+}
+
+/// Names and spans of the lints in `a, b`, or `None` if the arguments are not such a list
+fn lint_names(args: &[TokenTree]) -> Option<Vec<(String, Span)>> {
+    let mut names = vec![];
+    for (i, tt) in args.iter().enumerate() {
+        let TokenTree::Token(t) = tt else {
+            return None;
+        };
+        match &t.kind {
+            TokenKind::Ident(name) if i % 2 == 0 => names.push((name.clone(), t.span.clone())),
+            TokenKind::Comma if i % 2 == 1 => (),
+            _ => return None,
+        }
+    }
+    if names.is_empty() {
+        return None;
+    }
+    Some(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        let mut levels = LintLevels::new();
+        assert_eq!(levels.get(&DEAD_CODE).0, LintLevel::Warn);
+        assert!(levels.set("unused", LintLevel::Deny, LevelSource::Default));
+        assert!(!levels.set("unused_imports", LintLevel::Deny, LevelSource::Default));
+        levels.push(&[]);
+        levels.set("dead_code", LintLevel::Allow, LevelSource::Default);
+        assert_eq!(levels.get(&DEAD_CODE).0, LintLevel::Allow);
+        assert_eq!(levels.get(&UNUSED_MUT).0, LintLevel::Deny);
+        assert_eq!(levels.get(&UNKNOWN_LINTS).0, LintLevel::Warn);
+        levels.pop();
+        assert_eq!(levels.get(&DEAD_CODE).0, LintLevel::Deny);
+    }
+}
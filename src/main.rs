@@ -1,89 +1,105 @@
-#![feature(let_chains)]
-mod ast;
-mod backend_llvm;
-mod lexer;
-//mod lvalue;
-mod middle;
-mod parse;
-mod resolve;
-mod span;
-mod typeck;
+use mini_rustc::diagnostics::{self, Diagnostic, ErrorFormat};
+use mini_rustc::driver::args::{self, ArgsError, Command, Config};
+use mini_rustc::driver::{link, EmitKind, Session};
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: mini-rustc file/source [options]");
-        println!("Options:");
-        println!("  --dump\tDump compiler states for debugging");
-        eprintln!("Invalid number of arguments");
-        std::process::exit(1);
-    }
-
-    // TODO: refine handling command line args
-    let dump_enabled = args.contains(&"--dump".to_string());
-
-    let path_or_src = args[1].clone();
-    let src = if args[1].ends_with(".rs") {
-        let res = std::fs::read_to_string(path_or_src);
-        if let Ok(src) = res {
-            src
-        } else {
-            eprintln!("Could not read file {}", args[1]);
-            std::process::exit(1);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match args::parse_args(&args) {
+        Ok(Command::Compile(config)) => config,
+        Ok(Command::Explain(code)) => explain(&code),
+        Ok(Command::Help) => {
+            println!("{}", args::USAGE);
+            std::process::exit(0);
+        }
+        Ok(Command::Version) => {
+            println!("mini-rustc {}", env!("CARGO_PKG_VERSION"));
+            std::process::exit(0);
+        }
+        Err(ArgsError(message)) => {
+            eprintln!("error: {}\n\nFor more information, try `mini-rustc --help`", message);
+            std::process::exit(2);
         }
-    } else {
-        path_or_src
     };
+    let Config {
+        input,
+        options,
+        outputs,
+        link,
+        error_format,
+    } = *config;
 
-    // Parse stage
-    let lexer = lexer::Lexer::new(src);
-    let mut parser = parse::Parser::new(lexer);
-    let parse_result = parser.parse_crate();
-
-    let Some(krate) = parse_result else {
-        eprintln!("Failed to parse source code");
-        std::process::exit(1);
+    let codegen = options.codegen.clone();
+    let mut sess = Session::new();
+    let result = sess.compile(&input, options);
+    for dump in &sess.dumps {
+        eprintln!("{}", dump);
+    }
+    emit_diagnostics(&sess.diagnostics, error_format);
+    let compiled = match result {
+        Ok(compiled) => compiled,
+        Err(e) => abort(error_format, &e.0),
     };
 
-    let mut ctx = middle::Ctxt::new(dump_enabled);
-
-    if ctx.dump_enabled {
-        dbg!(&krate);
+    for (kind, path) in outputs.iter().filter(|(kind, _)| !kind.needs_llc()) {
+        let text = match kind {
+            EmitKind::LlvmIr => compiled.llvm_ir.to_string(),
+            _ => match compiled.outputs.iter().find(|(k, _)| k == kind) {
+                Some((_, text)) => text.clone(),
+                None => abort(error_format, &format!("ICE: `--emit={}` was not produced", kind.name())),
+            },
+        };
+        match path {
+            Some(path) => {
+                if std::fs::write(path, text).is_err() {
+                    abort(error_format, &format!("Could not write output file {}", path.display()));
+                }
+            }
+            None => print!("{}", text),
+        }
     }
 
-    // Name resolution stage
-    ctx.run_resolver(&krate);
+    if let Some(metadata) = &compiled.metadata {
+        let path = link::metadata_path(&compiled, &outputs);
+        if std::fs::write(&path, metadata).is_err() {
+            abort(error_format, &format!("Could not write metadata file {}", path.display()));
+        }
+    }
 
-    if ctx.dump_enabled {
-        ctx.dump_ribs();
+    if let Err(e) = link::build(&compiled, &codegen, &link, &outputs) {
+        abort(error_format, &e.0);
     }
+}
 
-    // Typecheck stage
-    let typeck_result = typeck::typeck(&mut ctx, &krate);
-    let Ok(()) = typeck_result else {
-        if let Err(errors) = typeck_result {
-            for e in errors {
-                eprintln!("{}", e);
-            }
+/// Print the explanation of error code `code` for `--explain`, and exit
+fn explain(code: &str) -> ! {
+    match diagnostics::explanation(code) {
+        Some(text) => {
+            println!("{}", text);
+            std::process::exit(0);
+        }
+        None => {
+            eprintln!("error: `{}` is not a valid error code", code);
+            std::process::exit(1);
         }
-        eprintln!("Failed to typecheck crate");
-        std::process::exit(1);
-    };
-
-    if ctx.dump_enabled {
-        ctx.dump_resolution();
     }
+}
 
-    //dbg!(&ctx);
-
-    // Lvalue analysis stage
-    // lvalue::analyze(&mut ctx, &krate);
-
-    // Codegen stage
-    let codegen_result = backend_llvm::compile(&mut ctx, &krate);
+/// Print diagnostics to stderr, separated by blank lines like rustc or one per line as JSON
+fn emit_diagnostics(diags: &[Diagnostic], format: ErrorFormat) {
+    for diag in diags {
+        match format {
+            ErrorFormat::Human => eprintln!("{}\n", diag),
+            ErrorFormat::Json => eprintln!("{}", diag.render(format)),
+        }
+    }
+}
 
-    let Ok(()) = codegen_result else {
-        eprintln!("ICE: Failed to generate assembly");
-        std::process::exit(1);
-    };
+/// Report why compilation stopped, and exit. The message is a diagnostic without a
+/// location in JSON, so that each line of stderr stays a JSON object
+fn abort(format: ErrorFormat, message: &str) -> ! {
+    match format {
+        ErrorFormat::Human => eprintln!("{}", message),
+        ErrorFormat::Json => emit_diagnostics(&[Diagnostic::error(message)], format),
+    }
+    std::process::exit(1);
 }
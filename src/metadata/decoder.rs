@@ -0,0 +1,321 @@
+use super::{CrateMetadata, ExportedItem, ExportedItemKind, HEADER};
+use crate::ast::Visibility;
+use crate::middle::ctfe::ConstValue;
+use crate::middle::ty::{AdtDef, Ty, TyKind};
+use crate::resolve::{Binding, BindingKind, CanonicalPath};
+use crate::span::{FileId, Ident, SourceFile, Span};
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// Read the metadata file of a library
+pub fn load(path: &str, file_id: FileId) -> Result<CrateMetadata, String> {
+    let Ok(src) = std::fs::read_to_string(path) else {
+        return Err(format!("Could not read metadata file `{}`", path));
+    };
+    let file = Rc::new(SourceFile::new(file_id, path.to_string(), src));
+    let mut decoder = Decoder {
+        file: Rc::clone(&file),
+        name: Rc::new(String::new()),
+        items: vec![],
+        modules: HashSet::new(),
+        span: Span::new(0, 0, file),
+    };
+    decoder.decode()?;
+    Ok(CrateMetadata {
+        name: decoder.name,
+        items: decoder.items,
+        span: decoder.span,
+    })
+}
+
+struct Decoder {
+    file: Rc<SourceFile>,
+    name: Rc<String>,
+    items: Vec<ExportedItem>,
+    /// modules declared so far, starting with the crate root
+    modules: HashSet<Rc<CanonicalPath>>,
+    /// line of the crate name
+    span: Span,
+}
+
+impl Decoder {
+    fn decode(&mut self) -> Result<(), String> {
+        let file = Rc::clone(&self.file);
+        let mut lo = 0;
+        for (i, line) in file.src.lines().enumerate() {
+            let span = Span::new(lo, lo + line.len(), Rc::clone(&file));
+            lo += line.len() + 1;
+            let res = match i {
+                0 if line == HEADER => Ok(()),
+                0 => return Err(format!("`{}` is not a mini-rustc metadata file", file.name)),
+                _ => self.decode_line(line, &span),
+            };
+            if let Err(msg) = res {
+                return Err(format!("Invalid metadata at {}: {}", span.location(), msg));
+            }
+        }
+        if self.name.is_empty() {
+            return Err(format!("Invalid metadata in `{}`: missing crate name", file.name));
+        }
+        // modules can be imported before they are declared
+        for item in &self.items {
+            if let ExportedItemKind::Import { target, .. } = &item.kind {
+                if target.kind == BindingKind::Mod && !self.modules.contains(&target.cpath) {
+                    return Err(format!(
+                        "Invalid metadata at {}: module `{:?}` is not declared",
+                        item.span.location(),
+                        target.cpath
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_line(&mut self, line: &str, span: &Span) -> Result<(), String> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let arity = match fields[0] {
+            "crate" => 2,
+            "mod" | "struct" => 3,
+            "field" => 4,
+            "fn" | "const" | "static" => 5,
+            "use" => 6,
+            _ => return Err(format!("unknown entry `{}`", fields[0])),
+        };
+        if fields.len() != arity {
+            return Err(format!("expected {} fields, but found {}", arity, fields.len()));
+        }
+        if fields[0] == "crate" {
+            if !is_ident(fields[1]) {
+                return Err(format!("invalid crate name `{}`", fields[1]));
+            }
+            self.name = Rc::new(fields[1].to_string());
+            self.span = span.clone();
+            self.modules.insert(Rc::new(CanonicalPath::new(vec![Rc::clone(&self.name)])));
+            return Ok(());
+        }
+        if self.name.is_empty() {
+            return Err("items must come after the crate name".to_string());
+        }
+
+        let vis = decode_vis(fields[1])?;
+        if fields[0] == "field" {
+            let ty = self.decode_ty(fields[3])?;
+            let Some(ExportedItem {
+                kind: ExportedItemKind::Struct(adt),
+                ..
+            }) = self.items.last_mut()
+            else {
+                return Err("field must follow a struct".to_string());
+            };
+            let name = Rc::new(fields[2].to_string());
+            adt.fields.push((Rc::clone(&name), ty));
+            adt.field_vis.push((
+                vis,
+                Ident {
+                    symbol: name,
+                    span: span.clone(),
+                },
+            ));
+            return Ok(());
+        }
+
+        let cpath = self.decode_path(fields[2])?;
+        // names are defined in modules declared before them: `use` lines name their module,
+        // and other items are in the module of their parent path
+        let module = if fields[0] == "use" {
+            Some((*cpath).clone())
+        } else {
+            cpath.parent()
+        };
+        let Some(module) = module else {
+            return Err(format!("path `{}` does not name an item in a module", fields[2]));
+        };
+        if !self.modules.contains(&module) {
+            return Err(format!("module `{:?}` is not declared before its items", module));
+        }
+        if fields[0] == "mod" {
+            self.modules.insert(Rc::clone(&cpath));
+        }
+        let kind = match fields[0] {
+            "mod" => ExportedItemKind::Mod,
+            "fn" => {
+                let flags: Vec<&str> = fields[3].split(',').collect();
+                let ty = self.decode_ty(fields[4])?;
+                if !matches!(ty.kind, TyKind::Fn(_, _, _)) {
+                    return Err(format!("expected fn type, but found `{}`", fields[4]));
+                }
+                ExportedItemKind::Fn {
+                    ty,
+                    is_unsafe: flags.contains(&"unsafe"),
+                    is_foreign: flags.contains(&"foreign"),
+                }
+            }
+            "struct" => ExportedItemKind::Struct(AdtDef {
+                fields: vec![],
+                field_vis: vec![],
+            }),
+            "const" => {
+                let ty = self.decode_ty(fields[3])?;
+                let value = decode_const_value(fields[4])?;
+                ExportedItemKind::Const(ty, value)
+            }
+            "static" => {
+                let mutable = fields[3] == "mut";
+                ExportedItemKind::Static(self.decode_ty(fields[4])?, mutable)
+            }
+            "use" => {
+                let kind = match fields[4] {
+                    "mod" => BindingKind::Mod,
+                    "item" => BindingKind::Item,
+                    "const" => BindingKind::Const,
+                    "static" => BindingKind::Static(false),
+                    "static_mut" => BindingKind::Static(true),
+                    kind => return Err(format!("unknown kind of name `{}`", kind)),
+                };
+                ExportedItemKind::Import {
+                    name: Rc::new(fields[3].to_string()),
+                    target: Rc::new(Binding {
+                        cpath: self.decode_path(fields[5])?,
+                        kind,
+                    }),
+                }
+            }
+            _ => unreachable!(),
+        };
+        self.items.push(ExportedItem {
+            vis,
+            cpath,
+            kind,
+            span: span.clone(),
+        });
+        Ok(())
+    }
+
+    /// `mylib::a::b`
+    fn decode_path(&self, s: &str) -> Result<Rc<CanonicalPath>, String> {
+        let segments: Vec<Rc<String>> = s.split("::").map(|seg| Rc::new(seg.to_string())).collect();
+        if segments[0] != self.name {
+            return Err(format!("path `{}` is not in crate `{}`", s, self.name));
+        }
+        if segments.iter().any(|seg| !is_ident(seg)) {
+            return Err(format!("invalid path `{}`", s));
+        }
+        Ok(Rc::new(CanonicalPath::new(segments)))
+    }
+
+    fn decode_ty(&self, s: &str) -> Result<Rc<Ty>, String> {
+        let mut parser = TyParser { s, decoder: self };
+        let ty = parser.parse_ty()?;
+        if !parser.s.is_empty() {
+            return Err(format!("unexpected `{}` after type", parser.s));
+        }
+        Ok(ty)
+    }
+}
+
+/// ty ::= "()" | "!" | "i32" | "bool" | "str" | "&" ty | "*const " ty | "[" ty "; " num "]"
+///      | "fn(" (ty ("," ty)* (", ...")?)? ") -> " ty | path
+struct TyParser<'a> {
+    /// rest of the input
+    s: &'a str,
+    decoder: &'a Decoder,
+}
+
+impl TyParser<'_> {
+    fn eat(&mut self, prefix: &str) -> bool {
+        if let Some(rest) = self.s.strip_prefix(prefix) {
+            self.s = rest;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, prefix: &str) -> Result<(), String> {
+        if self.eat(prefix) {
+            Ok(())
+        } else {
+            Err(format!("expected `{}` in type, but found `{}`", prefix, self.s))
+        }
+    }
+
+    fn parse_ty(&mut self) -> Result<Rc<Ty>, String> {
+        let kind = if self.eat("()") {
+            TyKind::Unit
+        } else if self.eat("!") {
+            TyKind::Never
+        } else if self.eat("&") {
+            TyKind::Ref(self.parse_ty()?)
+        } else if self.eat("*const ") {
+            TyKind::ConstPtr(self.parse_ty()?)
+        } else if self.eat("[") {
+            let elem_ty = self.parse_ty()?;
+            self.expect("; ")?;
+            let len_end = self.s.find(']').unwrap_or(self.s.len());
+            let Ok(n) = self.s[..len_end].parse() else {
+                return Err(format!("invalid array length in `{}`", self.s));
+            };
+            self.s = &self.s[len_end..];
+            self.expect("]")?;
+            TyKind::Array(elem_ty, n)
+        } else if self.eat("fn(") {
+            let mut params = vec![];
+            let mut variadic = false;
+            while !self.eat(")") {
+                if !params.is_empty() || variadic {
+                    self.expect(", ")?;
+                }
+                if self.eat("...") {
+                    variadic = true;
+                } else {
+                    params.push(self.parse_ty()?);
+                }
+            }
+            self.expect(" -> ")?;
+            TyKind::Fn(Rc::new(params), self.parse_ty()?, variadic)
+        } else {
+            let end = self
+                .s
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == ':'))
+                .unwrap_or(self.s.len());
+            let name = &self.s[..end];
+            self.s = &self.s[end..];
+            match name {
+                "i32" => TyKind::I32,
+                "bool" => TyKind::Bool,
+                "str" => TyKind::Str,
+                _ => TyKind::Adt(self.decoder.decode_path(name)?),
+            }
+        };
+        Ok(Rc::new(Ty::new(kind)))
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn decode_vis(s: &str) -> Result<Visibility, String> {
+    match s {
+        "pub" => Ok(Visibility::Public),
+        "pub(crate)" => Ok(Visibility::Crate),
+        "pub(super)" => Ok(Visibility::Super),
+        "priv" => Ok(Visibility::Private),
+        _ => Err(format!("unknown visibility `{}`", s)),
+    }
+}
+
+fn decode_const_value(s: &str) -> Result<ConstValue, String> {
+    match s {
+        "true" => Ok(ConstValue::Bool(true)),
+        "false" => Ok(ConstValue::Bool(false)),
+        "()" => Ok(ConstValue::Unit),
+        _ => match s.parse() {
+            Ok(n) => Ok(ConstValue::I32(n)),
+            Err(_) => Err(format!("invalid constant value `{}`", s)),
+        },
+    }
+}
@@ -0,0 +1,184 @@
+use super::HEADER;
+use crate::ast::{Crate, Func, Item, ItemKind, Visibility};
+use crate::middle::ctfe::ConstValue;
+use crate::middle::intrinsics::INTRINSIC_ABI;
+use crate::middle::ty::{Ty, TyKind};
+use crate::middle::Ctxt;
+use crate::resolve::{BindingKind, CanonicalPath};
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// Serialize items of a typechecked library crate
+pub fn encode(ctx: &mut Ctxt, krate: &Crate) -> String {
+    let mut encoder = Encoder {
+        crate_name: ctx.crate_name(),
+        ctx,
+        out: String::new(),
+        modules: vec![Rc::new(CanonicalPath::krate())],
+    };
+    writeln!(encoder.out, "{}", HEADER).unwrap();
+    writeln!(encoder.out, "crate\t{}", encoder.crate_name).unwrap();
+    encoder.encode_items(&krate.items);
+    // imports come last so that the modules containing them are already defined
+    for module in std::mem::take(&mut encoder.modules) {
+        encoder.encode_imports(&module);
+    }
+    encoder.out
+}
+
+struct Encoder<'a, 'ctx> {
+    ctx: &'a mut Ctxt<'ctx>,
+    crate_name: Rc<String>,
+    out: String,
+    /// all modules in the crate
+    modules: Vec<Rc<CanonicalPath>>,
+}
+
+impl Encoder<'_, '_> {
+    fn encode_items(&mut self, items: &[Item]) {
+        for item in items {
+            match &item.kind {
+                ItemKind::Mod(module) => {
+                    let binding = self.ctx.get_binding(&module.name).unwrap();
+                    self.line("mod", module.vis, &binding.cpath, &[]);
+                    self.modules.push(Rc::clone(&binding.cpath));
+                    self.encode_items(&module.items);
+                }
+                ItemKind::Func(func) => self.encode_func(func, func.is_unsafe, false),
+                ItemKind::ExternBlock(block) => {
+                    // intrinsics have no symbols to link to
+                    for func in block.funcs.iter().filter(|f| f.ext.as_deref() != Some(INTRINSIC_ABI)) {
+                        self.encode_func(func, true, true);
+                    }
+                }
+                ItemKind::Struct(strct) => {
+                    let binding = self.ctx.get_binding(&strct.ident).unwrap();
+                    let adt = self.ctx.lookup_adt_def(&binding.cpath).unwrap();
+                    self.line("struct", strct.vis, &binding.cpath, &[]);
+                    for ((name, ty), (vis, _)) in adt.fields.iter().zip(adt.field_vis.iter()) {
+                        let ty = self.encode_ty(ty);
+                        writeln!(self.out, "field\t{}\t{}\t{}", encode_vis(*vis), name, ty).unwrap();
+                    }
+                }
+                ItemKind::Const(const_item) => {
+                    let binding = self.ctx.get_binding(&const_item.name).unwrap();
+                    let ty = self.ctx.lookup_name_type(&binding).unwrap();
+                    let value = self.ctx.lookup_const_value(&binding).unwrap();
+                    let fields = [self.encode_ty(&ty), encode_const_value(value)];
+                    self.line("const", const_item.vis, &binding.cpath, &fields);
+                }
+                ItemKind::Static(static_item) => {
+                    let binding = self.ctx.get_binding(&static_item.name).unwrap();
+                    let ty = self.ctx.lookup_name_type(&binding).unwrap();
+                    let mutability = if static_item.mutable { "mut" } else { "-" };
+                    let fields = [mutability.to_string(), self.encode_ty(&ty)];
+                    self.line("static", static_item.vis, &binding.cpath, &fields);
+                }
+                // methods and type aliases cannot be used from other crates yet
+                ItemKind::Impl(_) | ItemKind::TypeAlias(_) => (),
+                ItemKind::Use(_) | ItemKind::ExternCrate(_) => (),
+                // macros cannot be exported yet
+                ItemKind::MacroRules(_) => (),
+                ItemKind::MacCall(_) => panic!("ICE: macro calls are expanded before encoding"),
+                ItemKind::Err => panic!("ICE: crates with syntax errors are not encoded"),
+            }
+        }
+    }
+
+    fn encode_func(&mut self, func: &Func, is_unsafe: bool, is_foreign: bool) {
+        let binding = self.ctx.get_binding(&func.name).unwrap();
+        let ty = self.ctx.lookup_name_type(&binding).unwrap();
+        let mut flags = vec![];
+        if is_unsafe {
+            flags.push("unsafe");
+        }
+        if is_foreign {
+            flags.push("foreign");
+        }
+        let flags = if flags.is_empty() {
+            "-".to_string()
+        } else {
+            flags.join(",")
+        };
+        let fields = [flags, self.encode_ty(&ty)];
+        self.line("fn", func.vis, &binding.cpath, &fields);
+    }
+
+    /// Names imported into `module` by `pub use`
+    fn encode_imports(&mut self, module: &CanonicalPath) {
+        for (name, vis, binding) in self.ctx.module_imports(module) {
+            // items of other extern crates cannot be re-exported yet
+            if vis != Visibility::Public || !binding.cpath.is_local() {
+                continue;
+            }
+            let kind = match binding.kind {
+                BindingKind::Mod => "mod",
+                BindingKind::Item => "item",
+                BindingKind::Const => "const",
+                BindingKind::Static(false) => "static",
+                BindingKind::Static(true) => "static_mut",
+                BindingKind::Let(_, _) | BindingKind::Param => unreachable!(),
+            };
+            let fields = [name.to_string(), kind.to_string(), self.encode_path(&binding.cpath)];
+            self.line("use", vis, module, &fields);
+        }
+    }
+
+    fn line(&mut self, kind: &str, vis: Visibility, cpath: &CanonicalPath, fields: &[String]) {
+        let path = self.encode_path(cpath);
+        write!(self.out, "{}\t{}\t{}", kind, encode_vis(vis), path).unwrap();
+        for field in fields {
+            write!(self.out, "\t{}", field).unwrap();
+        }
+        writeln!(self.out).unwrap();
+    }
+
+    /// `crate::a::b` => `mylib::a::b`
+    fn encode_path(&self, cpath: &CanonicalPath) -> String {
+        let mut segments: Vec<&str> = cpath.segments().iter().map(|seg| seg.as_str()).collect();
+        if cpath.is_local() {
+            segments[0] = &self.crate_name;
+        }
+        segments.join("::")
+    }
+
+    fn encode_ty(&self, ty: &Ty) -> String {
+        match &ty.kind {
+            TyKind::Unit => "()".to_string(),
+            TyKind::Bool => "bool".to_string(),
+            TyKind::I32 => "i32".to_string(),
+            TyKind::Str => "str".to_string(),
+            TyKind::Never => "!".to_string(),
+            TyKind::Array(elem_ty, n) => format!("[{}; {}]", self.encode_ty(elem_ty), n),
+            TyKind::Fn(params, ret, variadic) => {
+                let mut params: Vec<String> = params.iter().map(|param| self.encode_ty(param)).collect();
+                if *variadic {
+                    params.push("...".to_string());
+                }
+                format!("fn({}) -> {}", params.join(", "), self.encode_ty(ret))
+            }
+            TyKind::Adt(cpath) => self.encode_path(cpath),
+            TyKind::Ref(inner) => format!("&{}", self.encode_ty(inner)),
+            TyKind::ConstPtr(inner) => format!("*const {}", self.encode_ty(inner)),
+            TyKind::Closure(_) => panic!("ICE: closure types cannot be written in items"),
+            TyKind::Error => panic!("ICE: typecheck failed but metadata is encoded"),
+        }
+    }
+}
+
+fn encode_vis(vis: Visibility) -> &'static str {
+    match vis {
+        Visibility::Public => "pub",
+        Visibility::Crate => "pub(crate)",
+        Visibility::Super => "pub(super)",
+        Visibility::Private => "priv",
+    }
+}
+
+fn encode_const_value(value: ConstValue) -> String {
+    match value {
+        ConstValue::I32(n) => n.to_string(),
+        ConstValue::Bool(b) => b.to_string(),
+        ConstValue::Unit => "()".to_string(),
+    }
+}
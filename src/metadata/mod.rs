@@ -0,0 +1,94 @@
+//! Crate metadata
+//!
+//! Compiling a library with `--crate-type lib` writes a metadata file describing its items:
+//! their canonical paths, visibilities, signatures, struct definitions and constant values.
+//! Another crate loads it with `--extern name=path` to resolve and typecheck `name::item`,
+//! and is linked against the object compiled from the library.
+//!
+//! The file is line based text, and each line has tab-separated fields:
+//!
+//! ```text
+//! mini-rustc metadata 1
+//! crate   mylib
+//! mod     pub     mylib::a
+//! fn      pub     mylib::a::f     -               fn(i32, &str) -> i32
+//! fn      pub     mylib::puts     unsafe,foreign  fn(&str) -> i32
+//! struct  pub     mylib::S
+//! field   priv    x               i32
+//! const   pub     mylib::N        i32             3
+//! static  pub     mylib::C        mut             i32
+//! use     pub     mylib::a        g               item            mylib::b::g
+//! ```
+//!
+//! Paths start with the name of the library instead of `crate`.
+//! `field` lines belong to the preceding `struct`, and `use` lines are names
+//! imported into a module by `pub use`. A module is declared by its `mod` line
+//! before the items and `use` lines in it.
+
+mod decoder;
+mod encoder;
+
+pub use self::decoder::load;
+pub use self::encoder::encode;
+
+use crate::ast::Visibility;
+use crate::middle::ctfe::ConstValue;
+use crate::middle::ty::{AdtDef, Ty};
+use crate::resolve::{Binding, BindingKind, CanonicalPath};
+use crate::span::Span;
+use std::rc::Rc;
+
+const HEADER: &str = "mini-rustc metadata 1";
+
+#[derive(Debug)]
+pub struct CrateMetadata {
+    /// name of the library, which is the first segment of all paths in it
+    pub name: Rc<String>,
+    pub items: Vec<ExportedItem>,
+    /// line of the crate name in the metadata file
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct ExportedItem {
+    pub vis: Visibility,
+    /// path of the item, or of the module containing the `pub use`
+    pub cpath: Rc<CanonicalPath>,
+    pub kind: ExportedItemKind,
+    /// line of the item in the metadata file
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ExportedItemKind {
+    Mod,
+    Fn {
+        ty: Rc<Ty>,
+        is_unsafe: bool,
+        /// declared in an `extern` block
+        is_foreign: bool,
+    },
+    Struct(AdtDef),
+    Const(Rc<Ty>, ConstValue),
+    /// `static` or `static mut`
+    Static(Rc<Ty>, bool),
+    /// name imported by `pub use`
+    Import { name: Rc<String>, target: Rc<Binding> },
+}
+
+impl ExportedItem {
+    /// Binding of the item, or `None` for imports
+    pub fn binding(&self) -> Option<Rc<Binding>> {
+        let kind = match &self.kind {
+            ExportedItemKind::Mod => BindingKind::Mod,
+            ExportedItemKind::Fn { .. } | ExportedItemKind::Struct(_) => BindingKind::Item,
+            ExportedItemKind::Const(_, _) => BindingKind::Const,
+            ExportedItemKind::Static(_, mutable) => BindingKind::Static(*mutable),
+            ExportedItemKind::Import { .. } => return None,
+        };
+        Some(Rc::new(Binding {
+            cpath: Rc::clone(&self.cpath),
+            kind,
+        }))
+    }
+}
@@ -0,0 +1,345 @@
+//! Compile-time function evaluation
+//!
+//! A small tree-walking interpreter over the AST. It evaluates `const` items,
+//! array lengths, `static` initializers and calls to `const fn`s.
+
+use crate::ast::{self, BinOp, ExprKind, Item, ItemKind, StmtKind, UnOp};
+use crate::diagnostics::Diagnostic;
+use crate::middle::Ctxt;
+use crate::resolve::{Binding, BindingKind};
+use crate::span::Span;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Maximum depth of nested `const fn` calls
+const RECURSION_LIMIT: usize = 128;
+
+/// Value computed at compile time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstValue {
+    I32(i32),
+    Bool(bool),
+    Unit,
+}
+
+#[derive(Debug)]
+pub struct ConstEvalError {
+    pub msg: String,
+    pub span: Span,
+}
+
+impl From<ConstEvalError> for Diagnostic {
+    fn from(e: ConstEvalError) -> Self {
+        Diagnostic::error("evaluation of constant value failed").code("E0080").span_label(&e.span, e.msg)
+    }
+}
+
+/// Reason why evaluation stopped before reaching the end of an expression
+enum Unwind {
+    /// `return` in a `const fn` body
+    Return(ConstValue),
+    /// An error, already recorded in `Interpreter::errors`
+    Error,
+}
+
+type EvalResult = Result<ConstValue, Unwind>;
+
+#[derive(Default)]
+pub struct Interpreter<'a> {
+    /// initializers of all `const` items in the crate
+    const_inits: HashMap<Rc<Binding>, &'a ast::Expr>,
+    /// all `const fn`s in the crate
+    const_fns: HashMap<Rc<Binding>, &'a ast::Func>,
+    /// `const` items being evaluated, used to detect cycles
+    evaluating: Vec<Rc<Binding>>,
+    /// `const` items whose evaluation failed
+    failed: Vec<Rc<Binding>>,
+    /// local variables of the `const fn` calls being evaluated
+    frames: Vec<HashMap<Rc<Binding>, ConstValue>>,
+    errors: Vec<ConstEvalError>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consts and const fns can be used before their declarations, so register them first
+    pub fn collect_items(&mut self, ctx: &mut Ctxt, items: &'a [Item]) {
+        for item in items {
+            match &item.kind {
+                ItemKind::Const(const_item) => {
+                    let binding = ctx.get_binding(&const_item.name).unwrap();
+                    self.const_inits.insert(binding, &const_item.init);
+                }
+                ItemKind::Func(func) if func.is_const && func.body.is_some() => {
+                    let binding = ctx.get_binding(&func.name).unwrap();
+                    self.const_fns.insert(binding, func);
+                }
+                ItemKind::Mod(module) => self.collect_items(ctx, &module.items),
+                _ => (),
+            }
+        }
+    }
+
+    pub fn is_const_fn(&self, binding: &Binding) -> bool {
+        self.const_fns.contains_key(binding)
+    }
+
+    /// Errors reported since the last call
+    pub fn take_errors(&mut self) -> Vec<ConstEvalError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Evaluate a `const` item. The result is cached in `ctx`.
+    pub fn eval_const_item(&mut self, ctx: &mut Ctxt, binding: &Rc<Binding>) -> Option<ConstValue> {
+        if let Some(val) = ctx.lookup_const_value(binding) {
+            return Some(val);
+        }
+        if self.failed.contains(binding) {
+            return None;
+        }
+        let init = *self.const_inits.get(binding).unwrap();
+        if self.evaluating.contains(binding) {
+            self.error(
+                format!("cycle detected when evaluating constant `{:?}`", binding.cpath),
+                &init.span,
+            );
+            return None;
+        }
+        self.evaluating.push(Rc::clone(binding));
+        let val = self.eval_const_expr(ctx, init);
+        self.evaluating.pop();
+        match val {
+            Some(val) => ctx.set_const_value(Rc::clone(binding), val),
+            None => self.failed.push(Rc::clone(binding)),
+        }
+        val
+    }
+
+    /// Evaluate an expression in a constant context such as an array length
+    pub fn eval_const_expr(&mut self, ctx: &mut Ctxt, expr: &ast::Expr) -> Option<ConstValue> {
+        // `const` initializers are evaluated in a fresh frame, independent of the caller
+        let saved_frames = std::mem::replace(&mut self.frames, vec![HashMap::new()]);
+        let val = match self.eval_expr(ctx, expr) {
+            Ok(val) => Some(val),
+            Err(Unwind::Return(_)) => {
+                self.error("`return` outside of a function".to_string(), &expr.span);
+                None
+            }
+            Err(Unwind::Error) => None,
+        };
+        self.frames = saved_frames;
+        val
+    }
+
+    fn error(&mut self, msg: String, span: &Span) {
+        self.errors.push(ConstEvalError {
+            msg,
+            span: span.clone(),
+        });
+    }
+
+    fn fail(&mut self, msg: String, span: &Span) -> EvalResult {
+        self.error(msg, span);
+        Err(Unwind::Error)
+    }
+
+    fn eval_expr(&mut self, ctx: &mut Ctxt, expr: &ast::Expr) -> EvalResult {
+        match &expr.kind {
+            ExprKind::NumLit(n) => match i32::try_from(*n) {
+                Ok(n) => Ok(ConstValue::I32(n)),
+                Err(_) => self.fail(format!("literal `{}` is out of range for `i32`", n), &expr.span),
+            },
+            ExprKind::BoolLit(b) => Ok(ConstValue::Bool(*b)),
+            ExprKind::Unit => Ok(ConstValue::Unit),
+            ExprKind::Unary(UnOp::Minus, inner) if matches!(inner.kind, ExprKind::NumLit(n) if n == 1 << 31) => {
+                Ok(ConstValue::I32(i32::MIN))
+            }
+            ExprKind::Unary(op, inner) => match (op, self.eval_expr(ctx, inner)?) {
+                (UnOp::Plus, ConstValue::I32(n)) => Ok(ConstValue::I32(n)),
+                (UnOp::Minus, ConstValue::I32(n)) => match n.checked_neg() {
+                    Some(n) => Ok(ConstValue::I32(n)),
+                    None => self.fail("attempt to negate with overflow".to_string(), &expr.span),
+                },
+                _ => self.unsupported(expr),
+            },
+            ExprKind::Binary(op, l, r) => {
+                let l = self.eval_expr(ctx, l)?;
+                let r = self.eval_expr(ctx, r)?;
+                self.eval_binary(op, l, r, expr)
+            }
+            ExprKind::Path(path) => {
+                let Some(binding) = ctx.resolve_path(path) else {
+                    return self.fail(format!("cannot resolve `{}`", path.span.to_snippet()), &path.span);
+                };
+                match binding.kind {
+                    BindingKind::Const => self
+                        .eval_const_item(ctx, &binding)
+                        .ok_or(Unwind::Error),
+                    BindingKind::Let(_, _) | BindingKind::Param => {
+                        match self.frames.last().unwrap().get(&binding) {
+                            Some(val) => Ok(*val),
+                            None => self.fail(
+                                format!(
+                                    "`{}` is not a constant and cannot be used at compile time",
+                                    path.span.to_snippet()
+                                ),
+                                &path.span,
+                            ),
+                        }
+                    }
+                    BindingKind::Static(_) => self.fail(
+                        format!(
+                            "constants cannot refer to statics, use a constant instead of `{}`",
+                            path.span.to_snippet()
+                        ),
+                        &path.span,
+                    ),
+                    _ => self.fail(
+                        format!(
+                            "`{}` is not a constant and cannot be used at compile time",
+                            path.span.to_snippet()
+                        ),
+                        &path.span,
+                    ),
+                }
+            }
+            ExprKind::Block(block) => self.eval_block(ctx, block),
+            ExprKind::If(cond, then, els) => match self.eval_expr(ctx, cond)? {
+                ConstValue::Bool(true) => self.eval_expr(ctx, then),
+                ConstValue::Bool(false) => match els {
+                    Some(els) => self.eval_expr(ctx, els),
+                    None => Ok(ConstValue::Unit),
+                },
+                _ => self.unsupported(cond),
+            },
+            ExprKind::Return(val) => {
+                let val = self.eval_expr(ctx, val)?;
+                Err(Unwind::Return(val))
+            }
+            ExprKind::Assign(lhs, rhs) => {
+                let val = self.eval_expr(ctx, rhs)?;
+                let ExprKind::Path(path) = &lhs.kind else {
+                    return self.unsupported(lhs);
+                };
+                let binding = ctx.resolve_path(path);
+                match binding {
+                    Some(binding) if self.frames.last().unwrap().contains_key(&binding) => {
+                        self.frames.last_mut().unwrap().insert(binding, val);
+                        Ok(ConstValue::Unit)
+                    }
+                    _ => self.fail(
+                        format!("cannot assign to `{}` at compile time", path.span.to_snippet()),
+                        &lhs.span,
+                    ),
+                }
+            }
+            ExprKind::Call(callee, args) => self.eval_call(ctx, callee, args, expr),
+            _ => self.unsupported(expr),
+        }
+    }
+
+    fn unsupported(&mut self, expr: &ast::Expr) -> EvalResult {
+        self.fail(
+            "this expression cannot be evaluated at compile time".to_string(),
+            &expr.span,
+        )
+    }
+
+    fn eval_binary(&mut self, op: &BinOp, l: ConstValue, r: ConstValue, expr: &ast::Expr) -> EvalResult {
+        let (l, r) = match (op, l, r) {
+            (BinOp::Eq, l, r) => return Ok(ConstValue::Bool(l == r)),
+            (BinOp::Ne, l, r) => return Ok(ConstValue::Bool(l != r)),
+            (_, ConstValue::I32(l), ConstValue::I32(r)) => (l, r),
+            _ => return self.unsupported(expr),
+        };
+        let (val, msg) = match op {
+            BinOp::Add => (l.checked_add(r), "attempt to add with overflow"),
+            BinOp::Sub => (l.checked_sub(r), "attempt to subtract with overflow"),
+            BinOp::Mul => (l.checked_mul(r), "attempt to multiply with overflow"),
+            BinOp::Div if r == 0 => (None, "attempt to divide by zero"),
+            BinOp::Div => (l.checked_div(r), "attempt to divide with overflow"),
+            BinOp::Rem if r == 0 => (
+                None,
+                "attempt to calculate the remainder with a divisor of zero",
+            ),
+            BinOp::Rem => (
+                l.checked_rem(r),
+                "attempt to calculate the remainder with overflow",
+            ),
+            BinOp::Gt => return Ok(ConstValue::Bool(l > r)),
+            BinOp::Lt => return Ok(ConstValue::Bool(l < r)),
+            BinOp::Eq | BinOp::Ne => unreachable!(),
+        };
+        match val {
+            Some(val) => Ok(ConstValue::I32(val)),
+            None => self.fail(msg.to_string(), &expr.span),
+        }
+    }
+
+    fn eval_block(&mut self, ctx: &mut Ctxt, block: &ast::Block) -> EvalResult {
+        let mut val = ConstValue::Unit;
+        for stmt in &block.stmts {
+            val = match &stmt.kind {
+                StmtKind::Expr(expr) => self.eval_expr(ctx, expr)?,
+                StmtKind::Semi(expr) => {
+                    self.eval_expr(ctx, expr)?;
+                    ConstValue::Unit
+                }
+                StmtKind::Let(let_stmt) => {
+                    let binding = ctx.get_binding(&let_stmt.ident).unwrap();
+                    if let Some(init) = &let_stmt.init {
+                        let init = self.eval_expr(ctx, init)?;
+                        self.frames.last_mut().unwrap().insert(binding, init);
+                    }
+                    ConstValue::Unit
+                }
+            };
+        }
+        Ok(val)
+    }
+
+    fn eval_call(
+        &mut self,
+        ctx: &mut Ctxt,
+        callee: &ast::Expr,
+        args: &[ast::Expr],
+        expr: &ast::Expr,
+    ) -> EvalResult {
+        let ExprKind::Path(path) = &callee.kind else {
+            return self.unsupported(callee);
+        };
+        let binding = ctx.resolve_path(path);
+        let Some(func) = binding.and_then(|binding| self.const_fns.get(&binding).copied()) else {
+            return self.fail(
+                format!(
+                    "cannot call non-const fn `{}` in constant context",
+                    path.span.to_snippet()
+                ),
+                &expr.span,
+            );
+        };
+        if self.frames.len() > RECURSION_LIMIT {
+            return self.fail(
+                format!(
+                    "reached the recursion limit while calling `{}`",
+                    path.span.to_snippet()
+                ),
+                &expr.span,
+            );
+        }
+        let mut frame = HashMap::new();
+        for ((param, _), arg) in func.params.iter().zip(args) {
+            let val = self.eval_expr(ctx, arg)?;
+            frame.insert(ctx.get_binding(param).unwrap(), val);
+        }
+        self.frames.push(frame);
+        let val = self.eval_block(ctx, func.body.as_ref().unwrap());
+        self.frames.pop();
+        match val {
+            Ok(val) | Err(Unwind::Return(val)) => Ok(val),
+            Err(Unwind::Error) => Err(Unwind::Error),
+        }
+    }
+}
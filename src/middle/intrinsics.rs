@@ -0,0 +1,31 @@
+//! Intrinsics: functions implemented by the compiler
+//!
+//! They are declared in `extern "rust-intrinsic"` blocks, and calls to them are lowered to
+//! instructions instead of calls.
+
+/// ABI of the extern blocks declaring intrinsics
+pub const INTRINSIC_ABI: &str = "rust-intrinsic";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intrinsic {
+    WrappingAdd,
+    WrappingSub,
+    WrappingMul,
+    SaturatingAdd,
+    SaturatingSub,
+    SaturatingMul,
+}
+
+impl Intrinsic {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wrapping_add" => Some(Intrinsic::WrappingAdd),
+            "wrapping_sub" => Some(Intrinsic::WrappingSub),
+            "wrapping_mul" => Some(Intrinsic::WrappingMul),
+            "saturating_add" => Some(Intrinsic::SaturatingAdd),
+            "saturating_sub" => Some(Intrinsic::SaturatingSub),
+            "saturating_mul" => Some(Intrinsic::SaturatingMul),
+            _ => None,
+        }
+    }
+}
@@ -1,17 +1,34 @@
+pub mod ctfe;
+pub mod intrinsics;
 pub mod ty;
 
-use crate::ast::{self, Crate, NodeId, Path};
+use crate::ast::{self, Crate, NodeId, Path, Visibility};
+use crate::diagnostics::Diagnostic;
 //use crate::hir::{self, HirId, LocalDefId};
 //use crate::hir::HirId;
+use crate::middle::ctfe::ConstValue;
+use crate::middle::intrinsics::Intrinsic;
+use crate::metadata::{CrateMetadata, ExportedItemKind};
 use crate::middle::ty::{AdtDef, Ty};
-use crate::resolve::{Binding, CanonicalPath, Resolver};
+use crate::resolve::{Binding, BindingKind, CanonicalPath, PathSuggestion, Resolver};
 use crate::span::Ident;
 use std::collections::HashMap;
 use std::rc::Rc;
 
+/// `--crate-type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrateType {
+    Bin,
+    Lib,
+}
+
 #[derive(Debug)]
 pub struct Ctxt<'ctx> {
     pub dump_enabled: bool,
+    crate_name: Rc<String>,
+    pub crate_type: CrateType,
+    /// libraries loaded by `--extern`
+    extern_crates: Vec<CrateMetadata>,
     // Set during name resolution stage
     resolver: Resolver,
 
@@ -28,6 +45,12 @@ pub struct Ctxt<'ctx> {
     pub name_ty_mappings: HashMap<Rc<Binding>, Rc<Ty>>,
     // TODO: use NameBinding
     adt_defs: HashMap<Rc<CanonicalPath>, Rc<AdtDef>>,
+    /// values of `const` items and initializers of `static` items
+    const_values: HashMap<Rc<Binding>, ConstValue>,
+    /// variables holding closures which are coerced to `fn` pointers, and the closure expressions
+    coerced_closures: HashMap<NodeId, NodeId>,
+    /// functions called by method call expressions
+    method_callees: HashMap<NodeId, Rc<Binding>>,
     // Set during rvalue anlaysis stage
     // all node ids of place expressions
     // ref: https://doc.rust-lang.org/reference/expressions.html?highlight=rvalue#place-expressions-and-value-expressions
@@ -35,27 +58,79 @@ pub struct Ctxt<'ctx> {
 }
 
 impl<'ctx> Ctxt<'ctx> {
-    pub fn new(dump_enabled: bool) -> Self {
+    pub fn new(dump_enabled: bool, crate_name: Rc<String>, crate_type: CrateType) -> Self {
         Ctxt {
             dump_enabled,
+            crate_name,
+            crate_type,
+            extern_crates: vec![],
             resolver: Resolver::new(),
 
             //hir_root_module: LocalDefId::dummy(),
             //hir_items: HashMap::new(),
             //hir_ty_mappings: HashMap::new(),
-            phantom: std::marker::PhantomData::default(),
+            phantom: std::marker::PhantomData,
 
             ty_mappings: HashMap::new(),
             name_ty_mappings: HashMap::new(),
             adt_defs: HashMap::new(),
+            const_values: HashMap::new(),
+            coerced_closures: HashMap::new(),
+            method_callees: HashMap::new(),
             // lvalues: HashSet::new(),
         }
     }
 
+    pub fn crate_name(&self) -> Rc<String> {
+        Rc::clone(&self.crate_name)
+    }
+
+    // Loading extern crates
+
+    /// Make items of a library usable as `name::item`
+    pub fn load_extern_crate(&mut self, name: Rc<String>, metadata: CrateMetadata) -> Result<(), String> {
+        if self.extern_crates.iter().any(|krate| krate.name == metadata.name) {
+            return Err(format!(
+                "Crate `{}` is loaded more than once at {}",
+                metadata.name,
+                metadata.span.location()
+            ));
+        }
+        self.resolver.define_extern_crate(name, &metadata);
+        for item in &metadata.items {
+            match &item.kind {
+                ExportedItemKind::Fn { ty, .. } | ExportedItemKind::Static(ty, _) => {
+                    self.set_name_type(item.binding().unwrap(), Rc::clone(ty));
+                }
+                ExportedItemKind::Const(ty, value) => {
+                    let binding = item.binding().unwrap();
+                    self.set_name_type(Rc::clone(&binding), Rc::clone(ty));
+                    self.set_const_value(binding, *value);
+                }
+                ExportedItemKind::Struct(adt) => {
+                    self.set_adt_def(Rc::clone(&item.cpath), adt.clone());
+                }
+                ExportedItemKind::Mod | ExportedItemKind::Import { .. } => (),
+            }
+        }
+        self.extern_crates.push(metadata);
+        Ok(())
+    }
+
+    pub fn extern_crates(&self) -> &[CrateMetadata] {
+        &self.extern_crates
+    }
+
     // Resolution Stage
 
-    pub fn run_resolver(&mut self, krate: &Crate) {
+    pub fn run_resolver(&mut self, krate: &Crate) -> Result<(), Vec<Diagnostic>> {
         ast::visitor::go(&mut self.resolver, krate);
+        let errors = self.resolver.take_errors();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 
     /// Resolve identifiers in var decls (func params or local variables) to canonical paths
@@ -68,12 +143,51 @@ impl<'ctx> Ctxt<'ctx> {
         self.resolver.resolve_path(path)
     }
 
-    pub fn dump_ribs(&self) {
-        self.resolver.dump_ribs();
+    /// Function of `i32` named `method`, which can be called like `x.method()` or `i32::method(x)`
+    pub fn resolve_i32_method(&self, method: &Ident) -> Option<Rc<Binding>> {
+        self.resolver.resolve_i32_item(method)
+    }
+
+    /// Similar name or path for `path`, which does not resolve to a value
+    pub fn suggest_value_path(&self, path: &Path) -> Option<PathSuggestion> {
+        self.resolver.suggest_path(path, |binding| {
+            binding.kind != BindingKind::Mod && !self.resolver.is_struct(&binding.cpath)
+        })
+    }
+
+    /// Similar name or path for `path`, which does not resolve to a struct
+    pub fn suggest_struct_path(&self, path: &Path) -> Option<PathSuggestion> {
+        self.resolver.suggest_path(path, |binding| self.resolver.is_struct(&binding.cpath))
+    }
+
+    pub fn is_foreign_fn(&self, cpath: &CanonicalPath) -> bool {
+        self.resolver.is_foreign_fn(cpath)
+    }
+
+    /// Intrinsic declared as `cpath` in an `extern "rust-intrinsic"` block
+    pub fn lookup_intrinsic(&self, cpath: &CanonicalPath) -> Option<Intrinsic> {
+        if self.resolver.is_intrinsic(cpath) {
+            Intrinsic::from_name(cpath.segments().last().unwrap())
+        } else {
+            None
+        }
     }
 
-    pub fn dump_resolution(&self) {
-        self.resolver.dump_resolution();
+    /// Local variables of enclosing functions used in the closure expression `closure`
+    pub fn closure_captures(&self, closure: NodeId) -> Vec<Rc<Binding>> {
+        self.resolver.closure_captures(closure).to_vec()
+    }
+
+    pub fn module_imports(&self, module: &CanonicalPath) -> Vec<(Rc<String>, Visibility, Rc<Binding>)> {
+        self.resolver.module_imports(module)
+    }
+
+    pub fn dump_ribs(&self) -> String {
+        self.resolver.dump_ribs()
+    }
+
+    pub fn dump_resolution(&self) -> String {
+        self.resolver.dump_resolution()
     }
 
     // Typecheck Stage
@@ -86,6 +200,30 @@ impl<'ctx> Ctxt<'ctx> {
         Rc::clone(self.ty_mappings.get(&node_id).unwrap())
     }
 
+    pub fn lookup_type(&self, node_id: NodeId) -> Option<Rc<Ty>> {
+        self.ty_mappings.get(&node_id).map(Rc::clone)
+    }
+
+    /// Record that the expression `expr` holding the closure `closure` is used as a `fn` pointer
+    pub fn set_coerced_closure(&mut self, expr: NodeId, closure: NodeId) {
+        self.coerced_closures.insert(expr, closure);
+    }
+
+    /// Closure held by `expr` if it is coerced to a `fn` pointer
+    pub fn coerced_closure(&self, expr: NodeId) -> Option<NodeId> {
+        self.coerced_closures.get(&expr).copied()
+    }
+
+    /// Record that the method call expression `expr` calls the function `callee`
+    pub fn set_method_callee(&mut self, expr: NodeId, callee: Rc<Binding>) {
+        self.method_callees.insert(expr, callee);
+    }
+
+    /// Function called by the method call expression `expr`
+    pub fn method_callee(&self, expr: NodeId) -> Rc<Binding> {
+        Rc::clone(&self.method_callees[&expr])
+    }
+
     pub fn lookup_name_type(&self, binding: &Binding) -> Option<Rc<Ty>> {
         self.name_ty_mappings.get(binding).map(Rc::clone)
     }
@@ -106,6 +244,14 @@ impl<'ctx> Ctxt<'ctx> {
         &self.adt_defs
     }
 
+    pub fn lookup_const_value(&self, binding: &Binding) -> Option<ConstValue> {
+        self.const_values.get(binding).copied()
+    }
+
+    pub fn set_const_value(&mut self, binding: Rc<Binding>, value: ConstValue) {
+        self.const_values.insert(binding, value);
+    }
+
     // Rvalue analysis stage
     /*
     pub fn register_lvalue(&mut self, node_id: NodeId) {
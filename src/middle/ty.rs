@@ -1,6 +1,8 @@
 use std::rc::Rc;
 
+use crate::ast::{NodeId, Visibility};
 use crate::resolve::CanonicalPath;
+use crate::span::Ident;
 
 #[derive(PartialEq, Eq)]
 pub struct Ty {
@@ -19,9 +21,22 @@ pub enum TyKind {
     Ref(Rc<Ty>),
     Never,
     ConstPtr(Rc<Ty>),
+    /// type of a closure expression, distinct from that of any other closure
+    Closure(Rc<ClosureTy>),
     Error,
 }
 
+/// Signature of a closure expression
+#[derive(PartialEq, Eq)]
+pub struct ClosureTy {
+    /// the closure expression
+    pub id: NodeId,
+    /// location of the closure expression for diagnostics
+    pub location: String,
+    pub params: Vec<Rc<Ty>>,
+    pub ret_ty: Rc<Ty>,
+}
+
 impl Ty {
     pub fn new(kind: TyKind) -> Self {
         Ty { kind }
@@ -51,6 +66,7 @@ impl Ty {
         }
     }
 
+    #[allow(clippy::type_complexity)]
     pub fn get_func_type(&self) -> Option<(Rc<Vec<Rc<Ty>>>, Rc<Ty>)> {
         if let TyKind::Fn(params, ret, _) = &self.kind {
             Some((Rc::clone(params), Rc::clone(ret)))
@@ -59,6 +75,16 @@ impl Ty {
         }
     }
 
+    /// Parameter and return types of a function or closure, and whether it is variadic
+    #[allow(clippy::type_complexity)]
+    pub fn get_callable_sig(&self) -> Option<(&[Rc<Ty>], &Rc<Ty>, bool)> {
+        match &self.kind {
+            TyKind::Fn(params, ret, variadic) => Some((params, ret, *variadic)),
+            TyKind::Closure(closure) => Some((&closure.params, &closure.ret_ty, false)),
+            _ => None,
+        }
+    }
+
     /*
     pub fn is_adt(&self) -> bool {
         matches!(self, Ty::Adt(_))
@@ -68,11 +94,18 @@ impl Ty {
     pub fn is_never(&self) -> bool {
         matches!(&self.kind, TyKind::Never)
     }
+
+    /// The type of erroneous code, whose errors are already reported
+    pub fn is_error(&self) -> bool {
+        matches!(&self.kind, TyKind::Error)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AdtDef {
     pub fields: Vec<(Rc<String>, Rc<Ty>)>,
+    /// visibilities and declarations of `fields`
+    pub field_vis: Vec<(Visibility, Ident)>,
 }
 
 impl std::fmt::Debug for Ty {
@@ -80,3 +113,41 @@ impl std::fmt::Debug for Ty {
         write!(f, "{:?}", self.kind)
     }
 }
+
+impl std::fmt::Debug for ClosureTy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{closure@{}}}", self.location)
+    }
+}
+
+/// Type in Rust syntax for diagnostics, e.g. `[&str; 3]`
+impl std::fmt::Display for Ty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            TyKind::Unit => write!(f, "()"),
+            TyKind::Bool => write!(f, "bool"),
+            TyKind::I32 => write!(f, "i32"),
+            TyKind::Str => write!(f, "str"),
+            TyKind::Array(elem_ty, n) => write!(f, "[{}; {}]", elem_ty, n),
+            TyKind::Fn(params, ret_ty, variadic) => {
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                if *variadic {
+                    write!(f, ", ...")?;
+                }
+                write!(f, ") -> {}", ret_ty)
+            }
+            TyKind::Adt(cpath) => write!(f, "{:?}", cpath),
+            TyKind::Ref(inner) => write!(f, "&{}", inner),
+            TyKind::Never => write!(f, "!"),
+            TyKind::ConstPtr(inner) => write!(f, "*const {}", inner),
+            TyKind::Closure(closure) => write!(f, "{:?}", closure),
+            TyKind::Error => write!(f, "{{error}}"),
+        }
+    }
+}
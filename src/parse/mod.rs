@@ -1,24 +1,106 @@
 mod parse_expr;
 mod parse_item;
+mod parse_macro;
 mod parse_stmt;
 
+pub use self::parse_macro::can_begin_fragment;
 use self::parse_item::is_item_start;
-use crate::ast::{Crate, Item, NodeId, Path};
+use crate::ast::{Crate, Item, ItemKind, NodeId, Path};
+use crate::diagnostics::Diagnostic;
 use crate::lexer::{Lexer, Token, TokenKind};
-use crate::span::Ident;
+use crate::span::{FileId, Ident, Span};
+use std::path::PathBuf;
 use std::rc::Rc;
 
+/// Syntax errors found by the lexer and the parser
+#[derive(Debug)]
+pub struct ParseError {
+    pub errors: Vec<Diagnostic>,
+    /// The crate with the items which failed to parse replaced by `ItemKind::Err`, which
+    /// later stages can still check to report their errors too. `None` for the prelude.
+    pub recovered: Option<Crate>,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 pub struct Parser {
     lexer: Lexer,
     next_node_id: u32,
+    /// Directory where files of child modules of the current module are searched
+    module_dir: PathBuf,
+    next_file_id: u32,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Parser {
-    pub fn new(lexer: Lexer) -> Self {
+    /// `root_dir` is the directory containing the crate root file
+    pub fn new(lexer: Lexer, root_dir: PathBuf) -> Self {
+        let next_file_id = lexer.file().id.0 + 1;
         Parser {
             lexer,
             next_node_id: 0,
+            module_dir: root_dir,
+            next_file_id,
+            diagnostics: vec![],
+        }
+    }
+
+    /// Errors found so far, including those of the lexer
+    pub(crate) fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        let mut diags = self.lexer.take_diagnostics();
+        diags.append(&mut self.diagnostics);
+        diags
+    }
+
+    /// Record an error. An error at the same place as the previous one is a consequence
+    /// of recovering from it, and is dropped.
+    fn error(&mut self, diag: Diagnostic) {
+        let last_span = self.diagnostics.last().and_then(Diagnostic::primary_span);
+        if last_span.is_some() && last_span == diag.primary_span() {
+            return;
+        }
+        self.diagnostics.push(diag);
+    }
+
+    /// Record that `expected` was expected instead of the next token
+    fn expected(&mut self, expected: &str) {
+        let t = self.peek_token().clone();
+        self.expected_at(expected, &t);
+    }
+
+    /// Record that `expected` was expected instead of `found`
+    fn expected_at(&mut self, expected: &str, found: &Token) {
+        // invalid tokens are already reported by the lexer
+        if found.kind == TokenKind::Unknown {
+            return;
         }
+        let found_descr = match found.kind {
+            TokenKind::Eof => "end of input".to_string(),
+            _ => format!("`{}`", found.span.to_snippet()),
+        };
+        self.error(
+            Diagnostic::error(format!("expected {}, found {}", expected, found_descr))
+                .code("M0001")
+                .span_label(&found.span, format!("expected {}", expected)),
+        );
+    }
+
+    /// First file id not used by the parsed files
+    pub fn next_file_id(&self) -> FileId {
+        FileId(self.next_file_id)
     }
 
     pub fn get_next_id(&mut self) -> NodeId {
@@ -56,27 +138,94 @@ impl Parser {
         )
     }
 
-    /// crate ::= item*
-    pub fn parse_crate(&mut self) -> Option<Crate> {
-        let items = self.parse_items()?;
-        if !self.at_eof() {
-            eprintln!(
-                "Expected crate item but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
-            return None;
-        }
+    /// crate ::= innerAttr* item*
+    pub fn parse_crate(&mut self) -> Result<Crate, ParseError> {
+        let attrs = self.parse_inner_attrs();
+        let items = self.parse_items_to_eof();
         let id = self.get_next_id();
-        Some(Crate { items, id })
+        let krate = Crate { attrs, items, id };
+        let errors = self.take_diagnostics();
+        if errors.is_empty() {
+            Ok(krate)
+        } else {
+            Err(ParseError {
+                errors,
+                recovered: Some(krate),
+            })
+        }
     }
 
-    fn parse_items(&mut self) -> Option<Vec<Item>> {
+    /// items ::= item*
+    /// Parse items up to the `}` closing the module or the end of the file.
+    /// An item which fails to parse is replaced by `ItemKind::Err`, and parsing
+    /// resumes at the start of the next item.
+    fn parse_items(&mut self) -> Vec<Item> {
         let mut items = vec![];
+        loop {
+            let depth = self.lexer.brace_depth();
+            let t = self.peek_token();
+            if matches!(t.kind, TokenKind::CloseBrace | TokenKind::Eof) {
+                return items;
+            }
+            let start = t.span.clone();
+            let item = if is_item_start(t) {
+                self.parse_item()
+            } else {
+                self.expected("item");
+                None
+            };
+            items.push(item.unwrap_or_else(|| {
+                self.recover_item(&start, depth);
+                Item {
+                    attrs: vec![],
+                    kind: ItemKind::Err,
+                }
+            }));
+        }
+    }
 
-        while is_item_start(self.peek_token()) {
-            items.push(self.parse_item()?);
+    /// Parse all items of a file, skipping unmatched `}`
+    fn parse_items_to_eof(&mut self) -> Vec<Item> {
+        let mut items = self.parse_items();
+        while !self.at_eof() {
+            let t = self.skip_token();
+            self.error(
+                Diagnostic::error("unexpected closing delimiter: `}`")
+                    .code("M0002")
+                    .span_label(&t.span, "unexpected closing delimiter"),
+            );
+            items.append(&mut self.parse_items());
+        }
+        items
+    }
+
+    /// Skip the rest of an item which failed to parse at `start`, up to the start of
+    /// the next item or the `}` closing the module. A `;` ends the broken item.
+    /// Blocks opened since the start of the item, at `depth`, are skipped as a whole.
+    fn recover_item(&mut self, start: &Span, depth: usize) {
+        // always make progress, even if the item failed at its first token
+        if self.peek_token().span == *start {
+            self.skip_token();
+        }
+        loop {
+            let nested = self.lexer.brace_depth() > depth;
+            let t = self.peek_token();
+            match &t.kind {
+                TokenKind::Eof => return,
+                _ if nested => (),
+                TokenKind::CloseBrace => return,
+                TokenKind::Semi => {
+                    self.skip_token();
+                    return;
+                }
+                // identifiers are not used as sync points since they also start macro calls
+                TokenKind::Ident(name) if name == "macro_rules" => return,
+                TokenKind::Ident(_) => (),
+                _ if is_item_start(t) => return,
+                _ => (),
+            }
+            self.skip_token();
         }
-        Some(items)
     }
 
     fn parse_ident(&mut self) -> Option<Ident> {
@@ -87,16 +236,25 @@ impl Parser {
                 span: t.span,
             })
         } else {
-            eprintln!("Expected ident, but found `{}`", t.span.to_snippet());
+            self.expected_at("identifier", &t);
             None
         }
     }
 
-    /// path ::= pathSegment ("::" PathSegment)*
+    /// path ::= (pathSegment | "i32") ("::" PathSegment)*
     /// pathSegment ::= ident
     /// ref: https://doc.rust-lang.org/reference/paths.html#paths
     fn parse_path(&mut self) -> Option<Path> {
-        let ident = self.parse_ident()?;
+        // paths to associated items of primitive types such as `i32::MAX`
+        let ident = if self.peek_token().kind == TokenKind::I32 {
+            let t = self.skip_token();
+            Ident {
+                symbol: Rc::new("i32".to_string()),
+                span: t.span,
+            }
+        } else {
+            self.parse_ident()?
+        };
         let mut span = ident.span.clone();
         let mut segs = vec![ident];
 
@@ -9,10 +9,18 @@ pub fn is_expr_start(token: &Token) -> bool {
         TokenKind::NumLit(_)
             | TokenKind::StrLit(_)
             | TokenKind::Ident(_)
+            | TokenKind::I32
             | TokenKind::OpenParen
             | TokenKind::OpenBrace
             | TokenKind::OpenBracket
-            | TokenKind::BinOp(lexer::BinOp::Plus | lexer::BinOp::Minus | lexer::BinOp::And | lexer::BinOp::Star)
+            | TokenKind::BinOp(
+                lexer::BinOp::Plus
+                    | lexer::BinOp::Minus
+                    | lexer::BinOp::And
+                    | lexer::BinOp::Star
+                    | lexer::BinOp::Or
+            )
+            | TokenKind::Move
             | TokenKind::Return
             | TokenKind::True
             | TokenKind::False
@@ -31,10 +39,7 @@ impl Parser {
     fn parse_if_expr(&mut self) -> Option<Expr> {
         let mut span = self.peek_token().span.clone();
         if !self.skip_expected_token(TokenKind::If) {
-            eprintln!(
-                "Expected \"if\", but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`if`");
             return None;
         }
         let cond = self.parse_expr()?;
@@ -137,47 +142,53 @@ impl Parser {
         })
     }
 
-    /// add ::= mul (("+"|"-") add)?
+    /// add ::= mul (("+"|"-") mul)*
     fn parse_binary_add(&mut self) -> Option<Expr> {
-        let lhs = self.parse_binary_mul()?;
-        let t = self.lexer.peek_token();
-        let binop = match t.kind {
-            TokenKind::BinOp(lexer::BinOp::Plus) => ast::BinOp::Add,
-            TokenKind::BinOp(lexer::BinOp::Minus) => ast::BinOp::Sub,
-            _ => {
-                return Some(lhs);
-            }
-        };
-        self.lexer.skip_token();
+        let mut lhs = self.parse_binary_mul()?;
+        loop {
+            let t = self.lexer.peek_token();
+            let binop = match t.kind {
+                TokenKind::BinOp(lexer::BinOp::Plus) => ast::BinOp::Add,
+                TokenKind::BinOp(lexer::BinOp::Minus) => ast::BinOp::Sub,
+                _ => {
+                    return Some(lhs);
+                }
+            };
+            self.lexer.skip_token();
 
-        let rhs = self.parse_binary_add()?;
+            let rhs = self.parse_binary_mul()?;
 
-        Some(Expr {
-            span: lhs.span.concat(&rhs.span),
-            kind: ExprKind::Binary(binop, Box::new(lhs), Box::new(rhs)),
-            id: self.get_next_id(),
-        })
+            lhs = Expr {
+                span: lhs.span.concat(&rhs.span),
+                kind: ExprKind::Binary(binop, Box::new(lhs), Box::new(rhs)),
+                id: self.get_next_id(),
+            };
+        }
     }
 
-    /// mul ::= typeCastExpr ("*" mul)?
+    /// mul ::= typeCastExpr (("*"|"/"|"%") typeCastExpr)*
     fn parse_binary_mul(&mut self) -> Option<Expr> {
-        let lhs = self.parse_binary_cast()?;
-        let t = self.lexer.peek_token();
-        let binop = match t.kind {
-            TokenKind::BinOp(lexer::BinOp::Star) => ast::BinOp::Mul,
-            _ => {
-                return Some(lhs);
-            }
-        };
-        self.lexer.skip_token();
+        let mut lhs = self.parse_binary_cast()?;
+        loop {
+            let t = self.lexer.peek_token();
+            let binop = match t.kind {
+                TokenKind::BinOp(lexer::BinOp::Star) => ast::BinOp::Mul,
+                TokenKind::BinOp(lexer::BinOp::Slash) => ast::BinOp::Div,
+                TokenKind::BinOp(lexer::BinOp::Percent) => ast::BinOp::Rem,
+                _ => {
+                    return Some(lhs);
+                }
+            };
+            self.lexer.skip_token();
 
-        let rhs = self.parse_binary_mul()?;
+            let rhs = self.parse_binary_cast()?;
 
-        Some(Expr {
-            span: lhs.span.concat(&rhs.span),
-            kind: ExprKind::Binary(binop, Box::new(lhs), Box::new(rhs)),
-            id: self.get_next_id(),
-        })
+            lhs = Expr {
+                span: lhs.span.concat(&rhs.span),
+                kind: ExprKind::Binary(binop, Box::new(lhs), Box::new(rhs)),
+                id: self.get_next_id(),
+            };
+        }
     }
 
     /// typeCastExpr ::= unary ("as" type)*
@@ -267,6 +278,7 @@ impl Parser {
                 }
             }
             TokenKind::If => self.parse_if_expr()?,
+            TokenKind::BinOp(BinOp::Or) | TokenKind::Move => self.parse_closure_expr()?,
             TokenKind::Return => {
                 // TODO: parse `return;`
                 let span = self.skip_token().span;
@@ -279,7 +291,7 @@ impl Parser {
             }
             // FIXME: ambiguity: parser cannot decide ident or struct expr.
             // e.g. `if s { } else {}`
-            TokenKind::Ident(_) => self.parse_ident_or_struct_expr()?,
+            TokenKind::Ident(_) | TokenKind::I32 => self.parse_ident_or_struct_expr()?,
             TokenKind::OpenBracket => self.parse_array_expr()?,
             TokenKind::OpenParen => {
                 let mut span = self.peek_token().span.clone();
@@ -300,10 +312,7 @@ impl Parser {
                     span = span.concat(&self.peek_token().span);
                     // skip ')'
                     if !self.skip_expected_token(TokenKind::CloseParen) {
-                        eprintln!(
-                            "Expected ')', but found `{}`",
-                            self.peek_token().span.to_snippet()
-                        );
+                        self.expected("`)`");
                         return None;
                     }
                     // just expand span
@@ -315,11 +324,11 @@ impl Parser {
                 }
             }
             // unsafe block expression
-            // TODO: Should AST node have `unsafe` info?
             TokenKind::Unsafe => {
                 // skip "unsafe"
                 let unsafe_span = self.skip_token().span;
-                let block = self.parse_block()?;
+                let mut block = self.parse_block()?;
+                block.is_unsafe = true;
                 Expr {
                     span: unsafe_span.concat(&block.span),
                     kind: ExprKind::Block(block),
@@ -339,7 +348,7 @@ impl Parser {
                 let span = self.skip_token().span; 
                 let path = self.parse_expr()?; 
                 Expr {
-                    span,
+                    span: span.concat(&path.span),
                     kind: ExprKind::Ref(Box::new(path)), 
                     id: self.get_next_id(), 
                 }
@@ -356,12 +365,15 @@ impl Parser {
                    id: self.get_next_id(), 
                 }
             }
+            // the token is left to the enclosing construct, which can often continue after it
             _ => {
-                eprintln!(
-                    "Expected num or (expr), but found `{}`",
-                    t.span.to_snippet()
-                );
-                return None;
+                self.expected("expression");
+                let span = self.peek_token().span.clone();
+                return Some(Expr {
+                    kind: ExprKind::Err,
+                    id: self.get_next_id(),
+                    span,
+                });
             }
         };
 
@@ -374,7 +386,12 @@ impl Parser {
                     expr = self.parse_call_expr(expr)?;
                 }
                 TokenKind::OpenBracket => expr = self.parse_index_expr(expr)?,
-                TokenKind::Dot => expr = self.parse_field_expr(expr)?,
+                TokenKind::Dot => {
+                    expr = self.parse_field_expr(expr)?;
+                    if self.peek_token().kind == TokenKind::OpenParen {
+                        expr = self.parse_method_call_expr(expr)?;
+                    }
+                }
                 _ => break,
             }
         }
@@ -382,12 +399,72 @@ impl Parser {
         Some(expr)
     }
 
-    /// ident | structExpr
+    /// closureExpr ::= "move"? "|" closureParams? "|" ("->" type blockExpr | expr)
+    /// closureParams ::= closureParam ("," closureParam)* ","?
+    /// closureParam ::= ident (":" type)?
+    /// https://doc.rust-lang.org/reference/expressions/closure-expr.html
+    fn parse_closure_expr(&mut self) -> Option<Expr> {
+        let span = self.peek_token().span.clone();
+        let is_move = self.skip_expected_token(TokenKind::Move);
+        if !self.skip_expected_token(TokenKind::BinOp(BinOp::Or)) {
+            self.expected("`|`");
+            return None;
+        }
+
+        let mut params = vec![];
+        while !self.skip_expected_token(TokenKind::BinOp(BinOp::Or)) {
+            let ident = self.parse_ident()?;
+            let ty = if self.skip_expected_token(TokenKind::Colon) {
+                Some(self.parse_type()?)
+            } else {
+                None
+            };
+            params.push((ident, ty));
+            if !self.skip_expected_token(TokenKind::Comma)
+                && self.peek_token().kind != TokenKind::BinOp(BinOp::Or)
+            {
+                self.expected("`,` or `|`");
+                return None;
+            }
+        }
+
+        // the body must be a block if the return type is written
+        let ret_ty = if self.skip_expected_token(TokenKind::Arrow) {
+            let ty = self.parse_type()?;
+            if self.peek_token().kind != TokenKind::OpenBrace {
+                self.expected("`{` after the return type of closure");
+                return None;
+            }
+            Some(ty)
+        } else {
+            None
+        };
+        let body = self.parse_expr()?;
+        Some(Expr {
+            span: span.concat(&body.span),
+            kind: ExprKind::Closure(Box::new(ast::Closure {
+                is_move,
+                params,
+                ret_ty,
+                body,
+            })),
+            id: self.get_next_id(),
+        })
+    }
+
+    /// ident | structExpr | macCall
     fn parse_ident_or_struct_expr(&mut self) -> Option<Expr> {
         let path = self.parse_path()?;
         let t = self.peek_token();
         if let TokenKind::OpenBrace = t.kind {
             self.parse_struct_expr(path)
+        } else if let TokenKind::Bang = t.kind {
+            let mac = self.parse_mac_call(path)?;
+            Some(Expr {
+                span: mac.span.clone(),
+                kind: ExprKind::MacCall(mac),
+                id: self.get_next_id(),
+            })
         } else {
             Some(Expr {
                 span: path.span.clone(),
@@ -403,10 +480,7 @@ impl Parser {
         let mut span = self.peek_token().span.clone();
 
         if !self.skip_expected_token(TokenKind::OpenBrace) {
-            eprintln!(
-                "Expected '{{ for struct expr', but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`{` for struct expr");
             return None;
         }
 
@@ -418,10 +492,7 @@ impl Parser {
 
         span = span.concat(&self.peek_token().span);
         if !self.skip_expected_token(TokenKind::CloseBrace) {
-            eprintln!(
-                "Expected '}}' for struct expr, but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`}` for struct expr");
             return None;
         }
         Some(Expr {
@@ -450,10 +521,7 @@ impl Parser {
     fn parse_struct_expr_field(&mut self) -> Option<(Ident, Box<Expr>)> {
         let ident = self.parse_ident()?;
         if !self.skip_expected_token(TokenKind::Colon) {
-            eprintln!(
-                "Expected ':', but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`:`");
             return None;
         }
         let expr = self.parse_expr()?;
@@ -467,8 +535,8 @@ impl Parser {
 
         // skip '['
         self.skip_token();
-        let elems = if self.peek_token().kind == TokenKind::CloseBracket {
-            vec![]
+        let kind = if self.peek_token().kind == TokenKind::CloseBracket {
+            ExprKind::Array(vec![])
         } else {
             self.parse_array_elements()?
         };
@@ -476,33 +544,34 @@ impl Parser {
         // skip ']'
         span = span.concat(&self.peek_token().span);
         if !self.skip_expected_token(TokenKind::CloseBracket) {
-            eprintln!(
-                "Expected ']', but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`]`");
             return None;
         }
         Some(Expr {
-            kind: ExprKind::Array(elems),
+            kind,
             id: self.get_next_id(),
             span,
         })
     }
 
-    /// arrayElements ::= expr ("," expr)* ","?
+    /// arrayElements ::= expr ("," expr)* ","? | expr ";" expr
     /// https://doc.rust-lang.org/reference/expressions/array-expr.html
-    /// TODO: `;` separator
-    fn parse_array_elements(&mut self) -> Option<Vec<Expr>> {
-        let mut elems = vec![];
-        elems.push(self.parse_expr()?);
+    fn parse_array_elements(&mut self) -> Option<ExprKind> {
+        let first = self.parse_expr()?;
+        if self.peek_token().kind == TokenKind::Semi {
+            self.skip_token();
+            let count = self.parse_expr()?;
+            return Some(ExprKind::Repeat(Box::new(first), Box::new(count)));
+        }
 
+        let mut elems = vec![first];
         while matches!(self.peek_token().kind, TokenKind::Comma) {
             self.skip_token();
             if is_expr_start(self.peek_token()) {
                 elems.push(self.parse_expr()?);
             }
         }
-        Some(elems)
+        Some(ExprKind::Array(elems))
     }
 
     /// callExpr ::= primary "(" callParams? ")"
@@ -520,10 +589,7 @@ impl Parser {
 
         span = span.concat(&self.peek_token().span);
         if !self.skip_expected_token(TokenKind::CloseParen) {
-            eprintln!(
-                "Expected ')', but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`)`");
             return None;
         }
         Some(Expr {
@@ -555,10 +621,7 @@ impl Parser {
 
         // skip '['
         if !self.skip_expected_token(TokenKind::OpenBracket) {
-            eprintln!(
-                "Expected '[', but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`[`");
             return None;
         }
         let index = self.parse_expr()?;
@@ -566,10 +629,7 @@ impl Parser {
         span = span.concat(&self.peek_token().span);
         // skip ']'
         if !self.skip_expected_token(TokenKind::CloseBracket) {
-            eprintln!(
-                "Expected ']', but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`]`");
             return None;
         }
         Some(Expr {
@@ -595,4 +655,21 @@ impl Parser {
             span,
         })
     }
+
+    /// methodCallExpr ::= primary "." ident "(" callParams? ")"
+    /// NOTE: `primary "." ident` is already parsed as a field expression
+    fn parse_method_call_expr(&mut self, field: Expr) -> Option<Expr> {
+        let call = self.parse_call_expr(field)?;
+        let ExprKind::Call(callee, args) = call.kind else {
+            unreachable!()
+        };
+        let ExprKind::Field(recv, method) = callee.kind else {
+            unreachable!()
+        };
+        Some(Expr {
+            kind: ExprKind::MethodCall(recv, method, args),
+            id: call.id,
+            span: call.span,
+        })
+    }
 }
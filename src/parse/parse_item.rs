@@ -1,57 +1,325 @@
-use super::Parser;
-use crate::ast::{ExternBlock, Func, Item, Impl, ItemKind, Module, StructItem, Ty, TyKind, Type};
-use crate::lexer::{self, Token, TokenKind};
-use crate::span::Ident;
+use super::{ParseError, Parser};
+use crate::ast::tokenstream::Delimiter;
+use crate::ast::{
+    Attribute, ConstItem, Expr, ExternBlock, ExternCrateItem, FieldDef, Func, Impl, Item, ItemKind, Module, StaticItem,
+    StructItem, Ty, TyKind, Type, UseItem, UseTree, UseTreeKind, Visibility,
+};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{self, Lexer, Token, TokenKind};
+use crate::middle::intrinsics::INTRINSIC_ABI;
+use crate::span::{FileId, Ident, SourceFile, Span};
+use std::rc::Rc;
 
 pub fn is_item_start(token: &Token) -> bool {
     matches!(
         token.kind,
-        TokenKind::Fn | TokenKind::Extern | TokenKind::Struct | TokenKind::Mod | TokenKind::Impl | TokenKind::Type 
+        TokenKind::Fn
+            | TokenKind::Extern
+            | TokenKind::Struct
+            | TokenKind::Mod
+            | TokenKind::Impl
+            | TokenKind::Type
+            | TokenKind::Const
+            | TokenKind::Static
+            | TokenKind::Unsafe
+            | TokenKind::Pub
+            | TokenKind::Use
+            | TokenKind::Pound
+            // macro calls and `macro_rules!`
+            | TokenKind::Ident(_)
     )
 }
 
 impl Parser {
-    /// item ::= func | structItem | externBlock | module
+    /// item ::= attr* vis (func | constFunc | unsafeFunc | structItem | externBlock | externCrate | module | constItem | staticItem | useItem | macroItem)
     pub fn parse_item(&mut self) -> Option<Item> {
+        let attrs = self.parse_outer_attrs()?;
+        let vis_span = self.peek_token().span.clone();
+        let vis = self.parse_visibility()?;
+        let mut item = Item {
+            attrs,
+            kind: self.parse_item_without_vis()?,
+        };
+        match &mut item.kind {
+            ItemKind::Func(func) => func.vis = vis,
+            ItemKind::Struct(strct) => strct.vis = vis,
+            ItemKind::Mod(module) => module.vis = vis,
+            ItemKind::TypeAlias(alias) => alias.vis = vis,
+            ItemKind::Const(const_item) => const_item.vis = vis,
+            ItemKind::Static(static_item) => static_item.vis = vis,
+            ItemKind::Use(use_item) => use_item.vis = vis,
+            ItemKind::ExternCrate(extern_crate) => extern_crate.vis = vis,
+            ItemKind::Err => (),
+            ItemKind::ExternBlock(_) | ItemKind::Impl(_) | ItemKind::MacroRules(_) | ItemKind::MacCall(_) => {
+                if vis != Visibility::Private {
+                    self.error(
+                        Diagnostic::error("visibility qualifiers are not permitted here")
+                            .code("E0449")
+                            .span_label(&vis_span, "not permitted here"),
+                    );
+                    return None;
+                }
+            }
+        }
+        Some(item)
+    }
+
+    /// vis ::= ("pub" ("(" ("crate" | "super" | "self") ")")?)?
+    /// https://doc.rust-lang.org/reference/visibility-and-privacy.html
+    fn parse_visibility(&mut self) -> Option<Visibility> {
+        if !self.skip_expected_token(TokenKind::Pub) {
+            return Some(Visibility::Private);
+        }
+        if !self.skip_expected_token(TokenKind::OpenParen) {
+            return Some(Visibility::Public);
+        }
+        let scope = self.parse_ident()?;
+        let vis = match scope.symbol.as_str() {
+            "crate" => Visibility::Crate,
+            "super" => Visibility::Super,
+            "self" => Visibility::Private,
+            _ => {
+                self.error(
+                    Diagnostic::error(format!("incorrect visibility restriction `{}`", scope.symbol))
+                        .code("E0704")
+                        .span_label(&scope.span, "expected `crate`, `super` or `self`"),
+                );
+                return None;
+            }
+        };
+        if !self.skip_expected_token(TokenKind::CloseParen) {
+            self.expected("`)`");
+            return None;
+        }
+        Some(vis)
+    }
+
+    fn parse_item_without_vis(&mut self) -> Option<ItemKind> {
         let t = self.peek_token();
         match &t.kind {
-            TokenKind::Fn => Some(Item {
-                kind: ItemKind::Func(self.parse_func(None)?),
-            }),
-            TokenKind::Struct => Some(Item {
-                kind: ItemKind::Struct(self.parse_struct_item()?),
-            }),
-            TokenKind::Extern => Some(Item {
-                kind: ItemKind::ExternBlock(self.parse_extern_block()?),
-            }),
-            TokenKind::Mod => Some(Item {
-                kind: ItemKind::Mod(self.parse_module()?),
-            }),
-            TokenKind::Impl => Some(Item {
-                kind: ItemKind::Impl(self.parse_impl()?), 
-            }),
-            TokenKind::Type => Some(Item {
-                kind: ItemKind::TypeAlias(self.parse_type_alias()?), 
-            }),
+            TokenKind::Fn => Some(ItemKind::Func(self.parse_func(None)?)),
+            TokenKind::Struct => Some(ItemKind::Struct(self.parse_struct_item()?)),
+            TokenKind::Extern => self.parse_extern_block_or_crate(),
+            TokenKind::Mod => Some(ItemKind::Mod(self.parse_module()?)),
+            TokenKind::Impl => Some(ItemKind::Impl(self.parse_impl()?)),
+            TokenKind::Type => Some(ItemKind::TypeAlias(self.parse_type_alias()?)),
+            TokenKind::Const => self.parse_const_item_or_func(),
+            TokenKind::Static => Some(ItemKind::Static(self.parse_static_item()?)),
+            TokenKind::Unsafe => Some(ItemKind::Func(self.parse_unsafe_func()?)),
+            TokenKind::Use => Some(ItemKind::Use(self.parse_use_item()?)),
+            TokenKind::Ident(_) => self.parse_macro_item(),
             _ => {
-                eprintln!(
-                    "Expected item, but found `{}`",
-                    self.peek_token().span.to_snippet()
-                );
+                self.expected("item");
                 None
             }
         }
     }
-    
+
+    /// Outer attributes `#[...]` of the next item
+    fn parse_outer_attrs(&mut self) -> Option<Vec<Attribute>> {
+        let mut attrs = vec![];
+        while self.peek_token().kind == TokenKind::Pound {
+            if self.lexer.peek_nth_token(1).kind == TokenKind::Bang {
+                let attr = self.parse_attr(true)?;
+                self.error(
+                    Diagnostic::error("an inner attribute is not permitted in this context")
+                        .span_label(&attr.span, "not permitted here")
+                        .note("inner attributes like `#![allow(...)]` are only permitted at the start of a file or a module"),
+                );
+                continue;
+            }
+            attrs.push(self.parse_attr(false)?);
+        }
+        Some(attrs)
+    }
+
+    /// Inner attributes `#![...]` at the start of a file or a module, which apply to it
+    pub(super) fn parse_inner_attrs(&mut self) -> Vec<Attribute> {
+        let mut attrs = vec![];
+        while self.peek_token().kind == TokenKind::Pound && self.lexer.peek_nth_token(1).kind == TokenKind::Bang {
+            match self.parse_attr(true) {
+                Some(attr) => attrs.push(attr),
+                // the rest is recovered from as an item
+                None => break,
+            }
+        }
+        attrs
+    }
+
+    /// attr ::= "#" "!"? "[" path delimTokenTree? "]"
+    fn parse_attr(&mut self, inner: bool) -> Option<Attribute> {
+        let pound = self.skip_token();
+        if inner {
+            // skip `!`
+            self.skip_token();
+        }
+        if !self.skip_expected_token(TokenKind::OpenBracket) {
+            self.expected("`[`");
+            return None;
+        }
+        let path = self.parse_path()?;
+        let args = if Delimiter::from_open(&self.peek_token().kind).is_some() {
+            Some(self.parse_delimited()?.tts)
+        } else {
+            None
+        };
+        let close = self.peek_token().span.clone();
+        if !self.skip_expected_token(TokenKind::CloseBracket) {
+            self.expected("`]`");
+            return None;
+        }
+        Some(Attribute {
+            path,
+            args,
+            span: pound.span.concat(&close),
+        })
+    }
+
+    /// constItem ::= "const" ident ":" type "=" expr ";"
+    /// constFunc ::= "const" func
+    /// https://doc.rust-lang.org/reference/items/constant-items.html
+    /// https://doc.rust-lang.org/reference/const_eval.html#const-functions
+    fn parse_const_item_or_func(&mut self) -> Option<ItemKind> {
+        // skip `const`
+        self.skip_token();
+        if self.peek_token().kind == TokenKind::Fn {
+            let mut func = self.parse_func(None)?;
+            func.is_const = true;
+            return Some(ItemKind::Func(func));
+        }
+        let (name, ty, init) = self.parse_global_item_body("constant")?;
+        Some(ItemKind::Const(ConstItem {
+            vis: Visibility::Private,
+            name,
+            ty,
+            init,
+            id: self.get_next_id(),
+        }))
+    }
+
+    /// unsafeFunc ::= "unsafe" func
+    /// https://doc.rust-lang.org/reference/unsafe-keyword.html#unsafe-functions-unsafe-fn
+    fn parse_unsafe_func(&mut self) -> Option<Func> {
+        // skip `unsafe`
+        self.skip_token();
+        let mut func = self.parse_func(None)?;
+        func.is_unsafe = true;
+        Some(func)
+    }
+
+    /// useItem ::= "use" useTree ";"
+    /// https://doc.rust-lang.org/reference/items/use-declarations.html
+    fn parse_use_item(&mut self) -> Option<UseItem> {
+        // skip `use`
+        self.skip_token();
+        let tree = self.parse_use_tree()?;
+        if !self.skip_expected_token(TokenKind::Semi) {
+            self.expected("`;` after use declaration");
+            return None;
+        }
+        Some(UseItem {
+            vis: Visibility::Private,
+            tree,
+            id: self.get_next_id(),
+        })
+    }
+
+    /// useTree ::= (path "::")? ("*" | "{" (useTree ("," useTree)* ","?)? "}")
+    ///           | path ("as" ident)?
+    fn parse_use_tree(&mut self) -> Option<UseTree> {
+        let mut span = self.peek_token().span.clone();
+        let mut prefix = vec![];
+        let kind = loop {
+            let t = self.peek_token();
+            match t.kind {
+                TokenKind::Ident(_) => {
+                    let seg = self.parse_ident()?;
+                    span = span.concat(&seg.span);
+                    prefix.push(seg);
+                    if !self.skip_expected_token(TokenKind::ColCol) {
+                        break UseTreeKind::Simple(None);
+                    }
+                }
+                TokenKind::BinOp(lexer::BinOp::Star) => {
+                    span = span.concat(&self.skip_token().span);
+                    break UseTreeKind::Glob;
+                }
+                TokenKind::OpenBrace => {
+                    self.skip_token();
+                    let mut trees = vec![];
+                    while self.peek_token().kind != TokenKind::CloseBrace {
+                        trees.push(self.parse_use_tree()?);
+                        if !self.skip_expected_token(TokenKind::Comma) {
+                            break;
+                        }
+                    }
+                    let t = self.skip_token();
+                    if t.kind != TokenKind::CloseBrace {
+                        self.expected_at("`}` in use tree", &t);
+                        return None;
+                    }
+                    span = span.concat(&t.span);
+                    break UseTreeKind::Nested(trees);
+                }
+                _ => {
+                    self.expected("path, `*` or `{` in use tree");
+                    return None;
+                }
+            }
+        };
+        let kind = if matches!(kind, UseTreeKind::Simple(_)) && self.skip_expected_token(TokenKind::As) {
+            let rename = self.parse_ident()?;
+            span = span.concat(&rename.span);
+            UseTreeKind::Simple(Some(rename))
+        } else {
+            kind
+        };
+        Some(UseTree { prefix, kind, span })
+    }
+
+    /// staticItem ::= "static" "mut"? ident ":" type "=" expr ";"
+    /// https://doc.rust-lang.org/reference/items/static-items.html
+    fn parse_static_item(&mut self) -> Option<StaticItem> {
+        // skip `static`
+        self.skip_token();
+        let mutable = self.skip_expected_token(TokenKind::Mut);
+        let (name, ty, init) = self.parse_global_item_body("static")?;
+        Some(StaticItem {
+            vis: Visibility::Private,
+            name,
+            ty,
+            init,
+            mutable,
+            id: self.get_next_id(),
+        })
+    }
+
+    /// ident ":" type "=" expr ";"
+    fn parse_global_item_body(&mut self, what: &str) -> Option<(Ident, Ty, Expr)> {
+        let name = self.parse_ident()?;
+        if !self.skip_expected_token(TokenKind::Colon) {
+            self.expected(&format!("`:` for {} item", what));
+            return None;
+        }
+        let ty = self.parse_type()?;
+        if !self.skip_expected_token(TokenKind::Eq) {
+            self.expected(&format!("`=` for {} item", what));
+            return None;
+        }
+        let init = self.parse_expr()?;
+        if !self.skip_expected_token(TokenKind::Semi) {
+            self.expected(&format!("`;` after {} item", what));
+            return None;
+        }
+        Some((name, ty, init))
+    }
+
     fn parse_type_alias(&mut self) -> Option<Type> {
         self.skip_token(); 
         let name = self.parse_ident()?; 
 
         if !self.skip_expected_token(TokenKind::Eq){
-            eprintln!(
-                "Expected '=' in type alias, but found `{}`", 
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`=` in type alias");
 
             return None;
         }
@@ -59,15 +327,13 @@ impl Parser {
         let ty = self.parse_type()?;
 
         if !self.skip_expected_token(TokenKind::Semi) {
-            eprintln!(
-                "Expected ';' after type alias, but found `{}`", 
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`;` after type alias");
 
             return None;
         }
 
         Some (Type {
+            vis: Visibility::Private,
             name, 
             aliasof: ty, 
         })
@@ -80,33 +346,30 @@ impl Parser {
         let name = self.parse_ident()?; 
         
         if !self.skip_expected_token(TokenKind::OpenBrace) {
-            eprintln!(
-                "Expected '{{' for extern block, but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`{` for extern block");
             return None;
         }
 
         let mut funcs = vec![];
-        while self.peek_token().kind == TokenKind::Fn {
-            funcs.push(self.parse_func(None)?);
+        while matches!(self.peek_token().kind, TokenKind::Fn | TokenKind::Pub) {
+            let vis = self.parse_visibility()?;
+            let mut func = self.parse_func(None)?;
+            func.vis = vis;
+            funcs.push(func);
         }
 
         if !self.skip_expected_token(TokenKind::CloseBrace) {
-            eprintln!(
-                "Expected '}}' or external item, but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`}` or external item");
             return None;
         }
         
-        return Some(Impl {
+        Some(Impl {
             name, 
             methods: funcs, 
-        });
+        })
     }
 
-    /// module ::= "mod" ident "{" item* "}"
+    /// module ::= "mod" ident ("{" item* "}" | ";")
     /// https://doc.rust-lang.org/reference/items/modules.html
     fn parse_module(&mut self) -> Option<Module> {
         // skip `mod`
@@ -114,74 +377,239 @@ impl Parser {
 
         let name = self.parse_ident()?;
 
+        // `mod name;` is loaded from a file
+        if self.skip_expected_token(TokenKind::Semi) {
+            let (attrs, items) = self.parse_module_file(&name)?;
+            return Some(Module {
+                vis: Visibility::Private,
+                name,
+                attrs,
+                items,
+                id: self.get_next_id(),
+            });
+        }
+
         // `{`
         if !self.skip_expected_token(TokenKind::OpenBrace) {
-            eprintln!(
-                "Expected '{{' for extern block, but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`{` or `;` for module");
             return None;
         }
 
-        let items = self.parse_items()?;
+        let (attrs, items) = self.in_module_dir(&name, |p| (p.parse_inner_attrs(), p.parse_items()));
 
-        // `{`
+        // `}`
         if !self.skip_expected_token(TokenKind::CloseBrace) {
-            eprintln!(
-                "Expected '}}' for extern block, but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`}` for module");
             return None;
         }
 
         Some(Module {
+            vis: Visibility::Private,
             name,
+            attrs,
+            items,
+            id: self.get_next_id(),
+        })
+    }
+
+    /// Run `f` with the directory of child modules set to that of module `name`.
+    /// Files of child modules of both `foo.rs` and `foo/mod.rs` are in `foo/`.
+    fn in_module_dir<T>(&mut self, name: &Ident, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.module_dir.push(name.symbol.as_str());
+        let res = f(self);
+        self.module_dir.pop();
+        res
+    }
+
+    /// Parse items of out-of-line module `name`, which is in either
+    /// `<dir>/name.rs` or `<dir>/name/mod.rs`
+    fn parse_module_file(&mut self, name: &Ident) -> Option<(Vec<Attribute>, Vec<Item>)> {
+        let file_path = self.module_dir.join(format!("{}.rs", name.symbol));
+        let mod_rs_path = self.module_dir.join(name.symbol.as_str()).join("mod.rs");
+        let path = match (file_path.is_file(), mod_rs_path.is_file()) {
+            (true, false) => file_path,
+            (false, true) => mod_rs_path,
+            (true, true) => {
+                self.error(
+                    Diagnostic::error(format!(
+                        "file for module `{}` found at both \"{}\" and \"{}\"",
+                        name.symbol,
+                        file_path.display(),
+                        mod_rs_path.display()
+                    ))
+                    .code("E0761")
+                    .span_label(&name.span, "ambiguous module file")
+                    .help("delete or rename one of them to remove the ambiguity"),
+                );
+                return None;
+            }
+            (false, false) => {
+                self.error(
+                    Diagnostic::error(format!("file not found for module `{}`", name.symbol))
+                        .code("E0583")
+                        .span_label(&name.span, "module file not found")
+                        .help(format!(
+                            "to create the module `{}`, create file \"{}\" or \"{}\"",
+                            name.symbol,
+                            file_path.display(),
+                            mod_rs_path.display()
+                        )),
+                );
+                return None;
+            }
+        };
+        let Ok(src) = std::fs::read_to_string(&path) else {
+            self.error(
+                Diagnostic::error(format!(
+                    "couldn't read file \"{}\" for module `{}`",
+                    path.display(),
+                    name.symbol
+                ))
+                .code("M0005")
+                .span_label(&name.span, "module declared here"),
+            );
+            return None;
+        };
+
+        let file = self.new_source_file(path.display().to_string(), src);
+        Some(self.in_module_dir(name, |p| p.parse_file_items(file)))
+    }
+
+    fn new_source_file(&mut self, name: String, src: String) -> Rc<SourceFile> {
+        let file = SourceFile::new(FileId(self.next_file_id), name, src);
+        self.next_file_id += 1;
+        Rc::new(file)
+    }
+
+    /// Parse all items in a source file other than the one being parsed
+    fn parse_file_items(&mut self, file: Rc<SourceFile>) -> (Vec<Attribute>, Vec<Item>) {
+        let parent_lexer = std::mem::replace(&mut self.lexer, Lexer::from_file(file));
+        let attrs = self.parse_inner_attrs();
+        let items = self.parse_items_to_eof();
+        let mut file_lexer = std::mem::replace(&mut self.lexer, parent_lexer);
+        self.diagnostics.append(&mut file_lexer.take_diagnostics());
+        (attrs, items)
+    }
+
+    /// Parse the prelude bundled with the compiler as the module `prelude`,
+    /// and inject it with `use prelude::*;` which imports its items into the crate root
+    pub fn parse_prelude(&mut self, src: &str) -> Result<Vec<Item>, ParseError> {
+        let file = self.new_source_file("<prelude>".to_string(), src.to_string());
+        let name = Ident {
+            symbol: Rc::new("prelude".to_string()),
+            span: Span::new(0, 0, Rc::clone(&file)),
+        };
+        let (attrs, items) = self.parse_file_items(file);
+        let errors = self.take_diagnostics();
+        if !errors.is_empty() {
+            return Err(ParseError {
+                errors,
+                recovered: None,
+            });
+        }
+        let module = Module {
+            vis: Visibility::Private,
+            name: name.clone(),
+            attrs,
             items,
             id: self.get_next_id(),
+        };
+        let tree = UseTree {
+            prefix: vec![name.clone()],
+            kind: UseTreeKind::Glob,
+            span: name.span,
+        };
+        let import = UseItem {
+            vis: Visibility::Private,
+            tree,
+            id: self.get_next_id(),
+        };
+        Ok(vec![
+            Item {
+                attrs: vec![],
+                kind: ItemKind::Mod(module),
+            },
+            Item {
+                attrs: vec![],
+                kind: ItemKind::Use(import),
+            },
+        ])
+    }
+
+    fn parse_extern_block_or_crate(&mut self) -> Option<ItemKind> {
+        // skip `extern`
+        self.skip_token();
+        if matches!(&self.peek_token().kind, TokenKind::Ident(s) if s == "crate") {
+            Some(ItemKind::ExternCrate(self.parse_extern_crate()?))
+        } else {
+            Some(ItemKind::ExternBlock(self.parse_extern_block()?))
+        }
+    }
+
+    /// externCrate ::= "extern" "crate" ident ("as" ident)? ";"
+    /// https://doc.rust-lang.org/reference/items/extern-crates.html
+    fn parse_extern_crate(&mut self) -> Option<ExternCrateItem> {
+        // skip `crate`
+        self.skip_token();
+        let name = self.parse_ident()?;
+        let rename = if self.skip_expected_token(TokenKind::As) {
+            Some(self.parse_ident()?)
+        } else {
+            None
+        };
+        if !self.skip_expected_token(TokenKind::Semi) {
+            self.expected("`;` for extern crate");
+            return None;
+        }
+        Some(ExternCrateItem {
+            vis: Visibility::Private,
+            name,
+            rename,
+            id: self.get_next_id(),
         })
     }
 
     /// externBlock ::= "extern" abi "{" externalItem* "}"
-    /// abi ::= "\"C\""
+    /// abi ::= "\"C\"" | "\"rust-intrinsic\""
     /// https://doc.rust-lang.org/reference/items/external-blocks.html
     fn parse_extern_block(&mut self) -> Option<ExternBlock> {
-        // skip `extern`
-        self.skip_token();
         // parse ABI
         let t = self.skip_token();
         let abi = if let TokenKind::StrLit(s) = t.kind {
             s
         } else {
-            eprintln!("Expected extern ABI, but found `{}`", t.span.to_snippet());
+            self.expected_at("extern ABI", &t);
             return None;
         };
-        // check if ABI is "C"
-        if abi != "C" {
-            eprintln!(
-                "Found `extern {}`, but `extern \"C\"` can only be supported",
-                abi
+        // check if ABI is "C" or "rust-intrinsic"
+        if abi != "C" && abi != INTRINSIC_ABI {
+            self.error(
+                Diagnostic::error(format!("invalid ABI: found `\"{}\"`", abi))
+                    .code("E0703")
+                    .span_label(&t.span, "invalid ABI")
+                    .note(format!(
+                        "only `extern \"C\"` and `extern \"{}\"` are supported",
+                        INTRINSIC_ABI
+                    )),
             );
             return None;
         }
 
         if !self.skip_expected_token(TokenKind::OpenBrace) {
-            eprintln!(
-                "Expected '{{' for extern block, but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`{` for extern block");
             return None;
         }
 
         let mut funcs = vec![];
-        while self.peek_token().kind == TokenKind::Fn {
-            funcs.push(self.parse_func(Some(abi.clone()))?);
+        while matches!(self.peek_token().kind, TokenKind::Fn | TokenKind::Pub) {
+            let vis = self.parse_visibility()?;
+            let mut func = self.parse_func(Some(abi.clone()))?;
+            func.vis = vis;
+            funcs.push(func);
         }
 
         if !self.skip_expected_token(TokenKind::CloseBrace) {
-            eprintln!(
-                "Expected '}}' or external item, but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`}` or external item");
             return None;
         }
 
@@ -192,18 +620,12 @@ impl Parser {
     /// https://doc.rust-lang.org/reference/items/functions.html
     pub fn parse_func(&mut self, ext: Option<String>) -> Option<Func> {
         if !self.skip_expected_token(TokenKind::Fn) {
-            eprintln!(
-                "Expected \"fn\", but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`fn`");
             return None;
         }
         let name = self.parse_ident()?;
         if !self.skip_expected_token(TokenKind::OpenParen) {
-            eprintln!(
-                "Expected '(', but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`(`");
             return None;
         }
         let t = self.peek_token();
@@ -214,38 +636,31 @@ impl Parser {
         };
         let mut variadic = false; 
         if !self.skip_expected_token(TokenKind::CloseParen) {
-            if self.peek_token().kind == TokenKind::Dot { 
-                let mut tmp = String::from(""); 
-                while self.peek_token().kind == TokenKind::Dot {
-                    self.skip_token(); 
-                    tmp.push_str("."); 
-                }
+            if self.peek_token().kind != TokenKind::Dot {
+                self.expected("`,` or `)`");
+                return None;
+            }
+            let mut span = self.peek_token().span.clone();
+            let mut tmp = String::from("");
+            while self.peek_token().kind == TokenKind::Dot {
+                span = span.concat(&self.skip_token().span);
+                tmp.push('.');
+            }
 
-                if tmp.as_str() == "..." {
-                    variadic = true;
-                    if !self.skip_expected_token(TokenKind::CloseParen) {
-                        eprintln!(
-                            "Expected ')', but found `{}`",
-                            self.peek_token().span.to_snippet()
-                        );
-                        return None
-                    }
-                } else {
-                    println!("Expected ')' but found unfinished variadic"); 
+            if tmp.as_str() == "..." {
+                variadic = true;
+                if !self.skip_expected_token(TokenKind::CloseParen) {
+                    self.expected("`)`");
                     return None;
                 }
-                                    
             } else {
-              
+                self.error(Diagnostic::error("expected `...`").code("M0001").span_label(&span, "unfinished variadic"));
+                return None;
             }
-            
         }
 
         if !self.skip_expected_token(TokenKind::Arrow) {
-            eprintln!(
-                "Expected '->', but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`->`");
             return None;
         }
         let ret_ty = self.parse_type()?;
@@ -257,20 +672,20 @@ impl Parser {
             self.skip_token();
             None
         } else {
-            eprintln!(
-                "Expected function body or ';', but found `{}`",
-                t.span.to_snippet()
-            );
+            self.expected("function body or `;`");
             return None;
         };
 
         Some(Func {
+            vis: Visibility::Private,
             name,
             params,
-            ret_ty: ret_ty,
+            ret_ty,
             ext,
             body,
             variadic, 
+            is_const: false,
+            is_unsafe: false,
             id: self.get_next_id(),
         })
     }
@@ -293,10 +708,7 @@ impl Parser {
     fn parse_func_param(&mut self) -> Option<(Ident, Ty)> {
         let ident = self.parse_ident()?;
         if !self.skip_expected_token(TokenKind::Colon) {
-            eprintln!(
-                "Expected ':', but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`:`");
             return None;
         }
         let ty = self.parse_type()?;
@@ -305,66 +717,87 @@ impl Parser {
 
     fn parse_struct_item(&mut self) -> Option<StructItem> {
         if !self.skip_expected_token(TokenKind::Struct) {
-            eprintln!(
-                "Expected \"struct\", but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`struct`");
             return None;
         }
         let ident = self.parse_ident()?;
         if !self.skip_expected_token(TokenKind::OpenBrace) {
-            eprintln!(
-                "Expected '{{' for struct definiton, but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`{` for struct definition");
             return None;
         }
 
-        let fields = if matches!(self.peek_token().kind, TokenKind::Ident(_)) {
+        let fields = if matches!(self.peek_token().kind, TokenKind::Ident(_) | TokenKind::Pub) {
             self.parse_struct_fields()?
         } else {
             vec![]
         };
         if !self.skip_expected_token(TokenKind::CloseBrace) {
-            eprintln!(
-                "Expected '}}' for struct definition, but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`}` for struct definition");
             return None;
         }
 
         Some(StructItem {
+            vis: Visibility::Private,
             ident,
             fields,
             id: self.get_next_id(),
         })
     }
 
-    fn parse_struct_fields(&mut self) -> Option<Vec<(Ident, Ty)>> {
+    fn parse_struct_fields(&mut self) -> Option<Vec<FieldDef>> {
         let mut fields = vec![];
         fields.push(self.parse_struct_field()?);
 
         while matches!(self.peek_token().kind, TokenKind::Comma) {
             self.skip_token();
-            if matches!(self.peek_token().kind, TokenKind::Ident(_)) {
+            if matches!(self.peek_token().kind, TokenKind::Ident(_) | TokenKind::Pub) {
                 fields.push(self.parse_struct_field()?);
             }
         }
         Some(fields)
     }
 
-    /// structField ::= ident ":" type
-    fn parse_struct_field(&mut self) -> Option<(Ident, Ty)> {
+    /// structField ::= vis ident ":" type
+    fn parse_struct_field(&mut self) -> Option<FieldDef> {
+        let vis = self.parse_visibility()?;
         let name = self.parse_ident()?;
         if !self.skip_expected_token(TokenKind::Colon) {
-            eprintln!(
-                "Expected ':', but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`:`");
             return None;
         }
         let ty = self.parse_type()?;
-        Some((name, ty))
+        Some(FieldDef { vis, name, ty })
+    }
+
+    /// fnPtrType ::= "fn" "(" (type ("," type)* ","?)? ")" ("->" type)?
+    /// NOTE: `fn` is already parsed
+    fn parse_fn_ptr_type(&mut self, span: Span) -> Option<Ty> {
+        if !self.skip_expected_token(TokenKind::OpenParen) {
+            self.expected("`(`");
+            return None;
+        }
+        let mut params = vec![];
+        while !self.skip_expected_token(TokenKind::CloseParen) {
+            params.push(self.parse_type()?);
+            if !self.skip_expected_token(TokenKind::Comma)
+                && self.peek_token().kind != TokenKind::CloseParen
+            {
+                self.expected("`,` or `)`");
+                return None;
+            }
+        }
+        let ret_ty = if self.skip_expected_token(TokenKind::Arrow) {
+            self.parse_type()?
+        } else {
+            Ty {
+                kind: TyKind::Unit,
+                span: span.clone(),
+            }
+        };
+        Some(Ty {
+            span: span.concat(&ret_ty.span),
+            kind: TyKind::Fn(params, Box::new(ret_ty)),
+        })
     }
 
     pub fn parse_type(&mut self) -> Option<Ty> {
@@ -382,10 +815,7 @@ impl Parser {
             // Unit type: ()
             TokenKind::OpenParen => {
                 if !self.skip_expected_token(TokenKind::CloseParen) {
-                    eprintln!(
-                        "Expected ')', but found `{}`",
-                        self.peek_token().span.to_snippet()
-                    );
+                    self.expected("`)`");
                     None
                 } else {
                     Some(Ty {
@@ -414,37 +844,42 @@ impl Parser {
                 kind: TyKind::Bool,
                 span,
             }),
-            // [type; n]
+            // [type; expr]
             TokenKind::OpenBracket => {
                 let elem_ty = self.parse_type()?;
                 if !self.skip_expected_token(TokenKind::Semi) {
-                    eprintln!(
-                        "Expected ';', but found `{}`",
-                        self.peek_token().span.to_snippet()
-                    );
+                    self.expected("`;`");
                     return None;
                 }
-                let t = self.skip_token();
-                let TokenKind::NumLit(n) = t.kind else {
-                    return None;
-                };
+                let len = self.parse_expr()?;
                 let span = span.concat(&self.peek_token().span);
                 if !self.skip_expected_token(TokenKind::CloseBracket) {
-                    eprintln!(
-                        "Expected ']', but found `{}`",
-                        self.peek_token().span.to_snippet()
-                    );
+                    self.expected("`]`");
                     return None;
                 }
-                // u32 is safely converted to usize
                 Some(Ty {
-                    kind: TyKind::Array(Box::new(elem_ty), n.try_into().unwrap()),
+                    kind: TyKind::Array(Box::new(elem_ty), Box::new(len)),
                     span,
                 })
             }
             TokenKind::Ident(_) => {
                 unreachable!()
             }
+            // fn(T1, T2) -> R
+            TokenKind::Fn => self.parse_fn_ptr_type(span),
+            // all functions use the C ABI, so `extern "C" fn` is the same as `fn`
+            TokenKind::Extern => {
+                let t = self.skip_token();
+                if t.kind != TokenKind::StrLit("C".to_string()) {
+                    self.expected_at("`\"C\"`", &t);
+                    return None;
+                }
+                if !self.skip_expected_token(TokenKind::Fn) {
+                    self.expected("`fn`");
+                    return None;
+                }
+                self.parse_fn_ptr_type(span)
+            }
             TokenKind::BinOp(lexer::BinOp::And) => {
                 let t = self.peek_token();
                 let region = if let TokenKind::Lifetime(_) = t.kind {
@@ -465,7 +900,7 @@ impl Parser {
                 // TODO: *mut T
                 let t = self.skip_token();
                 if t.kind != TokenKind::Const {
-                    eprintln!("Expected 'const', but found `{}`", t.span.to_snippet());
+                    self.expected_at("`const`", &t);
                     return None;
                 }
                 let referent = self.parse_type()?;
@@ -475,8 +910,8 @@ impl Parser {
                     span,
                 })
             }
-            _ => {
-                eprintln!("Expected type, but found `{}`", span.to_snippet());
+            kind => {
+                self.expected_at("type", &Token { kind, span });
                 None
             }
         }
@@ -0,0 +1,253 @@
+use super::parse_expr::is_expr_start;
+use super::parse_item::is_item_start;
+use super::parse_stmt::is_stmt_start;
+use super::Parser;
+use crate::ast::tokenstream::{flatten, token_count, Delimited, Delimiter, FragmentKind, TokenTree};
+use crate::ast::{Expr, Item, ItemKind, MacCall, MacroRulesDef, Path, Stmt};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{BinOp, Lexer, Token, TokenKind};
+use crate::span::Span;
+
+/// Whether a fragment of `kind` can start with `t`.
+/// The matcher of `macro_rules!` tries another rule instead of parsing when this is false.
+pub fn can_begin_fragment(kind: FragmentKind, t: &Token) -> bool {
+    match kind {
+        FragmentKind::Expr => is_expr_start(t),
+        FragmentKind::Ty => matches!(
+            t.kind,
+            TokenKind::Ident(_)
+                | TokenKind::OpenParen
+                | TokenKind::OpenBracket
+                | TokenKind::Bang
+                | TokenKind::I32
+                | TokenKind::Str
+                | TokenKind::Bool
+                | TokenKind::Fn
+                | TokenKind::Extern
+                | TokenKind::BinOp(BinOp::And | BinOp::Star)
+        ),
+        FragmentKind::Block => t.kind == TokenKind::OpenBrace,
+        FragmentKind::Ident | FragmentKind::Path => matches!(t.kind, TokenKind::Ident(_)),
+        FragmentKind::Literal => matches!(
+            t.kind,
+            TokenKind::NumLit(_)
+                | TokenKind::StrLit(_)
+                | TokenKind::True
+                | TokenKind::False
+                | TokenKind::BinOp(BinOp::Minus)
+        ),
+        FragmentKind::Item => is_item_start(t),
+        FragmentKind::Tt => !Delimiter::is_close(&t.kind) && t.kind != TokenKind::Eof,
+    }
+}
+
+impl Parser {
+    /// tokenTree ::= token | delimTokenTree
+    fn parse_token_tree(&mut self) -> Option<TokenTree> {
+        let t = self.peek_token();
+        if Delimiter::from_open(&t.kind).is_some() {
+            Some(TokenTree::Delimited(self.parse_delimited()?))
+        } else if Delimiter::is_close(&t.kind) || t.kind == TokenKind::Eof {
+            let t = t.clone();
+            self.expected_at("token tree", &t);
+            None
+        } else {
+            Some(TokenTree::Token(self.skip_token()))
+        }
+    }
+
+    /// delimTokenTree ::= "(" tokenTree* ")" | "[" tokenTree* "]" | "{" tokenTree* "}"
+    pub(super) fn parse_delimited(&mut self) -> Option<Delimited> {
+        let open = self.skip_token();
+        let Some(delim) = Delimiter::from_open(&open.kind) else {
+            self.expected_at("one of `(`, `[` or `{`", &open);
+            return None;
+        };
+        let mut tts = vec![];
+        loop {
+            let t = self.peek_token();
+            if t.kind == delim.close_token() {
+                break;
+            }
+            if Delimiter::is_close(&t.kind) || t.kind == TokenKind::Eof {
+                let diag = if t.kind == TokenKind::Eof {
+                    Diagnostic::error("this file contains an unclosed delimiter")
+                } else {
+                    Diagnostic::error(format!("mismatched closing delimiter: `{}`", t.span.to_snippet()))
+                        .span_label(&t.span, "mismatched closing delimiter")
+                };
+                self.error(diag.code("M0002").secondary_label(&open.span, "unclosed delimiter"));
+                return None;
+            }
+            tts.push(self.parse_token_tree()?);
+        }
+        let close = self.skip_token().span;
+        Some(Delimited {
+            delim,
+            open: open.span,
+            close,
+            tts,
+        })
+    }
+
+    /// macCall ::= path "!" delimTokenTree
+    /// NOTE: path is already parsed
+    pub(super) fn parse_mac_call(&mut self, path: Path) -> Option<MacCall> {
+        // skip `!`
+        if !self.skip_expected_token(TokenKind::Bang) {
+            self.expected("`!`");
+            return None;
+        }
+        let args = self.parse_delimited()?;
+        Some(MacCall {
+            span: path.span.concat(&args.close),
+            path,
+            delim: args.delim,
+            args: args.tts,
+        })
+    }
+
+    /// macroItem ::= macroRules | macCall ";"?
+    /// macroRules ::= "macro_rules" "!" ident delimTokenTree ";"?
+    /// Calls delimited by `()` or `[]` need `;`
+    /// https://doc.rust-lang.org/reference/macros-by-example.html
+    pub(super) fn parse_macro_item(&mut self) -> Option<ItemKind> {
+        let path = self.parse_path()?;
+        let is_macro_rules = path.segments.len() == 1 && *path.segments[0].symbol == "macro_rules";
+        if !is_macro_rules {
+            let mac = self.parse_mac_call(path)?;
+            if mac.delim != Delimiter::Brace && !self.skip_expected_token(TokenKind::Semi) {
+                self.expected("`;` after macro call");
+                return None;
+            }
+            return Some(ItemKind::MacCall(mac));
+        }
+
+        if !self.skip_expected_token(TokenKind::Bang) {
+            self.expected("`!`");
+            return None;
+        }
+        let name = self.parse_ident()?;
+        let body = self.parse_delimited()?;
+        let mut span = path.span.concat(&body.close);
+        if body.delim != Delimiter::Brace {
+            span = span.concat(&self.peek_token().span);
+            if !self.skip_expected_token(TokenKind::Semi) {
+                self.expected("`;` after macro definition");
+                return None;
+            }
+        }
+        Some(ItemKind::MacroRules(MacroRulesDef {
+            name,
+            body: body.tts,
+            span,
+            id: self.get_next_id(),
+        }))
+    }
+
+    /// Run `f` on `tokens` followed by EOF at `eof`.
+    /// Returns the result and the number of tokens which were not consumed.
+    fn with_tokens<T>(
+        &mut self,
+        tokens: Vec<Token>,
+        eof: Span,
+        f: impl FnOnce(&mut Self) -> Option<T>,
+    ) -> Option<(T, usize)> {
+        let parent_lexer = std::mem::replace(&mut self.lexer, Lexer::from_tokens(tokens, eof));
+        let res = f(self);
+        let remaining = self.lexer.remaining_tokens();
+        self.lexer = parent_lexer;
+        Some((res?, remaining))
+    }
+
+    /// Parse a fragment of `kind` at the start of `tts`, which is the input of a macro,
+    /// and returns how many token trees the fragment has
+    pub fn parse_fragment(&mut self, kind: FragmentKind, tts: &[TokenTree], eof: Span) -> Option<usize> {
+        let tokens = flatten(tts);
+        let total = tokens.len();
+        let (_, remaining) = self.with_tokens(tokens, eof, |p| match kind {
+            FragmentKind::Expr => p.parse_expr().map(|_| ()),
+            FragmentKind::Ty => p.parse_type().map(|_| ()),
+            FragmentKind::Block => p.parse_block().map(|_| ()),
+            FragmentKind::Path => p.parse_path().map(|_| ()),
+            FragmentKind::Item => p.parse_item().map(|_| ()),
+            FragmentKind::Ident | FragmentKind::Literal | FragmentKind::Tt => {
+                panic!("ICE: `{}` fragments are matched without parsing", kind.name())
+            }
+        })?;
+        // parsers consume balanced delimiters, so the fragment ends at a token tree
+        let mut consumed = total - remaining;
+        let mut n = 0;
+        while consumed > 0 {
+            consumed -= token_count(&tts[n]);
+            n += 1;
+        }
+        Some(n)
+    }
+
+    /// Parse the output of a macro called in expression position
+    pub fn parse_expr_expansion(&mut self, tokens: Vec<Token>, eof: Span) -> Option<Expr> {
+        let (expr, _) = self.with_tokens(tokens, eof, |p| {
+            let expr = p.parse_expr()?;
+            p.expect_expansion_end()?;
+            Some(expr)
+        })?;
+        Some(expr)
+    }
+
+    /// Parse the arguments of a built-in macro
+    /// macroArgs ::= (expr ("," expr)* ","?)?
+    pub fn parse_macro_args(&mut self, tokens: Vec<Token>, eof: Span) -> Option<Vec<Expr>> {
+        let (args, _) = self.with_tokens(tokens, eof, |p| {
+            let mut args = vec![];
+            while !p.at_eof() {
+                args.push(p.parse_expr()?);
+                if !p.skip_expected_token(TokenKind::Comma) && !p.at_eof() {
+                    p.expected("`,` in macro arguments");
+                    return None;
+                }
+            }
+            Some(args)
+        })?;
+        Some(args)
+    }
+
+    /// Parse the output of a macro called in statement position
+    pub fn parse_stmts_expansion(&mut self, tokens: Vec<Token>, eof: Span) -> Option<Vec<Stmt>> {
+        let (stmts, _) = self.with_tokens(tokens, eof, |p| {
+            let mut stmts = vec![];
+            while is_stmt_start(p.peek_token()) {
+                stmts.push(p.parse_stmt()?);
+            }
+            p.expect_expansion_end()?;
+            Some(stmts)
+        })?;
+        Some(stmts)
+    }
+
+    /// Parse the output of a macro called in item position
+    pub fn parse_items_expansion(&mut self, tokens: Vec<Token>, eof: Span) -> Option<Vec<Item>> {
+        let (items, _) = self.with_tokens(tokens, eof, |p| {
+            let mut items = vec![];
+            while is_item_start(p.peek_token()) {
+                items.push(p.parse_item()?);
+            }
+            p.expect_expansion_end()?;
+            Some(items)
+        })?;
+        Some(items)
+    }
+
+    fn expect_expansion_end(&mut self) -> Option<()> {
+        if self.at_eof() {
+            return Some(());
+        }
+        let t = self.peek_token().clone();
+        self.error(
+            Diagnostic::error(format!("macro expansion ignores token `{}`", t.span.to_snippet()))
+                .code("M0001")
+                .span_label(&t.span, "ignored"),
+        );
+        None
+    }
+}
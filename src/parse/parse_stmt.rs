@@ -1,7 +1,8 @@
 use super::parse_expr::is_expr_start;
 use super::Parser;
-use crate::ast::{Block, LetStmt, Stmt, StmtKind};
+use crate::ast::{Block, Expr, ExprKind, LetStmt, Stmt, StmtKind, Ty};
 use crate::lexer::{Token, TokenKind};
+use crate::span::Span;
 
 pub fn is_stmt_start(t: &Token) -> bool {
     is_expr_start(t) || matches!(t.kind, TokenKind::Let)
@@ -36,91 +37,48 @@ impl Parser {
                 }
             }
             _ => {
-                eprintln!(
-                    "Expected expr, but found `{}`",
-                    self.peek_token().span.to_snippet()
-                );
+                self.expected("expression");
                 None
             }
         }
     }
 
-    /// letStmt ::= "let" ident (: type)? ("=" expr)? ";"
+    /// letStmt ::= "let" "mut"? ident (":" type)? ("=" expr)? ";"
     /// https://doc.rust-lang.org/reference/statements.html#let-statements
     fn parse_let_stmt(&mut self) -> Option<Stmt> {
+        let depth = self.lexer.brace_depth();
         // skip "let"
-        let mut span = self.skip_token().span;
-        let mut is_mut = false; 
-        let ident = if self.peek_token().kind == TokenKind::Mut {
-            self.skip_token();
-            is_mut = true; 
-            self.parse_ident()?
-        } else {
-            self.parse_ident()?
-        }; 
-
-        // skip colon
-        if !self.skip_expected_token(TokenKind::Colon) {
-            if self.peek_token().kind == TokenKind::Eq {
-                self.skip_token(); 
-                let init = self.parse_expr(); 
-                span = span.concat(&self.peek_token().span);
-                if !self.skip_expected_token(TokenKind::Semi) {
-                    eprintln!(
-                        "Expected ';' for let statement, but found `{}`",
-                        self.peek_token().span.to_snippet()
-                    );
-                    return None;
-                }
+        let span = self.skip_token().span;
+        let mutable = self.skip_expected_token(TokenKind::Mut);
+        let ident = self.parse_ident()?;
 
-                let result = Some(Stmt {
-                    kind: StmtKind::Let(LetStmt {
-                        ident: ident.clone(),
-                        mutable: is_mut, 
-                        ty: None,
-                        init,
-                    }),
-                    id: self.get_next_id(),
-                    span: span.clone(),
-                }); 
-                
-                return result; 
-            } else {
-                eprintln!(
-                    "Expected ':', but found `{}`",
-                    self.peek_token().span.to_snippet()
-                );
-                return None;
-            }
-        }
-
-        // parse type
-        let ty = self.parse_type()?;
-
-        // parse ("=" expr)?
-        let t = self.peek_token();
-        let init = if t.kind == TokenKind::Eq {
-            self.skip_token();
-            Some(self.parse_expr()?)
-        } else {
-            None
+        let Some((ty, init)) = self.parse_let_stmt_rest() else {
+            // keep the binding so that its uses are not reported as unresolved
+            let span = self.recover_stmt(span, depth);
+            let init = Expr {
+                kind: ExprKind::Err,
+                id: self.get_next_id(),
+                span: span.clone(),
+            };
+            return Some(Stmt {
+                kind: StmtKind::Let(LetStmt {
+                    ident,
+                    mutable,
+                    ty: None,
+                    init: Some(init),
+                }),
+                id: self.get_next_id(),
+                span,
+            });
         };
 
         // skip semi
-        span = span.concat(&self.peek_token().span);
-        if !self.skip_expected_token(TokenKind::Semi) {
-            eprintln!(
-                "Expected ';' for let statement, but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
-            return None;
-        }
-
+        let span = span.concat(&self.skip_token().span);
         Some(Stmt {
             kind: StmtKind::Let(LetStmt {
                 ident,
-                mutable: is_mut, 
-                ty: Some(ty),
+                mutable,
+                ty,
                 init,
             }),
             id: self.get_next_id(),
@@ -128,40 +86,102 @@ impl Parser {
         })
     }
 
+    /// The type and initializer of a let statement, which is followed by `;`
+    fn parse_let_stmt_rest(&mut self) -> Option<(Option<Ty>, Option<Expr>)> {
+        let ty = if self.skip_expected_token(TokenKind::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let init = if self.skip_expected_token(TokenKind::Eq) {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        if ty.is_none() && init.is_none() {
+            self.expected("`:` or `=`");
+            return None;
+        }
+        if self.peek_token().kind != TokenKind::Semi {
+            self.expected("`;` for let statement");
+            return None;
+        }
+        Some((ty, init))
+    }
+
     /// block ::= "{" stmt* "}"
+    /// A statement which fails to parse is replaced by an error expression,
+    /// and parsing resumes after the next `;`.
     pub fn parse_block(&mut self) -> Option<Block> {
         let mut span = self.peek_token().span.clone();
 
         if !self.skip_expected_token(TokenKind::OpenBrace) {
-            eprintln!(
-                "Expected '{{' but found `{}`",
-                self.peek_token().span.to_snippet()
-            );
+            self.expected("`{`");
             return None;
         }
         let mut stmts = vec![];
         loop {
+            let depth = self.lexer.brace_depth();
             let t = self.peek_token();
-            if is_stmt_start(t) {
-                let stmt = self.parse_stmt()?;
-                span = span.concat(&stmt.span);
-                stmts.push(stmt);
-            } else if t.kind == TokenKind::CloseBrace {
-                // skip '}'
-                span = span.concat(&self.skip_token().span);
-                return Some(Block {
-                    stmts,
-                    span,
-                    id: self.get_next_id(),
-                });
+            match t.kind {
+                TokenKind::CloseBrace => {
+                    // skip '}'
+                    span = span.concat(&self.skip_token().span);
+                    return Some(Block {
+                        stmts,
+                        span,
+                        id: self.get_next_id(),
+                        is_unsafe: false,
+                    });
+                }
+                TokenKind::Eof => {
+                    self.expected("`}` or statement");
+                    return None;
+                }
+                _ => (),
+            }
+            let start = t.span.clone();
+            let stmt = if is_stmt_start(t) {
+                self.parse_stmt()
             } else {
-                eprintln!(
-                    "Expected '}}' or statement, but found `{}`",
-                    t.span.to_snippet()
-                );
-                break;
+                self.expected("`}` or statement");
+                None
+            };
+            let stmt = stmt.unwrap_or_else(|| {
+                let span = self.recover_stmt(start, depth);
+                let expr = Expr {
+                    kind: ExprKind::Err,
+                    id: self.get_next_id(),
+                    span: span.clone(),
+                };
+                Stmt {
+                    kind: StmtKind::Semi(Box::new(expr)),
+                    id: self.get_next_id(),
+                    span,
+                }
+            });
+            span = span.concat(&stmt.span);
+            stmts.push(stmt);
+        }
+    }
+
+    /// Skip the rest of a statement which failed to parse at `start`, up to and including
+    /// the next `;`, or up to the next `let` or the `}` closing the block, and return the
+    /// span of the statement. Blocks opened since the start of the statement, at `depth`,
+    /// are skipped as a whole.
+    fn recover_stmt(&mut self, start: Span, depth: usize) -> Span {
+        let mut span = start;
+        loop {
+            let nested = self.lexer.brace_depth() > depth;
+            let t = self.peek_token();
+            match t.kind {
+                TokenKind::Eof => return span,
+                _ if nested => (),
+                TokenKind::CloseBrace | TokenKind::Let => return span,
+                TokenKind::Semi => return span.concat(&self.skip_token().span),
+                _ => (),
             }
+            span = span.concat(&self.skip_token().span);
         }
-        None
     }
 }
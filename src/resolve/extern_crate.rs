@@ -0,0 +1,86 @@
+use std::rc::Rc;
+
+use super::imports::ModuleName;
+use super::{Binding, BindingKind, CanonicalPath, Resolver, Rib, RibId, RibKind};
+use crate::ast::Visibility;
+use crate::metadata::{CrateMetadata, ExportedItemKind};
+use crate::span::Ident;
+
+impl Resolver {
+    /// Define modules and items of an extern crate from its metadata,
+    /// and make its root module usable as `name` in every module
+    pub fn define_extern_crate(&mut self, name: Rc<String>, metadata: &CrateMetadata) {
+        let root_cpath = CanonicalPath::new(vec![Rc::clone(&metadata.name)]);
+        self.define_extern_module(&root_cpath, None);
+        let root = Rc::new(Binding {
+            cpath: Rc::new(root_cpath),
+            kind: BindingKind::Mod,
+        });
+        self.extern_prelude.insert(
+            Rc::clone(&name),
+            ModuleName {
+                binding: root,
+                vis: Visibility::Public,
+                ident: Ident {
+                    symbol: name,
+                    span: metadata.span.clone(),
+                },
+                is_glob: false,
+                ambiguous_with: None,
+            },
+        );
+
+        for item in &metadata.items {
+            let ident = Ident {
+                symbol: Rc::clone(item.cpath.segments().last().unwrap()),
+                span: item.span.clone(),
+            };
+            match &item.kind {
+                ExportedItemKind::Import { name, target } => {
+                    let module = *self.module_ribs.get(&item.cpath).unwrap();
+                    let name = ModuleName {
+                        binding: Rc::clone(target),
+                        vis: item.vis,
+                        ident: Ident {
+                            symbol: Rc::clone(name),
+                            span: item.span.clone(),
+                        },
+                        is_glob: false,
+                        ambiguous_with: None,
+                    };
+                    self.get_rib_mut(module)
+                        .imports
+                        .insert(Rc::clone(&name.ident.symbol), name);
+                    continue;
+                }
+                ExportedItemKind::Mod => {
+                    let parent = self.module_ribs.get(&item.cpath.parent().unwrap()).copied();
+                    self.define_extern_module(&item.cpath, parent);
+                }
+                ExportedItemKind::Fn { is_foreign: true, .. } => {
+                    self.foreign_fns.insert(Rc::clone(&item.cpath));
+                }
+                ExportedItemKind::Struct(_) => {
+                    self.structs.insert(Rc::clone(&item.cpath));
+                }
+                _ => (),
+            }
+            let binding = item.binding().unwrap();
+            let parent = *self.module_ribs.get(&item.cpath.parent().unwrap()).unwrap();
+            self.item_decls
+                .insert(Rc::clone(&item.cpath), (item.vis, ident.clone()));
+            self.get_rib_mut(parent)
+                .insert_binding(ident.symbol, (*binding).clone());
+        }
+    }
+
+    fn define_extern_module(&mut self, cpath: &CanonicalPath, parent: Option<RibId>) {
+        let rib_id = self.get_next_rib_id();
+        if let Some(parent) = parent {
+            self.get_rib_mut(parent).children.push(rib_id);
+        }
+        self.interned
+            .insert(rib_id, Rib::new(rib_id, RibKind::Mod, parent, cpath.clone()));
+        self.module_ribs.insert(cpath.clone(), rib_id);
+    }
+}
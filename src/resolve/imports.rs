@@ -0,0 +1,276 @@
+use std::rc::Rc;
+
+use super::{is_accessible, Binding, BindingKind, Resolver, RibId};
+use crate::{ast::Visibility, diagnostics::Diagnostic, span::Ident, span::Span};
+
+/// A name defined in a module, either by an item or by a `use`
+#[derive(Debug, Clone)]
+pub(super) struct ModuleName {
+    pub binding: Rc<Binding>,
+    pub vis: Visibility,
+    /// name in the item declaration or in the `use` tree
+    pub ident: Ident,
+    pub is_glob: bool,
+    /// another glob import of the same name resolving to a different item
+    pub ambiguous_with: Option<Ident>,
+}
+
+/// A single `use` path, flattened from a `use` tree
+#[derive(Debug)]
+pub(super) struct ImportDirective {
+    /// module containing the `use`
+    pub module: RibId,
+    pub path: Vec<Ident>,
+    pub kind: ImportKind,
+    pub vis: Visibility,
+    pub span: Span,
+    pub resolved: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum ImportKind {
+    /// `use a::b;` or `use a::b as c;` binding the name
+    Single(Ident),
+    /// `use a::*;`
+    Glob,
+}
+
+pub(super) fn path_to_string(path: &[Ident]) -> String {
+    path.iter()
+        .map(|seg| seg.symbol.as_str())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+impl Resolver {
+    /// Names visible in a module, either declared or imported
+    pub(super) fn lookup_in_module(&self, module: RibId, symbol: &Rc<String>) -> Option<ModuleName> {
+        let rib = self.get_rib(module);
+        if let Some(binding) = rib.bindings.get(symbol) {
+            let (vis, ident) = self.item_decls.get(&binding.cpath).unwrap();
+            Some(ModuleName {
+                binding: Rc::clone(binding),
+                vis: *vis,
+                ident: ident.clone(),
+                is_glob: false,
+                ambiguous_with: None,
+            })
+        } else {
+            rib.imports.get(symbol).cloned()
+        }
+    }
+
+    pub(super) fn module_names(&self, module: RibId) -> Vec<ModuleName> {
+        let rib = self.get_rib(module);
+        let mut names: Vec<ModuleName> = rib
+            .bindings
+            .keys()
+            .filter_map(|symbol| self.lookup_in_module(module, symbol))
+            .collect();
+        names.extend(rib.imports.values().cloned());
+        names
+    }
+
+    /// Resolve `use` declarations.
+    /// An import can depend on names introduced by other imports,
+    /// so they are resolved repeatedly until no more names are added.
+    pub(super) fn resolve_imports(&mut self) {
+        loop {
+            let mut changed = false;
+            for i in 0..self.imports.len() {
+                changed |= self.resolve_import(i);
+            }
+            if !changed {
+                break;
+            }
+        }
+        for i in 0..self.imports.len() {
+            if !self.imports[i].resolved {
+                self.report_unresolved_import(i);
+            }
+        }
+    }
+
+    /// Returns true if a name was added or updated
+    fn resolve_import(&mut self, i: usize) -> bool {
+        let directive = &self.imports[i];
+        if directive.resolved && matches!(directive.kind, ImportKind::Single(_)) {
+            return false;
+        }
+        let module = directive.module;
+        let vis = directive.vis;
+        let kind = directive.kind.clone();
+        let span = directive.span.clone();
+        let mut errors = vec![];
+        let Some(binding) =
+            self.resolve_segments(module, &directive.path, &directive.span, &mut errors)
+        else {
+            return false;
+        };
+        let first_time = !directive.resolved;
+        if first_time {
+            self.imports[i].resolved = true;
+            self.errors.append(&mut errors);
+        }
+
+        match kind {
+            ImportKind::Single(ident) => {
+                self.define_import(
+                    module,
+                    ModuleName {
+                        binding,
+                        vis,
+                        ident,
+                        is_glob: false,
+                        ambiguous_with: None,
+                    },
+                );
+                true
+            }
+            ImportKind::Glob => {
+                if binding.kind != BindingKind::Mod {
+                    if first_time {
+                        self.errors.push(
+                            Diagnostic::error(format!(
+                                "`{:?}` is not a module, so it cannot be glob-imported",
+                                binding.cpath
+                            ))
+                            .code("M0101")
+                            .span_label(&span, "not a module"),
+                        );
+                    }
+                    return false;
+                }
+                let target = *self.module_ribs.get(&binding.cpath).unwrap();
+                let target_cpath = self.get_rib(target).cpath.clone();
+                let importing_cpath = self.get_rib(module).cpath.clone();
+                let mut changed = first_time;
+                for name in self.module_names(target) {
+                    if !is_accessible(name.vis, &target_cpath, &importing_cpath) {
+                        continue;
+                    }
+                    changed |= self.define_glob_import(
+                        module,
+                        ModuleName {
+                            binding: name.binding,
+                            vis,
+                            ident: Ident {
+                                symbol: name.ident.symbol,
+                                span: span.clone(),
+                            },
+                            is_glob: true,
+                            ambiguous_with: None,
+                        },
+                    );
+                }
+                changed
+            }
+        }
+    }
+
+    /// Explicit imports shadow glob imports, but conflict with items and other explicit imports
+    fn define_import(&mut self, module: RibId, name: ModuleName) {
+        let symbol = &name.ident.symbol;
+        let previous = match self.lookup_in_module(module, symbol) {
+            Some(prev) if !prev.is_glob => Some(prev.ident),
+            _ => None,
+        };
+        if let Some(prev) = previous {
+            self.errors.push(
+                Diagnostic::error(format!("the name `{}` is defined multiple times", symbol))
+                    .code("E0252")
+                    .span_label(&name.ident.span, format!("`{}` reimported here", symbol))
+                    .secondary_label(&prev.span, format!("previous definition of `{}` here", symbol)),
+            );
+            return;
+        }
+        self.get_rib_mut(module)
+            .imports
+            .insert(Rc::clone(symbol), name);
+    }
+
+    /// Returns true if a name was added or became ambiguous
+    fn define_glob_import(&mut self, module: RibId, name: ModuleName) -> bool {
+        let symbol = Rc::clone(&name.ident.symbol);
+        match self.lookup_in_module(module, &symbol) {
+            None => {
+                self.get_rib_mut(module).imports.insert(symbol, name);
+                true
+            }
+            Some(prev) if prev.is_glob && prev.binding != name.binding && prev.ambiguous_with.is_none() => {
+                let entry = self.get_rib_mut(module).imports.get_mut(&symbol).unwrap();
+                entry.ambiguous_with = Some(name.ident);
+                true
+            }
+            // shadowed by an item or an explicit import, or already imported
+            Some(_) => false,
+        }
+    }
+
+    /// Report an import that could not be resolved, or the cycle it belongs to
+    fn report_unresolved_import(&mut self, i: usize) {
+        let mut chain = vec![i];
+        let cycle_start = loop {
+            let directive = &self.imports[*chain.last().unwrap()];
+            let Some(next) = self.find_import_defining(directive) else {
+                break None;
+            };
+            if let Some(pos) = chain.iter().position(|j| *j == next) {
+                break Some(pos);
+            }
+            chain.push(next);
+        };
+
+        let directive = &self.imports[i];
+        match cycle_start {
+            Some(pos) if chain[pos..].contains(&i) => {
+                // report each cycle once
+                if chain[pos..].iter().min() != Some(&i) {
+                    return;
+                }
+                let mut cycle: Vec<String> = chain[pos..]
+                    .iter()
+                    .map(|j| format!("`{}`", path_to_string(&self.imports[*j].path)))
+                    .collect();
+                cycle.push(cycle[0].clone());
+                self.errors.push(
+                    Diagnostic::error("cyclic import")
+                        .code("M0102")
+                        .span_label(&directive.span, "imported here")
+                        .note(format!("the cycle is {}", cycle.join(" -> "))),
+                );
+            }
+            _ => {
+                let mut diag = Diagnostic::error(format!(
+                    "unresolved import `{}`",
+                    path_to_string(&directive.path)
+                ))
+                .code("E0432")
+                .span_label(&directive.span, "no such item");
+                if let Some(sugg) = self.suggest_import(directive.module, &directive.path) {
+                    diag = diag.help(format!("{}: `{}`", sugg.message, sugg.path));
+                }
+                self.errors.push(diag);
+            }
+        }
+    }
+
+    /// Unresolved import which would define the last segment of `directive`'s path
+    fn find_import_defining(&self, directive: &ImportDirective) -> Option<usize> {
+        let (last, prefix) = directive.path.split_last()?;
+        let module = if prefix.is_empty() {
+            directive.module
+        } else {
+            let binding = self.resolve_segments(directive.module, prefix, &directive.span, &mut vec![])?;
+            if binding.kind != BindingKind::Mod {
+                return None;
+            }
+            *self.module_ribs.get(&binding.cpath).unwrap()
+        };
+        self.imports.iter().position(|other| {
+            !other.resolved
+                && other.module == module
+                && matches!(&other.kind, ImportKind::Single(name) if name.symbol == last.symbol)
+        })
+    }
+}
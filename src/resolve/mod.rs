@@ -1,7 +1,21 @@
+mod extern_crate;
+mod imports;
 mod resolve_crate;
-
-use crate::{ast::Path, span::Ident};
-use std::{collections::HashMap, rc::Rc};
+mod suggestions;
+
+use self::imports::{ImportDirective, ModuleName};
+pub use self::suggestions::PathSuggestion;
+
+use crate::{
+    ast::{NodeId, Path, Visibility},
+    diagnostics::Diagnostic,
+    span::{Ident, Span, SyntaxContext},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    rc::Rc,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Binding {
@@ -13,6 +27,9 @@ pub struct Binding {
 pub enum BindingKind {
     Mod,
     Item,
+    Const,
+    /// `static` or `static mut`
+    Static(bool),
     Let(ShadowingIndex, bool),
     Param,
 }
@@ -20,13 +37,31 @@ pub enum BindingKind {
 pub type ShadowingIndex = u32;
 
 impl BindingKind {
-    pub fn is_param(&self) -> bool {
-        matches!(self, BindingKind::Param)
-    }
-
     pub fn is_let(&self) -> bool {
         matches!(self, BindingKind::Let(_, _))
     }
+
+    pub fn descr(&self) -> &'static str {
+        match self {
+            BindingKind::Mod => "module",
+            BindingKind::Item => "item",
+            BindingKind::Const => "constant",
+            BindingKind::Static(_) => "static",
+            BindingKind::Let(_, _) => "local variable",
+            BindingKind::Param => "parameter",
+        }
+    }
+}
+
+/// Whether an item with `vis` declared in `def_module` can be used from `from_module`
+pub fn is_accessible(vis: Visibility, def_module: &CanonicalPath, from_module: &CanonicalPath) -> bool {
+    let scope = match vis {
+        Visibility::Public => return true,
+        Visibility::Crate => def_module.root(),
+        Visibility::Super => def_module.parent().unwrap_or_else(CanonicalPath::krate),
+        Visibility::Private => def_module.clone(),
+    };
+    from_module.starts_with(&scope)
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -39,18 +74,47 @@ impl CanonicalPath {
         CanonicalPath { segments: vec![] }
     }
 
-    fn krate() -> Self {
+    pub fn krate() -> Self {
         CanonicalPath {
             segments: vec![Rc::new("crate".to_string())],
         }
     }
 
-    fn from_path(prefix: &CanonicalPath, path: &Path) -> Self {
-        let mut ret = prefix.clone();
-        for seg in &path.segments {
-            ret.segments.push(Rc::clone(&seg.symbol));
+    /// Path of an item in an extern crate, starting with the name of the crate
+    pub fn new(segments: Vec<Rc<String>>) -> Self {
+        assert!(!segments.is_empty());
+        CanonicalPath { segments }
+    }
+
+    pub fn segments(&self) -> &[Rc<String>] {
+        &self.segments
+    }
+
+    /// Path of the crate root which contains this path
+    pub fn root(&self) -> Self {
+        CanonicalPath {
+            segments: self.segments[..1].to_vec(),
+        }
+    }
+
+    /// Whether this path is in the crate being compiled, rather than in an extern crate
+    pub fn is_local(&self) -> bool {
+        *self.segments[0] == "crate"
+    }
+
+    /// Path of the enclosing module or item
+    pub fn parent(&self) -> Option<Self> {
+        if self.segments.len() <= 1 {
+            return None;
         }
-        ret
+        let mut parent = self.clone();
+        parent.segments.pop();
+        Some(parent)
+    }
+
+    /// Whether `self` is `prefix` or a path inside it
+    pub fn starts_with(&self, prefix: &CanonicalPath) -> bool {
+        self.segments.starts_with(&prefix.segments)
     }
 
     fn push_seg(&mut self, seg: Rc<String>) {
@@ -64,11 +128,11 @@ impl CanonicalPath {
     pub fn demangle(&self) -> String {
         let mut s = String::new();
         for (i, seg) in self.segments.iter().enumerate() {
-            // skip first `crate`
-            if i == 0 {
+            // skip first `crate`, but keep names of extern crates
+            if i == 0 && self.is_local() {
                 continue;
             }
-            s.push_str(&seg);
+            s.push_str(seg);
             if i != self.segments.len() - 1 {
                 s.push_str("..");
             }
@@ -102,11 +166,17 @@ pub struct Rib {
     kind: RibKind,
     cpath: CanonicalPath,
     bindings: HashMap<Rc<String>, Rc<Binding>>,
+    /// names imported by `use` into a module
+    imports: HashMap<Rc<String>, ModuleName>,
     parent: Option<RibId>,
     children: Vec<RibId>,
 }
 
 type RibId = u32;
+
+/// Local variables of a scope.
+/// Variables are distinguished by hygiene contexts as well as names.
+type VariableScope = HashMap<(Rc<String>, SyntaxContext), Rc<Binding>>;
 const DUMMY_RIB_ID: u32 = u32::MAX;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -122,6 +192,7 @@ impl Rib {
             id: rib_id,
             kind,
             bindings: HashMap::new(),
+            imports: HashMap::new(),
             parent,
             children: vec![],
             cpath,
@@ -142,16 +213,40 @@ pub struct Resolver {
     var_decl_to_res: HashMap<Ident, Rc<Binding>>,
     // Lookup map
     path_use_to_rib: HashMap<Path, ResolvedOrRib>,
+    // local variables in scope at uses of single-segment paths to items, suggested for typos
+    locals_at_use: HashMap<Path, Vec<Rc<Binding>>>,
     // stack of urrent name ribs
     current_ribs: Vec<RibId>,
     // current canonical path
     current_cpath: CanonicalPath,
     next_rib_id: u32,
     // stack representing name scopes of variables
-    current_variable_scopes: Vec<HashMap<Rc<String>, Rc<Binding>>>,
+    current_variable_scopes: Vec<VariableScope>,
+    // number of local variables declared so far with each name in the current function
+    shadowing_indices: HashMap<Rc<String>, ShadowingIndex>,
     // interned ribs
     interned: HashMap<RibId, Rib>,
     crate_rib_id: RibId,
+    // ribs of modules
+    module_ribs: HashMap<CanonicalPath, RibId>,
+    // visibilities and names of item declarations
+    item_decls: HashMap<Rc<CanonicalPath>, (Visibility, Ident)>,
+    // `use` declarations
+    imports: Vec<ImportDirective>,
+    // root modules of extern crates passed by `--extern`, usable in every module
+    extern_prelude: HashMap<Rc<String>, ModuleName>,
+    // functions declared in `extern` blocks, which are not mangled
+    foreign_fns: HashSet<Rc<CanonicalPath>>,
+    // foreign functions declared in `extern "rust-intrinsic"` blocks
+    intrinsics: HashSet<Rc<CanonicalPath>>,
+    // structs, which share `BindingKind::Item` with functions
+    structs: HashSet<Rc<CanonicalPath>>,
+    in_extern_block: bool,
+    // closures being resolved, with the number of variable scopes outside of each
+    current_closures: Vec<(NodeId, usize)>,
+    // local variables of enclosing functions used in closures, in the order of their first uses
+    captures: HashMap<NodeId, Vec<Rc<Binding>>>,
+    errors: Vec<Diagnostic>,
 
     cache: HashMap<Path, Rc<Binding>>,
 }
@@ -162,18 +257,37 @@ pub enum ResolvedOrRib {
     UnResolved(RibId),
 }
 
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Resolver {
     pub fn new() -> Self {
         Resolver {
             item_def_to_rib: HashMap::new(),
             var_decl_to_res: HashMap::new(),
             path_use_to_rib: HashMap::new(),
+            locals_at_use: HashMap::new(),
             current_ribs: vec![],
             current_cpath: CanonicalPath::empty(),
             current_variable_scopes: vec![],
+            shadowing_indices: HashMap::new(),
             interned: HashMap::new(),
             next_rib_id: 0,
             crate_rib_id: DUMMY_RIB_ID,
+            module_ribs: HashMap::new(),
+            item_decls: HashMap::new(),
+            imports: vec![],
+            extern_prelude: HashMap::new(),
+            foreign_fns: HashSet::new(),
+            intrinsics: HashSet::new(),
+            structs: HashSet::new(),
+            in_extern_block: false,
+            current_closures: vec![],
+            captures: HashMap::new(),
+            errors: vec![],
 
             cache: HashMap::new(),
         }
@@ -187,35 +301,41 @@ impl Resolver {
         self.interned.get_mut(&rib_id).unwrap()
     }
 
-    pub fn dump_ribs(&self) {
-        println!("===== Ribs in resolver =====");
+    /// Ribs and the uses and definitions of names in them, for `--dump`
+    pub fn dump_ribs(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "===== Ribs in resolver =====").unwrap();
         for (rib_id, rib) in &self.interned {
-            println!("{} => [", rib_id);
-            println!("\tcpath: {:?}", rib.cpath);
-            println!("\tkind: {:?}", rib.kind);
+            writeln!(out, "{} => [", rib_id).unwrap();
+            writeln!(out, "\tcpath: {:?}", rib.cpath).unwrap();
+            writeln!(out, "\tkind: {:?}", rib.kind).unwrap();
             for (s, binding) in &rib.bindings {
-                println!("\t\"{}\" => {:?}, ", s, binding);
+                writeln!(out, "\t\"{}\" => {:?}, ", s, binding).unwrap();
             }
-            println!("\tparent: {:?}", rib.parent);
-            println!("\tchildren: {:?}", rib.children);
-            println!("]");
+            writeln!(out, "\tparent: {:?}", rib.parent).unwrap();
+            writeln!(out, "\tchildren: {:?}", rib.children).unwrap();
+            writeln!(out, "]").unwrap();
         }
         for (ident, rib_id) in &self.item_def_to_rib {
-            println!("def of {:?} =>  {}, ", ident, rib_id);
+            writeln!(out, "def of {:?} =>  {}, ", ident, rib_id).unwrap();
         }
         for (ident, res_or_rib) in &self.path_use_to_rib {
-            println!("use of {:?} =>  {:?}, ", ident, res_or_rib);
+            writeln!(out, "use of {:?} =>  {:?}, ", ident, res_or_rib).unwrap();
         }
-        println!();
-        println!("============================");
+        writeln!(out).unwrap();
+        writeln!(out, "============================").unwrap();
+        out
     }
 
-    pub fn dump_resolution(&self) {
-        println!("===== Resolved names =======");
+    /// Paths resolved by the later stages, for `--dump`
+    pub fn dump_resolution(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "===== Resolved names =======").unwrap();
         for (ident, binding) in &self.cache {
-            println!("{:?} => {:?}", ident, binding);
+            writeln!(out, "{:?} => {:?}", ident, binding).unwrap();
         }
-        println!("============================");
+        writeln!(out, "============================").unwrap();
+        out
     }
 
     /// Resolve identifiers in declaration nodes (func params or local variables) to canonical paths
@@ -225,7 +345,7 @@ impl Resolver {
         } else if let Some(rib_id) = self.item_def_to_rib.get(ident) {
             let rib = self.get_rib(*rib_id);
             if let Some(binding) = rib.bindings.get(&ident.symbol) {
-                return Some(binding.clone());
+                Some(binding.clone())
             } else {
                 panic!(
                     "ICE: {:?} is in def_to_rib, but rib does not contain its def",
@@ -239,14 +359,16 @@ impl Resolver {
 
     /// Resolve paths to canonical paths
     pub fn resolve_path(&mut self, path: &Path) -> Option<Rc<Binding>> {
-        if let Some(binding) = self.cache.get(&path) {
+        if let Some(binding) = self.cache.get(path) {
             Some(Rc::clone(binding))
         } else {
             match &self.path_use_to_rib.get(path).unwrap() {
                 ResolvedOrRib::Resolved(binding) => Some(Rc::clone(binding)),
                 ResolvedOrRib::UnResolved(rib_id) => {
-                    let binding = self.resolve_path_from_rib(path, *rib_id)?;
-                    let binding = Rc::clone(&binding);
+                    let mut errors = vec![];
+                    let binding = self.resolve_path_from_rib(path, *rib_id, &mut errors);
+                    self.errors.append(&mut errors);
+                    let binding = binding?;
                     self.cache.insert(path.clone(), Rc::clone(&binding));
                     Some(binding)
                 }
@@ -254,36 +376,203 @@ impl Resolver {
         }
     }
 
-    /// Items visibie from a name space: `crate`, siblings items, and `use`d namespace
-    /// `path`: path in question
-    /// `rib_id`s: RibId of rib where path is used
-    fn resolve_path_from_rib(&self, path: &Path, rib_id: RibId) -> Option<Rc<Binding>> {
-        let emp_cpath = CanonicalPath::empty();
-        let crate_cpath = CanonicalPath::krate();
+    /// Public item `method` of `i32`, which is declared in the prelude module `num`
+    pub fn resolve_i32_item(&self, method: &Ident) -> Option<Rc<Binding>> {
+        let module = self.lookup_prelude_module("num")?;
+        let found = self.lookup_in_module(module, &method.symbol)?;
+        (found.vis == Visibility::Public && found.binding.kind == BindingKind::Item).then_some(found.binding)
+    }
+
+    /// Whether `cpath` is a function declared in an `extern` block
+    pub fn is_foreign_fn(&self, cpath: &CanonicalPath) -> bool {
+        self.foreign_fns.contains(cpath)
+    }
+
+    /// Whether `cpath` is a function declared in an `extern "rust-intrinsic"` block
+    pub fn is_intrinsic(&self, cpath: &CanonicalPath) -> bool {
+        self.intrinsics.contains(cpath)
+    }
+
+    /// Whether `cpath` is a struct
+    pub fn is_struct(&self, cpath: &CanonicalPath) -> bool {
+        self.structs.contains(cpath)
+    }
+
+    /// Variables captured by the closure expression `closure`
+    pub fn closure_captures(&self, closure: NodeId) -> &[Rc<Binding>] {
+        &self.captures[&closure]
+    }
+
+    /// Names imported by `use` into `module`
+    pub fn module_imports(&self, module: &CanonicalPath) -> Vec<(Rc<String>, Visibility, Rc<Binding>)> {
+        let rib = self.get_rib(*self.module_ribs.get(module).unwrap());
+        let mut imports: Vec<_> = rib
+            .imports
+            .iter()
+            .filter(|(_, name)| name.ambiguous_with.is_none())
+            .map(|(symbol, name)| (Rc::clone(symbol), name.vis, Rc::clone(&name.binding)))
+            .collect();
+        imports.sort_by(|a, b| a.0.cmp(&b.0));
+        imports
+    }
+
+    /// Errors found during resolution, such as uses of private items
+    pub fn take_errors(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Resolve all paths to items so that errors are reported in the resolution stage
+    fn resolve_all_paths(&mut self) {
+        let mut paths: Vec<Path> = self
+            .path_use_to_rib
+            .iter()
+            .filter(|(_, res)| matches!(res, ResolvedOrRib::UnResolved(_)))
+            .map(|(path, _)| path.clone())
+            .collect();
+        paths.sort_by_key(|path| path.span.lo());
+        for path in &paths {
+            self.resolve_path(path);
+        }
+    }
+
+    /// `rib_id`: RibId of rib where path is used
+    fn resolve_path_from_rib(
+        &self,
+        path: &Path,
+        rib_id: RibId,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Option<Rc<Binding>> {
         let rib = self.get_rib(rib_id);
-        let prefixes = vec![&emp_cpath, &crate_cpath, &rib.cpath];
-
-        // absolute path
-        if *path.segments.first().unwrap().symbol == "crate" {
-            let mut result = None;
-            // prefix: ["", "crate"]
-            self.resolve_to_item_with_dfs(&prefixes, path, self.crate_rib_id, &mut result);
-            result
+        let use_module = if rib.kind == RibKind::Mod {
+            rib
+        } else {
+            self.get_parent_module(rib_id).unwrap()
+        };
+        self.resolve_segments(use_module.id, &path.segments, &path.span, errors)
+    }
+
+    /// Resolve a path segment by segment, starting from the module where it is used.
+    /// The first segment can be `crate`, `self` or `super`s.
+    /// Otherwise it is looked up in the current module, including names imported by `use`,
+    /// and then in extern crates.
+    fn resolve_segments(
+        &self,
+        use_module: RibId,
+        segments: &[Ident],
+        span: &Span,
+        errors: &mut Vec<Diagnostic>,
+    ) -> Option<Rc<Binding>> {
+        let use_module_cpath = &self.get_rib(use_module).cpath;
+        let mut module = use_module;
+        let mut segments = segments.iter().peekable();
+        let mut relative = false;
+        match segments.peek()?.symbol.as_str() {
+            "crate" => {
+                segments.next();
+                module = self.crate_rib_id;
+            }
+            "self" => {
+                segments.next();
+            }
+            "super" => (),
+            // associated items of `i32` such as `i32::MAX` are declared in the prelude
+            "i32" => {
+                segments.next();
+                module = self.lookup_prelude_module("num")?;
+            }
+            _ => relative = true,
         }
-        // relative path
-        else {
-            // search in all siblings
-            let mut result = None;
-
-            // search from this module (if this rib is not module, starts from its parent module)
-            if rib.kind != RibKind::Mod {
-                let parent_module_rib = self.get_parent_module(rib_id).unwrap();
-                self.resolve_to_item_with_dfs(&prefixes, path, parent_module_rib.id, &mut result);
-            } else {
-                self.resolve_to_item_with_dfs(&prefixes, path, rib_id, &mut result);
+        while let Some(seg) = segments.next_if(|seg| *seg.symbol == "super") {
+            let Some(parent) = self.get_parent_module(module) else {
+                errors.push(
+                    Diagnostic::error("there are too many leading `super` keywords")
+                        .code("E0433")
+                        .span_label(&seg.span, "goes beyond the crate root"),
+                );
+                return None;
+            };
+            module = parent.id;
+        }
+        // `use super::*` and `use crate::*` name the module itself
+        if !relative && segments.peek().is_none() {
+            return Some(Rc::new(Binding {
+                cpath: Rc::new(self.get_rib(module).cpath.clone()),
+                kind: BindingKind::Mod,
+            }));
+        }
+
+        let mut binding: Option<Rc<Binding>> = None;
+        for (i, seg) in segments.enumerate() {
+            if let Some(binding) = binding {
+                // only modules can have items
+                if binding.kind != BindingKind::Mod {
+                    return None;
+                }
+                module = *self.module_ribs.get(&binding.cpath).unwrap();
             }
+            let found = match self.lookup_in_module(module, &seg.symbol) {
+                Some(found) => found,
+                None if i == 0 && relative => self.extern_prelude.get(&seg.symbol)?.clone(),
+                None => return None,
+            };
+            self.check_privacy(&found, &self.get_rib(module).cpath, use_module_cpath, span, errors);
+            self.check_ambiguity(&found, span, errors);
+            binding = Some(found.binding);
+        }
+        binding
+    }
 
-            result
+    /// Module `crate::prelude::name`
+    fn lookup_prelude_module(&self, name: &str) -> Option<RibId> {
+        let mut module = self.crate_rib_id;
+        for symbol in ["prelude", name] {
+            let found = self.lookup_in_module(module, &Rc::new(symbol.to_string()))?;
+            if found.binding.kind != BindingKind::Mod {
+                return None;
+            }
+            module = *self.module_ribs.get(&found.binding.cpath).unwrap();
+        }
+        Some(module)
+    }
+
+    fn check_privacy(
+        &self,
+        name: &ModuleName,
+        def_module: &CanonicalPath,
+        use_module: &CanonicalPath,
+        span: &Span,
+        errors: &mut Vec<Diagnostic>,
+    ) {
+        if !is_accessible(name.vis, def_module, use_module) {
+            errors.push(
+                Diagnostic::error(format!(
+                    "{} `{}` is private",
+                    name.binding.kind.descr(),
+                    name.ident.symbol
+                ))
+                .code("E0603")
+                .span_label(span, format!("private {}", name.binding.kind.descr()))
+                .secondary_label(&name.ident.span, format!("`{}` is declared here", name.ident.symbol)),
+            );
+        }
+    }
+
+    fn check_ambiguity(&self, name: &ModuleName, span: &Span, errors: &mut Vec<Diagnostic>) {
+        if let Some(other) = &name.ambiguous_with {
+            let symbol = &name.ident.symbol;
+            errors.push(
+                Diagnostic::error(format!("`{}` is ambiguous", symbol))
+                    .code("E0659")
+                    .span_label(span, "ambiguous name")
+                    .secondary_label(
+                        &name.ident.span,
+                        format!("`{}` could refer to the name imported here", symbol),
+                    )
+                    .secondary_label(
+                        &other.span,
+                        format!("`{}` could also refer to the name imported here", symbol),
+                    ),
+            );
         }
     }
 
@@ -300,36 +589,4 @@ impl Resolver {
             None
         }
     }
-
-    /// Resovle path to item or module
-    fn resolve_to_item_with_dfs(
-        &self,
-        prefixes: &[&CanonicalPath],
-        path: &Path,
-        rib_id: RibId,
-        result: &mut Option<Rc<Binding>>,
-    ) {
-        if result.is_some() {
-            return;
-        }
-
-        let rib = self.get_rib(rib_id);
-
-        for (_, binding) in &rib.bindings {
-            if matches!(binding.kind, BindingKind::Item | BindingKind::Mod) {
-                for prefix in prefixes {
-                    let path_with_prefix = CanonicalPath::from_path(prefix, path);
-                    if *binding.cpath == path_with_prefix {
-                        *result = Some(Rc::clone(binding));
-                        return;
-                    }
-                }
-            }
-        }
-
-        for child in &rib.children {
-            // TODO: if `pub`
-            self.resolve_to_item_with_dfs(prefixes, path, *child, result);
-        }
-    }
 }
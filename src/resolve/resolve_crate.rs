@@ -1,15 +1,18 @@
 use std::{collections::HashMap, rc::Rc};
 
-use super::{Binding, BindingKind, ResolvedOrRib, Resolver, Rib, RibId, RibKind};
+use super::imports::{ImportDirective, ImportKind};
+use super::{Binding, BindingKind, CanonicalPath, ResolvedOrRib, Resolver, Rib, RibId, RibKind, ShadowingIndex, VariableScope};
 use crate::{
-    ast::{self, Path, StmtKind},
+    ast::{self, Path, StmtKind, UseTree, UseTreeKind, Visibility},
+    diagnostics::Diagnostic,
+    middle::{intrinsics::INTRINSIC_ABI, ty::Ty},
     span::Ident,
 };
 
 impl Resolver {
     fn get_current_rib_mut(&mut self) -> &mut Rib {
         let current_rib_id = self.current_ribs.last().unwrap();
-        self.interned.get_mut(&current_rib_id).unwrap()
+        self.interned.get_mut(current_rib_id).unwrap()
     }
 
     fn push_rib(&mut self, kind: RibKind) {
@@ -19,6 +22,10 @@ impl Resolver {
             let parent_rib = self.get_rib_mut(parent_rib_id);
             parent_rib.children.push(new_rib_id);
         }
+        if kind == RibKind::Mod {
+            self.module_ribs
+                .insert(self.current_cpath.clone(), new_rib_id);
+        }
         let rib = Rib::new(new_rib_id, kind, parent_rib_id, self.current_cpath.clone());
         self.current_ribs.push(rib.id);
         self.interned.insert(rib.id, rib);
@@ -28,7 +35,7 @@ impl Resolver {
         self.current_ribs.pop().unwrap()
     }
 
-    fn get_next_rib_id(&mut self) -> u32 {
+    pub(super) fn get_next_rib_id(&mut self) -> u32 {
         let id = self.next_rib_id;
         self.next_rib_id += 1;
         id
@@ -50,38 +57,70 @@ impl Resolver {
         self.current_variable_scopes.pop();
     }
 
-    fn get_current_scope_mut(&mut self) -> Option<&mut HashMap<Rc<String>, Rc<Binding>>> {
+    fn get_current_scope_mut(&mut self) -> Option<&mut VariableScope> {
         self.current_variable_scopes.last_mut()
     }
 
-    fn get_current_scopes(&self) -> &[HashMap<Rc<String>, Rc<Binding>>] {
+    fn get_current_scopes(&self) -> &[VariableScope] {
         &self.current_variable_scopes
     }
 
-    fn insert_item_def(&mut self, ident: &Ident, kind: BindingKind) {
+    fn insert_item_def(&mut self, ident: &Ident, kind: BindingKind, vis: Visibility) -> Rc<CanonicalPath> {
         self.item_def_to_rib
             .insert(ident.clone(), *self.current_ribs.last().unwrap());
 
         let mut cpath = self.current_cpath.clone();
         cpath.push_seg(Rc::clone(&ident.symbol));
+        let cpath = Rc::new(cpath);
+        self.item_decls
+            .insert(Rc::clone(&cpath), (vis, ident.clone()));
         self.get_current_rib_mut().insert_binding(
             Rc::clone(&ident.symbol),
             Binding {
-                kind: kind,
-                cpath: Rc::new(cpath),
+                kind,
+                cpath: Rc::clone(&cpath),
             },
         );
+        cpath
     }
 
-    fn get_num_of_same_variable_name_in_scopes(&self, ident: &Ident) -> u32 {
-        let mut res = 0;
-        for scope in self.get_current_scopes() {
-            if let Some(bind) = scope.get(&ident.symbol)
-                && matches!(bind.kind, BindingKind::Let(_, _) | BindingKind::Param){
-                res+=1;
+    /// Flatten a `use` tree into import directives of the current module
+    fn insert_import_directives(&mut self, tree: &UseTree, parent: &[Ident], vis: Visibility) {
+        let mut path = parent.to_vec();
+        path.extend(tree.prefix.iter().cloned());
+        let kind = match &tree.kind {
+            UseTreeKind::Simple(rename) => {
+                // `use a::{self}` imports `a` itself
+                if path.len() > 1 && *path.last().unwrap().symbol == "self" {
+                    path.pop();
+                }
+                let name = rename.clone().unwrap_or_else(|| path.last().unwrap().clone());
+                ImportKind::Single(name)
             }
-        }
-        res
+            UseTreeKind::Glob => ImportKind::Glob,
+            UseTreeKind::Nested(trees) => {
+                for tree in trees {
+                    self.insert_import_directives(tree, &path, vis);
+                }
+                return;
+            }
+        };
+        self.imports.push(ImportDirective {
+            module: *self.current_ribs.last().unwrap(),
+            path,
+            kind,
+            vis,
+            span: tree.span.clone(),
+            resolved: false,
+        });
+    }
+
+    /// Index distinguishing local variables of the same name in the current function,
+    /// including those introduced by different macro expansions
+    fn get_next_shadowing_index(&mut self, ident: &Ident) -> ShadowingIndex {
+        let index = self.shadowing_indices.entry(Rc::clone(&ident.symbol)).or_insert(0);
+        *index += 1;
+        *index - 1
     }
 
     fn insert_var_decl(&mut self, ident: &Ident, kind: BindingKind) {
@@ -97,23 +136,38 @@ impl Resolver {
 
         self.get_current_scope_mut()
             .unwrap()
-            .insert(Rc::clone(&ident.symbol), binding);
+            .insert((Rc::clone(&ident.symbol), ident.span.ctxt()), binding);
     }
 
-    fn find_variable_in_scope(&self, path: &Path) -> Option<Rc<Binding>> {
+    /// Variable named by `path` and the index of its scope
+    fn find_variable_in_scope(&self, path: &Path) -> Option<(usize, Rc<Binding>)> {
         if path.segments.is_empty() || path.segments.len() > 1 {
             return None;
         }
         let ident = &path.segments[0];
         // search path from the current scope to the old scope
-        for scope in self.get_current_scopes().iter().rev() {
-            if let Some(binding) = scope.get(&ident.symbol) {
-                return Some(Rc::clone(binding));
+        let key = (Rc::clone(&ident.symbol), ident.span.ctxt());
+        for (i, scope) in self.get_current_scopes().iter().enumerate().rev() {
+            if let Some(binding) = scope.get(&key) {
+                return Some((i, Rc::clone(binding)));
             }
         }
         None
     }
 
+    /// Closures between the use of a variable and its scope capture it
+    fn insert_captures(&mut self, scope: usize, binding: &Rc<Binding>) {
+        for (closure, outer_scopes) in self.current_closures.iter().rev() {
+            if scope >= *outer_scopes {
+                break;
+            }
+            let captures = self.captures.get_mut(closure).unwrap();
+            if !captures.contains(binding) {
+                captures.push(Rc::clone(binding));
+            }
+        }
+    }
+
     fn insert_use_of_variable(&mut self, path: &Path, binding: Rc<Binding>) {
         self.path_use_to_rib
             .insert(path.clone(), ResolvedOrRib::Resolved(binding));
@@ -124,6 +178,17 @@ impl Resolver {
             path.clone(),
             ResolvedOrRib::UnResolved(*self.current_ribs.last().unwrap()),
         );
+        if let [ident] = path.segments.as_slice() {
+            let ctxt = ident.span.ctxt();
+            let locals = self
+                .get_current_scopes()
+                .iter()
+                .flat_map(|scope| scope.iter())
+                .filter(|((_, var_ctxt), _)| *var_ctxt == ctxt)
+                .map(|(_, binding)| Rc::clone(binding))
+                .collect();
+            self.locals_at_use.insert(path.clone(), locals);
+        }
     }
 }
 
@@ -145,12 +210,15 @@ impl<'ctx> ast::visitor::Visitor<'ctx> for Resolver {
         assert_eq!(*krate, "crate");
         // pop rib
         let krate_rib = self.pop_rib();
-        assert_eq!(krate_rib, 0);
+        assert_eq!(krate_rib, self.crate_rib_id);
+
+        self.resolve_imports();
+        self.resolve_all_paths();
     }
 
     fn visit_module_item(&mut self, module: &'ctx ast::Module) {
         // register cmodule name
-        self.insert_item_def(&module.name, BindingKind::Mod);
+        self.insert_item_def(&module.name, BindingKind::Mod, module.vis);
 
         // push module name to cpath
         self.push_segment_to_current_cpath(Rc::clone(&module.name.symbol));
@@ -168,7 +236,13 @@ impl<'ctx> ast::visitor::Visitor<'ctx> for Resolver {
 
     fn visit_func(&mut self, func: &'ctx ast::Func) {
         // register func name
-        self.insert_item_def(&func.name, BindingKind::Item);
+        let cpath = self.insert_item_def(&func.name, BindingKind::Item, func.vis);
+        if self.in_extern_block {
+            if func.ext.as_deref() == Some(INTRINSIC_ABI) {
+                self.intrinsics.insert(Rc::clone(&cpath));
+            }
+            self.foreign_fns.insert(cpath);
+        }
 
         // push func name to cpath
         self.push_segment_to_current_cpath(Rc::clone(&func.name.symbol));
@@ -178,6 +252,7 @@ impl<'ctx> ast::visitor::Visitor<'ctx> for Resolver {
 
         // push variable scope
         self.push_variable_scope();
+        self.shadowing_indices.clear();
 
         // insert parameters to rib
         for (param, _) in &func.params {
@@ -198,7 +273,55 @@ impl<'ctx> ast::visitor::Visitor<'ctx> for Resolver {
     }
 
     fn visit_struct_item(&mut self, strct: &'ctx ast::StructItem) {
-        self.insert_item_def(&strct.ident, BindingKind::Item);
+        let cpath = self.insert_item_def(&strct.ident, BindingKind::Item, strct.vis);
+        self.structs.insert(cpath);
+    }
+
+    fn visit_const_item(&mut self, const_item: &'ctx ast::ConstItem) {
+        self.insert_item_def(&const_item.name, BindingKind::Const, const_item.vis);
+    }
+
+    fn visit_static_item(&mut self, static_item: &'ctx ast::StaticItem) {
+        self.insert_item_def(
+            &static_item.name,
+            BindingKind::Static(static_item.mutable),
+            static_item.vis,
+        );
+    }
+
+    fn visit_extern_block(&mut self, _block: &'ctx ast::ExternBlock) {
+        self.in_extern_block = true;
+    }
+
+    fn visit_extern_block_post(&mut self, _block: &'ctx ast::ExternBlock) {
+        self.in_extern_block = false;
+    }
+
+    fn visit_use_item(&mut self, use_item: &'ctx ast::UseItem) {
+        self.insert_import_directives(&use_item.tree, &[], use_item.vis);
+    }
+
+    /// `extern crate a as b;` is `use a as b;` where `a` must be an extern crate
+    fn visit_extern_crate_item(&mut self, extern_crate: &'ctx ast::ExternCrateItem) {
+        let name = &extern_crate.name;
+        if !self.extern_prelude.contains_key(&name.symbol) {
+            self.errors.push(
+                Diagnostic::error(format!("can't find crate for `{}`", name.symbol))
+                    .code("E0463")
+                    .span_label(&name.span, "can't find crate")
+                    .note(format!("extern crates are passed with `--extern {}=<path>`", name.symbol)),
+            );
+            return;
+        }
+        let rename = extern_crate.rename.as_ref().unwrap_or(name);
+        self.imports.push(ImportDirective {
+            module: *self.current_ribs.last().unwrap(),
+            path: vec![name.clone()],
+            kind: ImportKind::Single(rename.clone()),
+            vis: extern_crate.vis,
+            span: name.span.clone(),
+            resolved: false,
+        });
     }
 
     fn visit_block(&mut self, _block: &'ctx ast::Block) {
@@ -220,14 +343,40 @@ impl<'ctx> ast::visitor::Visitor<'ctx> for Resolver {
     fn visit_stmt_post(&mut self, stmt: &'ctx ast::Stmt) {
         if let StmtKind::Let(let_stmt) = &stmt.kind {
             // insert local variables
-            let shadowing_index = self.get_num_of_same_variable_name_in_scopes(&let_stmt.ident);
+            let shadowing_index = self.get_next_shadowing_index(&let_stmt.ident);
             self.insert_var_decl(&let_stmt.ident, BindingKind::Let(shadowing_index, let_stmt.mutable));
         }
     }
 
+    fn visit_expr(&mut self, expr: &'ctx ast::Expr) {
+        let ast::ExprKind::Closure(closure) = &expr.kind else {
+            return;
+        };
+        // parameters and local variables of closures are distinguished from those of the function
+        self.push_segment_to_current_cpath(Rc::new(format!("{{closure{:?}}}", expr.id)));
+        self.push_rib(RibKind::Block);
+        self.current_closures.push((expr.id, self.get_current_scopes().len()));
+        self.captures.insert(expr.id, vec![]);
+        self.push_variable_scope();
+        for (param, _) in &closure.params {
+            self.insert_var_decl(param, BindingKind::Param);
+        }
+    }
+
+    fn visit_expr_post(&mut self, expr: &'ctx ast::Expr) -> Rc<Ty> {
+        if let ast::ExprKind::Closure(_) = &expr.kind {
+            self.pop_variable_scope();
+            self.current_closures.pop();
+            self.pop_rib();
+            self.pop_segment_from_current_cpath();
+        }
+        Rc::new(Ty::never())
+    }
+
     fn visit_path(&mut self, path: &'ctx Path) {
         // try to resolve path to local variables
-        if let Some(binding) = self.find_variable_in_scope(path) {
+        if let Some((scope, binding)) = self.find_variable_in_scope(path) {
+            self.insert_captures(scope, &binding);
             self.insert_use_of_variable(path, binding)
         } else {
             self.insert_use_of_item(path);
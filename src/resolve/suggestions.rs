@@ -0,0 +1,165 @@
+use std::rc::Rc;
+
+use super::imports::path_to_string;
+use super::{is_accessible, Binding, BindingKind, ResolvedOrRib, Resolver, RibId, RibKind};
+use crate::{ast::Path, diagnostics::find_best_match, span::Ident};
+
+/// What an unresolved path may have been meant to be
+pub struct PathSuggestion {
+    /// Why the path is suggested, like "a local variable with a similar name exists"
+    pub message: String,
+    /// The whole suggested path
+    pub path: String,
+}
+
+fn with_article(descr: &str) -> String {
+    let article = if descr.starts_with(['a', 'e', 'i', 'o', 'u']) { "an" } else { "a" };
+    format!("{} {}", article, descr)
+}
+
+impl Resolver {
+    /// Kind of `binding` in messages, telling functions from structs
+    fn describe(&self, binding: &Binding) -> &'static str {
+        match binding.kind {
+            BindingKind::Item if self.is_struct(&binding.cpath) => "struct",
+            BindingKind::Item => "function",
+            kind => kind.descr(),
+        }
+    }
+
+    /// Suggestion for `path`, which does not resolve to a name accepted by `expected`
+    pub fn suggest_path(&self, path: &Path, expected: impl Fn(&Binding) -> bool) -> Option<PathSuggestion> {
+        let ResolvedOrRib::UnResolved(rib_id) = self.path_use_to_rib.get(path)? else {
+            return None;
+        };
+        let rib = self.get_rib(*rib_id);
+        let use_module = if rib.kind == RibKind::Mod {
+            rib.id
+        } else {
+            self.get_parent_module(rib.id)?.id
+        };
+        let locals = self.locals_at_use.get(path).map_or(&[][..], Vec::as_slice);
+        self.suggest_segments(use_module, &path.segments, locals, expected)
+    }
+
+    /// Suggestion for the path of a `use` declaration in `module`, which cannot be resolved
+    pub(super) fn suggest_import(&self, module: RibId, segments: &[Ident]) -> Option<PathSuggestion> {
+        self.suggest_segments(module, segments, &[], |_| true)
+    }
+
+    /// Suggest a similar name for the first segment which cannot be resolved, or else the path
+    /// of the last segment declared in another module.
+    /// `locals` are the local variables in scope, which are candidates for a single segment.
+    fn suggest_segments(
+        &self,
+        use_module: RibId,
+        segments: &[Ident],
+        locals: &[Rc<Binding>],
+        expected: impl Fn(&Binding) -> bool,
+    ) -> Option<PathSuggestion> {
+        // the longest prefix resolving to a module, in which the next segment is looked up
+        let mut module = None;
+        let mut index = 0;
+        while index + 1 < segments.len() {
+            match self.resolve_segments(use_module, &segments[..=index], &segments[0].span, &mut vec![]) {
+                Some(binding) if binding.kind == BindingKind::Mod => {
+                    module = Some(*self.module_ribs.get(&binding.cpath)?);
+                    index += 1;
+                }
+                _ => break,
+            }
+        }
+        let is_last = index + 1 == segments.len();
+        let is_expected = |binding: &Binding| {
+            if is_last {
+                expected(binding)
+            } else {
+                binding.kind == BindingKind::Mod
+            }
+        };
+
+        let mut candidates: Vec<(Rc<String>, Rc<Binding>)> = vec![];
+        let use_module_cpath = &self.get_rib(use_module).cpath;
+        match module {
+            Some(module) => {
+                let module_cpath = &self.get_rib(module).cpath;
+                for name in self.module_names(module) {
+                    if name.ambiguous_with.is_none() && is_accessible(name.vis, module_cpath, use_module_cpath) {
+                        candidates.push((name.ident.symbol, name.binding));
+                    }
+                }
+            }
+            None => {
+                if segments.len() == 1 {
+                    for local in locals {
+                        candidates.push((Rc::clone(local.cpath.segments().last().unwrap()), Rc::clone(local)));
+                    }
+                }
+                for name in self.module_names(use_module) {
+                    if name.ambiguous_with.is_none() {
+                        candidates.push((name.ident.symbol, name.binding));
+                    }
+                }
+                for (symbol, name) in &self.extern_prelude {
+                    candidates.push((Rc::clone(symbol), Rc::clone(&name.binding)));
+                }
+            }
+        }
+        candidates.retain(|(_, binding)| is_expected(binding));
+        // names are collected from hash maps
+        candidates.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let lookup = &segments[index].symbol;
+        let names = candidates.iter().map(|(symbol, _)| symbol.as_str());
+        if let Some(found) = find_best_match(names, lookup) {
+            let (_, binding) = candidates.iter().find(|(symbol, _)| **symbol == found).unwrap();
+            let mut suggested = segments.to_vec();
+            suggested[index].symbol = Rc::new(found.to_string());
+            return Some(PathSuggestion {
+                message: format!("{} with a similar name exists", with_article(self.describe(binding))),
+                path: path_to_string(&suggested),
+            });
+        }
+        if is_last {
+            return self.suggest_declared_elsewhere(use_module, lookup, expected);
+        }
+        None
+    }
+
+    /// Path of an item named `symbol` declared in another module, preferring accessible ones
+    fn suggest_declared_elsewhere(
+        &self,
+        use_module: RibId,
+        symbol: &Rc<String>,
+        expected: impl Fn(&Binding) -> bool,
+    ) -> Option<PathSuggestion> {
+        let use_module_cpath = &self.get_rib(use_module).cpath;
+        let mut found: Vec<(bool, &Rc<Binding>)> = self
+            .module_ribs
+            .values()
+            .filter(|module| **module != use_module)
+            .filter_map(|module| self.get_rib(*module).bindings.get(symbol))
+            .filter(|binding| expected(binding))
+            .map(|binding| {
+                let (vis, _) = &self.item_decls[&binding.cpath];
+                let def_module = binding.cpath.parent().unwrap();
+                (is_accessible(*vis, &def_module, use_module_cpath), binding)
+            })
+            .collect();
+        found.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cpath.segments().cmp(b.1.cpath.segments())));
+        let (accessible, binding) = found.first()?;
+        let def_module = binding.cpath.parent().unwrap();
+        let mut message = format!(
+            "{} with this name exists in module `{:?}`",
+            with_article(self.describe(binding)),
+            def_module
+        );
+        if !accessible {
+            message.push_str(", but it is private");
+        }
+        Some(PathSuggestion {
+            message,
+            path: format!("{:?}", binding.cpath),
+        })
+    }
+}
@@ -1,32 +1,130 @@
 use std::cmp::{max, min};
 use std::rc::Rc;
 
+/// Identifier of a source file, unique in a compilation session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(pub u32);
+
+/// A loaded source file
+#[derive(Debug)]
+pub struct SourceFile {
+    pub id: FileId,
+    /// Path of the file, or `<anon>` for source given on the command line
+    pub name: String,
+    pub src: String,
+    /// byte offsets of the starts of lines, which map offsets to lines and columns
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(id: FileId, name: String, src: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceFile {
+            id,
+            name,
+            src,
+            line_starts,
+        }
+    }
+
+    /// 1-based line and column of the byte offset `pos`. Columns count characters.
+    pub fn lookup_line_col(&self, pos: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= pos);
+        let line_start = self.line_starts[line - 1];
+        let col = self.src[line_start..pos].chars().count() + 1;
+        (line, col)
+    }
+
+    /// Text of the 1-based line `line` without its line break
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self.line_starts.get(line).map_or(self.src.len(), |&next| next - 1);
+        self.src[start..end].trim_end_matches('\r')
+    }
+}
+
+impl PartialEq for SourceFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for SourceFile {}
+
+impl std::hash::Hash for SourceFile {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+/// Hygiene context of a span.
+/// Tokens written in the body of a macro get a fresh context for each expansion,
+/// so that local variables introduced by the macro and those of the caller do not see each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SyntaxContext(pub u32);
+
+impl SyntaxContext {
+    /// Context of code which is not produced by macros
+    pub fn root() -> Self {
+        SyntaxContext(0)
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Hash)]
 pub struct Span {
     lo: usize,
     hi: usize,
-    src: Rc<String>,
+    file: Rc<SourceFile>,
+    ctxt: SyntaxContext,
 }
 
 impl Span {
-    pub fn new(lo: usize, hi: usize, src: Rc<String>) -> Self {
-        Span { lo, hi, src }
+    pub fn new(lo: usize, hi: usize, file: Rc<SourceFile>) -> Self {
+        Span {
+            lo,
+            hi,
+            file,
+            ctxt: SyntaxContext::root(),
+        }
+    }
+
+    pub fn file(&self) -> &Rc<SourceFile> {
+        &self.file
+    }
+
+    pub fn ctxt(&self) -> SyntaxContext {
+        self.ctxt
+    }
+
+    /// The same location in another hygiene context
+    pub fn with_ctxt(&self, ctxt: SyntaxContext) -> Span {
+        Span {
+            ctxt,
+            ..self.clone()
+        }
     }
 
     pub fn to_snippet(&self) -> String {
         assert!(self.lo <= self.hi);
-        assert!(self.hi <= self.src.len());
-        let src = &*self.src;
+        assert!(self.hi <= self.file.src.len());
+        let src = &self.file.src;
         let s = &src[self.lo()..self.hi()];
         // replace tabs and newlines
         s.replace("\r\n", "").replace('\n', "").replace("    ", " ")
     }
 
     pub fn concat(&self, span: &Span) -> Span {
+        // code expanded from a macro can mix tokens of different files
+        if self.file != span.file {
+            return self.clone();
+        }
         Span {
             lo: min(self.lo, span.lo),
             hi: max(self.hi, span.hi),
-            src: Rc::clone(&self.src),
+            file: Rc::clone(&self.file),
+            ctxt: self.ctxt,
         }
     }
 
@@ -37,6 +135,22 @@ impl Span {
     pub fn hi(&self) -> usize {
         self.hi
     }
+
+    /// 1-based line and column of the start of the span
+    pub fn line_col(&self) -> (usize, usize) {
+        self.file.lookup_line_col(self.lo)
+    }
+
+    /// 1-based line and column of the end of the span
+    pub fn end_line_col(&self) -> (usize, usize) {
+        self.file.lookup_line_col(self.hi)
+    }
+
+    /// `file:line:col` of the start of the span
+    pub fn location(&self) -> String {
+        let (line, col) = self.line_col();
+        format!("{}:{}:{}", self.file.name, line, col)
+    }
 }
 
 impl std::fmt::Debug for Span {
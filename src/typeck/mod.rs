@@ -1,13 +1,21 @@
-use crate::ast::{self, BinOp, Crate, ExprKind, LetStmt, Stmt, StmtKind};
+use crate::ast::{self, BinOp, Crate, ExprKind, Item, ItemKind, LetStmt, NodeId, Stmt, StmtKind};
+use crate::diagnostics::{find_best_match, Diagnostic};
 use crate::middle::ty::{self, AdtDef, Ty, TyKind};
+use crate::middle::ctfe::{ConstValue, Interpreter};
+use crate::middle::intrinsics::{Intrinsic, INTRINSIC_ABI};
 use crate::middle::Ctxt;
+use crate::resolve::{self, Binding, BindingKind, CanonicalPath, PathSuggestion};
+use crate::span::{Ident, Span};
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 pub fn typeck<'ctx, 'chk>(
     ctx: &'chk mut Ctxt<'ctx>,
     krate: &'chk Crate,
-) -> Result<(), Vec<String>> {
+) -> Result<(), Vec<Diagnostic>> {
     let mut checker = TypeChecker::new(ctx);
+    checker.interp.collect_items(checker.ctx, &krate.items);
+    checker.set_global_item_types(&krate.items);
     ast::visitor::go(&mut checker, krate);
     if checker.errors.is_empty() {
         Ok(())
@@ -16,35 +24,709 @@ pub fn typeck<'ctx, 'chk>(
     }
 }
 
+/// Add the suggestion for an unresolved `path`, if any
+fn with_path_suggestion(diag: Diagnostic, path: &ast::Path, sugg: Option<PathSuggestion>) -> Diagnostic {
+    match sugg {
+        Some(sugg) => diag.span_suggestion(&path.span, sugg.message, sugg.path),
+        None => diag,
+    }
+}
+
 struct TypeChecker<'ctx, 'chk> {
     ctx: &'chk mut Ctxt<'ctx>,
-    current_return_type: Option<Ty>,
-    errors: Vec<String>,
+    /// return types of the function and the closures being checked, innermost last
+    return_tys: Vec<Rc<Ty>>,
+    errors: Vec<Diagnostic>,
+    /// compile-time evaluator of `const` items and `const fn`s
+    interp: Interpreter<'chk>,
+    /// evaluated array lengths
+    array_lens: HashMap<NodeId, Option<usize>>,
+    /// types required by the context of expressions, used to infer the type of `[]`
+    expected_tys: HashMap<NodeId, Rc<Ty>>,
+    /// callees of calls, which are the only places intrinsics can be used
+    callees: HashSet<NodeId>,
+    /// whether the body being checked is a `const fn`
+    in_const_fn: bool,
+    /// stack of modules being checked
+    modules: Vec<Rc<CanonicalPath>>,
 }
 
 impl<'ctx, 'chk> TypeChecker<'ctx, 'chk> {
     fn new(ctx: &'chk mut Ctxt<'ctx>) -> Self {
         TypeChecker {
             ctx,
-            current_return_type: None,
+            return_tys: vec![],
             errors: vec![],
+            interp: Interpreter::new(),
+            array_lens: HashMap::new(),
+            expected_tys: HashMap::new(),
+            callees: HashSet::new(),
+            in_const_fn: false,
+            modules: vec![Rc::new(CanonicalPath::krate())],
         }
     }
 
-    fn error(&mut self, e: String) {
-        self.errors.push(e);
+    /// Set types of consts and statics before typechecking bodies
+    fn set_global_item_types(&mut self, items: &'chk [Item]) {
+        for item in items {
+            let (name, ty) = match &item.kind {
+                ItemKind::Const(const_item) => (&const_item.name, &const_item.ty),
+                ItemKind::Static(static_item) => (&static_item.name, &static_item.ty),
+                ItemKind::Mod(module) => {
+                    self.set_global_item_types(&module.items);
+                    continue;
+                }
+                _ => continue,
+            };
+            let binding = self.ctx.get_binding(name).unwrap();
+            let ty = self.ast_ty_to_ty(ty);
+            self.ctx.set_name_type(binding, Rc::new(ty));
+        }
     }
 
-    fn peek_return_type(&self) -> &Ty {
-        self.current_return_type.as_ref().unwrap()
+    /// Evaluate expression at compile time
+    fn eval_const_expr(&mut self, expr: &ast::Expr) -> Option<ConstValue> {
+        let val = self.interp.eval_const_expr(self.ctx, expr);
+        self.report_const_eval_errors();
+        val
     }
 
-    fn push_return_type(&mut self, ty: Ty) {
-        self.current_return_type = Some(ty);
+    fn eval_const_item(&mut self, binding: &Rc<Binding>) -> Option<ConstValue> {
+        let val = self.interp.eval_const_item(self.ctx, binding);
+        self.report_const_eval_errors();
+        val
+    }
+
+    /// `const fn`s can only call other `const fn`s
+    fn check_const_fn_call(&mut self, callee: &ast::Expr) {
+        let ExprKind::Path(path) = &callee.kind else {
+            self.error(
+                Diagnostic::error("cannot call function pointers in constant functions")
+                    .code("E0015")
+                    .span_label(&callee.span, "function pointer called here"),
+            );
+            return;
+        };
+        let Some(binding) = self.ctx.resolve_path(path) else {
+            return;
+        };
+        if binding.kind != BindingKind::Item {
+            self.error(
+                Diagnostic::error("cannot call function pointers in constant functions")
+                    .code("E0015")
+                    .span_label(&callee.span, "function pointer called here"),
+            );
+        } else if !self.interp.is_const_fn(&binding) {
+            self.error(
+                Diagnostic::error(format!(
+                    "cannot call non-const fn `{}` in constant functions",
+                    path.span.to_snippet()
+                ))
+                .code("E0015")
+                .span_label(&path.span, "not a `const fn`"),
+            );
+        }
+    }
+
+    /// Report use of a private field outside the module of its struct
+    fn check_field_privacy(&mut self, adt_cpath: &CanonicalPath, adt: &AdtDef, field: &Ident) {
+        let Some((vis, decl)) = adt.field_vis.iter().find(|(_, f)| f.symbol == field.symbol) else {
+            return;
+        };
+        let def_module = adt_cpath.parent().unwrap();
+        if !resolve::is_accessible(*vis, &def_module, self.modules.last().unwrap()) {
+            self.error(
+                Diagnostic::error(format!(
+                    "field `{}` of struct `{:?}` is private",
+                    field.symbol, adt_cpath
+                ))
+                .code("E0616")
+                .span_label(&field.span, "private field")
+                .secondary_label(&decl.span, format!("`{}` is declared here", field.symbol)),
+            );
+        }
+    }
+
+    /// Check that a struct expression gives each field of the struct once, with a value of
+    /// the type of the field. Returns whether it does, after reporting errors if not.
+    fn check_struct_fields(
+        &mut self,
+        adt_cpath: &CanonicalPath,
+        adt: &AdtDef,
+        path: &ast::Path,
+        fields: &[(Ident, Box<ast::Expr>)],
+    ) -> bool {
+        let mut ok = true;
+        let mut given: HashMap<Rc<String>, &Ident> = HashMap::new();
+        for (field, value) in fields {
+            let Some((_, field_ty)) = adt.fields.iter().find(|(name, _)| *name == field.symbol) else {
+                let mut diag = Diagnostic::error(format!(
+                    "struct `{:?}` has no field named `{}`",
+                    adt_cpath, field.symbol
+                ))
+                .code("E0560")
+                .span_label(&field.span, "unknown field");
+                let names = adt.fields.iter().map(|(name, _)| name.as_str());
+                if let Some(similar) = find_best_match(names, &field.symbol) {
+                    diag = diag.span_suggestion(&field.span, "a field with a similar name exists", similar);
+                }
+                self.error(diag);
+                ok = false;
+                continue;
+            };
+            if let Some(first) = given.insert(Rc::clone(&field.symbol), field) {
+                self.error(
+                    Diagnostic::error(format!("field `{}` specified more than once", field.symbol))
+                        .code("E0062")
+                        .span_label(&field.span, "used more than once")
+                        .secondary_label(&first.span, format!("first use of `{}`", field.symbol)),
+                );
+                ok = false;
+                continue;
+            }
+            let value_ty = self.ctx.get_type(value.id);
+            // errors in the value are already reported
+            if value_ty.is_error() {
+                ok = false;
+            } else if value_ty != *field_ty && !value_ty.is_never() {
+                self.error(
+                    Diagnostic::error("mismatched types")
+                        .code("E0308")
+                        .span_label(&value.span, format!("expected `{}`, found `{}`", field_ty, value_ty)),
+                );
+                ok = false;
+            }
+        }
+        let missing: Vec<String> = adt
+            .fields
+            .iter()
+            .filter(|(name, _)| !given.contains_key(name))
+            .map(|(name, _)| format!("`{}`", name))
+            .collect();
+        if !missing.is_empty() {
+            let plural = if missing.len() == 1 { "" } else { "s" };
+            self.error(
+                Diagnostic::error(format!(
+                    "missing field{} {} in initializer of `{:?}`",
+                    plural,
+                    missing.join(", "),
+                    adt_cpath
+                ))
+                .code("E0063")
+                .span_label(&path.span, format!("missing {}", missing.join(", "))),
+            );
+            ok = false;
+        }
+        ok
+    }
+
+    /// Check that each placeholder of `format_args!` can format its argument
+    fn check_format_args(&mut self, fmt: &'chk ast::FormatArgs) -> Rc<Ty> {
+        let mut ok = true;
+        for piece in &fmt.pieces {
+            let ast::FormatPiece::Placeholder(placeholder) = piece else {
+                continue;
+            };
+            let arg = &fmt.args[placeholder.arg];
+            // like binary operands, arguments may not be visited yet in the initializer of `let`
+            let ty = match self.ctx.lookup_type(arg.id) {
+                Some(ty) => ty,
+                None => ast::visitor::Visitor::visit_expr_post(self, arg),
+            };
+            if ty.kind == TyKind::Error {
+                ok = false;
+                continue;
+            }
+            let (formattable, name, spec) = if placeholder.debug {
+                (self.is_debug(&ty), "Debug", "{:?}")
+            } else {
+                (is_display(&ty), "Display", "{}")
+            };
+            if !formattable {
+                let mut diag = Diagnostic::error(format!(
+                    "`{}` cannot be formatted with `{}` because it doesn't implement `{}`",
+                    ty, spec, name
+                ))
+                .code("E0277")
+                .span_label(&placeholder.span, format!("`{}` cannot be formatted", ty))
+                .secondary_label(&arg.span, "argument formatted here");
+                if !placeholder.debug && self.is_debug(&ty) {
+                    let snippet = placeholder.span.to_snippet();
+                    let replacement = format!("{}:?}}", snippet.trim_end_matches('}'));
+                    diag = diag.span_suggestion(
+                        &placeholder.span,
+                        "use `Debug` formatting",
+                        replacement,
+                    );
+                }
+                self.error(diag);
+                ok = false;
+            }
+        }
+        if ok {
+            Rc::new(Ty::new(TyKind::Ref(Rc::new(Ty::new(TyKind::Str)))))
+        } else {
+            Rc::new(Ty::error())
+        }
+    }
+
+    /// Intrinsics must be known to the compiler and have the signature it expects
+    fn check_intrinsic_decl(&mut self, func: &ast::Func, func_ty: &Ty) {
+        if Intrinsic::from_name(&func.name.symbol).is_none() {
+            self.error(
+                Diagnostic::error(format!(
+                    "unrecognized intrinsic function: `{}`",
+                    func.name.symbol
+                ))
+                .code("E0093")
+                .span_label(&func.name.span, "unknown intrinsic"),
+            );
+            return;
+        }
+        // all intrinsics take two `i32`s and return an `i32`
+        let i32_ty = Rc::new(Ty::new(TyKind::I32));
+        let expected = Ty::new(TyKind::Fn(
+            Rc::new(vec![Rc::clone(&i32_ty), Rc::clone(&i32_ty)]),
+            i32_ty,
+            false,
+        ));
+        if *func_ty != expected {
+            self.error(
+                Diagnostic::error(format!("intrinsic `{}` has wrong type", func.name.symbol))
+                    .code("E0308")
+                    .span_label(
+                        &func.name.span,
+                        format!("expected `{}`, found `{}`", expected, func_ty),
+                    ),
+            );
+        }
+    }
+
+    /// Types printable by `{:?}`: those printable by `{}`, structs and arrays of them
+    fn is_debug(&self, ty: &Ty) -> bool {
+        match &ty.kind {
+            TyKind::Adt(cpath) => match self.ctx.lookup_adt_def(cpath) {
+                Some(adt) => adt.fields.iter().all(|(_, fd_ty)| self.is_debug(fd_ty)),
+                None => false,
+            },
+            TyKind::Array(elem_ty, _) => self.is_debug(elem_ty),
+            _ => is_display(ty),
+        }
+    }
+
+    /// Indices known at compile time must be in bounds, since the access would always panic
+    fn check_const_index(&mut self, index: &ast::Expr, len: usize) {
+        if !self.is_const_index(index) {
+            return;
+        }
+        if let Some(ConstValue::I32(n)) = self.eval_const_expr(index) {
+            if n < 0 || n as usize >= len {
+                self.error(
+                    Diagnostic::error("this operation will panic at runtime").code("M0201").span_label(
+                        &index.span,
+                        format!(
+                            "index out of bounds: the length is {} but the index is {}",
+                            len, n
+                        ),
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Literals, constants and arithmetic on them
+    fn is_const_index(&mut self, expr: &ast::Expr) -> bool {
+        match &expr.kind {
+            ExprKind::NumLit(_) => true,
+            ExprKind::Unary(_, inner) => self.is_const_index(inner),
+            ExprKind::Binary(_, l, r) => self.is_const_index(l) && self.is_const_index(r),
+            ExprKind::Path(path) => {
+                matches!(self.ctx.resolve_path(path), Some(binding) if binding.kind == BindingKind::Const)
+            }
+            _ => false,
+        }
+    }
+
+    fn report_const_eval_errors(&mut self) {
+        for e in self.interp.take_errors() {
+            self.error(e.into());
+        }
+    }
+
+    fn eval_array_len(&mut self, len: &ast::Expr) -> Option<usize> {
+        if let Some(n) = self.array_lens.get(&len.id) {
+            return *n;
+        }
+        let n = match self.eval_const_expr(len) {
+            Some(ConstValue::I32(n)) if n >= 0 => Some(n as usize),
+            Some(val) => {
+                self.error(
+                    Diagnostic::error("array length must be a non-negative integer")
+                        .code("M0202")
+                        .span_label(&len.span, format!("evaluates to {:?}", val)),
+                );
+                None
+            }
+            None => None,
+        };
+        self.array_lens.insert(len.id, n);
+        n
+    }
+
+    /// Arguments of a call to a known function are expected to have its parameter types
+    fn set_expected_arg_tys(&mut self, func: &ast::Expr, args: &[ast::Expr]) {
+        self.callees.insert(func.id);
+        let ExprKind::Path(path) = &func.kind else {
+            return;
+        };
+        let Some(binding) = self.ctx.resolve_path(path) else {
+            return;
+        };
+        let Some(func_ty) = self.ctx.lookup_name_type(&binding) else {
+            return;
+        };
+        if let Some((params, _, _)) = func_ty.get_callable_sig() {
+            for (arg, param_ty) in args.iter().zip(params) {
+                self.expected_tys.insert(arg.id, Rc::clone(param_ty));
+            }
+        }
+    }
+
+    /// The value assigned to a variable is expected to have its type
+    fn set_expected_rhs_ty(&mut self, lhs: &ast::Expr, rhs: &ast::Expr) {
+        let ExprKind::Path(path) = &lhs.kind else {
+            return;
+        };
+        let Some(binding) = self.ctx.resolve_path(path) else {
+            return;
+        };
+        if let Some(ty) = self.ctx.lookup_name_type(&binding) {
+            self.expected_tys.insert(rhs.id, ty);
+        }
+    }
+
+    /// Values of the fields of a struct expression are expected to have the types of the fields
+    fn set_expected_field_tys(&mut self, path: &ast::Path, fields: &[(Ident, Box<ast::Expr>)]) {
+        let Some(binding) = self.ctx.resolve_path(path) else {
+            return;
+        };
+        let Some(adt) = self.ctx.lookup_adt_def(&binding.cpath) else {
+            return;
+        };
+        for (field, value) in fields {
+            if let Some((_, ty)) = adt.fields.iter().find(|(name, _)| *name == field.symbol) {
+                self.expected_tys.insert(value.id, Rc::clone(ty));
+            }
+        }
+    }
+
+    /// Set the types of the parameters of a closure and the return type of its body,
+    /// which are taken from the `fn` type expected for the closure unless annotated
+    fn enter_closure(&mut self, expr: &ast::Expr, closure: &ast::Closure) {
+        let expected = match self.expected_tys.get(&expr.id).map(|ty| &ty.kind) {
+            Some(TyKind::Fn(params, ret_ty, _)) => Some((Rc::clone(params), Rc::clone(ret_ty))),
+            _ => None,
+        };
+        for (i, (param, ty)) in closure.params.iter().enumerate() {
+            let expected_param = expected.as_ref().and_then(|(params, _)| params.get(i));
+            let param_ty = match (ty, expected_param) {
+                (Some(ty), _) => Rc::new(self.ast_ty_to_ty(ty)),
+                (None, Some(param_ty)) => Rc::clone(param_ty),
+                (None, None) => {
+                    self.error(
+                        Diagnostic::error("type annotations needed for closure parameter")
+                            .code("E0282")
+                            .span_label(&param.span, "cannot infer its type")
+                            .help(format!("annotate it like `|{}: T| ..`", param.symbol)),
+                    );
+                    Rc::new(Ty::error())
+                }
+            };
+            let binding = self.ctx.get_binding(param).unwrap();
+            self.ctx.set_name_type(binding, param_ty);
+        }
+        // an error type stands for a return type inferred from the body
+        let ret_ty = match (&closure.ret_ty, expected) {
+            (Some(ty), _) => Rc::new(self.ast_ty_to_ty(ty)),
+            (None, Some((_, ret_ty))) => ret_ty,
+            (None, None) => Rc::new(Ty::error()),
+        };
+        if ret_ty.kind != TyKind::Error {
+            self.expected_tys.insert(closure.body.id, Rc::clone(&ret_ty));
+        }
+        self.push_return_type(ret_ty);
+    }
+
+    /// Type of a closure after its body is checked. Closures capturing nothing coerce to
+    /// `fn` pointers
+    fn check_closure(&mut self, expr: &ast::Expr, closure: &ast::Closure) -> Rc<Ty> {
+        let declared_ret_ty = self.peek_return_type();
+        self.pop_return_type();
+        let params: Vec<Rc<Ty>> = closure
+            .params
+            .iter()
+            .map(|(param, _)| {
+                let binding = self.ctx.get_binding(param).unwrap();
+                self.ctx.lookup_name_type(&binding).unwrap()
+            })
+            .collect();
+        let body_ty = self.ctx.get_type(closure.body.id);
+        let ret_ty = if declared_ret_ty.kind == TyKind::Error {
+            body_ty
+        } else if body_ty.is_never() || body_ty == declared_ret_ty || body_ty.kind == TyKind::Error
+        {
+            declared_ret_ty
+        } else {
+            self.error(Diagnostic::error("mismatched types").code("E0308").span_label(
+                &closure.body.span,
+                format!("expected `{}`, found `{}`", declared_ret_ty, body_ty),
+            ));
+            return Rc::new(Ty::error());
+        };
+        if ret_ty.kind == TyKind::Error || params.iter().any(|ty| ty.kind == TyKind::Error) {
+            return Rc::new(Ty::error());
+        }
+
+        let expects_fn = matches!(
+            self.expected_tys.get(&expr.id).map(|ty| &ty.kind),
+            Some(TyKind::Fn(..))
+        );
+        if !expects_fn {
+            return Rc::new(Ty::new(TyKind::Closure(Rc::new(ty::ClosureTy {
+                id: expr.id,
+                location: expr.span.location(),
+                params,
+                ret_ty,
+            }))));
+        }
+        if !self.check_closure_captures_nothing(expr.id, &expr.span) {
+            return Rc::new(Ty::error());
+        }
+        Rc::new(Ty::new(TyKind::Fn(Rc::new(params), ret_ty, false)))
+    }
+
+    /// Only closures capturing nothing can be coerced to `fn` pointers
+    fn check_closure_captures_nothing(&mut self, closure: NodeId, span: &Span) -> bool {
+        let captures = self.ctx.closure_captures(closure);
+        let Some(capture) = captures.first() else {
+            return true;
+        };
+        self.error(
+            Diagnostic::error("closures can only be coerced to `fn` types if they do not capture any variables")
+                .code("E0308")
+                .span_label(span, "expected a `fn` pointer")
+                .note(format!(
+                    "`{}` is captured by the closure",
+                    capture.cpath.segments().last().unwrap()
+                )),
+        );
+        false
+    }
+
+    /// Check that `lhs` of an assignment is a place that can be assigned to.
+    /// Returns whether it is, after reporting errors if not.
+    fn check_assign_place(&mut self, lhs: &ast::Expr) -> bool {
+        let root = place_root(lhs);
+        let path = match &root.kind {
+            ExprKind::Path(path) | ExprKind::Deref(path) => path,
+            _ => {
+                self.error(
+                    Diagnostic::error("invalid left-hand side of assignment")
+                        .code("E0070")
+                        .span_label(&lhs.span, "cannot assign to this expression"),
+                );
+                return false;
+            }
+        };
+        // unresolved names are already reported
+        let Some(binding) = self.ctx.resolve_path(path) else {
+            return false;
+        };
+        match &binding.kind {
+            BindingKind::Let(_, false) => {
+                self.error(
+                    Diagnostic::error(format!(
+                        "cannot assign twice to immutable variable `{}`",
+                        path.span.to_snippet()
+                    ))
+                    .code("E0384")
+                    .span_label(&lhs.span, "cannot assign twice to immutable variable")
+                    .help(format!("consider making this binding mutable: `mut {}`", path.span.to_snippet())),
+                );
+                false
+            }
+            BindingKind::Let(_, true) => {
+                let ty = self.ctx.lookup_name_type(&binding);
+                match ty.as_ref().map(|ty| &ty.kind) {
+                    Some(TyKind::ConstPtr(inner)) if matches!(root.kind, ExprKind::Deref(_)) => {
+                        self.error(
+                            Diagnostic::error(format!(
+                                "cannot assign to `{}`, which is behind a `*const {}` pointer",
+                                lhs.span.to_snippet(),
+                                inner
+                            ))
+                            .code("E0594")
+                            .span_label(&lhs.span, "cannot assign"),
+                        );
+                        false
+                    }
+                    _ => true,
+                }
+            }
+            BindingKind::Static(false) => {
+                self.error(
+                    Diagnostic::error(format!("cannot assign to immutable static item `{}`", path.span.to_snippet()))
+                        .code("E0594")
+                        .span_label(&lhs.span, "cannot assign"),
+                );
+                false
+            }
+            BindingKind::Static(true) => true,
+            _ => {
+                self.error(
+                    Diagnostic::error("invalid left-hand side of assignment")
+                        .code("E0070")
+                        .span_label(&lhs.span, "cannot assign to this expression")
+                        .note(format!("`{}` is not a variable or a static item", path.span.to_snippet())),
+                );
+                false
+            }
+        }
+    }
+
+    /// Check the arguments of `call` against the parameter types of the callee.
+    /// Returns whether they match, after reporting errors if not.
+    fn check_call_args(&mut self, call: &ast::Expr, params: &[Rc<Ty>], variadic: bool, args: &[ast::Expr]) -> bool {
+        // variadic functions take any number of trailing arguments
+        if params.len() != args.len() && !(variadic && args.len() >= params.len()) {
+            let plural = if params.len() == 1 { "" } else { "s" };
+            self.error(
+                Diagnostic::error(format!(
+                    "this function takes {} argument{} but {} were supplied",
+                    params.len(),
+                    plural,
+                    args.len()
+                ))
+                .code("E0061")
+                .span_label(&call.span, format!("expected {} argument{}", params.len(), plural)),
+            );
+            return false;
+        }
+        let mut ok = true;
+        for (arg, param_ty) in args.iter().zip(params) {
+            let arg_ty = &self.ctx.get_type(arg.id);
+            // errors in the argument are already reported
+            if arg_ty != param_ty && !arg_ty.is_error() && !param_ty.is_error() {
+                self.error(
+                    Diagnostic::error("mismatched types")
+                        .code("E0308")
+                        .span_label(&arg.span, format!("expected `{}`, found `{}`", param_ty, arg_ty)),
+                );
+                ok = false;
+            }
+        }
+        ok
+    }
+
+    /// Function called by `receiver.method(..)` where the receiver has type `receiver_ty`.
+    /// Only `i32` has methods, which are the functions of the prelude module `num` taking
+    /// an `i32` first.
+    fn lookup_method(&self, receiver_ty: &Ty, method: &Ident) -> Option<(Rc<Binding>, Rc<Ty>)> {
+        if receiver_ty.kind != TyKind::I32 {
+            return None;
+        }
+        let binding = self.ctx.resolve_i32_method(method)?;
+        let func_ty = self.ctx.lookup_name_type(&binding)?;
+        let (params, _, _) = func_ty.get_callable_sig()?;
+        if params.first()?.kind != receiver_ty.kind {
+            return None;
+        }
+        Some((binding, func_ty))
+    }
+
+    /// `receiver.method(args)` is checked like `i32::method(receiver, args)`, but the receiver
+    /// does not count as an argument
+    fn check_method_call(
+        &mut self,
+        expr: &ast::Expr,
+        receiver: &ast::Expr,
+        method: &Ident,
+        args: &[ast::Expr],
+    ) -> Rc<Ty> {
+        let receiver_ty = self.ctx.get_type(receiver.id);
+        if receiver_ty.is_error() {
+            return Rc::new(Ty::error());
+        }
+        let Some((binding, func_ty)) = self.lookup_method(&receiver_ty, method) else {
+            self.error(
+                Diagnostic::error(format!("no method named `{}` found for type `{}`", method.symbol, receiver_ty))
+                    .code("E0599")
+                    .span_label(&method.span, format!("method not found in `{}`", receiver_ty)),
+            );
+            return Rc::new(Ty::error());
+        };
+        if self.in_const_fn && !self.interp.is_const_fn(&binding) {
+            self.error(
+                Diagnostic::error(format!(
+                    "cannot call non-const method `i32::{}` in constant functions",
+                    method.symbol
+                ))
+                .code("E0015")
+                .span_label(&method.span, "not a `const fn`"),
+            );
+        }
+        let (params, ret_ty, variadic) = func_ty.get_callable_sig().unwrap();
+        self.ctx.set_method_callee(expr.id, binding);
+        if self.check_call_args(expr, &params[1..], variadic, args) {
+            Rc::clone(ret_ty)
+        } else {
+            Rc::new(Ty::error())
+        }
+    }
+
+    /// Type of `expr` of type `ty` after coercing a variable holding a closure to the `fn` type
+    /// expected for it, like a closure expression where a `fn` is expected
+    fn coerce_closure_var(&mut self, expr: &ast::Expr, ty: Rc<Ty>) -> Rc<Ty> {
+        let Some(expected) = self.expected_tys.get(&expr.id).map(Rc::clone) else {
+            return ty;
+        };
+        let (TyKind::Closure(closure), TyKind::Fn(params, ret_ty, false)) = (&ty.kind, &expected.kind) else {
+            return ty;
+        };
+        if !matches!(expr.kind, ExprKind::Path(_)) || closure.params != **params || closure.ret_ty != *ret_ty {
+            return ty;
+        }
+        if !self.check_closure_captures_nothing(closure.id, &expr.span) {
+            return Rc::new(Ty::error());
+        }
+        self.ctx.set_coerced_closure(expr.id, closure.id);
+        expected
+    }
+
+    fn error(&mut self, diag: Diagnostic) {
+        self.errors.push(diag);
+    }
+
+    /// Report operands of a binary operator with unexpected types
+    fn operand_error(&mut self, msg: &str, l: &ast::Expr, r: &ast::Expr, lhs_ty: &Ty, rhs_ty: &Ty) {
+        self.error(
+            Diagnostic::error(msg)
+                .code("E0369")
+                .span_label(&l.span, lhs_ty.to_string())
+                .span_label(&r.span, rhs_ty.to_string()),
+        );
+    }
+
+    fn peek_return_type(&self) -> Rc<Ty> {
+        Rc::clone(self.return_tys.last().unwrap())
+    }
+
+    fn push_return_type(&mut self, ty: Rc<Ty>) {
+        self.return_tys.push(ty);
     }
 
     fn pop_return_type(&mut self) {
-        self.current_return_type = None;
+        self.return_tys.pop();
     }
 
     fn get_block_type(&self, block: &ast::Block) -> Rc<Ty> {
@@ -64,22 +746,38 @@ impl<'ctx, 'chk> TypeChecker<'ctx, 'chk> {
             ast::TyKind::Unit => ty::TyKind::Unit,
             ast::TyKind::Str => ty::TyKind::Str,
             ast::TyKind::Ref(_region, referent) => {
-                ty::TyKind::Ref(Rc::new(self.ast_ty_to_ty(&referent)))
+                ty::TyKind::Ref(Rc::new(self.ast_ty_to_ty(referent)))
             }
-            ast::TyKind::Array(elem_ty, n) => {
-                ty::TyKind::Array(Rc::new(self.ast_ty_to_ty(elem_ty)), *n)
+            ast::TyKind::Array(elem_ty, len) => {
+                let elem_ty = Rc::new(self.ast_ty_to_ty(elem_ty));
+                if let Some(n) = self.eval_array_len(len) {
+                    ty::TyKind::Array(elem_ty, n)
+                } else {
+                    ty::TyKind::Error
+                }
             }
             ast::TyKind::Adt(path) => {
                 if let Some(binding) = self.ctx.resolve_path(path) {
                     ty::TyKind::Adt(Rc::clone(&binding.cpath))
                 } else {
-                    self.error(format!("{:?}", path));
+                    let diag = Diagnostic::error(format!(
+                        "cannot find type `{}` in this scope",
+                        path.span.to_snippet()
+                    ))
+                    .code("E0412")
+                    .span_label(&path.span, "not found in this scope");
+                    let sugg = self.ctx.suggest_struct_path(path);
+                    self.error(with_path_suggestion(diag, path, sugg));
                     ty::TyKind::Error
                 }
             }
             ast::TyKind::ConstPtr(referent) => {
                 ty::TyKind::ConstPtr(Rc::new(self.ast_ty_to_ty(referent)))
             }
+            ast::TyKind::Fn(params, ret_ty) => {
+                let params = params.iter().map(|param| Rc::new(self.ast_ty_to_ty(param))).collect();
+                ty::TyKind::Fn(Rc::new(params), Rc::new(self.ast_ty_to_ty(ret_ty)), false)
+            }
         };
 
         Ty::new(kind)
@@ -96,30 +794,37 @@ impl<'chk> ast::visitor::Visitor<'chk> for TypeChecker<'_, 'chk> {
     // TODO: external func must not have its body (correct?)
     fn visit_func(&mut self, func: &'chk ast::Func) {
         // TODO: typecheck main func
-        let param_tys = func
+        // convert the signature once, so that errors in it are reported once
+        let param_tys: Vec<Rc<Ty>> = func
             .params
             .iter()
             .map(|(_ident, ty)| Rc::new(self.ast_ty_to_ty(ty)))
             .collect();
+        let ret_ty = Rc::new(self.ast_ty_to_ty(&func.ret_ty));
         let func_ty = Rc::new(Ty::new(TyKind::Fn(
-            Rc::new(param_tys), 
-            Rc::new(self.ast_ty_to_ty(&func.ret_ty)),
-            true, 
+            Rc::new(param_tys.clone()),
+            Rc::clone(&ret_ty),
+            func.variadic,
         )));
 
         let binding = self.ctx.get_binding(&func.name).unwrap();
+        if func.ext.as_deref() == Some(INTRINSIC_ABI) {
+            self.check_intrinsic_decl(func, &func_ty);
+        }
         self.ctx.set_name_type(Rc::clone(&binding), func_ty);
 
         // push scope
-        for (param, param_ty) in &func.params {
+        for ((param, _), param_ty) in func.params.iter().zip(param_tys) {
             let binding = self.ctx.get_binding(param).unwrap();
-            let param_ty = self.ast_ty_to_ty(param_ty);
-            self.ctx
-                .set_name_type(Rc::clone(&binding), Rc::new(param_ty));
+            self.ctx.set_name_type(Rc::clone(&binding), param_ty);
+        }
+        // the value of the body is expected to have the return type
+        if let Some(tail) = func.body.as_ref().and_then(tail_expr) {
+            self.expected_tys.insert(tail.id, Rc::clone(&ret_ty));
         }
         // push return type
-        let ret_ty = self.ast_ty_to_ty(&func.ret_ty);
         self.push_return_type(ret_ty);
+        self.in_const_fn = func.is_const;
     }
 
     fn visit_func_post(&mut self, func: &'chk ast::Func) {
@@ -130,54 +835,150 @@ impl<'chk> ast::visitor::Visitor<'chk> for TypeChecker<'_, 'chk> {
         let body_ty = self.ctx.get_type(body.id);
 
         let expected = self.peek_return_type();
-        if !body_ty.is_never() && *body_ty != *expected {
-            self.error(format!(
-                "Expected type {:?} for func body, but found {:?}",
-                expected, body_ty
-            ));
+        if !body_ty.is_never() && *body_ty != *expected && !body_ty.is_error() && !expected.is_error() {
+            // point at the value of the body if there is one
+            let span = body.stmts.last().map_or(&body.span, |stmt| &stmt.span);
+            self.error(
+                Diagnostic::error("mismatched types")
+                    .code("E0308")
+                    .span_label(span, format!("expected `{}`, found `{}`", expected, body_ty))
+                    .secondary_label(&func.ret_ty.span, "expected due to this return type"),
+            );
         }
         // pop return type
         self.pop_return_type();
+        self.in_const_fn = false;
+    }
+
+    fn visit_module_item(&mut self, module: &'chk ast::Module) {
+        let binding = self.ctx.get_binding(&module.name).unwrap();
+        self.modules.push(Rc::clone(&binding.cpath));
+    }
+
+    fn visit_module_item_post(&mut self, _module: &'chk ast::Module) {
+        self.modules.pop();
     }
 
     fn visit_struct_item(&mut self, strct: &'chk ast::StructItem) {
         let field_tys: Vec<(Rc<String>, Rc<Ty>)> = strct
             .fields
             .iter()
-            .map(|(name, ty)| (Rc::clone(&name.symbol), Rc::new(self.ast_ty_to_ty(ty))))
+            .map(|field| (Rc::clone(&field.name.symbol), Rc::new(self.ast_ty_to_ty(&field.ty))))
             .collect();
-        let adt = AdtDef { fields: field_tys };
+        let field_vis = strct
+            .fields
+            .iter()
+            .map(|field| (field.vis, field.name.clone()))
+            .collect();
+        let adt = AdtDef {
+            fields: field_tys,
+            field_vis,
+        };
         let binding = self.ctx.get_binding(&strct.ident).unwrap();
         self.ctx.set_adt_def(Rc::clone(&binding.cpath), adt);
     }
 
+    fn visit_const_item_post(&mut self, const_item: &'chk ast::ConstItem) {
+        let binding = self.ctx.get_binding(&const_item.name).unwrap();
+        let expected = self.ctx.lookup_name_type(&binding).unwrap();
+        let init_ty = self.ctx.get_type(const_item.init.id);
+        if init_ty.is_error() || expected.is_error() {
+            return;
+        }
+        if *init_ty != *expected {
+            self.error(
+                Diagnostic::error("mismatched types")
+                    .code("E0308")
+                    .span_label(
+                        &const_item.init.span,
+                        format!("expected `{}`, found `{}`", expected, init_ty),
+                    )
+                    .secondary_label(&const_item.ty.span, "expected due to this type"),
+            );
+            return;
+        }
+        self.eval_const_item(&binding);
+    }
+
+    fn visit_static_item_post(&mut self, static_item: &'chk ast::StaticItem) {
+        let binding = self.ctx.get_binding(&static_item.name).unwrap();
+        let expected = self.ctx.lookup_name_type(&binding).unwrap();
+        if expected.is_error() {
+            return;
+        }
+        if !matches!(expected.kind, TyKind::I32 | TyKind::Bool | TyKind::Unit) {
+            self.error(
+                Diagnostic::error(format!("unsupported type of static `{}`", static_item.name.symbol))
+                    .code("M0203")
+                    .span_label(&static_item.ty.span, format!("`{}` is not supported", expected))
+                    .note("only i32, bool and () statics are supported"),
+            );
+            return;
+        }
+        let init_ty = self.ctx.get_type(static_item.init.id);
+        if init_ty.is_error() {
+            return;
+        }
+        if *init_ty != *expected {
+            self.error(
+                Diagnostic::error("mismatched types")
+                    .code("E0308")
+                    .span_label(
+                        &static_item.init.span,
+                        format!("expected `{}`, found `{}`", expected, init_ty),
+                    )
+                    .secondary_label(&static_item.ty.span, "expected due to this type"),
+            );
+            return;
+        }
+        if let Some(val) = self.eval_const_expr(&static_item.init) {
+            self.ctx.set_const_value(binding, val);
+        }
+    }
+
     fn visit_stmt_post(&mut self, stmt: &'chk ast::Stmt) {
         let ty: Rc<Ty> = match &stmt.kind {
             StmtKind::Semi(expr) => {
                 let expr_ty = self.ctx.get_type(expr.id);
                 if expr_ty.is_never() {
                     Rc::new(Ty::never())
+                } else if matches!(expr.kind, ExprKind::Err) {
+                    // the statement failed to parse, and may have been the value of the block
+                    Rc::new(Ty::error())
                 } else {
                     Rc::new(Ty::unit())
                 }
             }
-            StmtKind::Let(LetStmt { init, ty, ident: _, mutable: _ }) => {
+            StmtKind::Let(LetStmt { init, ty, ident, mutable: _ }) => {
                 if let Some(init) = init {
                     let init_ty = self.ctx.get_type(init.id);
-                    // let annotated_ty = self.ast_ty_to_ty(ty.as_ref().unwrap());
                     let annotated_ty = match ty {
-                        Some(t) => Rc::new(self.ast_ty_to_ty(t)), 
-                        None => self.visit_expr_post(init),  
+                        // converted by `visit_stmt`
+                        Some(_) => {
+                            let binding = self.ctx.get_binding(ident).unwrap();
+                            self.ctx.lookup_name_type(&binding).unwrap()
+                        }
+                        None => {
+                            // the type of the variable is the one of its initializer
+                            let binding = self.ctx.get_binding(ident).unwrap();
+                            self.ctx.set_name_type(binding, Rc::clone(&init_ty));
+                            Rc::clone(&init_ty)
+                        }
                     };
 
                     if init_ty.is_never() {
                         Rc::new(Ty::never())
                     } else {
-                        if *annotated_ty != *init_ty {
-                            self.error(format!(
-                                "Expected `{:?}` type, but found `{:?}`",
-                                annotated_ty, init_ty
-                            ));
+                        // errors in the initializer are already reported
+                        if *annotated_ty != *init_ty && !init_ty.is_error() && !annotated_ty.is_error() {
+                            let mut diag = Diagnostic::error("mismatched types").code("E0308").span_label(
+                                &init.span,
+                                format!("expected `{}`, found `{}`", annotated_ty, init_ty),
+                            );
+                            if let Some(ty) = ty {
+                                diag = diag.secondary_label(&ty.span, "expected due to this");
+                            }
+                            self.error(diag);
                             Rc::new(Ty::error())
                         } else {
                             Rc::new(Ty::unit())
@@ -195,32 +996,79 @@ impl<'chk> ast::visitor::Visitor<'chk> for TypeChecker<'_, 'chk> {
     // TODO: handling local variables properly
     // TODO: shadowing
     fn visit_stmt(&mut self, stmt: &'chk Stmt) {
-        match &stmt.kind {
-            StmtKind::Let(let_stmt) => {
-                // set local variable type
-                let binding = self.ctx.get_binding(&let_stmt.ident).unwrap();
-                // set type of local variable
-                // TODO: unwrap
-                let e = let_stmt.init.as_ref(); 
-                let e = e.unwrap(); 
+        if let StmtKind::Let(let_stmt) = &stmt.kind {
+            // the parser rejects `let` without both type and initializer, and the type of
+            // `let` without type is set after its initializer is checked
+            let Some(ty) = &let_stmt.ty else {
+                return;
+            };
+            // set local variable type
+            let binding = self.ctx.get_binding(&let_stmt.ident).unwrap();
+            // set type of local variable
+            // let annotated_ty = self.ast_ty_to_ty(let_stmt.ty.as_ref().unwrap());
+            let annotated_ty = Rc::new(self.ast_ty_to_ty(ty));
+            if let Some(init) = &let_stmt.init {
+                self.expected_tys.insert(init.id, Rc::clone(&annotated_ty));
+            }
 
-                // let annotated_ty = self.ast_ty_to_ty(let_stmt.ty.as_ref().unwrap());
-                let annotated_ty = match &let_stmt.ty {
-                    Some(ty) => Rc::new(self.ast_ty_to_ty(ty)), 
-                    None =>  self.visit_expr_post(&e), 
-                }; 
 
+            self.ctx
+                .set_name_type(Rc::clone(&binding), annotated_ty.clone());
+            // set type of statement
+            //
+            // let stmt_ty = self.ast_ty_to_ty(let_stmt.ty.as_ref().unwrap());
+            let stmt_ty = annotated_ty.clone(); 
+            // TODO: unwrap
+            self.ctx.insert_type(stmt.id, stmt_ty);
+        }
+    }
 
-                self.ctx
-                    .set_name_type(Rc::clone(&binding), annotated_ty.clone());
-                // set type of statement
-                //
-                // let stmt_ty = self.ast_ty_to_ty(let_stmt.ty.as_ref().unwrap());
-                let stmt_ty = annotated_ty.clone(); 
-                // TODO: unwrap
-                self.ctx.insert_type(stmt.id, stmt_ty);
+    fn visit_expr(&mut self, expr: &'chk ast::Expr) {
+        match &expr.kind {
+            ExprKind::Call(func, args) => self.set_expected_arg_tys(func, args),
+            ExprKind::Closure(closure) => self.enter_closure(expr, closure),
+            ExprKind::Return(value) => {
+                let ret_ty = self.peek_return_type();
+                if !ret_ty.is_error() {
+                    self.expected_tys.insert(value.id, ret_ty);
+                }
+            }
+            ExprKind::Assign(lhs, rhs) => self.set_expected_rhs_ty(lhs, rhs),
+            ExprKind::Struct(path, fields) => self.set_expected_field_tys(path, fields),
+            _ => (),
+        }
+        // the value of a block or an `if` is expected to have the type expected for it
+        if let Some(expected) = self.expected_tys.get(&expr.id).map(Rc::clone) {
+            match &expr.kind {
+                ExprKind::Block(block) => {
+                    if let Some(tail) = tail_expr(block) {
+                        self.expected_tys.insert(tail.id, expected);
+                    }
+                }
+                ExprKind::If(_, then, els) => {
+                    self.expected_tys.insert(then.id, Rc::clone(&expected));
+                    if let Some(els) = els {
+                        self.expected_tys.insert(els.id, expected);
+                    }
+                }
+                _ => (),
+            }
+        }
+        // elements of an array are expected to have its element type
+        let Some(TyKind::Array(elem_ty, _)) = self.expected_tys.get(&expr.id).map(|ty| &ty.kind) else {
+            return;
+        };
+        let elem_ty = Rc::clone(elem_ty);
+        match &expr.kind {
+            ExprKind::Array(elems) => {
+                for elem in elems {
+                    self.expected_tys.insert(elem.id, Rc::clone(&elem_ty));
+                }
+            }
+            ExprKind::Repeat(elem, _) => {
+                self.expected_tys.insert(elem.id, elem_ty);
             }
-            _ => {}
+            _ => (),
         }
     }
 
@@ -234,33 +1082,42 @@ impl<'chk> ast::visitor::Visitor<'chk> for TypeChecker<'_, 'chk> {
             ExprKind::Assign(l, r) => {
                 let lhs_ty = &self.ctx.get_type(l.id);
                 let rhs_ty = &self.ctx.get_type(r.id);
-                if rhs_ty.is_never() || **lhs_ty == **rhs_ty {
+                if !self.check_assign_place(l) {
+                    Rc::new(Ty::error())
+                } else if rhs_ty.is_never() || **lhs_ty == **rhs_ty {
                     Rc::new(Ty::unit())
+                } else if lhs_ty.is_error() || rhs_ty.is_error() {
+                    Rc::new(Ty::error())
                 } else {
-                    self.error(format!("Cannot assign {:?} to {:?}", rhs_ty, lhs_ty));
+                    self.error(
+                        Diagnostic::error("mismatched types")
+                            .code("E0308")
+                            .span_label(&r.span, format!("expected `{}`, found `{}`", lhs_ty, rhs_ty))
+                            .secondary_label(&l.span, "expected due to the type of this place"),
+                    );
                     Rc::new(Ty::error())
                 }
             }
             // TODO: deal with never type
             ExprKind::Binary(op, l, r) => {
-                // let lhs_ty = &self.ctx.get_type(l.id);
-                // let rhs_ty = &self.ctx.get_type(r.id);
-                let lhs_ty = self.visit_expr_post(l); 
-                let rhs_ty = self.visit_expr_post(r); 
+                let lhs_ty = self.ctx.get_type(l.id);
+                let rhs_ty = self.ctx.get_type(r.id);
                 match op {
-                    BinOp::Add | BinOp::Sub | BinOp::Mul => {
+                    // errors in the operands are already reported
+                    _ if lhs_ty.is_error() || rhs_ty.is_error() => Rc::new(Ty::error()),
+                    BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem => {
                         if lhs_ty.kind == TyKind::I32 && rhs_ty.kind == TyKind::I32 {
                             Rc::new(Ty::new(TyKind::I32))
                         } else {
-                            self.error("Both lhs and rhs must be type of i32".to_string());
+                            self.operand_error("both operands must be of type `i32`", l, r, &lhs_ty, &rhs_ty);
                             Rc::new(Ty::error())
                         }
                     }
                     BinOp::Gt | BinOp::Lt => {
                         if lhs_ty.kind == TyKind::I32 && rhs_ty.kind == TyKind::I32 {
-                            Rc::new(Ty::new(TyKind::I32))
+                            Rc::new(Ty::new(TyKind::Bool))
                         } else {
-                            self.error("Both lhs and rhs must be type of i32".to_string());
+                            self.operand_error("both operands must be of type `i32`", l, r, &lhs_ty, &rhs_ty);
                             Rc::new(Ty::error())
                         }
                     }
@@ -271,7 +1128,13 @@ impl<'chk> ast::visitor::Visitor<'chk> for TypeChecker<'_, 'chk> {
                         {
                             Rc::new(Ty::new(TyKind::Bool))
                         } else {
-                            self.error("Both lhs and rhs must have the same type".to_string());
+                            self.operand_error(
+                                "both operands must be `i32`s or `bool`s",
+                                l,
+                                r,
+                                &lhs_ty,
+                                &rhs_ty,
+                            );
                             Rc::new(Ty::error())
                         }
                     }
@@ -282,132 +1145,168 @@ impl<'chk> ast::visitor::Visitor<'chk> for TypeChecker<'_, 'chk> {
                 let inner_ty = &self.ctx.get_type(inner.id);
                 if inner_ty.kind == TyKind::I32 {
                     Rc::new(Ty::new(TyKind::I32))
+                } else if inner_ty.is_error() {
+                    Rc::new(Ty::error())
                 } else {
-                    self.error("inner expr of unary must be type of i32".to_string());
+                    self.error(
+                        Diagnostic::error("the operand of `-` must be of type `i32`")
+                            .code("E0600")
+                            .span_label(&inner.span, format!("found `{}`", inner_ty)),
+                    );
                     Rc::new(Ty::error())
                 }
             }
             ExprKind::Path(path) => {
                 // find symbols in local variables, parameters, and in functions
                 if let Some(binding) = self.ctx.resolve_path(path) {
-                    if let Some(ty) = self.ctx.lookup_name_type(&binding) {
+                    if self.ctx.lookup_intrinsic(&binding.cpath).is_some()
+                        && !self.callees.contains(&expr.id)
+                    {
+                        self.error(
+                            Diagnostic::error(format!(
+                                "intrinsic `{}` can only be called, not used as a value",
+                                path.span.to_snippet()
+                            ))
+                            .code("M0204")
+                            .span_label(&path.span, "used as a value"),
+                        );
+                        Rc::new(Ty::error())
+                    } else if let Some(ty) = self.ctx.lookup_name_type(&binding) {
                         ty
                     } else {
-                        self.error(format!("Cannot use `{:?}` before declaration", path));
+                        self.error(
+                            Diagnostic::error(format!(
+                                "cannot use `{}` before its declaration",
+                                path.span.to_snippet()
+                            ))
+                            .code("E0425")
+                            .span_label(&path.span, "used here"),
+                        );
                         Rc::new(Ty::error())
                     }
+                } else if path.segments.len() == 2 && *path.segments[0].symbol == "i32" {
+                    let item = &path.segments[1];
+                    self.error(
+                        Diagnostic::error(format!(
+                            "no function or associated item named `{}` found for type `i32`",
+                            item.symbol
+                        ))
+                        .code("E0599")
+                        .span_label(&item.span, "function or associated item not found in `i32`"),
+                    );
+                    Rc::new(Ty::error())
                 } else {
-                    self.error(format!("Could not resolve ident `{:?}`", path));
+                    let diag = Diagnostic::error(format!(
+                        "cannot find value `{}` in this scope",
+                        path.span.to_snippet()
+                    ))
+                    .code("E0425")
+                    .span_label(&path.span, "not found in this scope");
+                    let sugg = self.ctx.suggest_value_path(path);
+                    self.error(with_path_suggestion(diag, path, sugg));
                     Rc::new(Ty::error())
                 }
             }
-            ExprKind::Ref(path) => {
-                // find symbols in local variables, parameters, and in functions
-                /*if let Some(binding) = self.ctx.resolve_path(path) {
-                    if let Some(ty) = self.ctx.lookup_name_type(&binding) {
-                        Rc::new(Ty::new(ty::TyKind::Ref(ty)))
-                    } else {
-                        self.error(format!("Cannot reference `{:?}` before declaration", path));
-                        Rc::new(Ty::error())
-                    }
-                } else {
-                    self.error(format!("Could not resolve ident `{:?}`", path));
+            ExprKind::Ref(inner) => {
+                let inner_ty = self.ctx.get_type(inner.id);
+                // errors in the operand are already reported
+                if inner_ty.is_error() {
                     Rc::new(Ty::error())
-                } */
-
-                let type_info = self.ctx.get_type(path.id);
-                 Rc::new(Ty::new(ty::TyKind::Ref(type_info)))
+                } else {
+                    Rc::new(Ty::new(ty::TyKind::Ref(inner_ty)))
+                }
             }
+            ExprKind::FormatArgs(fmt) => self.check_format_args(fmt),
+            ExprKind::MacCall(_) => panic!("ICE: macro calls are expanded before typeck"),
+            ExprKind::MethodCall(receiver, method, args) => self.check_method_call(expr, receiver, method, args),
 
             ExprKind::Deref(path) => {
                 if let Some(binding) = self.ctx.resolve_path(path) {
                     if let Some(ty) = self.ctx.lookup_name_type(&binding) {
-                        let type_info = match &ty.clone().kind {
+                        match &ty.clone().kind {
                             ty::TyKind::Ref(inner) => {
                                 inner.clone() 
                             }, 
                             ty::TyKind::ConstPtr(inner) => {
                                 inner.clone()
                             }, 
+                            ty::TyKind::Error => Rc::new(Ty::error()),
                             _ => {
-                                self.error(format!("Cannot dereference non reference type")); 
+                                self.error(
+                                    Diagnostic::error(format!("type `{}` cannot be dereferenced", ty))
+                                        .code("E0614")
+                                        .span_label(&path.span, "not a reference or a pointer"),
+                                );
                                 Rc::new(Ty::error())
                             }
-                        }; 
-
-                        type_info
+                        }
                     } else {
-                        self.error(format!("Cannot dereference `{:?}` before declaration", path));
+                        self.error(
+                            Diagnostic::error(format!(
+                                "cannot use `{}` before its declaration",
+                                path.span.to_snippet()
+                            ))
+                            .code("E0425")
+                            .span_label(&path.span, "dereferenced here"),
+                        );
                         Rc::new(Ty::error())
                     }
                 } else {
-                    self.error(format!("Could not dereference ident before declaration `{:?}`", path));
+                    let diag = Diagnostic::error(format!(
+                        "cannot find value `{}` in this scope",
+                        path.span.to_snippet()
+                    ))
+                    .code("E0425")
+                    .span_label(&path.span, "not found in this scope");
+                    let sugg = self.ctx.suggest_value_path(path);
+                    self.error(with_path_suggestion(diag, path, sugg));
                     Rc::new(Ty::error())
                 }
             }
-            ExprKind::Return(expr) => {
-                let actual_ret_ty = self.ctx.get_type(expr.id);
+            ExprKind::Return(value) => {
+                let actual_ret_ty = self.ctx.get_type(value.id);
                 let expected_ret_ty = self.peek_return_type();
-                if *actual_ret_ty == *expected_ret_ty {
+                if expected_ret_ty.kind == TyKind::Error {
+                    // the return type of a closure is inferred from its body only
+                    self.error(
+                        Diagnostic::error(
+                            "cannot infer the return type of a closure containing `return`",
+                        )
+                        .code("E0282")
+                        .span_label(&expr.span, "returns from the closure")
+                        .help("annotate the return type like `|..| -> T { .. }`"),
+                    );
+                    Rc::new(Ty::error())
+                } else if *actual_ret_ty == *expected_ret_ty || actual_ret_ty.is_error() {
                     Rc::new(Ty::never())
                 } else {
-                    self.error(format!(
-                        "Expected {:?} type, but {:?} returned",
-                        expected_ret_ty, actual_ret_ty
+                    self.error(Diagnostic::error("mismatched types").code("E0308").span_label(
+                        &value.span,
+                        format!("expected `{}`, found `{}`", expected_ret_ty, actual_ret_ty),
                     ));
                     Rc::new(Ty::error())
                 }
             }
             // TODO: deal with never type params
-            ExprKind::Call(expr, args) => {
-                let maybe_func_ty = self.ctx.get_type(expr.id);
-                if let TyKind::Fn(param_ty, ret_ty, variadic) = &maybe_func_ty.kind {
-                    
-                    if param_ty.len() == args.len() && !variadic.clone() {
-                        let mut ok = true;
-                         for (arg, param_ty) in args.iter().zip(param_ty.iter()) {
-                            let arg_ty = &self.ctx.get_type(arg.id);
-                            if arg_ty != param_ty {
-                                self.error(format!(
-                                    "Expected {:?} type argument, but found {:?} type",
-                                    param_ty, arg_ty
-                                ));
-                                ok = false;
-                            }
-                        }
-                        if ok {
-                            Rc::clone(ret_ty)
-                        } else {
-                            Rc::new(Ty::error())
-                        }
-                   }  else if variadic.clone() {
-                        let mut ok = true;
-                        for (arg, param_ty) in args.iter().zip(param_ty.iter()) {
-                        let arg_ty = &self.ctx.get_type(arg.id);
-                            if arg_ty != param_ty {
-                            self.error(format!(
-                                    "Expected {:?} type argument, but found {:?} type",
-                                    param_ty, arg_ty
-                                ));
-                                ok = false;
-                            }
-                        }
-                        if ok {
-                            Rc::clone(ret_ty)
-                        } else {
-                            Rc::new(Ty::error())
-                        }
+            ExprKind::Call(callee, args) => {
+                if self.in_const_fn {
+                    self.check_const_fn_call(callee);
+                }
+                let maybe_func_ty = self.ctx.get_type(callee.id);
+                if let Some((param_ty, ret_ty, variadic)) = maybe_func_ty.get_callable_sig() {
+                    if self.check_call_args(expr, param_ty, variadic, args) {
+                        Rc::clone(ret_ty)
                     } else {
-                       
-                        self.error(format!(
-                            "Expected {} arguments, but found {}",
-                            param_ty.len(),
-                            args.len()
-                        ));
                         Rc::new(Ty::error())
                     }
+                } else if maybe_func_ty.is_error() {
+                    Rc::new(Ty::error())
                 } else {
-                    self.error(format!("Expected fn type, but found {:?}", maybe_func_ty));
+                    self.error(
+                        Diagnostic::error(format!("expected function, found `{}`", maybe_func_ty))
+                            .code("E0618")
+                            .span_label(&callee.span, "call expression requires function"),
+                    );
                     Rc::new(Ty::error())
                 }
             }
@@ -415,38 +1314,78 @@ impl<'chk> ast::visitor::Visitor<'chk> for TypeChecker<'_, 'chk> {
             ExprKind::If(cond, then, els) => {
                 let cond_ty = self.ctx.get_type(cond.id);
                 let then_ty = self.ctx.get_type(then.id);
-                if cond_ty.is_never() || cond_ty.kind == TyKind::Bool {
+                if cond_ty.is_never() || cond_ty.kind == TyKind::Bool || cond_ty.is_error() {
                     let els_ty = if let Some(els) = els {
                         self.ctx.get_type(els.id)
                     } else {
                         Rc::new(Ty::unit())
                     };
 
-                    if then_ty.is_never() || then_ty.kind == els_ty.kind {
+                    // a diverging branch takes the type of the other one
+                    if then_ty.is_never() {
+                        els_ty
+                    } else if els_ty.is_never() || then_ty.kind == els_ty.kind {
                         then_ty
+                    } else if then_ty.is_error() || els_ty.is_error() {
+                        Rc::new(Ty::error())
                     } else {
-                        self.error(format!(
-                            "Type mismatch then block has `{:?}`, but else block has `{:?}`",
-                            then_ty, els_ty
-                        ));
+                        let mut diag = Diagnostic::error("`if` and `else` have incompatible types")
+                            .code("E0308")
+                            .secondary_label(&then.span, format!("this is of type `{}`", then_ty));
+                        diag = match els {
+                            Some(els) => diag.span_label(
+                                &els.span,
+                                format!("expected `{}`, found `{}`", then_ty, els_ty),
+                            ),
+                            None => diag
+                                .span_label(&expr.span, "`if` without `else` has type `()`"),
+                        };
+                        self.error(diag);
                         Rc::new(Ty::error())
                     }
                 } else {
-                    self.error(format!(
-                        "Expected bool for conditional, but found {:?}",
-                        cond_ty
+                    self.error(Diagnostic::error("mismatched types").code("E0308").span_label(
+                        &cond.span,
+                        format!("expected `bool`, found `{}`", cond_ty),
                     ));
                     Rc::new(Ty::error())
                 }
             }
-            ExprKind::Index(array, _index) => {
+            ExprKind::Index(array, index) => {
                 let maybe_array_ty = self.ctx.get_type(array.id);
-                // TODO: typecheck index
-                if let TyKind::Array(elem_ty, _) = &maybe_array_ty.kind {
-                    Rc::clone(elem_ty)
-                } else {
-                    self.error(format!("type {:?} cannot be indexed", maybe_array_ty));
-                    Rc::new(Ty::error())
+                let index_ty = self.ctx.get_type(index.id);
+                match &maybe_array_ty.kind {
+                    TyKind::Array(_, _) | TyKind::Error if index_ty.kind == TyKind::Error => {
+                        Rc::new(Ty::error())
+                    }
+                    TyKind::Array(elem_ty, len) => {
+                        if index_ty.kind == TyKind::I32 || index_ty.is_never() {
+                            self.check_const_index(index, *len);
+                            Rc::clone(elem_ty)
+                        } else {
+                            self.error(
+                                Diagnostic::error(format!(
+                                    "the type `{}` cannot be indexed by `{}`",
+                                    maybe_array_ty, index_ty
+                                ))
+                                .code("E0277")
+                                .span_label(&index.span, "arrays are indexed by `i32`"),
+                            );
+                            Rc::new(Ty::error())
+                        }
+                    }
+                    TyKind::Error => Rc::new(Ty::error()),
+                    _ => {
+                        self.error(
+                            Diagnostic::error(format!(
+                                "cannot index into a value of type `{}`",
+                                maybe_array_ty
+                            ))
+                            .code("E0608")
+                            .span_label(&array.span, "not an array"),
+                        );
+                        Rc::new(Ty::error())
+                    }
                 }
             }
             ExprKind::Field(receiver, field) => {
@@ -455,64 +1394,123 @@ impl<'chk> ast::visitor::Visitor<'chk> for TypeChecker<'_, 'chk> {
                     if let Some(adt) = self.ctx.lookup_adt_def(cpath) {
                         let r = adt.fields.iter().find(|(f, _)| field.symbol == *f);
                         if let Some((_, ty)) = r {
+                            self.check_field_privacy(cpath, &adt, field);
                             Rc::clone(ty)
                         } else {
-                            self.error(format!(
-                                "Type {:?} does not have field `{}`",
-                                cpath, field.symbol
-                            ));
+                            let mut diag = Diagnostic::error(format!(
+                                "no field `{}` on type `{}`",
+                                field.symbol, maybe_adt
+                            ))
+                            .code("E0609")
+                            .span_label(&field.span, "unknown field");
+                            let names = adt.fields.iter().map(|(name, _)| name.as_str());
+                            if let Some(similar) = find_best_match(names, &field.symbol) {
+                                diag = diag.span_suggestion(
+                                    &field.span,
+                                    "a field with a similar name exists",
+                                    similar,
+                                );
+                            }
+                            self.error(diag);
                             Rc::new(Ty::error())
                         }
                     } else {
-                        self.error(format!("receiver is not struct, but {:?}", maybe_adt));
+                        self.error(
+                            Diagnostic::error(format!("`{}` is not a struct", maybe_adt))
+                                .code("E0609")
+                                .span_label(&receiver.span, "field accessed here"),
+                        );
                         Rc::new(Ty::error())
                     }
                 } else {
-                    self.error("field access can used only for ADT".to_string());
+                    if maybe_adt.kind != TyKind::Error {
+                        self.error(
+                            Diagnostic::error(format!("`{}` is a primitive type and has no fields", maybe_adt))
+                                .code("E0610")
+                                .span_label(&field.span, "unknown field"),
+                        );
+                    }
                     Rc::new(Ty::error())
                 }
             }
-            ExprKind::Struct(path, _fds) => {
+            ExprKind::Struct(path, fds) => {
                 if let Some(binding) = self.ctx.resolve_path(path) {
-                    if let Some(_adt) = self.ctx.lookup_adt_def(&binding.cpath) {
-                        // TODO: typecheck fields
-                        Rc::new(Ty::new(TyKind::Adt(Rc::clone(&binding.cpath))))
+                    if let Some(adt) = self.ctx.lookup_adt_def(&binding.cpath) {
+                        for (field, _) in fds {
+                            self.check_field_privacy(&binding.cpath, &adt, field);
+                        }
+                        if self.check_struct_fields(&binding.cpath, &adt, path, fds) {
+                            Rc::new(Ty::new(TyKind::Adt(Rc::clone(&binding.cpath))))
+                        } else {
+                            Rc::new(Ty::error())
+                        }
                     } else {
-                        self.error(format!("{:?} does not have struct type", binding.cpath));
+                        self.error(
+                            Diagnostic::error(format!("expected struct, found `{:?}`", binding.cpath))
+                                .code("E0574")
+                                .span_label(&path.span, "not a struct"),
+                        );
                         Rc::new(Ty::error())
                     }
                 } else {
-                    self.error(format!("Could not resolve {}", path.span.to_snippet()));
+                    let diag = Diagnostic::error(format!(
+                        "cannot find struct `{}` in this scope",
+                        path.span.to_snippet()
+                    ))
+                    .code("E0422")
+                    .span_label(&path.span, "not found in this scope");
+                    let sugg = self.ctx.suggest_struct_path(path);
+                    self.error(with_path_suggestion(diag, path, sugg));
                     Rc::new(Ty::error())
                 }
             }
             ExprKind::Array(elems) => {
                 if elems.is_empty() {
-                    // TODO: type inference: typecheck arary with zero element
-                    self.error("Array with zero element is not supported".to_string());
-                    Rc::new(Ty::error())
+                    match self.expected_tys.get(&expr.id).map(|ty| &ty.kind) {
+                        Some(TyKind::Array(elem_ty, _)) => {
+                            Rc::new(Ty::new(TyKind::Array(Rc::clone(elem_ty), 0)))
+                        }
+                        _ => {
+                            self.error(
+                                Diagnostic::error("type annotations needed for empty array")
+                                    .code("E0282")
+                                    .span_label(&expr.span, "cannot infer the element type"),
+                            );
+                            Rc::new(Ty::error())
+                        }
+                    }
                 } else {
                     let first_elem = elems.first().unwrap();
                     let first_elem_ty = self.ctx.get_type(first_elem.id);
 
-                    if first_elem_ty.is_never() {
-                        self.error(format!(
-                            "First element `{}` has never type. Could not infer type of array `{}`.",
-                            first_elem.span.to_snippet(),
-                            expr.span.to_snippet(),
-                        ));
+                    if first_elem_ty.is_error() {
+                        Rc::new(Ty::error())
+                    } else if first_elem_ty.is_never() {
+                        self.error(
+                            Diagnostic::error("cannot infer the type of the array")
+                                .code("E0282")
+                                .span_label(&first_elem.span, "the first element has type `!`"),
+                        );
                         Rc::new(Ty::error())
                     } else {
                         let mut saw_error = false;
                         for elem in elems {
                             let elem_ty = self.ctx.get_type(elem.id);
-                            if !elem_ty.is_never() && elem_ty != first_elem_ty {
-                                self.error(format!(
-                                    "Expected type `{:?}`, but `{}` has type `{:?}`",
-                                    first_elem_ty,
-                                    elem.span.to_snippet(),
-                                    elem_ty,
-                                ));
+                            if elem_ty.is_error() {
+                                saw_error = true;
+                            } else if !elem_ty.is_never() && elem_ty != first_elem_ty {
+                                self.error(
+                                    Diagnostic::error("mismatched types")
+                                        .code("E0308")
+                                        .span_label(
+                                            &elem.span,
+                                            format!("expected `{}`, found `{}`", first_elem_ty, elem_ty),
+                                        )
+                                        .secondary_label(
+                                            &first_elem.span,
+                                            "expected due to the first element",
+                                        ),
+                                );
                                 saw_error = true;
                             }
                         }
@@ -524,6 +1522,36 @@ impl<'chk> ast::visitor::Visitor<'chk> for TypeChecker<'_, 'chk> {
                     }
                 }
             }
+            ExprKind::Closure(closure) => self.check_closure(expr, closure),
+            // syntax errors are already reported
+            ExprKind::Err => Rc::new(Ty::error()),
+            ExprKind::Repeat(elem, count) => {
+                let elem_ty = self.ctx.get_type(elem.id);
+                let count_ty = self.ctx.get_type(count.id);
+                if count_ty.kind != TyKind::I32 {
+                    if count_ty.kind != TyKind::Error {
+                        self.error(Diagnostic::error("mismatched types").code("E0308").span_label(
+                            &count.span,
+                            format!("expected `i32`, found `{}`", count_ty),
+                        ));
+                    }
+                    Rc::new(Ty::error())
+                } else if elem_ty.is_never() {
+                    self.error(
+                        Diagnostic::error("cannot infer the type of the array")
+                            .code("E0282")
+                            .span_label(&elem.span, "the element has type `!`"),
+                    );
+                    Rc::new(Ty::error())
+                } else {
+                    match self.eval_array_len(count) {
+                        Some(n) if elem_ty.kind != TyKind::Error => {
+                            Rc::new(Ty::new(TyKind::Array(elem_ty, n)))
+                        }
+                        _ => Rc::new(Ty::error()),
+                    }
+                }
+            }
             ExprKind::Cast(expr, ty) => {
                 // ref: https://doc.rust-lang.org/reference/expressions/operator-expr.html#type-cast-expressions
                 let expr_ty = self.ctx.get_type(expr.id);
@@ -532,17 +1560,22 @@ impl<'chk> ast::visitor::Visitor<'chk> for TypeChecker<'_, 'chk> {
                 match (&expr_ty.kind, &cast_ty.kind) {
                     (TyKind::Ref(_), TyKind::ConstPtr(_))
                     | (TyKind::ConstPtr(_), TyKind::ConstPtr(_)) => Rc::new(cast_ty),
+                    (TyKind::Error, _) | (_, TyKind::Error) => Rc::new(Ty::error()),
                     _ => {
-                        self.error(format!(
-                            "Cannot cast {:?} to {}",
-                            expr_ty,
-                            ty.span.to_snippet()
-                        ));
+                        self.error(
+                            Diagnostic::error(format!(
+                                "non-primitive cast: `{}` as `{}`",
+                                expr_ty, cast_ty
+                            ))
+                            .code("E0605")
+                            .span_label(&expr.span, "invalid cast"),
+                        );
                         Rc::new(Ty::error())
                     }
                 }
             }
         };
+        let ty = self.coerce_closure_var(expr, ty);
         self.ctx.insert_type(expr.id, ty.clone());
         ty
     }
@@ -552,3 +1585,28 @@ impl<'chk> ast::visitor::Visitor<'chk> for TypeChecker<'_, 'chk> {
         self.ctx.insert_type(block.id, block_ty);
     }
 }
+
+/// Value of `block`, which is its last statement if it has no semicolon
+fn tail_expr(block: &ast::Block) -> Option<&ast::Expr> {
+    match &block.stmts.last()?.kind {
+        StmtKind::Expr(expr) => Some(expr),
+        _ => None,
+    }
+}
+
+/// `a[i].f` => `a`
+fn place_root(expr: &ast::Expr) -> &ast::Expr {
+    match &expr.kind {
+        ExprKind::Index(base, _) | ExprKind::Field(base, _) => place_root(base),
+        _ => expr,
+    }
+}
+
+/// Types printable by `{}`
+fn is_display(ty: &Ty) -> bool {
+    match &ty.kind {
+        TyKind::I32 | TyKind::Bool => true,
+        TyKind::Ref(inner) => inner.kind == TyKind::Str,
+        _ => false,
+    }
+}
@@ -0,0 +1,188 @@
+//! Unsafety checking
+//!
+//! Unsafe operations are calls to `unsafe fn`s and extern functions,
+//! dereferences of raw pointers and uses of `static mut` items.
+//! They are only allowed in `unsafe` blocks and `unsafe fn` bodies.
+
+use crate::ast::{self, Crate, ExprKind, Item, ItemKind};
+use crate::diagnostics::Diagnostic;
+use crate::metadata::ExportedItemKind;
+use crate::middle::ty::TyKind;
+use crate::middle::Ctxt;
+use crate::resolve::{Binding, BindingKind};
+use crate::span::Span;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// Returns warnings on success, and warnings followed by errors on failure
+pub fn unsafeck<'ctx, 'chk>(
+    ctx: &'chk mut Ctxt<'ctx>,
+    krate: &'chk Crate,
+) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    let mut checker = UnsafetyChecker::new(ctx);
+    checker.collect_extern_crate_unsafe_fns();
+    checker.collect_unsafe_fns(&krate.items);
+    ast::visitor::go(&mut checker, krate);
+    if checker.errors.is_empty() {
+        Ok(checker.warnings)
+    } else {
+        let mut diags = checker.warnings;
+        diags.append(&mut checker.errors);
+        Err(diags)
+    }
+}
+
+struct UnsafeBlock {
+    span: Span,
+    /// whether this block directly contains an unsafe operation
+    used: bool,
+    /// whether this block is inside another `unsafe` block
+    nested: bool,
+}
+
+struct UnsafetyChecker<'ctx, 'chk> {
+    ctx: &'chk mut Ctxt<'ctx>,
+    /// `unsafe fn`s and extern functions
+    unsafe_fns: HashSet<Rc<Binding>>,
+    /// whether the body being checked is an `unsafe fn`
+    in_unsafe_fn: bool,
+    /// enclosing `unsafe` blocks, outermost first
+    unsafe_blocks: Vec<UnsafeBlock>,
+    errors: Vec<Diagnostic>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'ctx, 'chk> UnsafetyChecker<'ctx, 'chk> {
+    fn new(ctx: &'chk mut Ctxt<'ctx>) -> Self {
+        UnsafetyChecker {
+            ctx,
+            unsafe_fns: HashSet::new(),
+            in_unsafe_fn: false,
+            unsafe_blocks: vec![],
+            errors: vec![],
+            warnings: vec![],
+        }
+    }
+
+    fn collect_unsafe_fns(&mut self, items: &[Item]) {
+        for item in items {
+            match &item.kind {
+                ItemKind::Func(func) if func.is_unsafe => {
+                    let binding = self.ctx.get_binding(&func.name).unwrap();
+                    self.unsafe_fns.insert(binding);
+                }
+                ItemKind::ExternBlock(block) => {
+                    for func in &block.funcs {
+                        let binding = self.ctx.get_binding(&func.name).unwrap();
+                        self.unsafe_fns.insert(binding);
+                    }
+                }
+                ItemKind::Mod(module) => self.collect_unsafe_fns(&module.items),
+                _ => (),
+            }
+        }
+    }
+
+    fn collect_extern_crate_unsafe_fns(&mut self) {
+        for krate in self.ctx.extern_crates() {
+            for item in &krate.items {
+                if let ExportedItemKind::Fn { is_unsafe: true, .. } = item.kind {
+                    self.unsafe_fns.insert(item.binding().unwrap());
+                }
+            }
+        }
+    }
+
+    /// Report `what` unless it is in an unsafe context
+    fn require_unsafe(&mut self, what: &str, span: &Span) {
+        // Only the outermost block counts as used; inner ones are redundant
+        if let Some(block) = self.unsafe_blocks.first_mut() {
+            block.used = true;
+        } else if !self.in_unsafe_fn {
+            self.errors.push(
+                Diagnostic::error(format!(
+                    "{} is unsafe and requires unsafe function or block",
+                    what
+                ))
+                .code("E0133")
+                .span_label(span, what),
+            );
+        }
+    }
+}
+
+impl<'chk> ast::visitor::Visitor<'chk> for UnsafetyChecker<'_, 'chk> {
+    fn visit_func(&mut self, func: &'chk ast::Func) {
+        self.in_unsafe_fn = func.is_unsafe;
+    }
+
+    fn visit_func_post(&mut self, _func: &'chk ast::Func) {
+        self.in_unsafe_fn = false;
+    }
+
+    fn visit_block(&mut self, block: &'chk ast::Block) {
+        if block.is_unsafe {
+            let nested = !self.unsafe_blocks.is_empty();
+            self.unsafe_blocks.push(UnsafeBlock {
+                span: block.span.clone(),
+                used: false,
+                nested,
+            });
+        }
+    }
+
+    fn visit_block_post(&mut self, block: &'chk ast::Block) {
+        if !block.is_unsafe {
+            return;
+        }
+        let block = self.unsafe_blocks.pop().unwrap();
+        if block.nested {
+            let outer = self.unsafe_blocks.first().unwrap();
+            self.warnings.push(
+                Diagnostic::warning("unnecessary `unsafe` block")
+                    .span_label(&block.span, "unnecessary `unsafe` block")
+                    .secondary_label(&outer.span, "because it's nested under this `unsafe` block"),
+            );
+        } else if !block.used {
+            self.warnings.push(
+                Diagnostic::warning("unnecessary `unsafe` block")
+                    .span_label(&block.span, "it contains no unsafe operations"),
+            );
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'chk ast::Expr) {
+        match &expr.kind {
+            ExprKind::Call(callee, _) => {
+                let ExprKind::Path(path) = &callee.kind else {
+                    return;
+                };
+                if let Some(binding) = self.ctx.resolve_path(path) {
+                    if self.unsafe_fns.contains(&binding) {
+                        let what = format!("call to unsafe function `{}`", path.span.to_snippet());
+                        self.require_unsafe(&what, &expr.span);
+                    }
+                }
+            }
+            ExprKind::Deref(path) => {
+                let Some(binding) = self.ctx.resolve_path(path) else {
+                    return;
+                };
+                if let Some(ty) = self.ctx.lookup_name_type(&binding) {
+                    if matches!(ty.kind, TyKind::ConstPtr(_)) {
+                        self.require_unsafe("dereference of raw pointer", &expr.span);
+                    }
+                }
+            }
+            ExprKind::Path(path) => {
+                if let Some(binding) = self.ctx.resolve_path(path) {
+                    if binding.kind == BindingKind::Static(true) {
+                        let what = format!("use of mutable static `{}`", path.span.to_snippet());
+                        self.require_unsafe(&what, &expr.span);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
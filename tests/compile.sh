@@ -8,11 +8,20 @@ GREEN='\033[0;32m'
 GRAY='\033[0;30m'
 NC='\033[0m' # No Color
 
+# compile `input`, the path to a root file or source code, with the options following it
+rustc() {
+    if [ -f "$1" ]; then
+        $RUSTC "$1" "${@:2}"
+    else
+        $RUSTC -e "$1" "${@:2}"
+    fi
+}
+
 compile() {
     input="$1"
 
     rm $TMP
-    $RUSTC "$input" >$TMP
+    rustc "$input" --emit=llvm-ir >$TMP
     res="$?"
 
     if [ "$res" = "0" ]; then
@@ -23,6 +32,20 @@ compile() {
     fi
 }
 
+# check that `--emit=kind` prints a line containing `expected` for `input` and the options following it
+assert_emit() {
+    kind="$1"
+    expected="$2"
+    input="$3"
+
+    if rustc "$input" --emit=$kind "${@:4}" | grep -qF "$expected"; then
+        echo -e "[${GREEN}OK${NC}] --emit=$kind $input"
+    else
+        echo -e "[${RED}ERROR${NC}] --emit=$kind $input ${GRAY}=> no line with \"$expected\"${NC}"
+        exit 1
+    fi
+}
+
 QT="'"
 NL=$'\n'
 
@@ -75,11 +98,14 @@ compile 'fn take(b: bool, n: i32) -> () { } fn main() -> () { take(false, 0); }'
 #compile 'fn take(s: &str) -> () { } fn main() -> () { let u: () = take("Hello"); }'
 compile 'fn f(n: i32) -> bool { true } fn main() -> () { let b: bool = f(1 + 2 * 3 - 4); }'
 # array expr
-#compile 'fn main() -> () { [1]; [1, 1]; [1, 2, 3]; [1, 1,]; }'
+compile 'fn main() -> () { [1]; [1, 1]; [1, 2, 3]; [1, 1,]; }'
+# TODO: arrays of `()`
 #compile 'fn main() -> () { [(), (), ()]; [(), (())];  }'
 #compile 'fn main() -> () { [[()], [()], [()],];  }'
-#compile 'fn main() -> () { [[[0]]]; }'
-#compile 'fn main() -> () { [1, return (), ]; }'
+compile 'fn main() -> () { [[[0]]]; }'
+compile 'fn main() -> () { [1, return (), ]; }'
+compile 'fn main() -> () { [0; 3]; [[1; 2]; 3]; }'
+compile 'fn main() -> () { let a: [i32; 0] = []; let b: [[bool; 0]; 2] = [[], []]; }'
 # TODO: compile 'fn main() -> () { [()][0] }'
 # if
 compile 'fn main() -> () { if (true) { } else { } }'
@@ -99,12 +125,12 @@ compile 'fn main() -> () { } mod a { mod a { mod a { fn a() -> () { }  } } }'
 compile 'fn main() -> () { let s: S; } struct S { }'
 # absolute path (`crate...`)
 compile 'fn f() -> () { } fn main() -> () { crate::f() }'
-compile 'mod a { fn f() -> () { } } fn main() -> () { crate::a::f() }'
-compile 'mod a { fn f() -> () { } } mod b { fn f() -> () { } } fn main() -> () { crate::b::f() }'
+compile 'mod a { pub fn f() -> () { } } fn main() -> () { crate::a::f() }'
+compile 'mod a { pub fn f() -> () { } } mod b { pub fn f() -> () { } } fn main() -> () { crate::b::f() }'
 # relative path
 compile 'fn f() -> () { } fn main() -> () { crate::f() }'
-compile 'mod a { fn f() -> () { } } fn main() -> () { a::f() }'
-compile 'mod a { fn f() -> () { } } mod b { fn f() -> () { } } fn main() -> () { b::f() }'
+compile 'mod a { pub fn f() -> () { } } fn main() -> () { a::f() }'
+compile 'mod a { pub fn f() -> () { } } mod b { pub fn f() -> () { } } fn main() -> () { b::f() }'
 # TODO: typecheck all items first and then typecheck bodies
 #compile 'fn main() -> () { f(); } fn f() -> () { }'
 # scope
@@ -112,3 +138,83 @@ compile 'fn main() -> () { let a: i32 = 0; let b: i32 = { { a } }; }'
 # shadowing
 compile 'fn main() -> () { let a: i32 = 0; let a: i32 = 1; }'
 compile 'struct S { a: i32 } fn main() -> () { let a: i32 = 4; let a: S; }'
+# const and static items
+compile 'const N: i32 = 4; fn main() -> i32 { N }'
+compile 'const N: i32 = M * 2; const M: i32 = 1 + 2; fn main() -> () { let a: [i32; N]; }'
+compile 'static S: i32 = 0; static mut C: i32 = 1; fn main() -> () { }'
+compile 'mod a { pub const K: bool = true; } fn main() -> () { let b: bool = a::K; }'
+
+# const fn and division
+compile 'const fn double(n: i32) -> i32 { n * 2 } const N: i32 = double(4) / 3 % 2; fn main() -> () { let a: [i32; N]; }'
+compile 'const N: i32 = -2147483648; fn main() -> i32 { N }'
+
+# unsafety checking
+compile 'extern "C" { fn abs(n: i32) -> i32; } fn main() -> i32 { unsafe { abs(-3) } }'
+compile 'unsafe fn f() -> i32 { 1 } fn main() -> i32 { unsafe { f() } }'
+compile 'extern "C" { fn abs(n: i32) -> i32; } unsafe fn f(n: i32) -> i32 { abs(n) } fn main() -> () { }'
+compile 'fn main() -> i32 { let n: i32 = 3; let r: &i32 = &n; let p: *const i32 = r as *const i32; unsafe { *p } }'
+compile 'fn main() -> i32 { let n: i32 = 3; let r: &i32 = &n; *r }'
+
+# visibility
+compile 'mod m { fn g() -> i32 { 1 } pub fn f() -> i32 { g() } } fn main() -> i32 { m::f() }'
+compile 'mod a { pub mod b { pub(crate) fn f() -> () { } } } fn main() -> () { a::b::f() }'
+compile 'mod a { fn f() -> () { } pub mod b { pub fn g() -> () { super::f() } } } fn main() -> () { a::b::g() }'
+compile 'mod a { pub mod b { pub(super) fn f() -> () { } } fn g() -> () { b::f() } } fn main() -> () { }'
+compile 'fn f() -> () { } mod a { pub fn g() -> () { crate::f(); super::f() } } fn main() -> () { self::a::g() }'
+compile 'mod a { pub struct S { pub x: i32, y: i32 } pub fn new() -> S { S { x: 1, y: 2 } } } fn main() -> i32 { let s: a::S = a::new(); s.x }'
+
+# use declarations
+compile 'mod a { pub fn f() -> i32 { 1 } } use a::f; fn main() -> i32 { f() }'
+compile 'mod a { pub fn f() -> i32 { 1 } } use a::f as g; fn main() -> i32 { g() }'
+compile 'mod a { pub fn f() -> i32 { 1 } pub mod b { pub fn h() -> i32 { 2 } } } use a::{f, b::*}; fn main() -> i32 { f() + h() }'
+compile 'mod a { pub mod b { pub fn h() -> i32 { 2 } } } use a::b::{self}; fn main() -> i32 { b::h() }'
+compile 'mod a { pub use self::inner::f; mod inner { pub fn f() -> i32 { 3 } } } fn main() -> i32 { a::f() }'
+compile 'mod a { pub fn x() -> () { } } mod b { pub fn x() -> () { } } use a::*; use b::*; fn main() -> () { }'
+compile 'mod a { pub fn x() -> () { } } mod b { pub fn x() -> () { } } use a::*; use b::x; fn main() -> () { x() }'
+compile 'mod c { pub fn y() -> () { } } mod b { pub use crate::c::y; } mod a { use crate::b::*; fn f() -> () { y() } } fn main() -> () { }'
+
+# output file
+rm -f $TMP
+if [ -n "$(rustc 'fn main() -> i32 { 7 }' --emit=llvm-ir -o $TMP)" ] || ! grep -q "define i32 @main()" $TMP; then
+    echo -e "[${RED}ERROR${NC}] -o ${GRAY}=> LLVM IR is not written to the output file${NC}"
+    exit 1
+fi
+echo -e "[${GREEN}OK${NC}] -o"
+
+# emit
+assert_emit tokens '1:4: Ident("main")' 'fn main() -> i32 { 7 }'
+assert_emit ast 'name: "main"' 'fn main() -> i32 { 7 }'
+assert_emit resolved '1:36: x => crate::main::x (local variable)' 'fn main() -> i32 { let x: i32 = 1; x }'
+assert_emit resolved '1:54: a::f => crate::a::f (item)' 'mod a { pub fn f() -> i32 { 1 } } fn main() -> i32 { a::f() }'
+assert_emit typed-ast '1:31: x + 1: i32' 'fn main() -> i32 { let x = 1; x + 1 }'
+assert_emit llvm-ir 'target triple = "aarch64-apple-darwin"' 'fn main() -> i32 { 7 }' --target aarch64-apple-darwin
+rm -f $TMP
+if ! rustc 'fn main() -> i32 { 7 }' --emit=tokens,llvm-ir=$TMP | grep -q "^1:1: Fn$" || ! grep -q "define i32 @main()" $TMP; then
+    echo -e "[${RED}ERROR${NC}] --emit=tokens,llvm-ir=PATH ${GRAY}=> tokens are not printed or LLVM IR is not written${NC}"
+    exit 1
+fi
+echo -e "[${GREEN}OK${NC}] --emit=tokens,llvm-ir=PATH"
+
+# llc and linker
+rm -f $TMP
+if ! rustc 'fn main() -> i32 { 7 }' --emit=asm -o $TMP || ! grep -q "^main:" $TMP; then
+    echo -e "[${RED}ERROR${NC}] --emit=asm ${GRAY}=> assembly is not written to the output file${NC}"
+    exit 1
+fi
+echo -e "[${GREEN}OK${NC}] --emit=asm"
+rm -f ../tmp.o
+if ! rustc 'fn main() -> i32 { 7 }' --emit=obj=../tmp.o -O2 || [ ! -s ../tmp.o ]; then
+    echo -e "[${RED}ERROR${NC}] --emit=obj ${GRAY}=> object file is not written${NC}"
+    exit 1
+fi
+echo -e "[${GREEN}OK${NC}] --emit=obj"
+rm -f ../tmp.o
+
+# temporary files
+tmpdir=$(mktemp -d)
+if ! TMPDIR=$tmpdir rustc 'fn main() -> i32 { 7 }' -o ../tmp || [ ! -x ../tmp ] || [ -n "$(ls -A $tmpdir)" ]; then
+    echo -e "[${RED}ERROR${NC}] --emit=link ${GRAY}=> executable is not written or temporary files are left${NC}"
+    exit 1
+fi
+echo -e "[${GREEN}OK${NC}] --emit=link"
+rm -rf $tmpdir ../tmp
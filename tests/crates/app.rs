@@ -0,0 +1,8 @@
+extern crate mylib as m;
+use mylib::inner;
+fn main() -> i32 {
+    let p: mylib::Point = mylib::Point { x: 7, y: 1 };
+    let a: i32 = inner::double(p.x) + m::twice(1) + mylib::N;
+    let b: i32 = unsafe { mylib::abs(-2) + mylib::danger() + mylib::COUNT };
+    a + b + mylib::get_y(p)
+}
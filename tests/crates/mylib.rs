@@ -0,0 +1,24 @@
+extern "C" {
+    pub fn abs(n: i32) -> i32;
+}
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+pub const N: i32 = 3 * 4;
+pub static mut COUNT: i32 = 0;
+pub fn make(x: i32) -> Point {
+    Point { x: x, y: 0 }
+}
+pub fn get_y(p: Point) -> i32 {
+    p.y
+}
+pub mod inner {
+    pub fn double(n: i32) -> i32 {
+        unsafe { crate::COUNT = crate::COUNT + 1; };
+        n * 2
+    }
+    fn hidden() -> () {}
+}
+pub use inner::double as twice;
+pub unsafe fn danger() -> i32 { 5 }
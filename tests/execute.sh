@@ -1,26 +1,28 @@
 #!/bin/bash
 cd $(dirname $0)
 RUSTC="../target/debug/mini-rustc"
-TMP="../tmp.ll"
-ASM="../tmp.s"
 EXE="../tmp"
-LLC="llc"
-CC="gcc"
 
 RED='\033[0;31m'
 GREEN='\033[0;32m'
 GRAY='\033[0;30m'
 NC='\033[0m' # No Color
 
+# compile `input`, the path to a root file or source code, with the options following it
+rustc() {
+    if [ -f "$1" ]; then
+        $RUSTC "$1" "${@:2}"
+    else
+        $RUSTC -e "$1" "${@:2}"
+    fi
+}
+
 assert() {
     expected="$1"
     input="$2"
 
-    rm $TMP $EXE
-    $RUSTC "$input" >$TMP
-    $LLC -o $ASM $TMP -opaque-pointers
-    $CC -o $EXE $ASM
-    chmod +x $EXE
+    rm -f $EXE
+    rustc "$input" "${@:3}" -o $EXE
     $EXE
     actual="$?"
 
@@ -32,6 +34,67 @@ assert() {
     fi
 }
 
+# like `assert`, but checks what the program prints to the standard output
+assert_output() {
+    expected="$1"
+    input="$2"
+
+    rm -f $EXE
+    rustc "$input" -o $EXE
+    actual="$($EXE)"
+
+    if [ "$actual" = "$expected" ]; then
+        echo -e "[${GREEN}OK${NC}] $input ${GRAY}=> $actual${NC}"
+    else
+        echo -e "[${RED}ERROR${NC}] $input ${GRAY}=> $expected expected, but got $actual${NC}"
+        exit 1
+    fi
+}
+
+# compile `lib` as library `mylib`, then compile `input` using it and link them
+assert_with_lib() {
+    expected="$1"
+    lib="$2"
+    input="$3"
+
+    rm -f $EXE libmylib.rmeta libmylib.a
+    rustc "$lib" --crate-type lib --crate-name mylib
+    rustc "$input" --extern mylib=libmylib.rmeta -o $EXE
+    $EXE
+    actual="$?"
+    rm -f libmylib.rmeta libmylib.a
+
+    if [ "$actual" = "$expected" ]; then
+        echo -e "[${GREEN}OK${NC}] $lib | $input ${GRAY}=> $actual${NC}"
+    else
+        echo -e "[${RED}ERROR${NC}] $lib | $input ${GRAY}=> $expected expected, but got $actual${NC}"
+        exit 1
+    fi
+}
+
+# like assert_with_lib, but with the library written by `-o` into directory `dir`
+assert_with_lib_in() {
+    expected="$1"
+    dir="$2"
+    lib="$3"
+    input="$4"
+
+    rm -rf $EXE "$dir"
+    mkdir -p "$dir"
+    rustc "$lib" --crate-type lib --crate-name mylib -o "$dir/libmylib.a"
+    rustc "$input" --extern mylib="$dir/libmylib.rmeta" -o $EXE
+    $EXE
+    actual="$?"
+    rm -rf "$dir"
+
+    if [ "$actual" = "$expected" ]; then
+        echo -e "[${GREEN}OK${NC}] $dir | $lib | $input ${GRAY}=> $actual${NC}"
+    else
+        echo -e "[${RED}ERROR${NC}] $dir | $lib | $input ${GRAY}=> $expected expected, but got $actual${NC}"
+        exit 1
+    fi
+}
+
 QT="'"
 
 echo "===== Execute Tests ====="
@@ -56,6 +119,11 @@ assert 9 'fn main() -> i32 { 4 + 5 }'
 assert 3 'fn main() -> i32 { 10 - 7 }'
 assert 6 'fn main() -> i32 { 2 * 3 }'
 assert 9 'fn main() -> i32 { 11 + 8 * 2 - 3 * (1 + 5) }'
+assert 3 'fn main() -> i32 { 10 - 4 - 3 }'
+assert 12 'fn main() -> i32 { 20 - 5 - 2 - 1 }'
+assert 1 'fn main() -> i32 { if 2 < 3 { 1 } else { 0 } }'
+assert 0 'fn main() -> i32 { if 3 > 4 { 1 } else { 0 } }'
+assert 0 'fn main() -> i32 { let b: bool = 3 > 4; 0 }'
 # let
 assert 0 'fn main() -> i32 { let a: i32; let b: i32; 0 }'
 assert 0 'fn main() -> i32 { let a: i32 = 0; let b: i32; a }'
@@ -128,3 +196,148 @@ assert 42 'struct S { a: i32 } fn f() -> S { S { a: 42 } } fn main() -> i32 { f(
 assert 100 'struct S { a: i32 } fn f(s: S) -> S { s } fn main() -> i32 { f(S { a: 100 }).a }'
 assert 5 'struct S { a: i32, b: i32 } fn f() -> S { S { a: 3, b: 5 } } fn main() -> i32 { f().b }'
 assert 7 'struct S { a: i32 } fn f(b: i32, c: i32) -> S { S { a: b + c } } fn main() -> i32 { f(3, 4).a }'
+# const and static items
+assert 8 'const N: i32 = 4; fn main() -> i32 { N + N }'
+assert 7 'const N: i32 = M - 1; const M: i32 = 8; fn main() -> i32 { let mut a: [i32; N]; a[N - 1] = N; a[6] }'
+assert 3 'static S: i32 = 1 + 2; fn main() -> i32 { S }'
+assert 10 'static mut C: i32 = 0; fn inc() -> () { unsafe { C = C + 5; }; } fn main() -> i32 { inc(); inc(); unsafe { C } }'
+
+# const fn and division
+assert 3 'fn main() -> i32 { 7 / 2 }'
+assert 1 'fn main() -> i32 { 7 % 3 }'
+assert 2 'fn main() -> i32 { 8 - 4 - 2 }'
+assert 55 'const fn fib(n: i32) -> i32 { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } } const F: i32 = fib(10); fn main() -> i32 { F }'
+assert 24 'const fn fact(n: i32) -> i32 { let mut acc: i32 = 1; let i: i32 = n; acc = if i > 1 { i * fact(i - 1) } else { acc }; acc } static S: i32 = fact(4); fn main() -> i32 { S }'
+assert 6 'const fn three() -> i32 { return 3; } fn main() -> i32 { let a: [i32; three() * 2]; three() * 2 }'
+
+# unsafety checking
+assert 3 'extern "C" { fn abs(n: i32) -> i32; } fn main() -> i32 { unsafe { abs(-3) } }'
+assert 4 'unsafe fn get() -> i32 { 4 } fn main() -> i32 { unsafe { get() } }'
+
+# visibility
+assert 7 'mod a { fn g() -> i32 { 3 } pub mod b { pub fn f() -> i32 { super::g() + 4 } } } fn main() -> i32 { a::b::f() }'
+
+# use declarations
+assert 5 'mod a { pub fn x() -> i32 { 5 } pub use crate::b::*; } mod b { pub use crate::a::*; } fn main() -> i32 { b::x() }'
+assert 3 'mod a { pub fn f() -> i32 { 1 } pub mod b { pub fn h() -> i32 { 2 } } } use a::{f as one, b::{self, h}}; fn main() -> i32 { one() + b::h() }'
+
+# out-of-line modules
+assert 7 'modules/ok/main.rs'
+
+# extern crates
+assert_with_lib 38 'crates/mylib.rs' 'crates/app.rs'
+assert_with_lib 3 'pub fn three() -> i32 { 3 }' 'fn main() -> i32 { mylib::three() }'
+assert_with_lib 12 'pub mod a { pub const N: i32 = 12; }' 'use mylib::a::N; fn main() -> i32 { let x: [i32; N]; N }'
+assert_with_lib 5 'pub mod a { pub fn f() -> i32 { 5 } } pub use a::*;' 'extern crate mylib as lib; fn main() -> i32 { lib::f() }'
+
+# prelude
+assert 4 'fn main() -> i32 { max(3, 4) }'
+assert 3 'fn main() -> i32 { min(3, 4) }'
+assert 7 'fn main() -> i32 { exit(7); 0 }'
+assert 5 'fn max(a: i32, b: i32) -> i32 { 5 } fn main() -> i32 { max(3, 4) }'
+assert 4 'mod a { use super::*; pub fn f() -> i32 { max(3, 4) } } fn main() -> i32 { a::f() }'
+assert 0 'extern "C" { fn puts(s: &str) -> i32; } fn main() -> i32 { println("prelude"); unsafe { puts("extern block") }; 0 }'
+assert_with_lib 2 'pub fn hello() -> () { println("hello from mylib") }' 'fn main() -> i32 { mylib::hello(); max(1, 2) }'
+
+# macro_rules
+assert 6 'macro_rules! double { ($e:expr) => { $e * 2 }; } fn main() -> i32 { double!(1 + 2) }'
+assert 11 'macro_rules! add_x { ($e:expr) => {{ let x = 10; $e + x }}; } fn main() -> i32 { let x = 1; add_x!(x) }'
+assert 6 'macro_rules! ten { () => { let x = 10; }; } fn main() -> i32 { let x = 1; let x = x + 2; ten!(); x * 2 }'
+assert 10 'macro_rules! sum { () => { 0 }; ($h:expr $(, $t:expr)*) => { $h + sum!($($t),*) }; } fn main() -> i32 { sum!(1, 2, 3, 4) }'
+assert 7 'macro_rules! set { ($v:ident = $e:expr) => { let $v = $e; }; } fn main() -> i32 { set!(y = 5); set!(x = 2); x + y }'
+assert 7 'macro_rules! make_fn { ($name:ident, $t:ty, $v:literal) => { fn $name() -> $t { $v } }; } make_fn!(seven, i32, 7); fn main() -> i32 { seven() }'
+assert 3 'macro_rules! fns { ($($n:ident => $v:expr),* $(,)?) => { $(fn $n() -> i32 { $v })* }; } fns! { a => 1, b => 2, } fn main() -> i32 { a() + b() }'
+assert 5 'macro_rules! blk { ($b:block) => { $b }; } macro_rules! call { ($p:path) => { $p() }; } mod a { pub fn g() -> i32 { 3 } } fn main() -> i32 { blk!({ 1 + 1 }) + call!(a::g) }'
+assert 5 'mod m { macro_rules! five { () => { 5 }; } pub fn f() -> i32 { five!() } } fn main() -> i32 { m::f() }'
+
+# format macros
+assert_output 'x = 42, ok = true' 'fn main() -> i32 { let x = 42; println!("x = {}, ok = {}", x, x == 42); 0 }'
+assert_output '-3 "hi" hi {} 100%' 'fn main() -> i32 { let s = "hi"; println!("{} {:?} {s} {{}} 100%", -3, s); 0 }'
+assert_output 'P { x: 1, q: Q { ok: false }, e: E } [1, 2]' 'struct E {} struct Q { ok: bool } struct P { x: i32, q: Q, e: E } fn main() -> i32 { let q: Q = Q { ok: false }; let p: P = P { x: 1, q: q, e: E {} }; let mut a: [i32; 2]; a[0] = 1; a[1] = 2; println!("{:?} {:?}", p, a); 0 }'
+assert_output 'b-a-b' 'fn main() -> i32 { let s = format_args!("{1}-{0}-{1}", "a", "b"); print!("{}", s); println!(); 0 }'
+assert 101 'fn main() -> i32 { panic!("bad {}", 1); 0 }'
+assert 101 'fn main() -> i32 { let x = 1; assert!(x == 2); 0 }'
+assert 3 'fn main() -> i32 { let x = 3; assert!(x == 3, "x is {}", x); let y: i32 = if x == 3 { x } else { panic!() }; y }'
+assert 5 'macro_rules! println { ($e:expr) => { $e }; } fn main() -> i32 { println!(5) }'
+
+# panics
+assert 101 'fn main() -> i32 { let x: i32 = 0; 1 / x }'
+assert 101 'fn main() -> i32 { let x: i32 = 0; let y: i32 = if x == 0 { 7 % x } else { 2 }; y }'
+assert 5 'fn main() -> i32 { let x: i32 = 2; 9 / x + 9 % 4 }'
+assert 101 'fn main() -> i32 { let x: i32 = 0; 1 / x }' --no-prelude
+assert_with_lib 101 'pub fn div(a: i32, b: i32) -> i32 { a / b }' 'fn main() -> i32 { mylib::div(1, 0) }'
+
+# bounds checks
+assert 101 'fn main() -> i32 { let mut a: [i32; 3]; let i: i32 = 3; a[i] = 1; 0 }'
+assert 101 'fn main() -> i32 { let mut a: [i32; 3]; let i: i32 = 0 - 1; a[i] }'
+assert 101 'fn main() -> i32 { let a = [1, 2, 3]; let i: i32 = i32::MIN; a[i] + a[0] }'
+assert 7 'fn main() -> i32 { let mut a: [[i32; 2]; 3]; let i: i32 = 2; a[i][1] = 7; a[i][i - 1] }'
+assert 101 'fn get(a: [i32; 4], i: i32) -> i32 { a[i] } fn main() -> i32 { let mut a: [i32; 4]; a[3] = 1; get(a, 4) }'
+assert 0 'fn main() -> i32 { let mut a: [i32; 4]; let i: i32 = 3; a[i] = 0; a[i] }' -C no-bounds-checks
+
+# overflow checks
+assert 101 'fn main() -> i32 { let x: i32 = i32::MAX; x + 1 }'
+assert 101 'fn main() -> i32 { let x: i32 = i32::MIN; x - 1 }'
+assert 101 'fn main() -> i32 { let x: i32 = 65536; x * x }'
+assert 101 'fn main() -> i32 { let x: i32 = i32::MIN; -x }'
+assert 101 'fn main() -> i32 { let x: i32 = i32::MIN; x / -1 }' --release
+assert 3 'fn main() -> i32 { let x: i32 = i32::MAX; if (x + 1 == i32::MIN) { 3 } else { 4 } }' -O
+assert 0 'fn main() -> i32 { let x: i32 = -2147483648; x - i32::MIN }'
+assert 3 'fn main() -> i32 { let x: i32 = i32::wrapping_add(i32::MAX, 1); if (x == i32::MIN) { 3 } else { 4 } }'
+assert 1 'fn main() -> i32 { if (i32::saturating_add(i32::MAX, 5) == i32::MAX) { 1 } else { 0 } }'
+assert_output '-2147483648 2147483647 -2147483648 65536' 'fn main() -> i32 { let a: i32 = i32::saturating_mul(65536, -65536); let b: i32 = i32::saturating_mul(-65536, -65536); let c: i32 = i32::saturating_sub(i32::MIN, 1); let d: i32 = i32::wrapping_mul(65536, 65537); println!("{} {} {} {}", a, b, c, d); 0 }'
+assert 3 'fn main() -> i32 { let x = i32::MAX; if (x.wrapping_add(1) == i32::MIN) { 3.saturating_sub(1).wrapping_mul(2) - 1 } else { 4 } }'
+assert 5 'fn main() -> i32 { let a = i32::MAX.checked_add(1); let b = 2.checked_add(3); if a.is_some { 1 } else { b.value } }'
+assert 9 'fn main() -> i32 { let x = 5; x.checked_add(1).value + x.wrapping_sub(2) }'
+assert_output 'false false false true -2147483648 -42' 'fn main() -> i32 { let c = i32::MIN.checked_sub(1); let d = i32::checked_mul(-1, i32::MIN); let e = 65536.checked_mul(32768); let f = (-65536).checked_mul(32768); let g = 7.checked_mul(-6); println!("{} {} {} {} {} {}", c.is_some, d.is_some, e.is_some, f.is_some, f.value, g.value); 0 }'
+assert 3 'struct S { f: fn(i32) -> i32 } fn g(x: i32) -> i32 { x + 2 } fn main() -> i32 { let s = S { f: g }; (s.f)(1) }'
+
+# array expressions
+assert 6 'fn main() -> i32 { let a: [i32; 3] = [1, 2, 3]; a[0] + a[1] + a[2] }'
+assert 6 'fn main() -> i32 { let a: [[i32; 2]; 3] = [[1, 2], [3, 4], [5, 6]]; a[2][1] }'
+assert 4 'struct P { x: i32, y: i32 } fn main() -> i32 { let a: [P; 2] = [P { x: 1, y: 2 }, P { x: 3, y: 4 }]; a[1].y }'
+assert 14 'fn main() -> i32 { let a: [i32; 1000] = [7; 1000]; a[0] + a[999] }'
+assert 0 'fn main() -> i32 { let a: [i32; 1000] = [0; 1000]; a[500] }'
+assert 3 'struct P { x: i32, y: i32 } fn main() -> i32 { let a: [P; 4] = [P { x: 1, y: 2 }; 4]; a[3].x + a[0].y }'
+assert 4 'fn main() -> i32 { let a: [[i32; 3]; 2] = [[4; 3]; 2]; a[1][2] }'
+assert 2 'fn main() -> i32 { let a: [bool; 5] = [false; 5]; if a[4] { 1 } else { 2 } }'
+assert 1 'fn main() -> i32 { let a: [bool; 2] = [false, true]; if a[1] { 1 } else { 2 } }'
+assert 4 'const N: i32 = 4; fn main() -> i32 { let a: [i32; 4] = [N; N]; a[3] }'
+assert 5 'fn main() -> i32 { let a: [i32; 0] = []; 5 }'
+assert 9 'fn f() -> [i32; 3] { [9; 3] } fn main() -> i32 { let a: [i32; 3] = f(); a[2] }'
+assert 4 'fn main() -> i32 { let x = 3; let a = [x + 1; 2]; a[1] }'
+assert_output '[1, 2, 3] [0, 0]' 'fn main() -> i32 { let a: [i32; 3] = [1, 2, 3]; println!("{:?} {:?}", a, [0; 2]); 0 }'
+
+# closures
+assert 24 'fn add1(x: i32) -> i32 { x + 1 } fn apply(f: fn(i32) -> i32, x: i32) -> i32 { f(x) } fn main() -> i32 { let f: fn(i32) -> i32 = add1; apply(f, 2) + apply(add1, 10) + apply(|x| x * 2, 5) }'
+assert 35 'fn main() -> i32 { let mut n = 3; let k = 4; let add = |x: i32| x + n + k; let a = add(1); n = 10; let b = add(1); let mut inc = || { n = n + 1; }; inc(); inc(); a + b + n }'
+assert 13 'fn main() -> i32 { let mut n = 3; let get = move || n; n = 10; get() + n }'
+assert 87 'fn main() -> i32 { let a = 1; let f = |x: i32| { let b = 10; let g = |y: i32| x + y + a + b; g(100) }; f(1000) }'
+assert 50 'struct P { x: i32, y: i32 } fn main() -> i32 { let arr = [1, 2, 3]; let p = P { x: 5, y: 6 }; let f = || arr[0] + arr[2] + p.y; let g = move || P { x: p.y, y: arr[1] }; let q = g(); f() + q.x * 100 + q.y * 1000 }'
+assert 9 'fn f(n: i32, a: [i32; 2]) -> i32 { let g = || n * 2 + a[1]; g() } fn main() -> i32 { let u = (); let h = |v: ()| { u; v; 1 }; f(3, [1, 2]) + h(()) }'
+assert 9 'fn mk() -> [i32; 3] { [1, 2, 3] } fn main() -> i32 { let f: fn() -> [i32; 3] = mk; let a = f(); let g = |n: i32| -> [i32; 3] { if n > 0 { return [n; 3]; }; [0, 0, 0] }; let b = g(7); a[1] + b[2] }'
+assert 12 'fn app(f: fn(i32) -> i32, x: i32) -> i32 { f(x) } fn main() -> i32 { let f = |x: i32| x + 1; let g: fn(i32) -> i32 = f; app(f, 2) + g(10) - f(1) }'
+assert 9 'fn main() -> i32 { let mut c = 0; let mut add = |n: i32| { c = c + n; c }; add(2); add(3); let get: fn() -> i32 = || 4; add(get()) }'
+assert 10 'struct S { f: fn(i32) -> i32, g: fn(i32) -> i32 } fn main() -> i32 { let h = |x: i32| x + 1; let s = S { f: |x: i32| x * 2, g: h }; (s.f)(3) + (s.g)(3) }'
+assert 17 'fn mk(b: bool) -> fn(i32) -> i32 { if (b) { return |x| x * 3; } let g = |x: i32| x - 1; if (b) { g } else { |x| x + 10 } } fn main() -> i32 { let f = mk(false); let h = mk(true); f(4) + h(1) }'
+assert 12 'fn main() -> i32 { let mut f: fn(i32) -> i32 = |x| x; f = |x: i32| x * 5; let g = |x: i32| x + 1; let r = f(2); f = g; r + f(1) }'
+assert_output '[1, 2, 3, 4, 5] [5, 4, 3, 2, 1]' 'extern "C" { fn qsort(base: &[i32; 5], n: i32, size: i32, cmp: fn(*const i32, *const i32) -> i32) -> (); } fn cmp(a: *const i32, b: *const i32) -> i32 { unsafe { *a - *b } } fn main() -> i32 { let arr = [5, 3, 4, 1, 2]; unsafe { qsort(&arr, 5, 4, cmp); } let r = [3, 1, 2, 5, 4]; unsafe { qsort(&r, 5, 4, |a, b| *b - *a); } println!("{:?} {:?}", arr, r); 0 }'
+assert 2 'fn main() -> i32 { let x: i32 = 1; let y: i32 = 2; let mut p: *const i32 = (&x) as *const i32; p = (&y) as *const i32; unsafe { *p } }'
+
+# lints
+assert 3 'fn unused() -> i32 { 1 } fn main() -> i32 { let x = 1; let mut y = 2; let y = 3; return y; 4 }'
+assert 3 '#![allow(unused)] struct S { a: i32 } fn main() -> i32 { let x = 1; 3 }' -D warnings
+assert 3 'fn main() -> i32 { let x = 1; 3 }' -A unused_variables -W dead-code
+
+# type aliases
+assert 3 'type T = i32; fn main() -> i32 { 3 }'
+
+# optimization levels and editions
+assert 3 'fn main() -> i32 { let x: i32 = 2147483647; if x + 1 < 0 { 3 } else { 4 } }' -O3
+assert 3 'fn main() -> i32 { 3 }' -O0 --edition 2015
+assert 3 'fn main() -> i32 { 3 }' --edition=2024
+
+# linking
+assert 3 'extern "C" { fn abs(n: i32) -> i32; } fn main() -> i32 { unsafe { abs(-3) } }' -l m -L /usr/lib
+assert 3 'extern "C" { fn abs(n: i32) -> i32; } fn main() -> i32 { unsafe { abs(-3) } }' -lc -C linker=cc -C llc=llc
+assert_with_lib_in 3 '../tmp-lib' 'pub fn three() -> i32 { 3 }' 'fn main() -> i32 { mylib::three() }'
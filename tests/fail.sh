@@ -10,9 +10,19 @@ GREEN='\033[0;32m'
 GRAY='\033[0;30m'
 NC='\033[0m' # No Color
 
+# compile `input`, the path to a root file or source code, with the options following it.
+# An option like `--explain` in place of the input is passed as it is
+rustc() {
+  if [ -f "$1" ] || [[ "$1" == -* ]]; then
+    $RUSTC "$1" "${@:2}"
+  else
+    $RUSTC -e "$1" "${@:2}"
+  fi
+}
+
 compile_fail() {
   input="$1"
-  $RUSTC "$input" #>&/dev/null
+  rustc "$input" "${@:2}" -o $EXE #>&/dev/null
   code="$?"
   if [ "$code" = 1 ]; then
     echo -e "[${GREEN}OK${NC}] $input"
@@ -22,6 +32,48 @@ compile_fail() {
   fi
 }
 
+# compile `lib` as library `mylib`, then expect `input` using it to fail
+compile_fail_with_lib() {
+  lib="$1"
+  input="$2"
+  rm -f libmylib.rmeta libmylib.a
+  rustc "$lib" --crate-type lib --crate-name mylib >/dev/null
+  rustc "$input" --extern mylib=libmylib.rmeta -o $EXE >/dev/null
+  code="$?"
+  rm -f libmylib.rmeta libmylib.a
+  if [ "$code" = 1 ]; then
+    echo -e "[${GREEN}OK${NC}] $lib | $input"
+  else
+    echo -e "[${RED}ERROR${NC}] $lib | $input ${GRAY}=> Unexpectedly exit with code $code${NC}"
+    exit 1
+  fi
+}
+
+# expect `input` to fail with exactly `count` errors
+compile_fail_count() {
+  input="$1"
+  count="$2"
+  errors=$(rustc "$input" -o $EXE 2>&1 | grep -c '^error\[')
+  if [ "$errors" = "$count" ]; then
+    echo -e "[${GREEN}OK${NC}] $input"
+  else
+    echo -e "[${RED}ERROR${NC}] $input ${GRAY}=> $count errors expected, but got $errors${NC}"
+    exit 1
+  fi
+}
+
+# expect the command line to be rejected with exit code 2 before compiling anything
+usage_error() {
+  $RUSTC "$@" 2>/dev/null
+  code="$?"
+  if [ "$code" = 2 ]; then
+    echo -e "[${GREEN}OK${NC}] $*"
+  else
+    echo -e "[${RED}ERROR${NC}] $* ${GRAY}=> Unexpectedly exit with code $code${NC}"
+    exit 1
+  fi
+}
+
 QT="'"
 
 echo "===== Failure Tests ====="
@@ -35,6 +87,7 @@ compile_fail 'fn main() -> i32 { let b: bool; b = 100; }'
 compile_fail 'fn main() -> i32 { let u: (); u = (return 0); }'
 # ill-typed arithmetic
 compile_fail 'fn main() -> i32 { return (1+true)*2; }'
+compile_fail 'fn main() -> i32 { let a: i32 = 1 < 2; a }'
 # unexpected type of return value
 compile_fail 'fn main() -> i32 { return true; }'
 # unexpected type of block expression
@@ -50,6 +103,11 @@ compile_fail 'fn main() -> () { []; }'
 compile_fail 'fn main() -> () { let a: [i32; 1] = [1, 2]; }'
 compile_fail 'fn main() -> () { let a: [i32; 1] = [true]; }'
 compile_fail 'fn main() -> () { let a: [i32; 1]; a[0] = true; }'
+# struct expression
+compile_fail 'struct S { a: i32 } fn main() -> i32 { let s = S { a: true }; 0 }'
+compile_fail 'struct S { a: i32 } fn main() -> i32 { let s = S { a: 1, b: 2 }; 0 }'
+compile_fail 'struct S { a: i32, b: i32 } fn main() -> i32 { let s = S { a: 1 }; s.b }'
+compile_fail 'struct S { a: i32 } fn main() -> i32 { let s = S { a: 1, a: 2 }; s.a }'
 # if
 compile_fail 'fn main() -> () { if (true) { } else { 1 } }'
 # name space
@@ -69,3 +127,212 @@ compile_fail 'fn main() -> () { { { let a: (); } }  { a } }'
 compile_fail 'fn main() -> () { a; let a: (); }'
 compile_fail 'fn main() -> () { let a: i32 = { { { let b: i32 = 0 } } b }; }'
 compile_fail 'fn main() -> () { let a: i32 = 0; let b: i32 = { { a + b } }; }'
+# const and static items
+compile_fail 'const A: i32 = B; const B: i32 = A; fn main() -> i32 { A }'
+compile_fail 'const A: bool = 1; fn main() -> () { }'
+compile_fail 'fn main() -> () { let n: i32 = 1; let a: [i32; n]; }'
+compile_fail 'static mut C: i32 = 0; fn main() -> i32 { C }'
+compile_fail 'static S: i32 = 0; fn main() -> () { S = 1; }'
+
+# const fn and division
+compile_fail 'const N: i32 = 2147483647 + 1; fn main() -> i32 { N }'
+compile_fail 'const N: i32 = 1 / 0; fn main() -> i32 { N }'
+compile_fail 'const N: i32 = 1 % (2 - 2); fn main() -> i32 { N }'
+compile_fail 'fn f() -> i32 { 1 } const N: i32 = f(); fn main() -> i32 { N }'
+compile_fail 'fn f() -> i32 { 1 } const fn g() -> i32 { f() } fn main() -> i32 { g() }'
+compile_fail 'const fn f(n: i32) -> i32 { f(n + 1) } const N: i32 = f(0); fn main() -> i32 { N }'
+compile_fail 'static S: i32 = 1; const N: i32 = S; fn main() -> i32 { N }'
+
+# unsafety checking
+compile_fail 'extern "C" { fn abs(n: i32) -> i32; } fn main() -> i32 { abs(-3) }'
+compile_fail 'unsafe fn f() -> i32 { 1 } fn main() -> i32 { f() }'
+compile_fail 'fn main() -> i32 { let n: i32 = 3; let r: &i32 = &n; let p: *const i32 = r as *const i32; *p }'
+compile_fail 'static mut C: i32 = 0; fn main() -> () { C = 1; }'
+
+# visibility
+compile_fail 'mod a { fn f() -> () { } } fn main() -> () { a::f() }'
+compile_fail 'mod a { mod b { pub fn f() -> () { } } } fn main() -> () { a::b::f() }'
+compile_fail 'mod a { pub mod b { pub(super) fn f() -> () { } } } fn main() -> () { a::b::f() }'
+compile_fail 'mod a { const K: i32 = 1; } fn main() -> i32 { a::K }'
+compile_fail 'mod a { pub struct S { x: i32 } pub fn new() -> S { S { x: 1 } } } fn main() -> i32 { let s: a::S = a::new(); s.x }'
+compile_fail 'mod a { pub struct S { x: i32 } } fn main() -> () { let s: a::S = a::S { x: 1 }; }'
+compile_fail 'fn main() -> () { super::f() }'
+
+# use declarations
+compile_fail 'mod a { pub fn x() -> () { } } use a::y; fn main() -> () { }'
+compile_fail 'mod a { fn x() -> () { } } use a::x; fn main() -> () { }'
+compile_fail 'mod a { pub fn x() -> () { } } fn x() -> () { } use a::x; fn main() -> () { }'
+compile_fail 'mod a { pub use crate::b::x; } mod b { pub use crate::a::x; } fn main() -> () { }'
+compile_fail 'mod a { pub fn x() -> () { } } mod b { pub fn x() -> () { } } use a::*; use b::*; fn main() -> () { x() }'
+compile_fail 'mod a { mod inner { pub fn f() -> i32 { 3 } } use self::inner::f; } fn main() -> i32 { a::f() }'
+compile_fail 'fn f() -> () { } use f::*; fn main() -> () { }'
+
+# out-of-line modules
+compile_fail 'modules/missing/main.rs'
+compile_fail 'modules/ambiguous/main.rs'
+compile_fail 'mod a { mod nothere; } fn main() -> () {}'
+
+# extern crates
+compile_fail_with_lib 'mod a { pub fn f() -> () { } }' 'fn main() -> () { mylib::a::f() }'
+compile_fail_with_lib 'pub(crate) fn f() -> () { }' 'fn main() -> () { mylib::f() }'
+compile_fail_with_lib 'pub struct S { x: i32 }' 'fn main() -> () { let s: mylib::S = mylib::S { x: 1 }; }'
+compile_fail_with_lib 'pub unsafe fn f() -> () { }' 'fn main() -> () { mylib::f() }'
+compile_fail 'extern crate nope; fn main() -> () { }'
+compile_fail 'fn main() -> () { nope::f() }'
+compile_fail 'fn main() -> i32 { 0 }' --extern mylib=crates/bad/one_segment.rmeta
+compile_fail 'fn main() -> i32 { 0 }' --extern mylib=crates/bad/undeclared_mod.rmeta
+compile_fail 'fn main() -> i32 { 0 }' --extern mylib=crates/bad/use_before_mod.rmeta
+compile_fail 'fn main() -> i32 { 0 }' --extern mylib=crates/bad/undeclared_use_target.rmeta
+
+# prelude
+compile_fail 'fn main() -> i32 { max(3, 4) }' --no-prelude
+compile_fail 'fn main() -> () { printf("unsafe"); }'
+
+# macro_rules
+compile_fail 'fn main() -> i32 { nope!(1) }'
+compile_fail 'macro_rules! one { (1) => { 1 }; } fn main() -> i32 { one!(2) }'
+compile_fail 'macro_rules! uses_x { () => { x }; } fn main() -> i32 { let x = 1; uses_x!() }'
+compile_fail 'macro_rules! s { ($t:tt) => { let y = $t; }; } fn main() -> i32 { s!(4); y }'
+compile_fail 'mod m { macro_rules! five { () => { 5 }; } } fn main() -> i32 { five!() }'
+compile_fail 'macro_rules! inf { () => { inf!() }; } fn main() -> i32 { inf!() }'
+compile_fail 'macro_rules! m { ($a:expr, $a:expr) => { 1 }; } fn main() -> i32 { 0 }'
+compile_fail 'macro_rules! m { ($a:foo) => { 1 }; } fn main() -> i32 { 0 }'
+compile_fail 'macro_rules! m { ($($a:expr),*; $($b:expr),*) => { 0 $(+ $a * $b)* }; } fn main() -> i32 { m!(1, 2; 3) }'
+
+# format macros
+compile_fail 'fn main() -> i32 { println!("{} {}", 1); 0 }'
+compile_fail 'fn main() -> i32 { println!("{}", 1, 2); 0 }'
+compile_fail 'fn main() -> i32 { println!("{2}", 1); 0 }'
+compile_fail 'fn main() -> i32 { println!("{:x}", 1); 0 }'
+compile_fail 'fn main() -> i32 { println!("{"); 0 }'
+compile_fail 'fn main() -> i32 { println!("}"); 0 }'
+compile_fail 'fn main() -> i32 { println!(1); 0 }'
+compile_fail 'fn main() -> i32 { println!("{y}"); 0 }'
+compile_fail 'struct S {} fn main() -> i32 { let s = S {}; println!("{}", s); 0 }'
+compile_fail 'fn main() -> i32 { println!("a"); 0 }' --no-prelude
+
+# bounds checks
+compile_fail 'fn main() -> i32 { let a: [i32; 3]; a[3] }'
+compile_fail 'fn main() -> i32 { let a: [i32; 3]; a[-1] }'
+compile_fail 'const N: i32 = 2; fn main() -> i32 { let a: [i32; 3]; a[N + 1] }'
+compile_fail 'fn main() -> i32 { let a: [i32; 3]; a[true] }'
+usage_error -e 'fn main() -> i32 { 0 }' -C no-such-option
+
+# overflow checks
+compile_fail 'fn main() -> i32 { i32::FOO }'
+compile_fail 'fn main() -> i32 { i32::MAX }' --no-prelude
+compile_fail 'extern "rust-intrinsic" { fn foo(a: i32, b: i32) -> i32; } fn main() -> i32 { 0 }'
+compile_fail 'extern "rust-intrinsic" { fn wrapping_add(a: i32) -> i32; } fn main() -> i32 { 0 }'
+compile_fail 'fn main() -> i32 { 1.wrapping_neg() }'
+compile_fail 'fn main() -> i32 { i32::wrapping_neg(1) }'
+compile_fail 'fn main() -> i32 { true.wrapping_add(1) }'
+compile_fail 'fn main() -> i32 { 1.wrapping_add(1) }' --no-prelude
+compile_fail 'struct S { x: i32 } fn main() -> i32 { let s = S { x: 1 }; s.foo() }'
+compile_fail 'fn main() -> i32 { let b = true; b.wrapping_add(1) }'
+compile_fail 'fn main() -> i32 { let x = 1; let c = x.checked_add(); 0 }'
+compile_fail 'fn main() -> i32 { 1.checked(true, 1); 0 }'
+compile_fail 'const fn f(x: i32) -> i32 { x.wrapping_add(1) } fn main() -> i32 { 0 }'
+
+# array expressions
+compile_fail 'fn main() -> i32 { let a = []; 0 }'
+compile_fail 'fn main() -> i32 { let a: [i32; 3] = []; 0 }'
+compile_fail 'fn main() -> i32 { let a: [i32; 2] = [1; true]; 0 }'
+compile_fail 'fn main() -> i32 { let n: i32 = 2; let a: [i32; 2] = [1; n]; 0 }'
+compile_fail 'fn main() -> i32 { let a: [i32; 3] = [1; 2]; 0 }'
+
+# closures
+compile_fail 'fn main() -> i32 { let x = 1; let f: fn(i32) -> i32 = |y| x + y; 0 }'
+compile_fail 'fn main() -> i32 { let f = |y| y; 0 }'
+compile_fail 'fn main() -> i32 { let f = |y: i32| y; f(true) }'
+compile_fail 'fn main() -> i32 { let f = |y: i32| { if y > 0 { return 1; }; 2 }; f(1) }'
+compile_fail 'fn main() -> i32 { let f = |y: i32| -> bool { y }; 0 }'
+compile_fail 'fn main() -> i32 { let x = 1; let f = |y: i32| x + y; let g: fn(i32) -> i32 = f; 0 }'
+compile_fail 'fn app(f: fn(i32) -> i32) -> i32 { f(1) } fn main() -> i32 { let x = 1; let f = |y: i32| x + y; app(f) }'
+compile_fail 'fn app(f: fn(i32) -> i32) -> i32 { f(1) } fn main() -> i32 { let f = |y: i32| y == 1; app(f) }'
+compile_fail 'extern "rust-intrinsic" { fn wrapping_add(a: i32, b: i32) -> i32; } fn main() -> i32 { let f: fn(i32, i32) -> i32 = wrapping_add; 0 }'
+compile_fail 'const fn g(f: fn() -> i32) -> i32 { f() } fn main() -> i32 { 0 }'
+compile_fail 'fn mk() -> fn(i32) -> i32 { let n = 1; |x: i32| x + n } fn main() -> i32 { 0 }'
+compile_fail 'fn mk() -> fn(i32) -> i32 { let n = 1; let g = |x: i32| x + n; return g; } fn main() -> i32 { 0 }'
+compile_fail 'fn main() -> i32 { let n = 1; let mut f: fn(i32) -> i32 = |x| x; f = |x: i32| x * n; 0 }'
+compile_fail 'struct S { f: fn(i32) -> i32 } fn main() -> i32 { let n = 2; let s = S { f: |x: i32| x * n }; 0 }'
+compile_fail 'struct S { f: fn(i32) -> i32 } fn main() -> i32 { let s = S { f: |x: bool| 1 }; 0 }'
+
+# each error is reported once
+compile_fail_count 'fn main() -> i32 { 1 + cout + 2 }' 1
+compile_fail_count 'struct S { x: i32 } fn fo() -> i32 { 1 } fn main() -> i32 { let s: S = S { x: 1 }; cout + s.y + fo() }' 2
+compile_fail_count 'struct Point { x: i32 } fn main() -> i32 { let p: Pont = Point { x: 1 }; 0 }' 1
+compile_fail_count 'struct Point { x: i32 } fn f(p: Pont) -> Pont { p } fn main() -> i32 { 0 }' 2
+compile_fail_count 'fn f(p: &i32) -> i32 { 0 } fn main() -> i32 { f(&nope) }' 1
+compile_fail_count 'fn f(p: &i32) -> i32 { 0 } fn main() -> i32 { f(&(1 as bool)) }' 1
+
+# parser recovery
+compile_fail 'fn main() -> i32 { let x = 1 + ; x }'
+compile_fail 'fn main() -> i32 { let x: i32 = ; let y = x * 2; y }'
+compile_fail 'fn main() -> i32 { let x = (1; let y = 2; y }'
+compile_fail 'fn main() -> i32 { foo(1, ; 0'
+compile_fail 'fn f(x: i32 -> i32 { x } fn main() -> i32 { f(1) }'
+compile_fail 'struct S { a: i32 b: i32 } fn main() -> i32 { 0 }'
+compile_fail 'let x = 1; fn main() -> i32 { 0 }'
+compile_fail 'fn main() -> i32 { 0 } }'
+compile_fail 'fn main() -> i32 { let a: [i32; 2] = [1, 2]; a[0] + }'
+
+# error codes
+compile_fail '--explain' E9999
+compile_fail 'fn main() -> i32 { let s = "abc; 0 }'
+compile_fail 'fn main() -> i32 { let s = "a\'
+compile_fail 'fn main() -> i32 { 1 @ 2 }'
+compile_fail 'fn main() -> i32 { -true }'
+compile_fail_count 'fn main() -> i32 { foo!(1) }' 1
+compile_fail_count 'macro_rules! m { ($x) => { $x }; } fn main() -> i32 { 0 }' 1
+compile_fail_count 'macro_rules! m { ($x:expr) => { $x }; } fn main() -> i32 { m!(1, 2) }' 1
+compile_fail_count 'macro_rules! m { () => { m!() }; } fn main() -> i32 { m!(); 0 }' 1
+compile_fail_count 'macro_rules! m { ($($x:expr),*) => { $x }; } fn main() -> i32 { m!(1, 2) }' 1
+compile_fail_count 'macro_rules! m { () => { 1 2 }; } fn main() -> i32 { let x = m!(); x }' 1
+compile_fail_count 'fn main() -> i32 { println!("{} {}", 1); 0 }' 1
+compile_fail_count 'println!("hello"); fn main() -> i32 { 0 }' 1
+compile_fail_count 'fn main() -> i32 { let x: i32 = 1; x = 2; x }' 1
+compile_fail_count 'static S: i32 = 1; fn main() -> i32 { S = 2; 0 }' 1
+compile_fail_count 'fn main() -> i32 { let x: i32 = 1; let mut p: *const i32 = (&x) as *const i32; *p = 2; 0 }' 1
+compile_fail_count 'fn f() -> i32 { 0 } fn main() -> i32 { f = f; 1 = 2; 0 }' 2
+compile_fail_count '#[inline] fn main() -> i32 { 0 }' 1
+
+# error format
+compile_fail 'fn main() -> i32 { let x: i32 = true; x }' --error-format=json
+compile_fail 'fn main() -> i32 { println!("{}", [1]); 0 }' --error-format json
+compile_fail 'fn main() -> i32 { 1 +; 0 }' --error-format=json
+usage_error -e 'fn main() -> i32 { 0 }' --error-format=xml
+
+# suggestions for unresolved names
+compile_fail 'fn main() -> i32 { let count: i32 = 1; coutn }'
+compile_fail 'struct Point { x: i32, y: i32 } fn main() -> i32 { let p: Piont = Point { x: 1, y: 2 }; p.z }'
+compile_fail 'mod alpha { pub fn f() -> i32 { 1 } } fn main() -> i32 { alpa::f() }'
+compile_fail 'mod a { pub fn f() -> i32 { 1 } } fn main() -> i32 { f() }'
+
+# lints
+compile_fail 'fn main() -> i32 { let x = 1; 0 }' -D unused_variables
+compile_fail 'fn main() -> i32 { let mut x = 1; x }' -Dunused
+compile_fail '#[deny(dead_code)] fn f() -> i32 { 1 } fn main() -> i32 { 0 }'
+compile_fail '#![deny(unreachable_code)] fn main() -> i32 { return 1; 2 }'
+compile_fail '#![deny(warnings)] fn main() -> i32 { let a = 1; let a = 2; a }'
+compile_fail '#[allow(dead_code = 1)] fn main() -> i32 { 0 }'
+compile_fail '#[inline] fn main() -> i32 { 0 }'
+usage_error -e 'fn main() -> i32 { 0 }' -D
+
+# command line
+usage_error
+usage_error -e 'fn main() -> i32 { 0 }' -O4
+usage_error -e 'fn main() -> i32 { 0 }' --emit=hir
+usage_error -e 'fn main() -> i32 { 0 }' --emit=ast,llvm-ir -o out.txt
+usage_error -e 'fn main() -> i32 { 0 }' --edition 2020
+usage_error -e 'fn main() -> i32 { 0 }' --target wasm32-unknown-unknown
+usage_error -e 'fn main() -> i32 { 0 }' -e 'fn main() -> i32 { 1 }'
+usage_error -e
+
+# llc and linker
+compile_fail 'fn main() -> i32 { 0 }' -C llc=no-such-llc
+compile_fail 'fn main() -> i32 { 0 }' -C linker=no-such-linker
+compile_fail 'fn main() -> i32 { 0 }' -l no_such_library
+compile_fail 'extern "C" { fn no_such_function() -> i32; } fn main() -> i32 { unsafe { no_such_function() } }'
+rustc 'pub fn f() -> i32 { 1 }' --crate-type lib --crate-name mylib --emit=llvm-ir >/dev/null
+compile_fail 'fn main() -> i32 { mylib::f() }' --extern mylib=libmylib.rmeta
+rm -f libmylib.rmeta
@@ -0,0 +1,3 @@
+mod x;
+
+fn main() -> () {}
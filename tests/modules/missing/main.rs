@@ -0,0 +1,3 @@
+mod nothere;
+
+fn main() -> () {}
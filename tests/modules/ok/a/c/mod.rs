@@ -0,0 +1,3 @@
+pub fn g() -> i32 {
+    super::f() + 1
+}
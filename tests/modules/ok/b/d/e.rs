@@ -0,0 +1,3 @@
+pub fn four() -> i32 {
+    4
+}
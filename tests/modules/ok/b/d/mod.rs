@@ -0,0 +1,5 @@
+mod e;
+
+pub fn h() -> i32 {
+    e::four()
+}
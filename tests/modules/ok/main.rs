@@ -0,0 +1,8 @@
+mod a;
+mod b {
+    pub mod d;
+}
+
+fn main() -> i32 {
+    a::f() + a::c::g() + b::d::h()
+}